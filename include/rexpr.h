@@ -0,0 +1,61 @@
+/*
+ * C interface to rexpr, from a build of the crate with the `ffi` feature.
+ */
+#ifndef REXPR_H
+#define REXPR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Evaluates the NUL-terminated `source` in a fresh session. On success
+ * stores the value in `*out` and returns 0. On failure returns 1 and, unless
+ * `err` is NULL, stores in `*err` a message to be freed with
+ * rexpr_free_string().
+ *
+ * The functions and constants of the standard library the crate was built
+ * with are in scope. Values are integers, so that `7 / 2` is 3, unless a
+ * float enters, as in `7 / 2.0` or `sqrt(2)`; either way `*out` holds the
+ * value as a double.
+ */
+int rexpr_eval(const char *source, double *out, char **err);
+
+/* Frees a message returned by rexpr_eval(). Does nothing for NULL. */
+void rexpr_free_string(char *s);
+
+/*
+ * Plugins: shared libraries exporting rexpr_plugin_declare(), loaded by a
+ * build with the `plugin` feature.
+ */
+#define REXPR_PLUGIN_ABI 1
+
+/*
+ * Reads `len` arguments from `args`, stores the result in `*out` and
+ * returns 0, or returns anything else to fail the evaluation.
+ */
+typedef int (*rexpr_native_fn)(const double *args, size_t len, double *out);
+
+typedef struct {
+    const char *name;
+    size_t arity;
+    rexpr_native_fn call;
+} rexpr_function;
+
+typedef struct {
+    uint32_t abi_version; /* REXPR_PLUGIN_ABI */
+    const char *name;
+    const rexpr_function *functions;
+    size_t len;
+} rexpr_plugin;
+
+const rexpr_plugin *rexpr_plugin_declare(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* REXPR_H */
@@ -0,0 +1,80 @@
+//!
+//! Expressions written in rexpr syntax and parsed at compile time.
+//!
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use rexpr::diagnostic::Diagnostic;
+use rexpr::parser::{Node, Parser};
+use rexpr::tokenizer::Lexer;
+use syn::{parse_macro_input, LitStr};
+
+///
+/// Parses the string literal as an expression when the crate is compiled and
+/// expands to the code building its `rexpr::parser::Node`, so a syntax error
+/// fails the build instead of showing up at run time:
+///
+/// ```
+/// use rexpr::parser::Node;
+/// use rexpr_macros::expr;
+///
+/// let area = expr!("r = 2.5; 3 * r ^ 2");
+/// assert_eq!(area, "r = 2.5; 3 * r ^ 2".parse::<Node>().unwrap());
+/// ```
+///
+/// ```compile_fail
+/// let bad = rexpr_macros::expr!("1 + * 2");
+/// ```
+///
+/// The expansion only uses constructors that are available without std.
+///
+#[proc_macro]
+pub fn expr(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let literal = parse_macro_input!(input as LitStr);
+    let source = literal.value();
+    match Parser::from_lexer(Lexer::new(&source)).parse() {
+        Ok(node) => build(&node).into(),
+        Err(err) => {
+            let rendered = Diagnostic::from(&err).render(&source);
+            // rustc prints its own `error: ` in front.
+            let message = rendered.strip_prefix("error: ").unwrap_or(&rendered);
+            syn::Error::new(literal.span(), message)
+                .to_compile_error()
+                .into()
+        }
+    }
+}
+
+fn build(node: &Node) -> TokenStream {
+    let node_type = quote!(::rexpr::parser::Node);
+    match node {
+        Node::Number(n) => quote!(#node_type::num(#n)),
+        Node::Float(x) => {
+            let x = x.0;
+            quote!(#node_type::Float(::rexpr::parser::Float(#x)))
+        }
+        Node::Identifier(name) => quote!(#node_type::var(#name)),
+        Node::UnaryExpr { op, child } => {
+            let op = Ident::new(&format!("{:?}", op), Span::call_site());
+            let child = build(child);
+            quote!(#node_type::unary(::rexpr::parser::UnaryOperator::#op, #child))
+        }
+        Node::BinaryExpr { op, lhs, rhs } => {
+            let op = Ident::new(&format!("{:?}", op), Span::call_site());
+            let (lhs, rhs) = (build(lhs), build(rhs));
+            quote!(#node_type::binary(::rexpr::parser::BinaryOperator::#op, #lhs, #rhs))
+        }
+        Node::Assign { name, value } => {
+            let value = build(value);
+            quote!(#node_type::assign(#name, #value))
+        }
+        Node::Block(stmts) => {
+            let stmts = stmts.iter().map(build);
+            quote!(#node_type::block([#(#stmts),*]))
+        }
+        Node::Call { name, args } => {
+            let args = args.iter().map(build);
+            quote!(#node_type::call(#name, [#(#args),*]))
+        }
+        Node::Use(path) => quote!(#node_type::Use(::std::string::String::from(#path))),
+    }
+}
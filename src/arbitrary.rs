@@ -0,0 +1,209 @@
+//!
+//! Random expression trees for property-based testing, available with the
+//! `quickcheck` feature.
+//!
+use crate::parser::*;
+use quickcheck::{empty_shrinker, Arbitrary, Gen};
+
+const NAMES: &[&str] = &["a", "b", "x", "y", "z"];
+
+impl Arbitrary for BinaryOperator {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.choose(&[
+            BinaryOperator::Plus,
+            BinaryOperator::Minus,
+            BinaryOperator::Mul,
+            BinaryOperator::Div,
+            BinaryOperator::Pow,
+            BinaryOperator::Shl,
+            BinaryOperator::Shr,
+            BinaryOperator::And,
+            BinaryOperator::Or,
+        ])
+        .unwrap()
+        .clone()
+    }
+}
+
+impl Arbitrary for UnaryOperator {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.choose(&[UnaryOperator::Neg, UnaryOperator::Not])
+            .unwrap()
+            .clone()
+    }
+}
+
+impl Node {
+    ///
+    /// Generates an expression at most `depth` operators deep, drawing from a
+    /// small pool of variable names so that generated trees share variables.
+    ///
+    /// Only expressions are produced; assignments and blocks, which the
+    /// grammar allows at statement level alone, come from `Node::arbitrary`.
+    ///
+    pub fn random(depth: usize, g: &mut Gen) -> Node {
+        let choice = if depth == 0 {
+            u8::arbitrary(g) % 2
+        } else {
+            u8::arbitrary(g) % 5
+        };
+        match choice {
+            0 => Node::Number(i32::arbitrary(g)),
+            1 => Node::var(*g.choose(NAMES).unwrap()),
+            2 => Node::unary(UnaryOperator::arbitrary(g), Node::random(depth - 1, g)),
+            _ => Node::binary(
+                BinaryOperator::arbitrary(g),
+                Node::random(depth - 1, g),
+                Node::random(depth - 1, g),
+            ),
+        }
+    }
+}
+
+fn random_stmt(depth: usize, g: &mut Gen) -> Node {
+    if bool::arbitrary(g) {
+        Node::assign(*g.choose(NAMES).unwrap(), Node::random(depth, g))
+    } else {
+        Node::random(depth, g)
+    }
+}
+
+impl Arbitrary for Node {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let depth = usize::arbitrary(g) % 6;
+        match u8::arbitrary(g) % 4 {
+            0 => {
+                let len = 2 + usize::arbitrary(g) % 3;
+                Node::Block((0..len).map(|_| random_stmt(depth, g)).collect())
+            }
+            1 => random_stmt(depth, g),
+            _ => Node::random(depth, g),
+        }
+    }
+
+    /// Shrinks towards the direct children and smaller literals.
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match self {
+            Node::Number(n) => Box::new(n.shrink().map(Node::Number)),
+            Node::Float(_) | Node::Identifier(_) | Node::Use(_) => empty_shrinker(),
+            Node::UnaryExpr { child, .. } => Box::new(std::iter::once((**child).clone())),
+            Node::BinaryExpr { lhs, rhs, .. } => {
+                Box::new(vec![(**lhs).clone(), (**rhs).clone()].into_iter())
+            }
+            Node::Assign { value, .. } => Box::new(std::iter::once((**value).clone())),
+            Node::Block(stmts) | Node::Call { args: stmts, .. } => {
+                Box::new(stmts.clone().into_iter())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::ExprArena;
+    use crate::boolean::simplify_bool;
+    use crate::compile::{compile, Vm};
+    use crate::eval::{Eval, Overflow};
+    use crate::optimize::optimize;
+    use crate::polynomial::expand;
+    use quickcheck::quickcheck;
+
+    #[test]
+    fn random_respects_depth() {
+        fn depth(node: &Node) -> usize {
+            match node {
+                Node::UnaryExpr { child, .. } => 1 + depth(child),
+                Node::BinaryExpr { lhs, rhs, .. } => 1 + depth(lhs).max(depth(rhs)),
+                _ => 0,
+            }
+        }
+
+        let mut g = Gen::new(100);
+        for _ in 0..100 {
+            assert!(depth(&Node::random(4, &mut g)) <= 4)
+        }
+    }
+
+    quickcheck! {
+        fn print_then_parse(node: Node) -> bool {
+            let node = normalize(&node);
+            node.to_string().parse::<Node>().unwrap() == node
+        }
+
+        fn canonicalize_is_idempotent(node: Node) -> bool {
+            let once = node.canonicalize();
+            once.canonicalize() == once
+        }
+
+        fn optimize_preserves_value(node: Node) -> bool {
+            let eval = |node: &Node| {
+                let mut eval = Eval::with_overflow(Overflow::Wrapping);
+                let init: Node = "a = 1; b = -2; x = 3; y = 0; z = 2147483647".parse().unwrap();
+                eval.eval(&init).unwrap();
+                eval.eval(node).ok()
+            };
+            eval(&node) == eval(&optimize(&node, Overflow::Wrapping))
+        }
+
+        fn vm_matches_eval(node: Node) -> bool {
+            let values = [("a", 1), ("b", -2), ("x", 3), ("y", 0), ("z", i32::MAX)];
+            let mut eval = Eval::with_overflow(Overflow::Wrapping);
+            for (name, value) in values {
+                eval.eval(&Node::assign(name, value)).unwrap();
+            }
+            let program = compile(&node);
+            let mut vars: Vec<i32> = program
+                .bindings()
+                .names()
+                .iter()
+                .map(|name| values.iter().find(|(n, _)| n == name).unwrap().1)
+                .collect();
+            let vm = Vm::with_overflow(Overflow::Wrapping).run(&program, &mut vars);
+            vm.ok() == eval.eval(&node).ok()
+        }
+
+        fn partial_eval_preserves_value(node: Node) -> bool {
+            let eval = || {
+                let mut eval = Eval::with_overflow(Overflow::Wrapping);
+                eval.eval(&"a = 1; b = -2; x = 3".parse().unwrap()).unwrap();
+                eval
+            };
+            let bindings = [("y".to_string(), 0), ("z".to_string(), i32::MAX)].into();
+            let residual = eval().partial_eval(&node, &bindings);
+            let mut full = eval();
+            full.eval(&Node::assign("y", 0)).unwrap();
+            full.eval(&Node::assign("z", i32::MAX)).unwrap();
+            residual.free_variables().is_empty() && full.eval(&node).ok() == eval().eval(&residual).ok()
+        }
+
+        fn expand_preserves_value(node: Node) -> bool {
+            let eval = |node: &Node| {
+                let mut eval = Eval::new();
+                eval.eval(&"a = 1; b = -2; x = 3; y = 0; z = 7".parse().unwrap()).unwrap();
+                eval.eval(node).ok()
+            };
+            match (eval(&node), expand(&node).ok().and_then(|expanded| eval(&expanded))) {
+                (Some(before), Some(after)) => before == after,
+                _ => true,
+            }
+        }
+
+        fn simplify_bool_preserves_value(node: Node) -> bool {
+            let eval = |node: &Node| {
+                let mut eval = Eval::new();
+                eval.eval(&"a = 1; b = 0; x = -3; y = 0; z = 2".parse().unwrap()).unwrap();
+                eval.eval(node).ok()
+            };
+            match eval(&node) {
+                Some(value) => eval(&simplify_bool(&node)) == Some(value),
+                None => true,
+            }
+        }
+
+        fn arena_round_trip(node: Node) -> bool {
+            let (arena, root) = ExprArena::from_node(&node);
+            arena.to_node(root) == node
+        }
+    }
+}
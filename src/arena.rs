@@ -0,0 +1,278 @@
+//!
+//! Flat, index-based storage for expression trees.
+//!
+use crate::parser::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A node whose children are ids into the arena that owns it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ArenaNode {
+    Number(i32),
+    Float(Float),
+    Identifier(String),
+    BinaryExpr {
+        op: BinaryOperator,
+        lhs: NodeId,
+        rhs: NodeId,
+    },
+    UnaryExpr {
+        op: UnaryOperator,
+        child: NodeId,
+    },
+    Assign {
+        name: String,
+        value: NodeId,
+    },
+    Block(Vec<NodeId>),
+    Call {
+        name: String,
+        args: Vec<NodeId>,
+    },
+    Use(String),
+}
+
+fn lower(node: &Node, insert: &mut impl FnMut(ArenaNode) -> NodeId) -> NodeId {
+    let node = match node {
+        Node::Number(n) => ArenaNode::Number(*n),
+        Node::Float(x) => ArenaNode::Float(*x),
+        Node::Identifier(name) => ArenaNode::Identifier(name.clone()),
+        Node::BinaryExpr { op, lhs, rhs } => ArenaNode::BinaryExpr {
+            op: op.clone(),
+            lhs: lower(lhs, insert),
+            rhs: lower(rhs, insert),
+        },
+        Node::UnaryExpr { op, child } => ArenaNode::UnaryExpr {
+            op: op.clone(),
+            child: lower(child, insert),
+        },
+        Node::Assign { name, value } => ArenaNode::Assign {
+            name: name.clone(),
+            value: lower(value, insert),
+        },
+        Node::Block(stmts) => {
+            ArenaNode::Block(stmts.iter().map(|stmt| lower(stmt, insert)).collect())
+        }
+        Node::Call { name, args } => ArenaNode::Call {
+            name: name.clone(),
+            args: args.iter().map(|arg| lower(arg, insert)).collect(),
+        },
+        Node::Use(path) => ArenaNode::Use(path.clone()),
+    };
+    insert(node)
+}
+
+///
+/// An expression tree stored as a flat vector of nodes that refer to their
+/// children by index instead of through a `Box`.
+///
+/// Children are always pushed before their parents, so every id refers to an
+/// earlier slot and the vector is a valid bottom-up evaluation order.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExprArena {
+    nodes: Vec<ArenaNode>,
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        ExprArena {
+            nodes: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.index()]
+    }
+
+    pub fn nodes(&self) -> &[ArenaNode] {
+        &self.nodes
+    }
+
+    /// Appends a node whose children must already be in the arena.
+    pub fn push(&mut self, node: ArenaNode) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        id
+    }
+
+    /// Copies a boxed tree into the arena and returns the id of its root.
+    pub fn add(&mut self, node: &Node) -> NodeId {
+        lower(node, &mut |node| self.push(node))
+    }
+
+    pub fn from_node(node: &Node) -> (ExprArena, NodeId) {
+        let mut arena = ExprArena::new();
+        let root = arena.add(node);
+        (arena, root)
+    }
+
+    pub fn to_node(&self, id: NodeId) -> Node {
+        match self.get(id) {
+            ArenaNode::Number(n) => Node::Number(*n),
+            ArenaNode::Float(x) => Node::Float(*x),
+            ArenaNode::Identifier(name) => Node::Identifier(name.clone()),
+            ArenaNode::BinaryExpr { op, lhs, rhs } => Node::BinaryExpr {
+                op: op.clone(),
+                lhs: Box::new(self.to_node(*lhs)),
+                rhs: Box::new(self.to_node(*rhs)),
+            },
+            ArenaNode::UnaryExpr { op, child } => Node::UnaryExpr {
+                op: op.clone(),
+                child: Box::new(self.to_node(*child)),
+            },
+            ArenaNode::Assign { name, value } => Node::Assign {
+                name: name.clone(),
+                value: Box::new(self.to_node(*value)),
+            },
+            ArenaNode::Block(stmts) => {
+                Node::Block(stmts.iter().map(|stmt| self.to_node(*stmt)).collect())
+            }
+            ArenaNode::Call { name, args } => Node::Call {
+                name: name.clone(),
+                args: args.iter().map(|arg| self.to_node(*arg)).collect(),
+            },
+            ArenaNode::Use(path) => Node::Use(path.clone()),
+        }
+    }
+}
+
+///
+/// An arena in which structurally identical subtrees are stored once.
+///
+/// Interning the same tree twice returns the same id, so two interned trees
+/// are equal exactly when their ids are.
+///
+#[derive(Debug, Default)]
+pub struct HashConsArena {
+    arena: ExprArena,
+    ids: HashMap<ArenaNode, NodeId>,
+}
+
+impl HashConsArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn arena(&self) -> &ExprArena {
+        &self.arena
+    }
+
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> &ArenaNode {
+        self.arena.get(id)
+    }
+
+    pub fn insert(&mut self, node: ArenaNode) -> NodeId {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = self.arena.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    pub fn intern(&mut self, node: &Node) -> NodeId {
+        lower(node, &mut |node| self.insert(node))
+    }
+
+    pub fn to_node(&self, id: NodeId) -> Node {
+        self.arena.to_node(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn hash_nodes() {
+        let nodes: HashSet<Node> = ["1 + x", "1+x", "(1 + x)", "x + 1"]
+            .into_iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(nodes.len(), 2)
+    }
+
+    #[test]
+    fn share_identical_subtrees() {
+        let mut arena = HashConsArena::new();
+        let expr: Node = "(a + 1) * (a + 1) - (a + 1)".parse().unwrap();
+        arena.intern(&expr);
+        // a, 1, a + 1, (a + 1) * (a + 1), and the subtraction
+        assert_eq!(arena.len(), 5)
+    }
+
+    #[test]
+    fn equal_trees_get_equal_ids() {
+        let mut arena = HashConsArena::new();
+        let a = arena.intern(&"x * (y - 2)".parse().unwrap());
+        let b = arena.intern(&"(x) * (y - 2)".parse().unwrap());
+        let c = arena.intern(&"x * (y - 3)".parse().unwrap());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut arena = HashConsArena::new();
+        let expr: Node = "x = 2; y = x * x; -(x + y) / x".parse().unwrap();
+        let id = arena.intern(&expr);
+        assert_eq!(arena.to_node(id), expr)
+    }
+
+    #[test]
+    fn children_precede_parents() {
+        let expr: Node = "1 + 2 * x".parse().unwrap();
+        let (arena, root) = ExprArena::from_node(&expr);
+        assert_eq!(arena.len(), 5);
+        assert_eq!(root.index(), 4);
+        for (i, node) in arena.nodes().iter().enumerate() {
+            if let ArenaNode::BinaryExpr { lhs, rhs, .. } = node {
+                assert!(lhs.index() < i && rhs.index() < i)
+            }
+        }
+        assert_eq!(arena.to_node(root), expr)
+    }
+
+    #[test]
+    fn build_by_hand() {
+        let mut arena = ExprArena::new();
+        let x = arena.push(ArenaNode::Identifier("x".to_string()));
+        let two = arena.push(ArenaNode::Number(2));
+        let root = arena.push(ArenaNode::BinaryExpr {
+            op: BinaryOperator::Mul,
+            lhs: x,
+            rhs: two,
+        });
+        assert_eq!(arena.to_node(root).to_string(), "x * 2")
+    }
+}
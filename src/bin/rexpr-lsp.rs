@@ -0,0 +1,6 @@
+use rexpr::lsp::Server;
+use std::io;
+
+fn main() -> io::Result<()> {
+    Server::new().run(io::stdin().lock(), io::stdout().lock())
+}
@@ -0,0 +1,361 @@
+//!
+//! Boolean algebra over the logical operators `&&`, `||` and `!`.
+//!
+//! Logical operators treat any non-zero value as true and yield 0 or 1, so
+//! `a && a` is `!!a` rather than `a` unless `a` is itself known to be 0 or 1.
+//!
+use crate::compile::{compile, Vm};
+use crate::error::ErrorCode;
+use crate::eval::{binary, unary, EvalError, Overflow};
+use crate::parser::*;
+use std::fmt::Display;
+
+/// The most variables `truth_table` enumerates, 65536 rows.
+const MAX_VARIABLES: usize = 16;
+
+/// Whether `node` always evaluates to 0 or 1.
+fn is_boolean(node: &Node) -> bool {
+    node.is_logical()
+        || matches!(
+            node,
+            Node::Number(0 | 1)
+                | Node::UnaryExpr {
+                    op: UnaryOperator::Not,
+                    ..
+                }
+        )
+}
+
+/// The truth value of `node` as 0 or 1.
+fn truth(node: Node) -> Node {
+    if is_boolean(&node) {
+        node
+    } else {
+        !!node
+    }
+}
+
+/// Collects the operands of a chain of `op`. Only the truth of an operand
+/// matters to a logical operator, so double negations are dropped.
+fn operands(node: &Node, op: &BinaryOperator, out: &mut Vec<Node>) {
+    match node {
+        Node::BinaryExpr {
+            op: inner,
+            lhs,
+            rhs,
+        } if inner == op => {
+            operands(lhs, op, out);
+            operands(rhs, op, out);
+        }
+        Node::UnaryExpr {
+            op: UnaryOperator::Not,
+            child,
+        } => match &**child {
+            Node::UnaryExpr {
+                op: UnaryOperator::Not,
+                child,
+            } => operands(child, op, out),
+            _ => out.push(node.clone()),
+        },
+        node => out.push(node.clone()),
+    }
+}
+
+fn is_negation_of(node: &Node, other: &Node) -> bool {
+    matches!(node, Node::UnaryExpr { op: UnaryOperator::Not, child } if **child == *other)
+}
+
+/// Simplifies a whole `&&` or `||` chain at once, so that duplicates and
+/// complements are found however the chain is nested.
+fn chain_rule(node: &Node, op: &BinaryOperator) -> Option<Node> {
+    // The operand value that decides the chain: 0 for `&&`, 1 for `||`.
+    let (absorbing, dual) = match op {
+        BinaryOperator::And => (0, BinaryOperator::Or),
+        _ => (1, BinaryOperator::And),
+    };
+    let mut flat = vec![];
+    operands(node, op, &mut flat);
+    let mut terms: Vec<Node> = vec![];
+    for term in flat {
+        match term {
+            Node::Number(n) if (n != 0) as i32 == absorbing => {
+                return Some(Node::Number(absorbing))
+            }
+            Node::Number(_) => {}
+            term if terms.contains(&term) => {}
+            term => terms.push(term),
+        }
+    }
+    if terms
+        .iter()
+        .any(|term| terms.iter().any(|other| is_negation_of(term, other)))
+    {
+        return Some(Node::Number(absorbing));
+    }
+    // Absorption: `a && (a || b)` is `a && 1`.
+    let absorbed: Vec<bool> = terms
+        .iter()
+        .map(|term| {
+            let mut inner = vec![];
+            operands(term, &dual, &mut inner);
+            inner.len() > 1 && inner.iter().any(|inner| terms.contains(inner))
+        })
+        .collect();
+    let mut terms = terms
+        .into_iter()
+        .zip(absorbed)
+        .filter(|(_, absorbed)| !absorbed)
+        .map(|(term, _)| term);
+    let simplified = match terms.next() {
+        None => Node::Number(1 - absorbing),
+        Some(first) => match terms.next() {
+            None => truth(first),
+            Some(second) => terms.fold(Node::binary(op.clone(), first, second), |lhs, rhs| {
+                Node::binary(op.clone(), lhs, rhs)
+            }),
+        },
+    };
+    (simplified != *node).then_some(simplified)
+}
+
+fn bool_rule(node: &Node) -> Option<Node> {
+    match node {
+        Node::UnaryExpr { op, child } => match (op, &**child) {
+            (_, Node::Number(n)) => unary(op, *n, Overflow::Checked).ok().map(Node::Number),
+            (
+                UnaryOperator::Not,
+                Node::UnaryExpr {
+                    op: UnaryOperator::Not,
+                    child,
+                },
+            ) if is_boolean(child) => Some((**child).clone()),
+            // De Morgan: negations are pushed down to the operands.
+            (UnaryOperator::Not, Node::BinaryExpr { op, lhs, rhs }) if child.is_logical() => {
+                let dual = match op {
+                    BinaryOperator::And => BinaryOperator::Or,
+                    _ => BinaryOperator::And,
+                };
+                Some(Node::binary(dual, !(**lhs).clone(), !(**rhs).clone()))
+            }
+            _ => None,
+        },
+        Node::BinaryExpr { op, lhs, rhs } => match (&**lhs, &**rhs) {
+            (Node::Number(l), Node::Number(r)) => {
+                binary(op, *l, *r, Overflow::Checked).ok().map(Node::Number)
+            }
+            _ if node.is_logical() => chain_rule(node, op),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+///
+/// Simplifies the logical parts of `node`:
+///
+/// - operations on literals are folded, and literal operands of `&&` and `||`
+///   are dropped or decide the whole chain (`a && 0` is `0`),
+/// - negations are pushed inwards by De Morgan's laws, so `!(a && b)` becomes
+///   `!a || !b`, and double negations of 0-or-1 values are removed,
+/// - duplicate operands are removed, complements decide the chain
+///   (`a || !a` is `1`), and absorption drops redundant alternatives
+///   (`a && (a || b)` is `!!a`).
+///
+/// Operands are compared structurally, so `a && b` and `b && a` are only
+/// recognized as equal after [`Node::canonicalize`]. Like `simplify`, rules
+/// that discard an operand assume it would have evaluated without error.
+///
+pub fn simplify_bool(node: &Node) -> Node {
+    node.clone().rewrite(&mut bool_rule)
+}
+
+/// The value of an expression under every 0/1 assignment of its variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    expression: String,
+    variables: Vec<String>,
+    rows: Vec<(Vec<i32>, i32)>,
+}
+
+impl TruthTable {
+    /// The free variables of the expression, in order of first use.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// One `(inputs, output)` pair per assignment, with `inputs` in the order
+    /// of `variables()`, counting up in binary from all zeros.
+    pub fn rows(&self) -> &[(Vec<i32>, i32)] {
+        &self.rows
+    }
+}
+
+/// Aligned columns, one per variable and one for the expression:
+///
+/// ```text
+/// a b | a && !b
+/// ----+--------
+/// 0 0 | 0
+/// ```
+impl Display for TruthTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut header: Vec<&str> = self.variables.iter().map(String::as_str).collect();
+        header.push("|");
+        header.push(&self.expression);
+        let rule: Vec<String> = header
+            .iter()
+            .map(|column| match *column {
+                "|" => "+".to_string(),
+                column => "-".repeat(column.len()),
+            })
+            .collect();
+        writeln!(f, "{}", header.join(" "))?;
+        writeln!(f, "{}", rule.join("-"))?;
+        for (inputs, output) in &self.rows {
+            for (input, name) in inputs.iter().zip(&self.variables) {
+                write!(f, "{:<width$} ", input, width = name.len())?;
+            }
+            writeln!(f, "| {}", output)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Evaluates `node` for every assignment of 0 or 1 to its free variables.
+/// Fails if the expression has more than 16 variables or fails to evaluate
+/// for some assignment.
+///
+pub fn truth_table(node: &Node) -> Result<TruthTable, EvalError> {
+    let variables = node.free_variables();
+    if variables.len() > MAX_VARIABLES {
+        return Err(EvalError::with_code(
+            ErrorCode::TooManyVariables,
+            format!(
+                "too many variables for a truth table: {}, at most {}",
+                variables.len(),
+                MAX_VARIABLES
+            ),
+        ));
+    }
+    let program = compile(node);
+    let slots: Vec<usize> = variables
+        .iter()
+        .map(|name| program.bindings().get(name).unwrap())
+        .collect();
+    let mut vm = Vm::new();
+    let mut rows = vec![];
+    for row in 0..1 << variables.len() {
+        let inputs: Vec<i32> = (0..variables.len())
+            .rev()
+            .map(|bit| (row >> bit) & 1)
+            .collect();
+        let mut vars = vec![0; program.bindings().len()];
+        for (slot, input) in slots.iter().zip(&inputs) {
+            vars[*slot] = *input;
+        }
+        let output = vm.run(&program, &mut vars)?;
+        rows.push((inputs, output));
+    }
+    Ok(TruthTable {
+        expression: node.to_string(),
+        variables,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    fn simplified(text: &str) -> String {
+        simplify_bool(&text.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn constant_folding() {
+        assert_eq!(simplified("a && 1"), "!!a");
+        assert_eq!(simplified("a && 0 && b"), "0");
+        assert_eq!(simplified("flag || 2"), "1");
+        assert_eq!(simplified("0 || a && b"), "a && b");
+        assert_eq!(simplified("!(1 && 0) || x"), "1")
+    }
+
+    #[test]
+    fn de_morgan() {
+        assert_eq!(simplified("!(a && b)"), "!a || !b");
+        assert_eq!(simplified("!(a || !b)"), "!a && b");
+        assert_eq!(simplified("!!(x && y)"), "x && y");
+        assert_eq!(simplified("!!!x"), "!x")
+    }
+
+    #[test]
+    fn duplicates_and_complements() {
+        assert_eq!(simplified("a && b && a"), "a && b");
+        assert_eq!(simplified("a && !a"), "0");
+        assert_eq!(simplified("a || b || !a"), "1");
+        assert_eq!(simplified("n + 1 || n + 1"), "!!(n + 1)")
+    }
+
+    #[test]
+    fn absorption() {
+        assert_eq!(simplified("a && (a || b)"), "!!a");
+        assert_eq!(simplified("(a && b) || a"), "!!a");
+        assert_eq!(simplified("!a && (b || !a)"), "!a");
+        assert_eq!(
+            simplified("beta && (legacy || beta) && !(!beta || 0)"),
+            "!!beta"
+        )
+    }
+
+    #[test]
+    fn preserves_value() {
+        let text = "!(a && (b || !c)) || (c && !(a || 0)) && (b || b && a)";
+        let node: Node = text.parse().unwrap();
+        let simple = simplify_bool(&node);
+        for a in -1..=2 {
+            for b in -1..=2 {
+                for c in -1..=2 {
+                    let mut eval = Eval::new();
+                    let init = format!("a = {}; b = {}; c = {}", a, b, c);
+                    eval.eval(&init.parse().unwrap()).unwrap();
+                    assert_eq!(eval.eval(&node).unwrap(), eval.eval(&simple).unwrap())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truth_table_rows() {
+        let table = truth_table(&"a && (b || !c)".parse().unwrap()).unwrap();
+        assert_eq!(table.variables(), ["a", "b", "c"]);
+        let outputs: Vec<i32> = table.rows().iter().map(|(_, output)| *output).collect();
+        assert_eq!(outputs, [0, 0, 0, 0, 1, 0, 1, 1]);
+        assert_eq!(table.rows()[6].0, [1, 1, 0])
+    }
+
+    #[test]
+    fn truth_table_display() {
+        let table = truth_table(&"on = 1; on && !debug".parse().unwrap()).unwrap();
+        assert_eq!(
+            table.to_string(),
+            "debug | on = 1; on && !debug\n\
+             ------+---------------------\n\
+             0     | 1\n\
+             1     | 0\n"
+        )
+    }
+
+    #[test]
+    fn truth_table_errors() {
+        let err = truth_table(&"1 / a".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: division by zero");
+        let names: Vec<String> = (0..17).map(|i| format!("v{}", i)).collect();
+        let err = truth_table(&names.join(" || ").parse().unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: too many variables for a truth table: 17, at most 16"
+        )
+    }
+}
@@ -0,0 +1,163 @@
+//!
+//! A cache of parsed expressions for programs that evaluate the same sources
+//! over and over.
+//!
+use crate::error::RexprError;
+use crate::eval::Overflow;
+use crate::optimize::optimize;
+use crate::parser::Node;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+struct Entry {
+    node: Arc<Node>,
+    last_used: u64,
+}
+
+///
+/// Parses source strings, keeping the most recently used `capacity` trees. Looking up a cached source neither tokenizes nor
+/// allocates; when the cache is full the least recently used tree is dropped.
+///
+/// Sources are matched exactly, so `1+x` and `1 + x` are cached separately.
+/// Sources that fail to parse are not cached.
+///
+/// Trees are cached as parsed unless the cache is made with
+/// [`ExprCache::optimizing`]. Optimizing walks the whole tree once more, so
+/// it pays off only for sources evaluated many times, and callers caching
+/// untrusted sources may rather leave it off.
+///
+pub struct ExprCache {
+    capacity: usize,
+    /// The overflow mode trees are optimized for, if they are.
+    overflow: Option<Overflow>,
+    entries: HashMap<String, Entry>,
+    /// Sources by the tick they were last used, oldest first.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl ExprCache {
+    pub fn new(capacity: usize) -> Self {
+        ExprCache {
+            capacity,
+            overflow: None,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// A cache whose trees are optimized for evaluation under `overflow`.
+    pub fn optimizing(capacity: usize, overflow: Overflow) -> Self {
+        ExprCache {
+            overflow: Some(overflow),
+            ..Self::new(capacity)
+        }
+    }
+
+    /// Returns the tree of `source`, parsing it on a miss.
+    pub fn get(&mut self, source: &str) -> Result<Arc<Node>, RexprError> {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(source) {
+            let key = self.recency.remove(&entry.last_used).unwrap();
+            self.recency.insert(self.tick, key);
+            entry.last_used = self.tick;
+            return Ok(entry.node.clone());
+        }
+        let node = source.parse()?;
+        let node = Arc::new(match self.overflow {
+            Some(overflow) => optimize(&node, overflow),
+            None => node,
+        });
+        if self.capacity == 0 {
+            return Ok(node);
+        }
+        if self.entries.len() == self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            source.to_string(),
+            Entry {
+                node: node.clone(),
+                last_used: self.tick,
+            },
+        );
+        self.recency.insert(self.tick, source.to_string());
+        Ok(node)
+    }
+
+    pub fn contains(&self, source: &str) -> bool {
+        self.entries.contains_key(source)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    #[test]
+    fn hit_returns_same_tree() {
+        let mut cache = ExprCache::new(2);
+        let first = cache.get("price * (2 + 3)").unwrap();
+        let second = cache.get("price * (2 + 3)").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.to_string(), "price * (2 + 3)");
+        assert_eq!(cache.len(), 1)
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut cache = ExprCache::new(2);
+        cache.get("a").unwrap();
+        cache.get("b").unwrap();
+        cache.get("a").unwrap();
+        cache.get("c").unwrap();
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+        assert_eq!(cache.len(), 2)
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let mut cache = ExprCache::new(4);
+        assert!(cache.get("1 +").is_err());
+        assert!(cache.is_empty());
+        let mut cache = ExprCache::new(0);
+        let node = cache.get("x = 6 * 7; x").unwrap();
+        assert_eq!(Eval::new().eval(&node).unwrap(), 42);
+        assert!(cache.is_empty())
+    }
+
+    #[test]
+    fn optimize_under_overflow() {
+        let mut cache = ExprCache::optimizing(1, Overflow::Wrapping);
+        let node = cache.get("2147483647 + 1").unwrap();
+        assert_eq!(*node, Node::Number(i32::MIN));
+        let mut cache = ExprCache::optimizing(1, Overflow::Checked);
+        assert_eq!(
+            cache.get("price * (2 + 3)").unwrap().to_string(),
+            "price * 5"
+        )
+    }
+}
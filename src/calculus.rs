@@ -0,0 +1,590 @@
+//!
+//! Calculus over expressions: symbolic derivatives and real-valued numeric
+//! routines.
+//!
+//! Everything here treats variables as real numbers. Expressions are
+//! evaluated in `f64` by [`eval_real`], where `/` is true division, rather
+//! than with the integer semantics of [`Eval`](crate::eval::Eval).
+//!
+use crate::optimize::simplify_rule;
+use crate::parser::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct CalculusError {
+    message: String,
+}
+
+impl Display for CalculusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CalculusError: {}", self.message)
+    }
+}
+
+impl Error for CalculusError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, CalculusError> {
+    Err(CalculusError {
+        message: message.into(),
+    })
+}
+
+fn real(node: &Node, vars: &mut HashMap<String, f64>) -> Result<f64, CalculusError> {
+    match node {
+        Node::Number(n) => Ok(*n as f64),
+        Node::Float(Float(x)) => Ok(*x),
+        Node::Identifier(name) => match vars.get(name) {
+            Some(value) => Ok(*value),
+            None => error(format!("undefined variable {}", name)),
+        },
+        Node::UnaryExpr { op, child } => {
+            let child = real(child, vars)?;
+            match op {
+                UnaryOperator::Neg => Ok(-child),
+                UnaryOperator::Not => Ok((child == 0.0) as i32 as f64),
+            }
+        }
+        Node::BinaryExpr { op, lhs, rhs } => {
+            let lhs = real(lhs, vars)?;
+            let rhs = real(rhs, vars)?;
+            match op {
+                BinaryOperator::Plus => Ok(lhs + rhs),
+                BinaryOperator::Minus => Ok(lhs - rhs),
+                BinaryOperator::Mul => Ok(lhs * rhs),
+                BinaryOperator::Div => Ok(lhs / rhs),
+                BinaryOperator::Pow => Ok(lhs.powf(rhs)),
+                BinaryOperator::Shl | BinaryOperator::Shr => {
+                    error(format!("{} requires integer operands", op))
+                }
+                BinaryOperator::And => Ok((lhs != 0.0 && rhs != 0.0) as i32 as f64),
+                BinaryOperator::Or => Ok((lhs != 0.0 || rhs != 0.0) as i32 as f64),
+            }
+        }
+        Node::Assign { name, value } => {
+            let value = real(value, vars)?;
+            vars.insert(name.clone(), value);
+            Ok(value)
+        }
+        Node::Block(stmts) => {
+            let mut last = 0.0;
+            for stmt in stmts {
+                last = real(stmt, vars)?;
+            }
+            Ok(last)
+        }
+        Node::Call { name, .. } => error(format!("undefined function {}", name)),
+        Node::Use(_) => Ok(0.0),
+    }
+}
+
+/// Evaluates `node` over the reals, following IEEE 754 for division by zero
+/// and other undefined results.
+pub fn eval_real(node: &Node, vars: &HashMap<String, f64>) -> Result<f64, CalculusError> {
+    real(node, &mut vars.clone())
+}
+
+/// `simplify` without the rules that are wrong over the reals, such as
+/// folding `1 / 2` to `0`.
+fn tidy(node: Node) -> Node {
+    node.rewrite(&mut |node| match node {
+        Node::BinaryExpr {
+            op: BinaryOperator::Div,
+            lhs,
+            rhs,
+        } if matches!((&**lhs, &**rhs), (Node::Number(_), Node::Number(_))) => None,
+        Node::BinaryExpr {
+            op: BinaryOperator::Pow,
+            lhs,
+            rhs,
+        } => match **rhs {
+            Node::Number(0) => Some(Node::Number(1)),
+            Node::Number(1) => Some((**lhs).clone()),
+            _ => simplify_rule(node),
+        },
+        node => simplify_rule(node),
+    })
+}
+
+fn derivative(node: &Node, var: &str) -> Result<Node, CalculusError> {
+    let depends = |node: &Node| node.identifiers().contains(var);
+    let d = |node: &Node| derivative(node, var);
+    match node {
+        Node::Number(_) | Node::Float(_) => Ok(Node::num(0)),
+        Node::Identifier(name) => Ok(Node::num((name == var) as i32)),
+        Node::UnaryExpr {
+            op: UnaryOperator::Neg,
+            child,
+        } => Ok(-d(child)?),
+        Node::UnaryExpr { .. } => error(format!(
+            "cannot differentiate {} with respect to {}",
+            node, var
+        )),
+        Node::BinaryExpr { op, lhs, rhs } => {
+            let (u, v) = ((**lhs).clone(), (**rhs).clone());
+            match op {
+                BinaryOperator::Plus => Ok(d(lhs)? + d(rhs)?),
+                BinaryOperator::Minus => Ok(d(lhs)? - d(rhs)?),
+                BinaryOperator::Mul => Ok(d(lhs)? * v + u * d(rhs)?),
+                BinaryOperator::Div => {
+                    Ok((d(lhs)? * v.clone() - u * d(rhs)?)
+                        / Node::binary(BinaryOperator::Pow, v, 2))
+                }
+                BinaryOperator::Pow if !depends(rhs) => {
+                    let power = Node::binary(BinaryOperator::Pow, u, v.clone() - 1);
+                    Ok(v * power * d(lhs)?)
+                }
+                BinaryOperator::Shl if !depends(rhs) => {
+                    Ok(d(lhs)? * Node::binary(BinaryOperator::Pow, 2, v))
+                }
+                _ => error(format!(
+                    "cannot differentiate {} with respect to {}",
+                    node, var
+                )),
+            }
+        }
+        Node::Call { .. } if !depends(node) => Ok(Node::num(0)),
+        Node::Call { .. } => error(format!(
+            "cannot differentiate {} with respect to {}",
+            node, var
+        )),
+        Node::Assign { .. } | Node::Block(_) | Node::Use(_) => {
+            error("cannot differentiate statements")
+        }
+    }
+}
+
+///
+/// The derivative of `node` with respect to `var`, simplified.
+///
+/// Powers with an exponent that depends on `var`, which would need
+/// logarithms, and `>>`, which rounds, cannot be differentiated.
+///
+pub fn diff(node: &Node, var: &str) -> Result<Node, CalculusError> {
+    Ok(tidy(derivative(node, var)?))
+}
+
+/// The partial derivatives of `node` with respect to each of `vars`.
+pub fn grad(node: &Node, vars: &[&str]) -> Result<Vec<Node>, CalculusError> {
+    vars.iter().map(|var| diff(node, var)).collect()
+}
+
+/// The gradient of `node` evaluated where each of `vars` takes the value at
+/// the same position in `point`.
+pub fn gradient_at(node: &Node, vars: &[&str], point: &[f64]) -> Result<Vec<f64>, CalculusError> {
+    if vars.len() != point.len() {
+        return error(format!(
+            "expected {} coordinates, got {}",
+            vars.len(),
+            point.len()
+        ));
+    }
+    let values: HashMap<String, f64> = vars
+        .iter()
+        .map(|var| var.to_string())
+        .zip(point.iter().copied())
+        .collect();
+    grad(node, vars)?
+        .iter()
+        .map(|partial| eval_real(partial, &values))
+        .collect()
+}
+
+///
+/// Settings for adaptive Simpson quadrature. An interval is split until the
+/// estimated error of each piece is below its share of `tolerance`, at most
+/// `max_depth` times.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quadrature {
+    pub tolerance: f64,
+    pub max_depth: u32,
+}
+
+impl Default for Quadrature {
+    fn default() -> Self {
+        Quadrature {
+            tolerance: 1e-10,
+            max_depth: 50,
+        }
+    }
+}
+
+struct Integrand<'a> {
+    node: &'a Node,
+    var: &'a str,
+    vars: HashMap<String, f64>,
+}
+
+impl Integrand<'_> {
+    fn at(&mut self, x: f64) -> Result<f64, CalculusError> {
+        self.vars.insert(self.var.to_string(), x);
+        let y = real(self.node, &mut self.vars)?;
+        if y.is_finite() {
+            Ok(y)
+        } else {
+            error(format!("integrand is not finite at {} = {}", self.var, x))
+        }
+    }
+}
+
+/// One step of Simpson's rule over `[a, b]`, returning the midpoint, the
+/// value there and the estimate.
+fn simpson(
+    f: &mut Integrand,
+    (a, fa): (f64, f64),
+    (b, fb): (f64, f64),
+) -> Result<(f64, f64, f64), CalculusError> {
+    let m = (a + b) / 2.0;
+    let fm = f.at(m)?;
+    Ok((m, fm, (b - a) / 6.0 * (fa + 4.0 * fm + fb)))
+}
+
+impl Quadrature {
+    #[allow(clippy::too_many_arguments)]
+    fn adapt(
+        &self,
+        f: &mut Integrand,
+        a: (f64, f64),
+        m: (f64, f64),
+        b: (f64, f64),
+        whole: f64,
+        tolerance: f64,
+        depth: u32,
+    ) -> Result<f64, CalculusError> {
+        let (lm, flm, left) = simpson(f, a, m)?;
+        let (rm, frm, right) = simpson(f, m, b)?;
+        let delta = left + right - whole;
+        if delta.abs() <= 15.0 * tolerance {
+            // Richardson extrapolation cancels the leading error term.
+            return Ok(left + right + delta / 15.0);
+        }
+        if depth == 0 {
+            return error(format!(
+                "integral did not converge near {} = {}",
+                f.var, m.0
+            ));
+        }
+        Ok(
+            self.adapt(f, a, (lm, flm), m, left, tolerance / 2.0, depth - 1)?
+                + self.adapt(f, m, (rm, frm), b, right, tolerance / 2.0, depth - 1)?,
+        )
+    }
+
+    /// Integrates `node` over `var` from `a` to `b`, with the other variables
+    /// taken from `vars`.
+    pub fn integrate(
+        &self,
+        node: &Node,
+        var: &str,
+        (a, b): (f64, f64),
+        vars: &HashMap<String, f64>,
+    ) -> Result<f64, CalculusError> {
+        if !a.is_finite() || !b.is_finite() {
+            return error("integration bounds must be finite");
+        }
+        if a > b {
+            return Ok(-self.integrate(node, var, (b, a), vars)?);
+        }
+        if a == b {
+            return Ok(0.0);
+        }
+        let mut f = Integrand {
+            node,
+            var,
+            vars: vars.clone(),
+        };
+        let (fa, fb) = (f.at(a)?, f.at(b)?);
+        let (m, fm, whole) = simpson(&mut f, (a, fa), (b, fb))?;
+        self.adapt(
+            &mut f,
+            (a, fa),
+            (m, fm),
+            (b, fb),
+            whole,
+            self.tolerance,
+            self.max_depth,
+        )
+    }
+}
+
+/// Integrates `node` over `var` from `a` to `b` with the default
+/// [`Quadrature`]. Every other variable must be assigned within `node`.
+pub fn integrate(node: &Node, var: &str, a: f64, b: f64) -> Result<f64, CalculusError> {
+    Quadrature::default().integrate(node, var, (a, b), &HashMap::new())
+}
+
+///
+/// Settings for root finding. Iteration stops once `|f(x)|` or the last step
+/// is below `tolerance`, or fails after `max_iterations` steps.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Solver {
+    pub tolerance: f64,
+    pub max_iterations: u32,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver {
+            tolerance: 1e-12,
+            max_iterations: 100,
+        }
+    }
+}
+
+struct Equation<'a> {
+    node: &'a Node,
+    derivative: Option<Node>,
+    var: &'a str,
+    vars: HashMap<String, f64>,
+}
+
+impl Equation<'_> {
+    fn at(&mut self, x: f64) -> Result<f64, CalculusError> {
+        self.vars.insert(self.var.to_string(), x);
+        real(self.node, &mut self.vars)
+    }
+
+    /// The slope at `x`, by central difference when there is no symbolic
+    /// derivative.
+    fn slope(&mut self, x: f64) -> Result<f64, CalculusError> {
+        match &self.derivative {
+            Some(derivative) => {
+                self.vars.insert(self.var.to_string(), x);
+                real(derivative, &mut self.vars)
+            }
+            None => {
+                let h = 1e-6 * (1.0 + x.abs());
+                Ok((self.at(x + h)? - self.at(x - h)?) / (2.0 * h))
+            }
+        }
+    }
+}
+
+impl Solver {
+    fn converged(&self, step: f64, x: f64) -> bool {
+        step.abs() <= self.tolerance * (1.0 + x.abs())
+    }
+
+    fn bisect(&self, f: &mut Equation, mut a: f64, mut b: f64) -> Result<f64, CalculusError> {
+        let mut fa = f.at(a)?;
+        // Halving any finite f64 interval reaches adjacent floats well within
+        // 1100 steps.
+        for _ in 0..1100 {
+            let m = (a + b) / 2.0;
+            let fm = f.at(m)?;
+            if fm == 0.0 || self.converged(b - a, m) {
+                return Ok(m);
+            }
+            if (fm < 0.0) == (fa < 0.0) {
+                (a, fa) = (m, fm);
+            } else {
+                b = m;
+            }
+        }
+        Ok((a + b) / 2.0)
+    }
+
+    /// Looks for a sign change at growing distances from `guess`.
+    fn bracket(&self, f: &mut Equation, guess: f64) -> Result<Option<(f64, f64)>, CalculusError> {
+        let f0 = f.at(guess)?;
+        let mut h = 1e-3 * (1.0 + guess.abs());
+        for _ in 0..64 {
+            for x in [guess - h, guess + h] {
+                let fx = f.at(x)?;
+                if fx.is_finite() && f0.is_finite() && (fx < 0.0) != (f0 < 0.0) {
+                    return Ok(Some((guess.min(x), guess.max(x))));
+                }
+            }
+            h *= 2.0;
+        }
+        Ok(None)
+    }
+
+    ///
+    /// Finds `x` near `guess` with `node == 0` where `var` is `x`, taking the
+    /// other variables from `vars`.
+    ///
+    /// Newton's method runs first, using the symbolic derivative when there
+    /// is one. When an iterate crosses the root it is bracketed and bisected;
+    /// when Newton's method stalls, a bracket is searched for around `guess`.
+    ///
+    pub fn solve(
+        &self,
+        node: &Node,
+        var: &str,
+        guess: f64,
+        vars: &HashMap<String, f64>,
+    ) -> Result<f64, CalculusError> {
+        let mut f = Equation {
+            node,
+            derivative: diff(node, var).ok(),
+            var,
+            vars: vars.clone(),
+        };
+        let mut x = guess;
+        let mut previous: Option<(f64, f64)> = None;
+        for _ in 0..self.max_iterations {
+            let fx = f.at(x)?;
+            if !fx.is_finite() {
+                break;
+            }
+            if fx.abs() <= self.tolerance {
+                return Ok(x);
+            }
+            if let Some((px, pfx)) = previous {
+                if (pfx < 0.0) != (fx < 0.0) {
+                    return self.bisect(&mut f, px.min(x), px.max(x));
+                }
+            }
+            let step = fx / f.slope(x)?;
+            if !step.is_finite() {
+                break;
+            }
+            previous = Some((x, fx));
+            x -= step;
+            if self.converged(step, x) {
+                return Ok(x);
+            }
+        }
+        match self.bracket(&mut f, guess)? {
+            Some((a, b)) => self.bisect(&mut f, a, b),
+            None => error(format!("no root found near {} = {}", var, guess)),
+        }
+    }
+}
+
+/// Finds a root of `node` in `var` near `guess` with the default [`Solver`].
+/// Every other variable must be assigned within `node`.
+pub fn solve(node: &Node, var: &str, guess: f64) -> Result<f64, CalculusError> {
+    Solver::default().solve(node, var, guess, &HashMap::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn derive(text: &str, var: &str) -> String {
+        diff(&text.parse().unwrap(), var).unwrap().to_string()
+    }
+
+    #[test]
+    fn derivatives() {
+        assert_eq!(derive("x ^ 3 + 2 * x - 7", "x"), "3 * x ^ 2 + 2");
+        assert_eq!(derive("1 / x", "x"), "-1 / x ^ 2");
+        assert_eq!(derive("x / 2", "x"), "2 / 4");
+        assert_eq!(derive("y * -x", "x"), "y * -1");
+        assert_eq!(derive("y << 3", "y"), "8")
+    }
+
+    #[test]
+    fn not_differentiable() {
+        let err = diff(&"2 ^ x".parse().unwrap(), "x").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "CalculusError: cannot differentiate 2 ^ x with respect to x"
+        );
+        assert!(diff(&"x >> 1".parse().unwrap(), "x").is_err());
+        assert!(diff(&"y = x; y".parse().unwrap(), "x").is_err())
+    }
+
+    #[test]
+    fn gradient() {
+        let node: Node = "x ^ 2 * y + 3 * y".parse().unwrap();
+        let partials: Vec<String> = grad(&node, &["x", "y"])
+            .unwrap()
+            .iter()
+            .map(Node::to_string)
+            .collect();
+        assert_eq!(partials, vec!["2 * x * y", "x ^ 2 + 3"]);
+        assert_eq!(
+            gradient_at(&node, &["x", "y"], &[3.0, 2.0]).unwrap(),
+            vec![12.0, 12.0]
+        );
+        assert_eq!(
+            gradient_at(&"x / 4".parse().unwrap(), &["x"], &[1.0]).unwrap(),
+            vec![0.25]
+        )
+    }
+
+    #[test]
+    fn real_evaluation() {
+        let vars = HashMap::from([("x".to_string(), 1.5)]);
+        let value = eval_real(&"k = x * 2; k / 4 + 2 ^ -1".parse().unwrap(), &vars).unwrap();
+        assert_eq!(value, 1.25);
+        assert!(eval_real(&"x << 1".parse().unwrap(), &vars).is_err())
+    }
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 1e-9
+    }
+
+    #[test]
+    fn integrals() {
+        let integral = |text: &str, a, b| integrate(&text.parse().unwrap(), "x", a, b).unwrap();
+        assert!(close(integral("x ^ 2", 0.0, 1.0), 1.0 / 3.0));
+        assert!(close(integral("1 / x", 1.0, 2.0), 2f64.ln()));
+        assert!(close(integral("3 * x ^ 2 - 2 * x", 2.0, -1.0), -6.0));
+        assert_eq!(integral("x", 5.0, 5.0), 0.0)
+    }
+
+    #[test]
+    fn integrate_with_parameters() {
+        let node: Node = "k * x ^ 3".parse().unwrap();
+        let vars = HashMap::from([("k".to_string(), 4.0)]);
+        let quadrature = Quadrature {
+            tolerance: 1e-6,
+            ..Quadrature::default()
+        };
+        let value = quadrature.integrate(&node, "x", (0.0, 2.0), &vars).unwrap();
+        assert!((value - 16.0).abs() < 1e-6)
+    }
+
+    #[test]
+    fn integration_errors() {
+        let err = integrate(&"1 / x".parse().unwrap(), "x", -1.0, 1.0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "CalculusError: integrand is not finite at x = 0"
+        );
+        let err = integrate(&"x * k".parse().unwrap(), "x", 0.0, 1.0).unwrap_err();
+        assert_eq!(err.to_string(), "CalculusError: undefined variable k");
+        let quadrature = Quadrature {
+            tolerance: 1e-12,
+            max_depth: 2,
+        };
+        let node: Node = "1 / x".parse().unwrap();
+        let err = quadrature
+            .integrate(&node, "x", (0.001, 1.0), &HashMap::new())
+            .unwrap_err();
+        assert!(err.to_string().contains("did not converge"))
+    }
+
+    #[test]
+    fn roots() {
+        let root = |text: &str, guess| solve(&text.parse().unwrap(), "x", guess).unwrap();
+        assert!(close(root("x ^ 2 - 2", 1.0), 2f64.sqrt()));
+        assert!(close(root("x ^ 2 - 2", -1.0), -(2f64.sqrt())));
+        assert!(close(root("x ^ 3 - x - 2", 1.5), 1.5213797068045676));
+        // `2 ^ x` has no symbolic derivative, so the slope is estimated.
+        assert!(close(root("2 ^ x - 8", 1.0), 3.0));
+        // Newton's method cycles between 0 and 1 here.
+        assert!(close(root("x ^ 3 - 2 * x + 2", 0.0), -1.7692923542386314))
+    }
+
+    #[test]
+    fn solve_with_parameters() {
+        let node: Node = "r ^ 2 * h - v".parse().unwrap();
+        let vars = HashMap::from([("h".to_string(), 2.0), ("v".to_string(), 50.0)]);
+        let r = Solver::default().solve(&node, "r", 1.0, &vars).unwrap();
+        assert!(close(r, 5.0))
+    }
+
+    #[test]
+    fn no_root() {
+        let err = solve(&"x ^ 2 + 1".parse().unwrap(), "x", 0.5).unwrap_err();
+        assert_eq!(err.to_string(), "CalculusError: no root found near x = 0.5")
+    }
+}
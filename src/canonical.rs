@@ -0,0 +1,192 @@
+//!
+//! Canonical forms for comparing and deduplicating expressions.
+//!
+use crate::parser::*;
+use std::cmp::Ordering;
+
+/// Orders nodes by kind first, then by content, so that sorted operands
+/// read as constants, variables and then compound terms.
+fn compare(a: &Node, b: &Node) -> Ordering {
+    fn rank(node: &Node) -> u8 {
+        match node {
+            Node::Number(_) => 0,
+            Node::Float(_) => 1,
+            Node::Identifier(_) => 2,
+            Node::UnaryExpr { .. } => 3,
+            Node::BinaryExpr { .. } => 4,
+            Node::Assign { .. } => 5,
+            Node::Block(_) => 6,
+            Node::Call { .. } => 7,
+            Node::Use(_) => 8,
+        }
+    }
+
+    fn compare_all(a: &[Node], b: &[Node]) -> Ordering {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    }
+
+    match (a, b) {
+        (Node::Number(a), Node::Number(b)) => a.cmp(b),
+        (Node::Float(Float(a)), Node::Float(Float(b))) => a.total_cmp(b),
+        (Node::Identifier(a), Node::Identifier(b)) | (Node::Use(a), Node::Use(b)) => a.cmp(b),
+        (
+            Node::UnaryExpr {
+                op: op_a,
+                child: child_a,
+            },
+            Node::UnaryExpr {
+                op: op_b,
+                child: child_b,
+            },
+        ) => (op_a.clone() as u8)
+            .cmp(&(op_b.clone() as u8))
+            .then_with(|| compare(child_a, child_b)),
+        (
+            Node::BinaryExpr {
+                op: op_a,
+                lhs: lhs_a,
+                rhs: rhs_a,
+            },
+            Node::BinaryExpr {
+                op: op_b,
+                lhs: lhs_b,
+                rhs: rhs_b,
+            },
+        ) => (op_a.clone() as u8)
+            .cmp(&(op_b.clone() as u8))
+            .then_with(|| compare(lhs_a, lhs_b))
+            .then_with(|| compare(rhs_a, rhs_b)),
+        (
+            Node::Assign {
+                name: name_a,
+                value: value_a,
+            },
+            Node::Assign {
+                name: name_b,
+                value: value_b,
+            },
+        ) => name_a.cmp(name_b).then_with(|| compare(value_a, value_b)),
+        (Node::Block(a), Node::Block(b)) => compare_all(a, b),
+        (
+            Node::Call {
+                name: name_a,
+                args: args_a,
+            },
+            Node::Call {
+                name: name_b,
+                args: args_b,
+            },
+        ) => name_a.cmp(name_b).then_with(|| compare_all(args_a, args_b)),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn is_commutative(op: &BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Plus | BinaryOperator::Mul | BinaryOperator::And | BinaryOperator::Or
+    )
+}
+
+/// Collects the operands of a chain of the same commutative operator.
+fn operands(node: Node, op: &BinaryOperator, out: &mut Vec<Node>) {
+    match node {
+        Node::BinaryExpr {
+            op: inner,
+            lhs,
+            rhs,
+        } if inner == *op => {
+            operands(*lhs, op, out);
+            operands(*rhs, op, out);
+        }
+        node => out.push(node),
+    }
+}
+
+impl Node {
+    ///
+    /// Rewrites the tree into a canonical form:
+    ///
+    /// - chains of `+`, `*`, `&&` and `||` are flattened and their operands sorted, then
+    ///   rebuilt left-associatively,
+    /// - double negations are removed.
+    ///
+    /// Two expressions that differ only in those respects canonicalize to
+    /// equal trees. Evaluation order of statements and the operands of `-`
+    /// and `/` are left untouched.
+    ///
+    pub fn canonicalize(&self) -> Node {
+        self.clone().fold(&mut |node| match node {
+            Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child,
+            } => match *child {
+                Node::UnaryExpr {
+                    op: UnaryOperator::Neg,
+                    child,
+                } => *child,
+                child => -child,
+            },
+            Node::BinaryExpr { op, lhs, rhs } if is_commutative(&op) => {
+                let mut terms = vec![];
+                operands(*lhs, &op, &mut terms);
+                operands(*rhs, &op, &mut terms);
+                terms.sort_by(compare);
+                let mut terms = terms.into_iter();
+                let first = terms.next().unwrap();
+                terms.fold(first, |lhs, rhs| Node::binary(op.clone(), lhs, rhs))
+            }
+            node => node,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn canonical(text: &str) -> String {
+        text.parse::<Node>().unwrap().canonicalize().to_string()
+    }
+
+    #[test]
+    fn sort_commutative_operands() {
+        assert_eq!(canonical("y * 2 + x"), "x + 2 * y");
+        assert_eq!(canonical("x + y * 2"), canonical("2 * y + x"))
+    }
+
+    #[test]
+    fn flatten_nested_additions() {
+        assert_eq!(canonical("c + (b + (a + 1))"), "1 + a + b + c");
+        assert_eq!(canonical("(z * y) * (x * 3)"), "3 * x * y * z");
+        assert_eq!(canonical("!b && (c && a) || !-a"), "!-a || a && c && !b");
+        assert_eq!(
+            canonical("f(b * a, 1) + x + f(a)"),
+            "x + f(a) + f(a * b, 1)"
+        )
+    }
+
+    #[test]
+    fn keep_non_commutative_operands() {
+        assert_eq!(canonical("b - a"), "b - a");
+        assert_eq!(canonical("(b + a) / (d * c)"), "(a + b) / (c * d)")
+    }
+
+    #[test]
+    fn fold_double_negation() {
+        assert_eq!(canonical("--x + ---y"), "x + -y");
+        assert_eq!(canonical("-(-(b + a))"), "a + b")
+    }
+
+    #[test]
+    fn canonicalize_is_idempotent() {
+        for text in ["x = b * (a + 2); --x - c * b * a", "(c + b) + a * -(-d)"] {
+            let once = text.parse::<Node>().unwrap().canonicalize();
+            assert_eq!(once.canonicalize(), once)
+        }
+    }
+}
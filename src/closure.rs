@@ -0,0 +1,185 @@
+//!
+//! Compilation of expressions to native Rust closures.
+//!
+//! Each node becomes one boxed closure that calls its children directly, with
+//! operators matched and variable names resolved to slots ahead of time.
+//!
+use crate::compile::Bindings;
+use crate::error::ErrorCode;
+use crate::eval::{binary_value, unary_value, undefined_function, EvalError, Overflow, Value};
+use crate::parser::*;
+use std::collections::HashMap;
+
+/// An expression compiled by [`Node::to_closure`]. It can be called from
+/// several threads at once.
+pub type Closure = Box<dyn Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync>;
+
+type Compiled = Box<dyn Fn(&[Value], &mut [Value]) -> Result<Value, EvalError> + Send + Sync>;
+
+/// Where a variable lives: in the caller's arguments or in a local written by
+/// an earlier assignment.
+struct Scope<'a> {
+    bindings: &'a Bindings,
+    locals: HashMap<String, usize>,
+}
+
+impl Scope<'_> {
+    fn compile(&mut self, node: &Node) -> Compiled {
+        let overflow = Overflow::default();
+        match node {
+            Node::Number(n) => {
+                let value = Value::Int(*n);
+                Box::new(move |_, _| Ok(value))
+            }
+            Node::Float(Float(x)) => {
+                let value = Value::Float(*x);
+                Box::new(move |_, _| Ok(value))
+            }
+            Node::Identifier(name) => {
+                if let Some(&slot) = self.locals.get(name) {
+                    Box::new(move |_, locals| Ok(locals[slot]))
+                } else if let Some(slot) = self.bindings.get(name) {
+                    Box::new(move |args, _| Ok(args[slot]))
+                } else {
+                    let message = format!("undefined variable {}", name);
+                    Box::new(move |_, _| {
+                        Err(EvalError::with_code(
+                            ErrorCode::UndefinedVariable,
+                            message.clone(),
+                        ))
+                    })
+                }
+            }
+            Node::UnaryExpr { op, child } => {
+                let op = op.clone();
+                let child = self.compile(child);
+                Box::new(move |args, locals| unary_value(&op, child(args, locals)?, overflow))
+            }
+            Node::BinaryExpr { op, lhs, rhs } => {
+                let op = op.clone();
+                let lhs = self.compile(lhs);
+                let rhs = self.compile(rhs);
+                Box::new(move |args, locals| {
+                    let lhs = lhs(args, locals)?;
+                    binary_value(&op, lhs, rhs(args, locals)?, overflow)
+                })
+            }
+            Node::Assign { name, value } => {
+                let value = self.compile(value);
+                let next = self.locals.len();
+                let slot = *self.locals.entry(name.clone()).or_insert(next);
+                Box::new(move |args, locals| {
+                    let value = value(args, locals)?;
+                    locals[slot] = value;
+                    Ok(value)
+                })
+            }
+            Node::Block(stmts) => {
+                let stmts: Vec<Compiled> = stmts.iter().map(|stmt| self.compile(stmt)).collect();
+                Box::new(move |args, locals| {
+                    let mut last = Value::Int(0);
+                    for stmt in &stmts {
+                        last = stmt(args, locals)?;
+                    }
+                    Ok(last)
+                })
+            }
+            Node::Call { name, .. } => {
+                let name = name.clone();
+                Box::new(move |_, _| Err(undefined_function(&name)))
+            }
+            Node::Use(_) => Box::new(|_, _| Ok(Value::Int(0))),
+        }
+    }
+}
+
+impl Node {
+    ///
+    /// Compiles the expression into a closure taking the value of each
+    /// variable in `bindings`, in slot order.
+    ///
+    /// Names that are assigned before they are read become locals of the
+    /// closure; a read of a name that is neither bound nor assigned fails with
+    /// "undefined variable" when the closure runs. Closures of trees without
+    /// assignments do not allocate.
+    ///
+    pub fn to_closure(&self, bindings: &Bindings) -> Closure {
+        let mut scope = Scope {
+            bindings,
+            locals: HashMap::new(),
+        };
+        let body = scope.compile(self);
+        let arity = bindings.len();
+        let locals = scope.locals.len();
+        Box::new(move |args| {
+            if args.len() != arity {
+                return Err(EvalError::with_code(
+                    ErrorCode::InvalidBinding,
+                    format!("expected {} variables, got {}", arity, args.len()),
+                ));
+            }
+            if locals == 0 {
+                body(args, &mut [])
+            } else {
+                body(args, &mut vec![Value::Int(0); locals])
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    fn closure(text: &str, names: &[&str]) -> Closure {
+        let node: Node = text.parse().unwrap();
+        node.to_closure(&names.iter().collect())
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let f = closure("x * x - 3 * y", &["x", "y"]);
+        for x in -3..3 {
+            assert_eq!(f(&[x.into(), 2.into()]).unwrap(), Value::Int(x * x - 6))
+        }
+    }
+
+    #[test]
+    fn float_promotion() {
+        let f = closure("x / 2 + y ^ 2", &["x", "y"]);
+        assert_eq!(f(&[3.into(), 2.into()]).unwrap(), Value::Int(5));
+        assert_eq!(f(&[3.0.into(), 2.into()]).unwrap(), Value::Float(5.5));
+        let err = closure("x << 1", &["x"])(&[1.5.into()]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: << requires integer operands")
+    }
+
+    #[test]
+    fn assignments_shadow_arguments() {
+        let f = closure("y = x; x = x + 10; x * y", &["x"]);
+        assert_eq!(f(&[2.into()]).unwrap(), Value::Int(24));
+        assert_eq!(f(&[3.into()]).unwrap(), Value::Int(39))
+    }
+
+    #[test]
+    fn matches_tree_walking() {
+        let text = "a = 7; b = -a ^ 2 / 3; (a << 2) - b * (a - 10) >> 1";
+        let node: Node = text.parse().unwrap();
+        let f = node.to_closure(&Bindings::new());
+        assert_eq!(
+            f(&[]).unwrap(),
+            Value::Int(Eval::new().eval(&node).unwrap())
+        )
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let f = closure("x / y + z", &["x", "y"]);
+        let err = f(&[1.into(), 0.into()]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: division by zero");
+        let err = f(&[1.into(), 1.into()]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined variable z");
+        let err = f(&[1.into()]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: expected 2 variables, got 1")
+    }
+}
@@ -0,0 +1,391 @@
+//!
+//! Rust source generation, so a formula checked in the REPL can be pasted
+//! into a Rust program.
+//!
+//! Arithmetic is emitted with Rust's native operators: `i32` code panics on
+//! overflow in debug builds and wraps in release builds rather than following
+//! [`Overflow`](crate::eval::Overflow), and `f64` code follows IEEE 754.
+//!
+use crate::parser::*;
+use std::error::Error;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct CodegenError {
+    message: String,
+}
+
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CodegenError: {}", self.message)
+    }
+}
+
+impl Error for CodegenError {}
+
+/// The numeric type the generated code computes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustType {
+    I32,
+    F64,
+}
+
+impl Display for RustType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustType::I32 => f.write_str("i32"),
+            RustType::F64 => f.write_str("f64"),
+        }
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+fn identifier(name: &str) -> String {
+    if let Some(position) = name.strip_prefix('$') {
+        format!("_{}", position)
+    } else if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, CodegenError> {
+    Err(CodegenError {
+        message: message.into(),
+    })
+}
+
+/// Binding strength in Rust: method calls and atoms bind tightest.
+fn precedence(node: &Node) -> u8 {
+    match node {
+        Node::BinaryExpr { op, .. } => match op {
+            BinaryOperator::Shl | BinaryOperator::Shr => 1,
+            BinaryOperator::Plus | BinaryOperator::Minus => 2,
+            BinaryOperator::Mul | BinaryOperator::Div => 3,
+            BinaryOperator::Pow => 5,
+            // Emitted as a cast, `(a != 0 & b != 0) as i32`.
+            BinaryOperator::And | BinaryOperator::Or => 4,
+        },
+        Node::UnaryExpr { .. } => 4,
+        Node::Number(n) if *n < 0 => 4,
+        Node::Float(Float(x)) if *x < 0.0 && x.is_finite() => 4,
+        _ => 5,
+    }
+}
+
+struct Emitter {
+    ty: RustType,
+}
+
+impl Emitter {
+    fn operand(&self, node: &Node, parenthesize: bool) -> Result<String, CodegenError> {
+        let code = self.expr(node)?;
+        Ok(if parenthesize {
+            format!("({})", code)
+        } else {
+            code
+        })
+    }
+
+    fn literal(&self, n: i32) -> String {
+        match self.ty {
+            RustType::I32 => n.to_string(),
+            RustType::F64 => format!("{}.0", n),
+        }
+    }
+
+    /// A float literal, which only `f64` code can hold.
+    fn float(&self, x: f64) -> Result<String, CodegenError> {
+        match self.ty {
+            RustType::I32 => error(format!("{:?} is not an i32", x)),
+            _ if x.is_nan() => Ok("f64::NAN".to_string()),
+            _ if x.is_infinite() && x < 0.0 => Ok("f64::NEG_INFINITY".to_string()),
+            _ if x.is_infinite() => Ok("f64::INFINITY".to_string()),
+            RustType::F64 => Ok(format!("{:?}", x)),
+        }
+    }
+
+    /// Converts a `bool` to the emitted type; Rust has no `bool as f64`.
+    fn cast_bool(&self, condition: String) -> String {
+        match self.ty {
+            RustType::I32 => format!("({}) as i32", condition),
+            RustType::F64 => format!("({}) as i32 as f64", condition),
+        }
+    }
+
+    fn truthy(&self, node: &Node) -> Result<String, CodegenError> {
+        Ok(format!("{} != {}", self.expr(node)?, self.literal(0)))
+    }
+
+    fn expr(&self, node: &Node) -> Result<String, CodegenError> {
+        let prec = precedence(node);
+        match node {
+            Node::Number(n) => Ok(self.literal(*n)),
+            Node::Float(Float(x)) => self.float(*x),
+            Node::Identifier(name) => Ok(identifier(name)),
+            Node::UnaryExpr {
+                op: UnaryOperator::Not,
+                child,
+            } => Ok(self.cast_bool(format!("{} == {}", self.expr(child)?, self.literal(0)))),
+            Node::UnaryExpr { child, .. } => {
+                // `--x` is legal Rust but reads like a decrement.
+                let nested = child.is_logical()
+                    || matches!(
+                        **child,
+                        Node::UnaryExpr { .. } | Node::Number(i32::MIN..=-1)
+                    );
+                Ok(format!(
+                    "-{}",
+                    self.operand(child, nested || precedence(child) < prec)?
+                ))
+            }
+            Node::BinaryExpr {
+                op: BinaryOperator::Pow,
+                lhs,
+                rhs,
+            } => {
+                // A literal receiver needs a suffix, or its type is ambiguous.
+                let base = match &**lhs {
+                    Node::Number(n) if *n < 0 => format!("({}_{})", self.literal(*n), self.ty),
+                    Node::Number(n) => format!("{}_{}", self.literal(*n), self.ty),
+                    Node::Float(Float(x)) if !x.is_finite() => self.float(*x)?,
+                    Node::Float(Float(x)) if *x < 0.0 => format!("({}_f64)", self.float(*x)?),
+                    Node::Float(Float(x)) => format!("{}_f64", self.float(*x)?),
+                    Node::UnaryExpr {
+                        op: UnaryOperator::Neg,
+                        child,
+                    } if matches!(**child, Node::Number(n) if n >= 0) => {
+                        format!("(-{}_{})", self.expr(child)?, self.ty)
+                    }
+                    _ => self.operand(lhs, precedence(lhs) < prec)?,
+                };
+                Ok(match (self.ty, &**rhs) {
+                    (RustType::I32, Node::Number(n)) if *n >= 0 => format!("{}.pow({})", base, n),
+                    (RustType::I32, rhs) => format!(
+                        "{}.pow({} as u32)",
+                        base,
+                        self.operand(rhs, precedence(rhs) < 4)?
+                    ),
+                    (RustType::F64, Node::Number(n)) => format!("{}.powi({})", base, n),
+                    (RustType::F64, rhs) => format!("{}.powf({})", base, self.expr(rhs)?),
+                })
+            }
+            Node::BinaryExpr { op, .. }
+                if self.ty == RustType::F64
+                    && matches!(op, BinaryOperator::Shl | BinaryOperator::Shr) =>
+            {
+                error(format!("{} requires integer operands", op))
+            }
+            // `&` and `|` on `bool` evaluate both operands, like the interpreter.
+            Node::BinaryExpr {
+                op: BinaryOperator::And,
+                lhs,
+                rhs,
+            } => Ok(self.cast_bool(format!("({}) & ({})", self.truthy(lhs)?, self.truthy(rhs)?))),
+            Node::BinaryExpr {
+                op: BinaryOperator::Or,
+                lhs,
+                rhs,
+            } => Ok(self.cast_bool(format!("({}) | ({})", self.truthy(lhs)?, self.truthy(rhs)?))),
+            Node::BinaryExpr { op, lhs, rhs } => Ok(format!(
+                "{} {} {}",
+                self.operand(lhs, precedence(lhs) < prec)?,
+                op,
+                self.operand(rhs, precedence(rhs) <= prec)?
+            )),
+            Node::Assign { name, .. } => {
+                error(format!("assignment to {} inside an expression", name))
+            }
+            Node::Block(_) => error("block inside an expression"),
+            Node::Use(path) => error(format!("use {} inside an expression", path)),
+            // Host functions are expected to be in scope under the same name.
+            Node::Call { name, args } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expr(arg))
+                    .collect::<Result<_, _>>()?;
+                Ok(format!("{}({})", identifier(name), args.join(", ")))
+            }
+        }
+    }
+
+    /// Statements of the body: every assignment becomes a `let`, and the value
+    /// of the last statement is the tail expression.
+    fn body(&self, node: &Node) -> Result<Vec<String>, CodegenError> {
+        let stmts = match node {
+            Node::Block(stmts) => stmts.as_slice(),
+            node => std::slice::from_ref(node),
+        };
+        let mut lines = vec![];
+        for stmt in stmts {
+            match stmt {
+                Node::Assign { name, value } => {
+                    lines.push(format!("let {} = {};", identifier(name), self.expr(value)?))
+                }
+                // Rust allows `use` in a block too.
+                Node::Use(path) => lines.push(format!("use {};", path)),
+                stmt => lines.push(format!("{};", self.expr(stmt)?)),
+            }
+        }
+        match stmts.last() {
+            Some(Node::Assign { name, .. }) => lines.push(identifier(name)),
+            Some(Node::Use(_)) => lines.push(self.literal(0)),
+            Some(_) => {
+                let tail = lines.pop().unwrap();
+                lines.push(tail.trim_end_matches(';').to_string())
+            }
+            None => lines.push(self.expr(&Node::Number(0))?),
+        }
+        Ok(lines)
+    }
+}
+
+///
+/// Emits `node` as a single Rust expression of type `ty`. Statements become a
+/// block expression, e.g. `{ let y = x * 2; y + 1 }`.
+///
+pub fn to_rust_expr(node: &Node, ty: RustType) -> Result<String, CodegenError> {
+    let emitter = Emitter { ty };
+    match node {
+        Node::Block(_) | Node::Assign { .. } | Node::Use(_) => {
+            Ok(format!("{{ {} }}", emitter.body(node)?.join(" ")))
+        }
+        node => emitter.expr(node),
+    }
+}
+
+///
+/// Emits `node` as a Rust function called `name` computing in `ty`. Every
+/// variable read before it is assigned becomes a parameter, in order of first
+/// use.
+///
+pub fn to_rust_fn(node: &Node, name: &str, ty: RustType) -> Result<String, CodegenError> {
+    let params: Vec<String> = node
+        .free_variables()
+        .iter()
+        .map(|param| format!("{}: {}", identifier(param), ty))
+        .collect();
+    let mut code = format!(
+        "fn {}({}) -> {} {{\n",
+        identifier(name),
+        params.join(", "),
+        ty
+    );
+    for line in (Emitter { ty }).body(node)? {
+        code.push_str("    ");
+        code.push_str(&line);
+        code.push('\n');
+    }
+    code.push_str("}\n");
+    Ok(code)
+}
+
+/// Emits `node` as an `i32` Rust function called `eval`. Panics if `node`
+/// has an assignment below statement level or a float literal.
+pub fn to_rust(node: &Node) -> String {
+    to_rust_fn(node, "eval", RustType::I32)
+        .expect("statement-level assignments and integer literals only")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expr(text: &str, ty: RustType) -> String {
+        to_rust_expr(&text.parse().unwrap(), ty).unwrap()
+    }
+
+    #[test]
+    fn function() {
+        let node: Node = "y = x * 2; z = y + offset; z / 3".parse().unwrap();
+        assert_eq!(
+            to_rust(&node),
+            "fn eval(x: i32, offset: i32) -> i32 {\n    \
+             let y = x * 2;\n    \
+             let z = y + offset;\n    \
+             z / 3\n\
+             }\n"
+        );
+        let node: Node = "$1 * 2 + $2".parse().unwrap();
+        assert_eq!(
+            to_rust(&node),
+            "fn eval(_1: i32, _2: i32) -> i32 {\n    _1 * 2 + _2\n}\n"
+        )
+    }
+
+    #[test]
+    fn parenthesization() {
+        assert_eq!(
+            expr("(a + b) * (c - d) - (e - f)", RustType::I32),
+            "(a + b) * (c - d) - (e - f)"
+        );
+        assert_eq!(expr("-(x + 1) ^ 2", RustType::I32), "-(x + 1).pow(2)");
+        assert_eq!(
+            expr("(-x) ^ n + --y", RustType::I32),
+            "(-x).pow(n as u32) + -(-y)"
+        );
+        assert_eq!(expr("1 << a + b", RustType::I32), "1 << a + b");
+        assert_eq!(
+            expr("-clamp(x * 2, 0, max) ^ 2", RustType::I32),
+            "-clamp(x * 2, 0, max).pow(2)"
+        );
+        assert_eq!(
+            expr("(-2) ^ (n - 1)", RustType::I32),
+            "(-2_i32).pow((n - 1) as u32)"
+        )
+    }
+
+    #[test]
+    fn float_typing() {
+        assert_eq!(
+            expr("x / 2 + x ^ 2 - 2 ^ x", RustType::F64),
+            "x / 2.0 + x.powi(2) - 2.0_f64.powf(x)"
+        );
+        assert_eq!(
+            expr("1.5 ^ x - 2.5e-7 * x", RustType::F64),
+            "1.5_f64.powf(x) - 2.5e-7 * x"
+        );
+        let node: Node = "x * 0.5".parse().unwrap();
+        let err = to_rust_expr(&node, RustType::I32).unwrap_err();
+        assert_eq!(err.to_string(), "CodegenError: 0.5 is not an i32");
+        let node: Node = "x << 1".parse().unwrap();
+        let err = to_rust_expr(&node, RustType::F64).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "CodegenError: << requires integer operands"
+        )
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(
+            expr("a && !b || -!c", RustType::I32),
+            "((((a != 0) & ((b == 0) as i32 != 0)) as i32 != 0) | (-((c == 0) as i32) != 0)) as i32"
+        );
+        assert_eq!(
+            expr("!x * 2", RustType::F64),
+            "(x == 0.0) as i32 as f64 * 2.0"
+        )
+    }
+
+    #[test]
+    fn statements_as_block_expression() {
+        assert_eq!(
+            expr("type = 3; type * type", RustType::I32),
+            "{ let r#type = 3; r#type * r#type }"
+        );
+        let node: Node = "a = 1".parse().unwrap();
+        assert_eq!(
+            to_rust_fn(&node, "one", RustType::F64).unwrap(),
+            "fn one() -> f64 {\n    let a = 1.0;\n    a\n}\n"
+        )
+    }
+}
@@ -0,0 +1,195 @@
+//!
+//! Columnar evaluation: variables are bound to whole columns of `f64` and
+//! every operator runs over all rows at once.
+//!
+//! Rows are processed in fixed-width chunks so the inner loops compile to
+//! SIMD instructions, instead of walking the tree once per row.
+//!
+use crate::error::ErrorCode;
+use crate::eval::{integer_operands, undefined_function, EvalError};
+use crate::parser::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Rows processed together in each inner loop.
+const LANES: usize = 8;
+
+/// A column of values, or one value standing for every row.
+#[derive(Clone)]
+enum Column<'a> {
+    Scalar(f64),
+    Vector(Cow<'a, [f64]>),
+}
+
+fn lanes(column: &Column, start: usize, len: usize) -> [f64; LANES] {
+    match column {
+        Column::Scalar(x) => [*x; LANES],
+        Column::Vector(values) => {
+            let mut lanes = [0.0; LANES];
+            lanes[..len].copy_from_slice(&values[start..start + len]);
+            lanes
+        }
+    }
+}
+
+fn truth(x: f64) -> f64 {
+    (x != 0.0) as i32 as f64
+}
+
+struct Columns<'a> {
+    rows: usize,
+    vars: HashMap<String, Column<'a>>,
+}
+
+impl<'a> Columns<'a> {
+    fn map(&self, child: Column<'a>, f: impl Fn(f64) -> f64) -> Column<'a> {
+        self.zip(child, Column::Scalar(0.0), |x, _| f(x))
+    }
+
+    fn zip(&self, lhs: Column<'a>, rhs: Column<'a>, f: impl Fn(f64, f64) -> f64) -> Column<'a> {
+        if let (Column::Scalar(l), Column::Scalar(r)) = (&lhs, &rhs) {
+            return Column::Scalar(f(*l, *r));
+        }
+        let mut out = Vec::with_capacity(self.rows);
+        for start in (0..self.rows).step_by(LANES) {
+            let len = LANES.min(self.rows - start);
+            let (l, r) = (lanes(&lhs, start, len), lanes(&rhs, start, len));
+            let mut chunk = [0.0; LANES];
+            for i in 0..LANES {
+                chunk[i] = f(l[i], r[i]);
+            }
+            out.extend_from_slice(&chunk[..len]);
+        }
+        Column::Vector(Cow::Owned(out))
+    }
+
+    fn eval(&mut self, node: &Node) -> Result<Column<'a>, EvalError> {
+        match node {
+            Node::Number(n) => Ok(Column::Scalar(*n as f64)),
+            Node::Float(Float(x)) => Ok(Column::Scalar(*x)),
+            Node::Identifier(name) => match self.vars.get(name) {
+                Some(column) => Ok(column.clone()),
+                None => Err(EvalError::with_code(
+                    ErrorCode::UndefinedVariable,
+                    format!("undefined variable {}", name),
+                )),
+            },
+            Node::UnaryExpr { op, child } => {
+                let child = self.eval(child)?;
+                Ok(match op {
+                    UnaryOperator::Neg => self.map(child, |x| -x),
+                    UnaryOperator::Not => self.map(child, |x| 1.0 - truth(x)),
+                })
+            }
+            Node::BinaryExpr { op, lhs, rhs } => {
+                let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+                match op {
+                    BinaryOperator::Plus => Ok(self.zip(lhs, rhs, |l, r| l + r)),
+                    BinaryOperator::Minus => Ok(self.zip(lhs, rhs, |l, r| l - r)),
+                    BinaryOperator::Mul => Ok(self.zip(lhs, rhs, |l, r| l * r)),
+                    BinaryOperator::Div => Ok(self.zip(lhs, rhs, |l, r| l / r)),
+                    BinaryOperator::Pow => Ok(self.zip(lhs, rhs, f64::powf)),
+                    BinaryOperator::Shl | BinaryOperator::Shr => Err(integer_operands(op)),
+                    BinaryOperator::And => Ok(self.zip(lhs, rhs, |l, r| truth(l) * truth(r))),
+                    BinaryOperator::Or => Ok(self.zip(lhs, rhs, |l, r| truth(truth(l) + truth(r)))),
+                }
+            }
+            Node::Assign { name, value } => {
+                let value = self.eval(value)?;
+                self.vars.insert(name.clone(), value.clone());
+                Ok(value)
+            }
+            Node::Block(stmts) => {
+                let mut last = Column::Scalar(0.0);
+                for stmt in stmts {
+                    last = self.eval(stmt)?;
+                }
+                Ok(last)
+            }
+            Node::Call { name, .. } => Err(undefined_function(name)),
+            Node::Use(_) => Ok(Column::Scalar(0.0)),
+        }
+    }
+}
+
+///
+/// Evaluates `node` over columns of data, binding each variable to a slice
+/// with one value per row, and returns the column of results.
+///
+/// Arithmetic is on `f64` as in `calculus::eval_real`: division by zero
+/// gives an infinity or NaN rather than an error, logical operators give 0
+/// or 1, and shifts are rejected. All columns must have the same number of
+/// rows; with no columns there are no rows.
+///
+pub fn eval_columns(node: &Node, columns: &[(&str, &[f64])]) -> Result<Vec<f64>, EvalError> {
+    let rows = columns.first().map_or(0, |(_, values)| values.len());
+    let mut vars = HashMap::new();
+    for (name, values) in columns {
+        if values.len() != rows {
+            return Err(EvalError::with_code(
+                ErrorCode::InvalidBinding,
+                format!(
+                    "column {} has {} rows, expected {}",
+                    name,
+                    values.len(),
+                    rows
+                ),
+            ));
+        }
+        vars.insert(name.to_string(), Column::Vector(Cow::Borrowed(*values)));
+    }
+    let mut columns = Columns { rows, vars };
+    Ok(match columns.eval(node)? {
+        Column::Scalar(x) => vec![x; rows],
+        Column::Vector(values) => values.into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::calculus::eval_real;
+
+    fn columns(text: &str, columns: &[(&str, &[f64])]) -> Vec<f64> {
+        eval_columns(&text.parse().unwrap(), columns).unwrap()
+    }
+
+    #[test]
+    fn arithmetic_over_rows() {
+        let price = [10.0, 20.0, 30.0];
+        let qty = [1.0, 2.0, 0.0];
+        assert_eq!(
+            columns("price * qty + 1", &[("price", &price), ("qty", &qty)]),
+            [11.0, 41.0, 1.0]
+        );
+        assert_eq!(columns("2 ^ 3", &[("price", &price)]), [8.0; 3]);
+        assert_eq!(columns("!qty || 0", &[("qty", &qty)]), [0.0, 0.0, 1.0])
+    }
+
+    #[test]
+    fn matches_scalar_evaluation() {
+        let text = "t = x * x - y / 3; -t ^ 2 + (x && !y) + t";
+        let node: Node = text.parse().unwrap();
+        let x: Vec<f64> = (0..37).map(|i| i as f64 * 0.25 - 4.0).collect();
+        let y: Vec<f64> = (0..37).map(|i| (i % 5) as f64).collect();
+        let result = eval_columns(&node, &[("x", &x), ("y", &y)]).unwrap();
+        for row in 0..x.len() {
+            let vars = HashMap::from([("x".to_string(), x[row]), ("y".to_string(), y[row])]);
+            assert_eq!(result[row], eval_real(&node, &vars).unwrap())
+        }
+    }
+
+    #[test]
+    fn column_errors() {
+        let node: Node = "a + b".parse().unwrap();
+        let err = eval_columns(&node, &[("a", &[1.0, 2.0]), ("b", &[1.0])]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: column b has 1 rows, expected 2"
+        );
+        let err = eval_columns(&node, &[("a", &[1.0])]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined variable b");
+        let node: Node = "a << 1".parse().unwrap();
+        assert!(eval_columns(&node, &[("a", &[1.0])]).is_err())
+    }
+}
@@ -0,0 +1,434 @@
+//!
+//! Compilation of expressions to bytecode for a small stack machine.
+//!
+//! Variables are resolved to numbered slots at compile time, so running a
+//! compiled program against new values is a tight loop over a flat
+//! instruction vector with no hashing or pointer chasing.
+//!
+use crate::error::ErrorCode;
+use crate::eval::{binary, float_literal, unary, undefined_function, EvalError, Overflow};
+use crate::parser::*;
+use crate::prelude::*;
+use crate::Map;
+use core::fmt::Display;
+
+/// Assigns each variable name a dense slot index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bindings {
+    names: Vec<String>,
+    slots: Map<String, usize>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the slot of `name`, allocating the next free one if needed.
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        let slot = self.names.len();
+        self.names.push(name.to_string());
+        self.slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+
+    /// Names in slot order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Bindings {
+    fn from_iter<I: IntoIterator<Item = S>>(names: I) -> Self {
+        let mut bindings = Bindings::new();
+        for name in names {
+            bindings.slot(name.as_ref());
+        }
+        bindings
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instr {
+    Const(i32),
+    /// A float literal, which the integer machine rejects when it runs it.
+    Float(Float),
+    /// Pushes the value of a slot.
+    Load(usize),
+    /// Writes the top of the stack to a slot, leaving it on the stack.
+    Store(usize),
+    Pop,
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Shl,
+    Shr,
+    Not,
+    And,
+    Or,
+    /// Pops `argc` arguments and pushes the result of calling a function,
+    /// named by its index in `Program::functions`.
+    Call {
+        function: usize,
+        argc: usize,
+    },
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Instr::Const(n) => write!(f, "const {}", n),
+            Instr::Float(Float(x)) => write!(f, "float {:?}", x),
+            Instr::Load(slot) => write!(f, "load {}", slot),
+            Instr::Store(slot) => write!(f, "store {}", slot),
+            Instr::Pop => f.write_str("pop"),
+            Instr::Neg => f.write_str("neg"),
+            Instr::Add => f.write_str("add"),
+            Instr::Sub => f.write_str("sub"),
+            Instr::Mul => f.write_str("mul"),
+            Instr::Div => f.write_str("div"),
+            Instr::Pow => f.write_str("pow"),
+            Instr::Shl => f.write_str("shl"),
+            Instr::Shr => f.write_str("shr"),
+            Instr::Not => f.write_str("not"),
+            Instr::And => f.write_str("and"),
+            Instr::Or => f.write_str("or"),
+            Instr::Call { function, argc } => write!(f, "call {} {}", function, argc),
+        }
+    }
+}
+
+impl From<&BinaryOperator> for Instr {
+    fn from(op: &BinaryOperator) -> Self {
+        match op {
+            BinaryOperator::Plus => Instr::Add,
+            BinaryOperator::Minus => Instr::Sub,
+            BinaryOperator::Mul => Instr::Mul,
+            BinaryOperator::Div => Instr::Div,
+            BinaryOperator::Pow => Instr::Pow,
+            BinaryOperator::Shl => Instr::Shl,
+            BinaryOperator::Shr => Instr::Shr,
+            BinaryOperator::And => Instr::And,
+            BinaryOperator::Or => Instr::Or,
+        }
+    }
+}
+
+/// A compiled expression together with the slots its variables live in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<Instr>,
+    bindings: Bindings,
+    functions: Vec<String>,
+    max_stack: usize,
+}
+
+impl Program {
+    pub fn code(&self) -> &[Instr] {
+        &self.code
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Names of the functions called, indexed by `Instr::Call`.
+    pub fn functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    /// The deepest the operand stack gets while running the program.
+    pub fn max_stack(&self) -> usize {
+        self.max_stack
+    }
+}
+
+/// Prints one instruction per line.
+impl Display for Program {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for instr in &self.code {
+            writeln!(f, "{}", instr)?;
+        }
+        Ok(())
+    }
+}
+
+struct Compiler {
+    code: Vec<Instr>,
+    bindings: Bindings,
+    functions: Vec<String>,
+    depth: usize,
+    max_stack: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, instr: Instr) {
+        match instr {
+            Instr::Const(_) | Instr::Float(_) | Instr::Load(_) => {
+                self.depth += 1;
+                self.max_stack = self.max_stack.max(self.depth);
+            }
+            Instr::Store(_) | Instr::Neg | Instr::Not => {}
+            Instr::Call { argc, .. } => {
+                self.depth = self.depth + 1 - argc;
+                self.max_stack = self.max_stack.max(self.depth);
+            }
+            _ => self.depth -= 1,
+        }
+        self.code.push(instr);
+    }
+
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Number(n) => self.emit(Instr::Const(*n)),
+            Node::Float(x) => self.emit(Instr::Float(*x)),
+            Node::Identifier(name) => {
+                let slot = self.bindings.slot(name);
+                self.emit(Instr::Load(slot))
+            }
+            Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child,
+            } => {
+                self.compile(child);
+                self.emit(Instr::Neg)
+            }
+            Node::UnaryExpr {
+                op: UnaryOperator::Not,
+                child,
+            } => {
+                self.compile(child);
+                self.emit(Instr::Not)
+            }
+            Node::BinaryExpr { op, lhs, rhs } => {
+                self.compile(lhs);
+                self.compile(rhs);
+                self.emit(op.into())
+            }
+            Node::Assign { name, value } => {
+                self.compile(value);
+                let slot = self.bindings.slot(name);
+                self.emit(Instr::Store(slot))
+            }
+            Node::Block(stmts) if stmts.is_empty() => self.emit(Instr::Const(0)),
+            Node::Use(_) => self.emit(Instr::Const(0)),
+            Node::Block(stmts) => {
+                for (i, stmt) in stmts.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(Instr::Pop);
+                    }
+                    self.compile(stmt);
+                }
+            }
+            Node::Call { name, args } => {
+                for arg in args {
+                    self.compile(arg);
+                }
+                let function = match self.functions.iter().position(|f| f == name) {
+                    Some(function) => function,
+                    None => {
+                        self.functions.push(name.clone());
+                        self.functions.len() - 1
+                    }
+                };
+                self.emit(Instr::Call {
+                    function,
+                    argc: args.len(),
+                })
+            }
+        }
+    }
+}
+
+/// Lowers `node` to bytecode, giving every variable it mentions a slot.
+pub fn compile(node: &Node) -> Program {
+    let mut compiler = Compiler {
+        code: vec![],
+        bindings: Bindings::new(),
+        functions: vec![],
+        depth: 0,
+        max_stack: 0,
+    };
+    compiler.compile(node);
+    Program {
+        code: compiler.code,
+        bindings: compiler.bindings,
+        functions: compiler.functions,
+        max_stack: compiler.max_stack,
+    }
+}
+
+///
+/// A stack machine that runs compiled programs.
+///
+/// The operand stack is kept between runs, so evaluating the same program
+/// over many sets of values does not allocate.
+///
+#[derive(Debug, Default)]
+pub struct Vm {
+    stack: Vec<i32>,
+    overflow: Overflow,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_overflow(overflow: Overflow) -> Self {
+        Vm {
+            stack: vec![],
+            overflow,
+        }
+    }
+
+    /// Runs `program` with `vars` holding the value of each slot, in the order
+    /// of `program.bindings()`. Assignments write back into `vars`.
+    pub fn run(&mut self, program: &Program, vars: &mut [i32]) -> Result<i32, EvalError> {
+        if vars.len() != program.bindings.len() {
+            return Err(EvalError::with_code(
+                ErrorCode::InvalidBinding,
+                format!(
+                    "expected {} variables, got {}",
+                    program.bindings.len(),
+                    vars.len()
+                ),
+            ));
+        }
+        let stack = &mut self.stack;
+        stack.clear();
+        stack.reserve(program.max_stack);
+        for instr in &program.code {
+            match *instr {
+                Instr::Const(n) => stack.push(n),
+                Instr::Float(Float(x)) => return Err(float_literal(x)),
+                Instr::Load(slot) => stack.push(vars[slot]),
+                Instr::Store(slot) => vars[slot] = *stack.last().unwrap(),
+                Instr::Pop => {
+                    stack.pop();
+                }
+                Instr::Neg => {
+                    let top = stack.last_mut().unwrap();
+                    *top = unary(&UnaryOperator::Neg, *top, self.overflow)?;
+                }
+                Instr::Not => {
+                    let top = stack.last_mut().unwrap();
+                    *top = (*top == 0) as i32;
+                }
+                // The machine runs without host functions.
+                Instr::Call { function, .. } => {
+                    return Err(undefined_function(&program.functions[function]))
+                }
+                instr => {
+                    let op = match instr {
+                        Instr::Add => BinaryOperator::Plus,
+                        Instr::Sub => BinaryOperator::Minus,
+                        Instr::Mul => BinaryOperator::Mul,
+                        Instr::Div => BinaryOperator::Div,
+                        Instr::Pow => BinaryOperator::Pow,
+                        Instr::Shl => BinaryOperator::Shl,
+                        Instr::Shr => BinaryOperator::Shr,
+                        Instr::And => BinaryOperator::And,
+                        Instr::Or => BinaryOperator::Or,
+                        _ => unreachable!(),
+                    };
+                    let rhs = stack.pop().unwrap();
+                    let top = stack.last_mut().unwrap();
+                    *top = binary(&op, *top, rhs, self.overflow)?;
+                }
+            }
+        }
+        Ok(stack.pop().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    fn program(text: &str) -> Program {
+        compile(&text.parse().unwrap())
+    }
+
+    #[test]
+    fn compile_expression() {
+        let program = program("x * (y + 2)");
+        assert_eq!(
+            program.code(),
+            [
+                Instr::Load(0),
+                Instr::Load(1),
+                Instr::Const(2),
+                Instr::Add,
+                Instr::Mul
+            ]
+        );
+        assert_eq!(program.bindings().names(), ["x", "y"]);
+        assert_eq!(program.max_stack(), 3)
+    }
+
+    #[test]
+    fn disassemble() {
+        let program = program("a = -1; a");
+        assert_eq!(program.to_string(), "const 1\nneg\nstore 0\npop\nload 0\n")
+    }
+
+    #[test]
+    fn run_with_many_bindings() {
+        let program = program("x * x - 3 * x + y");
+        let mut vm = Vm::new();
+        for x in -5..5 {
+            let mut vars = [x, 10];
+            assert_eq!(vm.run(&program, &mut vars).unwrap(), x * x - 3 * x + 10)
+        }
+    }
+
+    #[test]
+    fn assignments_write_back() {
+        let program = program("y = x + 1; z = y * y; z - x");
+        let slot = |name| program.bindings().get(name).unwrap();
+        let mut vars = [0; 3];
+        vars[slot("x")] = 4;
+        assert_eq!(Vm::new().run(&program, &mut vars).unwrap(), 21);
+        assert_eq!(vars[slot("y")], 5);
+        assert_eq!(vars[slot("z")], 25)
+    }
+
+    #[test]
+    fn matches_tree_walking() {
+        let text = "a = 7; b = -a ^ 2 / 3; c = (a << 2) - b * (a - 10) >> 1; c + (!b || a && 0)";
+        let node: Node = text.parse().unwrap();
+        let mut vars = [0; 3];
+        assert_eq!(
+            Vm::new().run(&compile(&node), &mut vars).unwrap(),
+            Eval::new().eval(&node).unwrap()
+        )
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let err = Vm::new().run(&program("x / 0"), &mut [1]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: division by zero");
+        let err = Vm::new().run(&program("x + y"), &mut [1]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: expected 2 variables, got 1")
+    }
+}
@@ -0,0 +1,344 @@
+//!
+//! The configuration file of the `rexpr` command, read at startup from
+//! `$XDG_CONFIG_HOME/rexpr/config.toml` or `~/.config/rexpr/config.toml`.
+//! It is written in a subset of TOML: `key = value` lines, with strings,
+//! integers, booleans and single-line arrays as values, and `#` comments.
+//!
+//! ```
+//! use rexpr::config::Config;
+//! use rexpr::format::FloatFormat;
+//! use rexpr::stdlib::Angle;
+//!
+//! let config = Config::parse(
+//!     r#"
+//! precision = 4          # or "auto"
+//! angle = "deg"
+//! prompt = "calc> "
+//! theme = "dark"
+//! history_size = 500
+//! history_file = "~/.rexpr_history"
+//! preload = ["~/defs.rx"]
+//! "#,
+//! )
+//! .unwrap();
+//! assert_eq!(config.precision, Some(FloatFormat::Fixed(4)));
+//! assert_eq!(config.angle, Some(Angle::Degrees));
+//! assert_eq!(config.preload, ["~/defs.rx"]);
+//! ```
+//!
+//! Every key is optional; command-line options take precedence.
+//!
+use crate::diagnostic::did_you_mean;
+use crate::format::FloatFormat;
+use crate::stdlib::Angle;
+use std::error::Error;
+use std::fmt::Display;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+const KEYS: [&str; 7] = [
+    "precision",
+    "angle",
+    "prompt",
+    "theme",
+    "history_size",
+    "history_file",
+    "preload",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// How to show floats: `Fixed` for a number of digits, or `Shortest`
+    /// for `"auto"`.
+    pub precision: Option<FloatFormat>,
+    pub angle: Option<Angle>,
+    pub prompt: Option<String>,
+    /// The color theme of the interactive session, `dark` or `light`.
+    pub theme: Option<String>,
+    /// The most entries the history keeps.
+    pub history_size: Option<usize>,
+    /// Where the history is kept, instead of `history.txt` in the data
+    /// directory.
+    pub history_file: Option<String>,
+    /// Scripts to run before anything else, as with `--preload`.
+    pub preload: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConfigError: {}", self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Bool(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(Value::String(text)),
+            Some('\\') => match chars.next() {
+                Some('"') => text.push('"'),
+                Some('\\') => text.push('\\'),
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some(c) => return Err(format!("unknown escape \\{}", c)),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => text.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_spaces(chars);
+    match chars.next() {
+        Some('"') => parse_string(chars),
+        Some('[') => {
+            let mut items = vec![];
+            loop {
+                skip_spaces(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(items));
+                }
+                items.push(parse_value(chars)?);
+                skip_spaces(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(items)),
+                    _ => return Err("expected , or ] in array".to_string()),
+                }
+            }
+        }
+        Some(c) => {
+            let mut word = c.to_string();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-_".contains(*c)) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => match word.replace('_', "").parse() {
+                    Ok(n) => Ok(Value::Integer(n)),
+                    Err(_) => Err(format!("invalid value {}", word)),
+                },
+            }
+        }
+        None => Err("expected a value".to_string()),
+    }
+}
+
+/// The key and value of a line that is not blank or a comment.
+fn parse_line(line: &str) -> Result<(String, Value), String> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| "expected key = value".to_string())?;
+    let key = key.trim();
+    let bare = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if key.starts_with('[') {
+        return Err("tables are not supported".to_string());
+    }
+    if key.is_empty() || !key.chars().all(bare) {
+        return Err(format!("invalid key {}", key));
+    }
+    let mut chars = value.chars().peekable();
+    let value = parse_value(&mut chars)?;
+    skip_spaces(&mut chars);
+    match chars.next() {
+        None | Some('#') => Ok((key.to_string(), value)),
+        Some(c) => Err(format!("unexpected character {} after the value", c)),
+    }
+}
+
+fn expected<T>(key: &str, what: &str, value: &Value) -> Result<T, String> {
+    Err(format!("{} must be {}, got {}", key, what, value.kind()))
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut seen: Vec<String> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let result = parse_line(line).and_then(|(key, value)| {
+                if seen.contains(&key) {
+                    return Err(format!("{} is set twice", key));
+                }
+                config.set(&key, value)?;
+                seen.push(key);
+                Ok(())
+            });
+            if let Err(message) = result {
+                return Err(ConfigError {
+                    message: format!("line {}: {}", i + 1, message),
+                });
+            }
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("precision", Value::String(auto)) if auto == "auto" => {
+                self.precision = Some(FloatFormat::Shortest)
+            }
+            ("precision", Value::Integer(digits)) if digits >= 0 => {
+                self.precision = Some(FloatFormat::Fixed(digits as usize))
+            }
+            ("precision", value) => return expected(key, "a number of digits or \"auto\"", &value),
+            ("angle", Value::String(angle)) => {
+                self.angle =
+                    Some(angle.parse().map_err(|_| {
+                        format!("angle must be \"rad\" or \"deg\", got \"{}\"", angle)
+                    })?)
+            }
+            ("prompt", Value::String(prompt)) => self.prompt = Some(prompt),
+            ("theme", Value::String(theme)) => self.theme = Some(theme),
+            ("history_size", Value::Integer(size)) if size >= 0 => {
+                self.history_size = Some(size as usize)
+            }
+            ("history_size", value) => return expected(key, "a positive integer", &value),
+            ("history_file", Value::String(path)) => self.history_file = Some(path),
+            ("preload", Value::String(path)) => self.preload = vec![path],
+            ("preload", Value::Array(paths)) => {
+                self.preload = paths
+                    .into_iter()
+                    .map(|path| match path {
+                        Value::String(path) => Ok(path),
+                        value => expected(key, "an array of strings", &value),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            ("preload", value) => return expected(key, "an array of paths", &value),
+            (key, value) if KEYS.contains(&key) => return expected(key, "a string", &value),
+            (key, _) => {
+                let message = format!("unknown key {}", key);
+                return Err(did_you_mean(message, key, KEYS));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Where the configuration is read from: `config.toml` in the `rexpr`
+    /// directory of `$XDG_CONFIG_HOME`, or of `~/.config` when that is not
+    /// set to an absolute path.
+    ///
+    pub fn path() -> Option<PathBuf> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| {
+                let home = std::env::var_os("HOME").filter(|home| !home.is_empty())?;
+                Some(PathBuf::from(home).join(".config"))
+            })?;
+        Some(config.join("rexpr").join("config.toml"))
+    }
+
+    /// The configuration at `path`, or the default if there is no such file.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|err| ConfigError {
+                message: format!("{}: {}", path.display(), err.message),
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(ConfigError {
+                message: format!("cannot read {}: {}", path.display(), err),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        Config::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_values() {
+        let config = Config::parse(
+            "# defaults\n\nprecision = \"auto\"\nprompt = \"\\\"x\\\" # > \"  # quoted\nhistory_size = 1_000\nhistory_file = \"h.txt\"\npreload = \"a.rx\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.precision, Some(FloatFormat::Shortest));
+        assert_eq!(config.prompt.as_deref(), Some("\"x\" # > "));
+        assert_eq!(config.history_size, Some(1000));
+        assert_eq!(config.history_file.as_deref(), Some("h.txt"));
+        assert_eq!(config.preload, ["a.rx"]);
+        assert_eq!(config.angle, None);
+        assert_eq!(Config::parse("preload = []").unwrap().preload.len(), 0)
+    }
+
+    #[test]
+    fn report_errors() {
+        assert_eq!(
+            error("\nprecison = 3"),
+            "ConfigError: line 2: unknown key precison, did you mean precision?"
+        );
+        assert_eq!(
+            error("precision = -1"),
+            "ConfigError: line 1: precision must be a number of digits or \"auto\", got an integer"
+        );
+        assert_eq!(
+            error("angle = \"grad\""),
+            "ConfigError: line 1: angle must be \"rad\" or \"deg\", got \"grad\""
+        );
+        assert_eq!(
+            error("prompt = 1"),
+            "ConfigError: line 1: prompt must be a string, got an integer"
+        );
+        assert_eq!(
+            error("theme = \"dark\"\ntheme = \"light\""),
+            "ConfigError: line 2: theme is set twice"
+        );
+        assert_eq!(
+            error("preload = [\"a\", 2]"),
+            "ConfigError: line 1: preload must be an array of strings, got an integer"
+        );
+        assert_eq!(error("[repl]"), "ConfigError: line 1: expected key = value");
+        assert_eq!(
+            error("prompt = \"a\" b"),
+            "ConfigError: line 1: unexpected character b after the value"
+        );
+        assert_eq!(
+            error("prompt = \"a"),
+            "ConfigError: line 1: unterminated string"
+        )
+    }
+}
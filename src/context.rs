@@ -0,0 +1,517 @@
+//!
+//! Variables and functions supplied by the host application, used by
+//! `Eval::eval_with`.
+//!
+//! A `Context` is `Send + Sync`, so one built up front can be put in an
+//! `Arc` and read by many threads at once. Each thread can layer its own
+//! variables on top with [`Context::extend`] instead of cloning it:
+//!
+//! ```
+//! use rexpr::context::Context;
+//! use rexpr::eval::{Eval, Value};
+//! use std::sync::Arc;
+//!
+//! let mut rates = Context::new();
+//! rates.set("rate", 0.25);
+//! rates.register_fn("round", 1, |args| Ok(Value::Int(args[0].as_f64().round() as i32)));
+//! let rates = Arc::new(rates);
+//! let node = "round(amount * rate)".parse().unwrap();
+//!
+//! let totals: Vec<Value> = std::thread::scope(|s| {
+//!     let workers: Vec<_> = (1..=4)
+//!         .map(|i| {
+//!             let (rates, node) = (&rates, &node);
+//!             s.spawn(move || {
+//!                 let mut ctx = Context::extend(rates.clone());
+//!                 ctx.set("amount", i * 100);
+//!                 Eval::new().eval_with(node, &ctx).unwrap()
+//!             })
+//!         })
+//!         .collect();
+//!     workers.into_iter().map(|w| w.join().unwrap()).collect()
+//! });
+//! assert_eq!(totals, [25, 50, 75, 100].map(Value::Int));
+//! ```
+//!
+use crate::diagnostic::did_you_mean;
+use crate::error::ErrorCode;
+use crate::eval::{EvalError, Value};
+use crate::prelude::*;
+use crate::Map;
+use alloc::sync::Arc;
+
+/// A function registered with [`Context::register_fn`], taking its arguments
+/// in call order.
+pub type NativeFn = Arc<dyn Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync>;
+
+/// A callback set with [`Context::on_missing_var`].
+pub type Resolver = Arc<dyn Fn(&str) -> Option<Value> + Send + Sync>;
+
+#[derive(Clone)]
+struct Function {
+    /// `None` for functions taking any number of arguments.
+    arity: Option<usize>,
+    f: NativeFn,
+}
+
+///
+/// A set of named values and functions for expressions to use:
+///
+/// ```
+/// use rexpr::context::Context;
+/// use rexpr::eval::{Eval, Value};
+///
+/// let mut ctx = Context::new();
+/// ctx.set("price", 10.5);
+/// ctx.set("qty", 2);
+/// ctx.register_fn("discount", 2, |args| {
+///     Ok(Value::Float(args[0].as_f64() * (1.0 - args[1].as_f64() / 100.0)))
+/// });
+/// let node = "discount(price * qty, 50)".parse().unwrap();
+/// assert_eq!(Eval::new().eval_with(&node, &ctx).unwrap(), Value::Float(10.5));
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct Context {
+    vars: Map<String, Value>,
+    functions: Map<String, Function>,
+    on_missing_var: Option<Resolver>,
+    /// Paths such as `math::*` searched for unqualified function names.
+    imports: Vec<String>,
+    /// Consulted for anything not found here.
+    base: Option<Arc<Context>>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// An empty context on top of `base`: variables, functions and the
+    /// missing-variable callback set here shadow those of `base`, and
+    /// anything else is looked up in `base`, which is shared rather than
+    /// copied and never changed.
+    ///
+    pub fn extend(base: Arc<Context>) -> Self {
+        Context {
+            base: Some(base),
+            ..Self::default()
+        }
+    }
+
+    /// The context this one extends, if any.
+    pub fn base(&self) -> Option<&Arc<Context>> {
+        self.base.as_ref()
+    }
+
+    /// This context followed by the contexts it extends, innermost first.
+    fn layers(&self) -> impl Iterator<Item = &Context> {
+        core::iter::successors(Some(self), |ctx| ctx.base.as_deref())
+    }
+
+    /// Binds `name` to `value`, returning the value it replaced.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        self.vars.insert(name.into(), value.into())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.layers().find_map(|ctx| ctx.vars.get(name).copied())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.vars.remove(name)
+    }
+
+    /// The variables set on this context, without those of its base.
+    pub fn vars(&self) -> &Map<String, Value> {
+        &self.vars
+    }
+
+    /// Names of all the variables visible here, in no particular order.
+    pub(crate) fn var_names(&self) -> impl Iterator<Item = &String> {
+        self.layers().enumerate().flat_map(move |(depth, ctx)| {
+            ctx.vars.keys().filter(move |name| {
+                // Skip names shadowed by an inner layer.
+                self.layers()
+                    .take(depth)
+                    .all(|inner| !inner.vars.contains_key(*name))
+            })
+        })
+    }
+
+    ///
+    /// Sets a callback asked for the value of any variable that is neither
+    /// set here nor known to the evaluator, so values can be fetched lazily
+    /// instead of set up front. Returning `None` leaves the variable
+    /// undefined. The callback is asked at most once per name and evaluation.
+    ///
+    pub fn on_missing_var(
+        &mut self,
+        resolve: impl Fn(&str) -> Option<Value> + Send + Sync + 'static,
+    ) {
+        self.on_missing_var = Some(Arc::new(resolve));
+    }
+
+    pub(crate) fn resolve_missing(&self, name: &str) -> Option<Value> {
+        self.layers()
+            .find_map(|ctx| ctx.on_missing_var.as_ref())
+            .and_then(|resolve| resolve(name))
+    }
+
+    ///
+    /// Makes `f` callable as `name(...)` with exactly `arity` arguments,
+    /// replacing any function registered under the same name. An error
+    /// returned by `f` fails the whole evaluation.
+    ///
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        f: impl Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync + 'static,
+    ) {
+        let f = Arc::new(f);
+        let arity = Some(arity);
+        self.functions.insert(name.into(), Function { arity, f });
+    }
+
+    /// Like [`register_fn`](Context::register_fn), for a function taking
+    /// any number of arguments; `f` checks how many it got.
+    pub fn register_variadic(
+        &mut self,
+        name: impl Into<String>,
+        f: impl Fn(&[Value]) -> Result<Value, EvalError> + Send + Sync + 'static,
+    ) {
+        let f = Arc::new(f);
+        let arity = None;
+        self.functions.insert(name.into(), Function { arity, f });
+    }
+
+    /// The number of arguments `name` takes, if it is registered with a fixed
+    /// number.
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        self.resolve(name, &[]).and_then(|function| function.arity)
+    }
+
+    fn function(&self, name: &str) -> Option<&Function> {
+        self.layers().find_map(|ctx| ctx.functions.get(name))
+    }
+
+    ///
+    /// Makes the functions under `path` callable by their unqualified name:
+    /// every function in a namespace for a glob such as `math::*`, or a
+    /// single one for a path such as `math::sin`. Names registered without a
+    /// namespace, and paths imported first, take precedence.
+    ///
+    pub fn import(&mut self, path: impl Into<String>) {
+        let path = path.into();
+        if !self.imports.contains(&path) {
+            self.imports.push(path);
+        }
+    }
+
+    /// The imported paths, including those of the base, in the order they
+    /// are searched.
+    pub fn imports(&self) -> impl Iterator<Item = &str> {
+        self.layers()
+            .flat_map(|ctx| ctx.imports.iter().map(String::as_str))
+    }
+
+    ///
+    /// The function `name` refers to: the one registered under that exact
+    /// name, or else the first match among `scripted`, the paths a script
+    /// imports with `use`, and then the context's own imports.
+    ///
+    fn resolve(&self, name: &str, scripted: &[String]) -> Option<&Function> {
+        if let Some(function) = self.function(name) {
+            return Some(function);
+        }
+        if name.contains("::") {
+            return None;
+        }
+        let mut imports = scripted.iter().map(String::as_str).chain(self.imports());
+        imports.find_map(|path| match path.strip_suffix("::*") {
+            Some(namespace) => self.function(&format!("{}::{}", namespace, name)),
+            None if path.rsplit("::").next() == Some(name) => self.function(path),
+            None => None,
+        })
+    }
+
+    /// Names of the registered functions, including those of the base, in no
+    /// particular order.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.layers().enumerate().flat_map(move |(depth, ctx)| {
+            ctx.functions
+                .keys()
+                .map(String::as_str)
+                .filter(move |name| {
+                    self.layers()
+                        .take(depth)
+                        .all(|inner| !inner.functions.contains_key(*name))
+                })
+        })
+    }
+
+    ///
+    /// Names a call can use: those of the registered functions, and the
+    /// unqualified names of the imported ones, in no particular order.
+    ///
+    pub fn callable(&self) -> impl Iterator<Item = &str> {
+        // Each short name once, for the function it resolves to.
+        let short = self.functions().filter_map(|name| {
+            let (_, short) = name.rsplit_once("::")?;
+            let resolved = self.resolve(short, &[])?;
+            core::ptr::eq(resolved, self.function(name)?).then_some(short)
+        });
+        self.functions().chain(short)
+    }
+
+    /// Calls the function registered as `name`, or imported under that name,
+    /// checking the number of arguments first.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value, EvalError> {
+        self.call_importing(name, args, &[])
+    }
+
+    /// Like [`call`](Context::call), also searching the paths a script
+    /// imports.
+    pub(crate) fn call_importing(
+        &self,
+        name: &str,
+        args: &[Value],
+        imports: &[String],
+    ) -> Result<Value, EvalError> {
+        let function = self.resolve(name, imports).ok_or_else(|| {
+            let message = format!("undefined function {}", name);
+            // Imported functions are suggested by the name they are called by.
+            let short = self
+                .functions()
+                .filter_map(|name| name.rsplit_once("::").map(|(_, short)| short))
+                .filter(|short| self.resolve(short, imports).is_some());
+            let message = did_you_mean(message, name, self.functions().chain(short));
+            EvalError::with_code(ErrorCode::UndefinedFunction, message)
+        })?;
+        match function.arity {
+            Some(arity) if args.len() != arity => {
+                return Err(EvalError::with_code(
+                    ErrorCode::ArgumentCount,
+                    format!("{} expects {} arguments, got {}", name, arity, args.len()),
+                ))
+            }
+            _ => {}
+        }
+        (function.f)(args)
+    }
+}
+
+impl core::fmt::Debug for Context {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut functions: Vec<&str> = self.functions().collect();
+        functions.sort();
+        f.debug_struct("Context")
+            .field("vars", &self.vars)
+            .field("functions", &functions)
+            .field("on_missing_var", &self.on_missing_var.is_some())
+            .field("imports", &self.imports)
+            .field("base", &self.base)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    #[test]
+    fn set_and_replace() {
+        let mut ctx = Context::new();
+        assert_eq!(ctx.set("rate", 3), None);
+        assert_eq!(ctx.set("rate", 0.5), Some(Value::Int(3)));
+        assert_eq!(ctx.get("rate"), Some(Value::Float(0.5)));
+        assert_eq!(ctx.remove("rate"), Some(Value::Float(0.5)));
+        assert!(ctx.vars().is_empty())
+    }
+
+    #[test]
+    fn call_functions() {
+        let mut ctx = Context::new();
+        ctx.register_fn("max", 2, |args| {
+            Ok(if args[0].as_f64() >= args[1].as_f64() {
+                args[0]
+            } else {
+                args[1]
+            })
+        });
+        ctx.register_fn("answer", 0, |_| Ok(Value::Int(42)));
+        ctx.set("floor", 5);
+        ctx.set("half", 0.5);
+        ctx.set("rate", 2);
+        let eval = |text: &str| Eval::new().eval_with(&text.parse().unwrap(), &ctx);
+        assert_eq!(eval("max(floor, 3) * 2").unwrap(), Value::Int(10));
+        assert_eq!(eval("max(answer(), half)").unwrap(), Value::Int(42));
+        assert_eq!(ctx.arity("max"), Some(2));
+
+        let err = eval("max(1)").unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: max expects 2 arguments, got 1");
+        let err = eval("min(1, 2)").unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined function min");
+        let err = eval("maxx(1, 2)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: undefined function maxx, did you mean max?"
+        );
+        let err = eval("rte * 2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: undefined variable rte, did you mean rate?"
+        )
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn resolve_missing_variables() {
+        let asked = Arc::new(std::sync::Mutex::new(vec![]));
+        let mut ctx = Context::new();
+        ctx.set("qty", 3);
+        let log = asked.clone();
+        ctx.on_missing_var(move |name| {
+            log.lock().unwrap().push(name.to_string());
+            name.strip_prefix("env_").map(|_| Value::Int(7))
+        });
+        let mut eval = Eval::new();
+        eval.eval(&"fee = 1".parse().unwrap()).unwrap();
+        let node = "env_rate * qty + env_rate + fee".parse().unwrap();
+        assert_eq!(eval.eval_with(&node, &ctx).unwrap(), Value::Int(29));
+        let err = eval
+            .eval_with(&"env_rate + other".parse().unwrap(), &ctx)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined variable other");
+        assert_eq!(*asked.lock().unwrap(), ["env_rate", "env_rate", "other"]);
+    }
+
+    #[test]
+    fn extend_shared_context() {
+        let mut base = Context::new();
+        base.set("rate", 2);
+        base.set("fee", 1);
+        base.register_fn("double", 1, |args| {
+            Ok(Value::Int(args[0].as_f64() as i32 * 2))
+        });
+        base.on_missing_var(|_| Some(Value::Int(100)));
+        let base = Arc::new(base);
+        let mut ctx = Context::extend(base.clone());
+        ctx.set("rate", 3);
+        ctx.register_fn("half", 1, |args| Ok(Value::Float(args[0].as_f64() / 2.0)));
+        let eval = |text: &str| Eval::new().eval_with(&text.parse().unwrap(), &ctx);
+        assert_eq!(eval("double(rate) + fee + other").unwrap(), Value::Int(107));
+        assert_eq!(eval("half(rate)").unwrap(), Value::Float(1.5));
+        assert_eq!(base.get("rate"), Some(Value::Int(2)));
+        assert!(base.arity("half").is_none());
+
+        let mut functions: Vec<&str> = ctx.functions().collect();
+        functions.sort();
+        assert_eq!(functions, ["double", "half"]);
+        let mut names: Vec<&String> = ctx.var_names().collect();
+        names.sort();
+        assert_eq!(names, ["fee", "rate"]);
+        let err = eval("dubble(1)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: undefined function dubble, did you mean double?"
+        )
+    }
+
+    #[test]
+    fn import_namespaces() {
+        let mut ctx = Context::new();
+        ctx.register_fn("geo::area", 2, |args| {
+            Ok(Value::Float(args[0].as_f64() * args[1].as_f64()))
+        });
+        ctx.register_fn("geo::scale", 1, |args| Ok(args[0]));
+        ctx.register_fn("fin::scale", 1, |args| {
+            Ok(Value::Float(args[0].as_f64() * 100.0))
+        });
+        let eval = |ctx: &Context, text: &str| Eval::new().eval_with(&text.parse().unwrap(), ctx);
+        assert_eq!(eval(&ctx, "geo::area(2, 3)").unwrap(), Value::Float(6.0));
+        let err = eval(&ctx, "area(2, 3)").unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined function area");
+        assert_eq!(
+            eval(&ctx, "use geo::*; area(2, 3)").unwrap(),
+            Value::Float(6.0)
+        );
+        assert_eq!(
+            eval(&ctx, "s = scale(2); use fin::scale; use geo::*; s").unwrap(),
+            Value::Float(200.0)
+        );
+
+        ctx.import("geo::*");
+        assert_eq!(ctx.arity("area"), Some(2));
+        let mut callable: Vec<&str> = ctx.callable().collect();
+        callable.sort_unstable();
+        assert_eq!(
+            callable,
+            ["area", "fin::scale", "geo::area", "geo::scale", "scale"]
+        );
+        assert_eq!(eval(&ctx, "scale(2)").unwrap(), Value::Int(2));
+        assert_eq!(
+            eval(&ctx, "use fin::*; scale(2)").unwrap(),
+            Value::Float(200.0)
+        );
+        let err = eval(&ctx, "aria(1, 2)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: undefined function aria, did you mean area?"
+        );
+        let err = eval(&ctx, "geo::area(1)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: geo::area expects 2 arguments, got 1"
+        )
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn share_between_threads() {
+        use crate::parser::Node;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Context>();
+        assert_send_sync::<Node>();
+        assert_send_sync::<Value>();
+        assert_send_sync::<Eval>();
+
+        let mut ctx = Context::new();
+        ctx.register_fn("square", 1, |args| {
+            Ok(Value::Int((args[0].as_f64() as i32).pow(2)))
+        });
+        let ctx = Arc::new(ctx);
+        let node: Arc<Node> = Arc::new("square(x) + 1".parse().unwrap());
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let (ctx, node) = (ctx.clone(), node.clone());
+                std::thread::spawn(move || {
+                    let mut local = Context::extend(ctx);
+                    local.set("x", i);
+                    Eval::new().eval_with(&node, &local).unwrap()
+                })
+            })
+            .collect();
+        let results: Vec<Value> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(results, [1, 2, 5, 10].map(Value::Int))
+    }
+
+    #[test]
+    fn propagate_errors() {
+        let mut ctx = Context::new();
+        ctx.register_fn("sqrt", 1, |args| match args[0].as_f64() {
+            x if x < 0.0 => Err(EvalError::new(format!("sqrt of negative number {}", x))),
+            x => Ok(Value::Float(x.sqrt())),
+        });
+        let node = "sqrt(1 - 5) + 1".parse().unwrap();
+        let err = Eval::new().eval_with(&node, &ctx).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: sqrt of negative number -4");
+        let node = "sqrt(1 / 0)".parse().unwrap();
+        let err = Eval::new().eval_with(&node, &ctx).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: division by zero")
+    }
+}
@@ -0,0 +1,227 @@
+//!
+//! Rendering errors against the source they came from, with the offending
+//! line and a caret under the span:
+//!
+//! ```text
+//! error: unexpected token )
+//!  --> line 1, col 5
+//!   |
+//! 1 | 1 + ) * 2
+//!   |     ^ expected a number, a variable or `(`
+//! ```
+//!
+use crate::error::RexprError;
+use crate::eval::EvalError;
+use crate::parser::ParserError;
+use crate::prelude::*;
+use crate::tokenizer::{Span, TokenizerError};
+use core::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    /// Shown next to the caret.
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    ///
+    /// Renders the diagnostic against `source`, the whole input its span
+    /// points into. Without a span, or with one that does not fit `source`,
+    /// only the message is shown.
+    ///
+    pub fn render(&self, source: &str) -> String {
+        let mut out = format!("error: {}", self.message);
+        let span = match self.span {
+            Some(span) if span.offset <= source.len() => span,
+            _ => return out,
+        };
+        let start = source[..span.offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = source[span.offset..]
+            .find('\n')
+            .map_or(source.len(), |i| span.offset + i);
+        let line = &source[start..end];
+        // Keep tabs so the caret lines up with the text above it.
+        let indent: String = source[start..span.offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = source[span.offset..(span.offset + span.len).min(end)]
+            .chars()
+            .count()
+            .max(1);
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let _ = write!(
+            out,
+            "\n{}--> line {}, col {}\n{} |\n{} | {}\n{} | {}{}",
+            gutter,
+            span.line,
+            span.col,
+            gutter,
+            number,
+            line,
+            gutter,
+            indent,
+            "^".repeat(width)
+        );
+        if let Some(hint) = &self.hint {
+            let _ = write!(out, " {}", hint);
+        }
+        out
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`, counting characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+///
+/// The candidate closest to `name`, if any is close enough to be a likely
+/// typo: within one edit for short names and a third of the length for
+/// longer ones, without replacing every character. Ties go to the
+/// alphabetically first candidate.
+///
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, candidate)| {
+            *distance <= limit && *distance < name.len().max(candidate.len())
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// `message` followed by a suggestion of the closest candidate to `name`.
+pub(crate) fn did_you_mean<'a>(
+    message: String,
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> String {
+    match suggest(name, candidates) {
+        Some(candidate) => format!("{}, did you mean {}?", message, candidate),
+        None => message,
+    }
+}
+
+impl From<&TokenizerError> for Diagnostic {
+    fn from(err: &TokenizerError) -> Self {
+        Diagnostic {
+            message: err.message.clone(),
+            span: Some(err.span()),
+            hint: None,
+        }
+    }
+}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(err: &ParserError) -> Self {
+        Diagnostic {
+            message: err.message().to_string(),
+            span: err.span(),
+            hint: err.hint().map(str::to_string),
+        }
+    }
+}
+
+impl From<&EvalError> for Diagnostic {
+    fn from(err: &EvalError) -> Self {
+        Diagnostic {
+            message: err.message().to_string(),
+            span: err.span(),
+            hint: None,
+        }
+    }
+}
+
+impl From<&RexprError> for Diagnostic {
+    fn from(err: &RexprError) -> Self {
+        match err {
+            RexprError::Tokenizer(err) => err.into(),
+            RexprError::Parser(err) => err.into(),
+            RexprError::Eval(err) => err.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+    use crate::parser::{Node, Parser};
+    use crate::tokenizer::Lexer;
+
+    fn render(source: &str) -> String {
+        let err = source.parse::<Node>().unwrap_err();
+        Diagnostic::from(&err).render(source)
+    }
+
+    #[test]
+    fn caret_under_span() {
+        assert_eq!(
+            render("1 + ) * 2"),
+            "error: unexpected token )\n \
+             --> line 1, col 5\n  \
+               |\n\
+             1 | 1 + ) * 2\n  \
+               |     ^ expected a number, a variable or `(`"
+        );
+        assert_eq!(
+            render("x = 1;\n(x * 2"),
+            "error: unknow token\n \
+             --> line 2, col 7\n  \
+               |\n\
+             2 | (x * 2\n  \
+               |       ^ expected `)`"
+        );
+        assert!(render("1 + 99999999999")
+            .ends_with("    ^^^^^^^^^^^ numbers must fit in a 32-bit integer"))
+    }
+
+    #[test]
+    fn suggest_close_names() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        let names = ["price", "qty", "sqrt", "pi"];
+        assert_eq!(suggest("prise", names), Some("price"));
+        assert_eq!(suggest("sqr", names), Some("sqrt"));
+        assert_eq!(suggest("p", names), Some("pi"));
+        assert_eq!(suggest("total", names), None);
+        assert_eq!(suggest("x", ["y", "z"]), None);
+        assert_eq!(suggest("ab", ["ad", "ac"]), Some("ac"))
+    }
+
+    #[test]
+    fn underline_failed_subexpression() {
+        let source = "x = 3;\n\ty = 6 / (x - x)";
+        let (node, spans) = Parser::from_lexer(Lexer::new(source))
+            .parse_spanned()
+            .unwrap();
+        let mut err = Eval::new().eval(&node).unwrap_err();
+        err.locate(&spans);
+        let rendered = Diagnostic::from(&err).render(source);
+        assert!(rendered.starts_with("error: division by zero\n --> line 2, col 6"));
+        assert!(rendered.ends_with("2 | \ty = 6 / (x - x)\n  | \t    ^^^^^^^^^^^"));
+        let err = Eval::new().eval(&node).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&err).render(source),
+            "error: division by zero"
+        )
+    }
+}
@@ -0,0 +1,137 @@
+//!
+//! Graphviz DOT export of the AST, and the same tree drawn as indented text
+//! for terminals.
+//!
+use crate::parser::*;
+use std::fmt::Write;
+
+fn label(node: &Node) -> String {
+    match node {
+        Node::Number(n) => n.to_string(),
+        Node::Float(_) => node.to_string(),
+        Node::Identifier(name) => name.clone(),
+        Node::UnaryExpr { op, .. } => op.to_string(),
+        Node::BinaryExpr { op, .. } => op.to_string(),
+        Node::Assign { name, .. } => format!("{} =", name),
+        Node::Block(_) => ";".to_string(),
+        Node::Call { name, .. } => format!("{}()", name),
+        Node::Use(path) => format!("use {}", path),
+    }
+}
+
+fn children(node: &Node) -> Vec<&Node> {
+    match node {
+        Node::Number(_) | Node::Float(_) | Node::Identifier(_) | Node::Use(_) => vec![],
+        Node::UnaryExpr { child, .. } => vec![child],
+        Node::BinaryExpr { lhs, rhs, .. } => vec![lhs, rhs],
+        Node::Assign { value, .. } => vec![value],
+        Node::Block(stmts) | Node::Call { args: stmts, .. } => stmts.iter().collect(),
+    }
+}
+
+fn write_node(out: &mut String, node: &Node, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let shape = match node {
+        Node::Number(_) | Node::Float(_) | Node::Identifier(_) => "box",
+        _ => "ellipse",
+    };
+    let label = label(node).replace('\\', "\\\\").replace('"', "\\\"");
+    writeln!(out, "    n{} [label=\"{}\", shape={}];", id, label, shape).unwrap();
+    for child in children(node) {
+        let child_id = write_node(out, child, next_id);
+        writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+    }
+    id
+}
+
+/// Writes the children of a node, each line after `prefix`.
+fn write_children(out: &mut String, node: &Node, prefix: &str) {
+    let children = children(node);
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        writeln!(out, "{}{}{}", prefix, branch, label(child)).unwrap();
+        write_children(out, child, &format!("{}{}", prefix, indent));
+    }
+}
+
+impl Node {
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph ast {\n");
+        write_node(&mut out, self, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    /// The tree drawn with one node per line, children indented under
+    /// their parent.
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{}", label(self)).unwrap();
+        write_children(&mut out, self, "");
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn expr_to_dot() {
+        let tokenizer = Tokenizer::new("1 + -x");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            expr.to_dot(),
+            concat!(
+                "digraph ast {\n",
+                "    n0 [label=\"+\", shape=ellipse];\n",
+                "    n1 [label=\"1\", shape=box];\n",
+                "    n0 -> n1;\n",
+                "    n2 [label=\"-\", shape=ellipse];\n",
+                "    n3 [label=\"x\", shape=box];\n",
+                "    n2 -> n3;\n",
+                "    n0 -> n2;\n",
+                "}\n"
+            )
+        )
+    }
+
+    #[test]
+    fn statements_to_dot() {
+        let tokenizer = Tokenizer::new("x = 1; x");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        let dot = expr.to_dot();
+        assert!(dot.contains("n0 [label=\";\", shape=ellipse];"));
+        assert!(dot.contains("n1 [label=\"x =\", shape=ellipse];"));
+        assert!(dot.contains("n0 -> n3;"));
+    }
+
+    #[test]
+    fn statements_to_tree() {
+        let tokens = Tokenizer::new("x = 1 + -y; max(x, 2)").tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            expr.to_tree(),
+            concat!(
+                ";\n",
+                "├── x =\n",
+                "│   └── +\n",
+                "│       ├── 1\n",
+                "│       └── -\n",
+                "│           └── y\n",
+                "└── max()\n",
+                "    ├── x\n",
+                "    └── 2\n",
+            )
+        )
+    }
+}
@@ -0,0 +1,208 @@
+//!
+//! Checking whether two expressions compute the same function.
+//!
+use crate::compile::{compile, Program, Vm};
+use crate::eval::EvalError;
+use crate::parser::*;
+use std::error::Error;
+use std::fmt::Display;
+
+/// An input on which two expressions disagree, with what each computed.
+#[derive(Debug)]
+pub struct Counterexample {
+    inputs: Vec<(String, i32)>,
+    lhs: Result<i32, EvalError>,
+    rhs: Result<i32, EvalError>,
+}
+
+impl Counterexample {
+    /// The value of every free variable of either expression.
+    pub fn inputs(&self) -> &[(String, i32)] {
+        &self.inputs
+    }
+
+    pub fn lhs(&self) -> &Result<i32, EvalError> {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &Result<i32, EvalError> {
+        &self.rhs
+    }
+}
+
+fn outcome(result: &Result<i32, EvalError>) -> String {
+    match result {
+        Ok(value) => value.to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inputs: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        write!(
+            f,
+            "{} gives {} and {}",
+            inputs.join(", "),
+            outcome(&self.lhs),
+            outcome(&self.rhs)
+        )
+    }
+}
+
+impl Error for Counterexample {}
+
+///
+/// Settings for [`equivalent`]: how many inputs to try and the seed of the
+/// generator that draws them. The same seed always tries the same inputs.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Equivalence {
+    pub samples: u32,
+    pub seed: u64,
+}
+
+impl Default for Equivalence {
+    fn default() -> Self {
+        Equivalence {
+            samples: 1000,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// SplitMix64, enough to spread inputs without a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A value in `-bound..=bound`.
+    fn between(&mut self, bound: i32) -> i32 {
+        (self.next() % (2 * bound as u64 + 1)) as i32 - bound
+    }
+}
+
+fn run(program: &Program, inputs: &[(String, i32)]) -> Result<i32, EvalError> {
+    let mut vars: Vec<i32> = program
+        .bindings()
+        .names()
+        .iter()
+        .map(
+            |name| match inputs.iter().find(|(input, _)| input == name) {
+                Some((_, value)) => *value,
+                None => 0,
+            },
+        )
+        .collect();
+    Vm::new().run(program, &mut vars)
+}
+
+impl Equivalence {
+    ///
+    /// Checks that `a` and `b` compute the same value, or fail alike, for
+    /// every input tried. Expressions with equal canonical forms are
+    /// equivalent outright; otherwise both are evaluated with every variable
+    /// set to 0, then 1, then -1, and then on `samples` random inputs, mostly
+    /// small and otherwise within ±1000.
+    ///
+    /// Sampling cannot prove equivalence: passing means no difference was
+    /// found, not that none exists.
+    ///
+    pub fn check(&self, a: &Node, b: &Node) -> Result<(), Counterexample> {
+        if a.canonicalize() == b.canonicalize() {
+            return Ok(());
+        }
+        let mut names = a.free_variables();
+        for name in b.free_variables() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let (lhs, rhs) = (compile(a), compile(b));
+        let mut rng = Rng(self.seed);
+        let fixed = [0, 1, -1].into_iter().map(|value| vec![value; names.len()]);
+        let random = (0..self.samples).map(|_| {
+            let bound = if rng.next() % 5 < 3 { 10 } else { 1000 };
+            (0..names.len()).map(|_| rng.between(bound)).collect()
+        });
+        for values in fixed.chain(random) {
+            let inputs: Vec<(String, i32)> = names.iter().cloned().zip(values).collect();
+            let (l, r) = (run(&lhs, &inputs), run(&rhs, &inputs));
+            let agree = match (&l, &r) {
+                (Ok(l), Ok(r)) => l == r,
+                (Err(l), Err(r)) => l.to_string() == r.to_string(),
+                _ => false,
+            };
+            if !agree {
+                return Err(Counterexample {
+                    inputs,
+                    lhs: l,
+                    rhs: r,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks `a` and `b` for equivalence with the default [`Equivalence`]
+/// settings.
+pub fn equivalent(a: &Node, b: &Node) -> Result<(), Counterexample> {
+    Equivalence::default().check(a, b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(a: &str, b: &str) -> Result<(), Counterexample> {
+        equivalent(&a.parse().unwrap(), &b.parse().unwrap())
+    }
+
+    #[test]
+    fn equivalent_expressions() {
+        assert!(check("price * qty + fee", "fee + qty * price").is_ok());
+        assert!(check("(x + 1) * (x - 1)", "x * x - 1").is_ok());
+        assert!(check("x * 8", "x << 3").is_ok());
+        assert!(check("a && b", "!(!a || !b)").is_ok())
+    }
+
+    #[test]
+    fn counterexample() {
+        let err = check("price * qty / 100", "price / 100 * qty").unwrap_err();
+        let (l, r) = (err.lhs().as_ref().unwrap(), err.rhs().as_ref().unwrap());
+        assert_ne!(l, r);
+        assert_eq!(err.inputs().len(), 2);
+
+        let err = check("x + 1", "x").unwrap_err();
+        assert_eq!(err.to_string(), "x = 0 gives 1 and 0")
+    }
+
+    #[test]
+    fn errors_must_match() {
+        assert!(check("a / b", "a / b + 0").is_ok());
+        let err = check("a / b * b", "a").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a = 0, b = 0 gives EvalError: division by zero and 0"
+        )
+    }
+
+    #[test]
+    fn variables_of_either_side() {
+        let err = check("total", "total + tip").unwrap_err();
+        assert_eq!(err.inputs()[1].0, "tip");
+        assert!(check("1", "2 - 1").is_ok())
+    }
+}
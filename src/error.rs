@@ -0,0 +1,169 @@
+use crate::eval::EvalError;
+use crate::parser::ParserError;
+use crate::tokenizer::{Span, TokenizerError};
+use core::error::Error;
+use core::fmt::Display;
+
+///
+/// A stable code for each kind of error, for tools to match on instead of
+/// the English message, which may change. `E00xx` codes are syntax errors
+/// and `E01xx` evaluation errors; a code is never reused for another kind.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// `E0001`: a `(` without its `)`.
+    UnclosedParen,
+    /// `E0002`: a token that cannot appear where it does.
+    UnexpectedToken,
+    /// `E0003`: the input ends in the middle of an expression.
+    UnexpectedEnd,
+    /// `E0004`: a number literal that does not fit in an `i32`.
+    NumberTooLarge,
+    /// `E0005`: an assignment to a placeholder such as `$1`.
+    AssignToPlaceholder,
+    /// `E0006`: a character that starts no token.
+    UnknownSymbol,
+    /// `E0007`: `$` without a number from 1 up.
+    InvalidPlaceholder,
+    /// `E0008`: a `/*` comment without its `*/`.
+    UnterminatedComment,
+    /// `E0009`: the input could not be read.
+    Read,
+    /// `E0010`: a request to the JSON server in [`crate::rpc`] that is not
+    /// an object with an `expr` string.
+    InvalidRequest,
+    /// `E0011`: an expression nested deeper than the parser allows.
+    TooDeep,
+    /// `E0100`: an error reported through [`EvalError::new`], e.g. by a host
+    /// function.
+    Other,
+    /// `E0101`: a variable that is neither bound nor assigned.
+    UndefinedVariable,
+    /// `E0102`: integer division by zero.
+    DivisionByZero,
+    /// `E0103`: an integer result out of range under `Overflow::Checked`.
+    IntegerOverflow,
+    /// `E0104`: an integer raised to a negative power.
+    NegativeExponent,
+    /// `E0105`: a shift by a negative amount or by the width or more.
+    ShiftOutOfRange,
+    /// `E0106`: an operator that takes integers applied to a float.
+    IntegerOperands,
+    /// `E0107`: a call to a function that is not registered.
+    UndefinedFunction,
+    /// `E0108`: a call or evaluation with the wrong number of arguments.
+    ArgumentCount,
+    /// `E0109`: values for an expression's variables that do not match them.
+    InvalidBinding,
+    /// `E0110`: an expression with too many variables for a truth table.
+    TooManyVariables,
+    /// `E0111`: an evaluation stopped through its
+    /// [`Cancel`](crate::eval::Cancel).
+    Cancelled,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::UnclosedParen => "E0001",
+            ErrorCode::UnexpectedToken => "E0002",
+            ErrorCode::UnexpectedEnd => "E0003",
+            ErrorCode::NumberTooLarge => "E0004",
+            ErrorCode::AssignToPlaceholder => "E0005",
+            ErrorCode::UnknownSymbol => "E0006",
+            ErrorCode::InvalidPlaceholder => "E0007",
+            ErrorCode::UnterminatedComment => "E0008",
+            ErrorCode::Read => "E0009",
+            ErrorCode::InvalidRequest => "E0010",
+            ErrorCode::TooDeep => "E0011",
+            ErrorCode::Other => "E0100",
+            ErrorCode::UndefinedVariable => "E0101",
+            ErrorCode::DivisionByZero => "E0102",
+            ErrorCode::IntegerOverflow => "E0103",
+            ErrorCode::NegativeExponent => "E0104",
+            ErrorCode::ShiftOutOfRange => "E0105",
+            ErrorCode::IntegerOperands => "E0106",
+            ErrorCode::UndefinedFunction => "E0107",
+            ErrorCode::ArgumentCount => "E0108",
+            ErrorCode::InvalidBinding => "E0109",
+            ErrorCode::TooManyVariables => "E0110",
+            ErrorCode::Cancelled => "E0111",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+///
+/// Any error from reading or evaluating an expression, so that a caller
+/// stitching the stages together can use `?` throughout. `source()` gives
+/// the underlying stage error.
+///
+#[derive(Debug)]
+pub enum RexprError {
+    Tokenizer(TokenizerError),
+    Parser(ParserError),
+    Eval(EvalError),
+}
+
+impl RexprError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RexprError::Tokenizer(err) => err.code,
+            RexprError::Parser(err) => err.code(),
+            RexprError::Eval(err) => err.code(),
+        }
+    }
+
+    /// Where in the source the error was found, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RexprError::Tokenizer(err) => Some(err.span()),
+            RexprError::Parser(err) => err.span(),
+            RexprError::Eval(err) => err.span(),
+        }
+    }
+}
+
+impl Display for RexprError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RexprError::Tokenizer(err) => write!(f, "{}", err),
+            RexprError::Parser(err) => write!(f, "{}", err),
+            RexprError::Eval(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for RexprError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RexprError::Tokenizer(err) => Some(err),
+            RexprError::Parser(err) => Some(err),
+            RexprError::Eval(err) => Some(err),
+        }
+    }
+}
+
+impl From<TokenizerError> for RexprError {
+    fn from(err: TokenizerError) -> Self {
+        RexprError::Tokenizer(err)
+    }
+}
+
+impl From<ParserError> for RexprError {
+    fn from(err: ParserError) -> Self {
+        RexprError::Parser(err)
+    }
+}
+
+impl From<EvalError> for RexprError {
+    fn from(err: EvalError) -> Self {
+        RexprError::Eval(err)
+    }
+}
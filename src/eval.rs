@@ -1,33 +1,1113 @@
+#[cfg(feature = "std")]
+use crate::arena::{ArenaNode, ExprArena, NodeId};
+use crate::compile::{compile, Vm};
+use crate::context::Context;
+use crate::diagnostic::did_you_mean;
+use crate::error::ErrorCode;
 use crate::parser::*;
+use crate::prelude::*;
+use crate::tokenizer::Span;
+use crate::Map;
+use alloc::sync::Arc;
+use core::error::Error;
+use core::fmt::Display;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-pub struct Eval {}
+#[derive(Debug)]
+pub struct EvalError {
+    code: ErrorCode,
+    message: String,
+    location: Option<Box<Location>>,
+}
+
+/// The subexpression an evaluation error came from.
+#[derive(Debug)]
+struct Location {
+    expression: String,
+    /// Child indices from the subexpression up to the root.
+    path: Vec<usize>,
+    span: Option<Span>,
+}
+
+impl EvalError {
+    /// An error with `message`, e.g. for a host function to report failure.
+    /// Its code is `ErrorCode::Other`.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self::with_code(ErrorCode::Other, message)
+    }
+
+    pub fn with_code(code: ErrorCode, message: impl Into<String>) -> Self {
+        EvalError {
+            code,
+            message: message.into(),
+            location: None,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The subexpression that failed, e.g. `6 / (x - x)` for a division by
+    /// zero, when the error came from evaluating a tree.
+    pub fn expression(&self) -> Option<&str> {
+        self.location
+            .as_ref()
+            .map(|location| location.expression.as_str())
+    }
+
+    /// Where in the source the failed subexpression is, once
+    /// [`located`](EvalError::locate).
+    pub fn span(&self) -> Option<Span> {
+        self.location.as_ref().and_then(|location| location.span)
+    }
+
+    ///
+    /// Finds the failed subexpression in `spans`, the spans of the tree that
+    /// was evaluated as returned by `Parser::parse_spanned`, so the error
+    /// can point into the source.
+    ///
+    pub fn locate(&mut self, spans: &SpanTree) {
+        if let Some(location) = &mut self.location {
+            let path: Vec<usize> = location.path.iter().rev().copied().collect();
+            location.span = spans.get(&path).map(|tree| tree.span);
+        }
+    }
+
+    /// Blames `node`, unless a subexpression of it is already blamed.
+    fn at(mut self, node: &Node) -> Self {
+        if self.location.is_none() {
+            self.location = Some(Box::new(Location {
+                expression: node.to_string(),
+                path: vec![],
+                span: None,
+            }));
+        }
+        self
+    }
+
+    /// Records that the error came from child `i` of the node being
+    /// evaluated.
+    fn within(mut self, i: usize) -> Self {
+        if let Some(location) = &mut self.location {
+            location.path.push(i);
+        }
+        self
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "EvalError: {}", self.message)?;
+        let Some(location) = &self.location else {
+            return Ok(());
+        };
+        match location.span {
+            Some(span) if span.line > 1 => write!(
+                f,
+                " in `{}` at line {}, col {}",
+                location.expression, span.line, span.col
+            ),
+            Some(span) => write!(f, " in `{}` at col {}", location.expression, span.col),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+/// A runtime value. Integer arithmetic follows the configured [`Overflow`];
+/// an operation with a float operand is carried out in `f64`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+}
+
+impl Value {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(n) => n as f64,
+            Value::Float(x) => x,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+/// What integer arithmetic does when a result does not fit in an `i32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Fail with an "integer overflow" error.
+    #[default]
+    Checked,
+    /// Wrap around in two's complement.
+    Wrapping,
+    /// Clamp to `i32::MIN` or `i32::MAX`.
+    Saturating,
+}
+
+///
+/// A flag to stop evaluations from another thread, as on Ctrl-C. Clones
+/// share the flag. An evaluator given one with [`Eval::set_cancel`] checks
+/// it before each node, failing with [`ErrorCode::Cancelled`] once it is
+/// set, so a long evaluation stops between nodes but not inside a function.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the flag, for the next evaluation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed)
+    }
+}
+
+///
+/// Called around each node of an evaluation by
+/// [`eval_hooked`](Eval::eval_hooked) or
+/// [`eval_with_hooked`](Eval::eval_with_hooked), for debuggers, tracers and
+/// profilers. Nodes are visited in the order they are evaluated, each
+/// `depth` levels below the root, and `exit` is called even when the node
+/// fails. Both do nothing unless overridden.
+///
+/// ```
+/// use rexpr::eval::{Bindings, Eval, EvalError, EvalHook, Value};
+/// use rexpr::parser::Node;
+///
+/// #[derive(Default)]
+/// struct Trace(Vec<String>);
+///
+/// impl EvalHook for Trace {
+///     fn exit(&mut self, node: &Node, depth: usize, result: Result<Value, &EvalError>) {
+///         if let Ok(value) = result {
+///             self.0.push(format!("{}{} = {}", "  ".repeat(depth), node, value));
+///         }
+///     }
+/// }
+///
+/// let mut trace = Trace::default();
+/// let node: Node = "(1 + 2) * 3".parse().unwrap();
+/// assert_eq!(Eval::new().eval_hooked(&node, &mut trace).unwrap(), 9);
+/// assert_eq!(
+///     trace.0,
+///     ["    1 = 1", "    2 = 2", "  1 + 2 = 3", "  3 = 3", "(1 + 2) * 3 = 9"]
+/// );
+/// ```
+///
+pub trait EvalHook {
+    /// Called before `node` is evaluated, with the variables bound so far.
+    fn enter(&mut self, _node: &Node, _depth: usize, _bindings: &Bindings) {}
+
+    /// Called with the value of `node`, or the error evaluating it.
+    fn exit(&mut self, _node: &Node, _depth: usize, _result: Result<Value, &EvalError>) {}
+}
+
+/// The hook of plain evaluation, which does nothing.
+impl EvalHook for () {}
+
+///
+/// The variables an evaluation has bound when a hook is called: those
+/// assigned so far by [`eval_with`](Eval::eval_with), which stay local to
+/// it, and the evaluator's own. Variables of the context are not included.
+///
+pub struct Bindings<'a> {
+    locals: Option<&'a Map<String, Value>>,
+    vars: &'a Map<String, Value>,
+}
+
+impl<'a> Bindings<'a> {
+    fn new(locals: Option<&'a Map<String, Value>>, vars: &'a Map<String, Value>) -> Self {
+        Bindings { locals, vars }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let local = self.locals.and_then(|locals| locals.get(name).copied());
+        local.or_else(|| self.vars.get(name).copied())
+    }
+
+    /// Every bound variable and its value, a local one hiding the
+    /// evaluator's variable of the same name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, Value)> + '_ {
+        let locals = self.locals.into_iter().flatten();
+        let locals = locals.map(|(name, value)| (name.as_str(), *value));
+        let vars = self
+            .vars
+            .iter()
+            .filter(|(name, _)| !self.locals.is_some_and(|locals| locals.contains_key(*name)));
+        locals.chain(vars.map(|(name, value)| (name.as_str(), *value)))
+    }
+}
+
+///
+/// Counts of what an evaluation did, collected by passing one as the hook
+/// of [`eval_hooked`](Eval::eval_hooked) or
+/// [`eval_with_hooked`](Eval::eval_with_hooked). A failed evaluation counts
+/// up to the node that failed. Counts add up over evaluations until
+/// [`reset`](EvalReport::reset).
+///
+/// ```
+/// use rexpr::eval::{Eval, EvalReport};
+/// use rexpr::parser::{BinaryOperator, Node};
+///
+/// let node: Node = "x = 2; -(x + 1) * x".parse().unwrap();
+/// let mut report = EvalReport::default();
+/// Eval::new().eval_hooked(&node, &mut report).unwrap();
+/// assert_eq!(report.nodes, 9);
+/// assert_eq!(report.binary[&BinaryOperator::Plus], 1);
+/// assert_eq!(report.max_depth, 4);
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalReport {
+    /// The nodes evaluated.
+    pub nodes: usize,
+    /// The binary operations carried out, by operator.
+    pub binary: Map<BinaryOperator, usize>,
+    /// The unary operations carried out, by operator.
+    pub unary: Map<UnaryOperator, usize>,
+    /// The function calls made.
+    pub calls: usize,
+    /// How deep the deepest node evaluated is, the root being at 0.
+    pub max_depth: usize,
+}
+
+impl EvalReport {
+    pub fn reset(&mut self) {
+        *self = Self::default()
+    }
+}
+
+impl EvalHook for EvalReport {
+    fn enter(&mut self, node: &Node, depth: usize, _bindings: &Bindings) {
+        self.nodes += 1;
+        self.max_depth = self.max_depth.max(depth);
+        match node {
+            Node::BinaryExpr { op, .. } => *self.binary.entry(op.clone()).or_default() += 1,
+            Node::UnaryExpr { op, .. } => *self.unary.entry(op.clone()).or_default() += 1,
+            Node::Call { .. } => self.calls += 1,
+            _ => {}
+        }
+    }
+}
+
+/// What [`Eval::eval_with`] evaluates against.
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+    ctx: &'a Context,
+    imports: &'a [String],
+}
+
+pub struct Eval {
+    vars: Map<String, Value>,
+    overflow: Overflow,
+    cancel: Option<Cancel>,
+}
+
+impl Default for Eval {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Eval {
     pub fn new() -> Self {
-        Eval {}
+        Eval {
+            vars: Map::new(),
+            overflow: Overflow::default(),
+            cancel: None,
+        }
+    }
+
+    pub fn with_overflow(overflow: Overflow) -> Self {
+        Eval {
+            vars: Map::new(),
+            overflow,
+            cancel: None,
+        }
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn vars(&self) -> &Map<String, Value> {
+        &self.vars
+    }
+
+    /// Stops [`eval`](Eval::eval) and [`eval_with`](Eval::eval_with) once
+    /// `cancel` is set.
+    pub fn set_cancel(&mut self, cancel: Option<Cancel>) {
+        self.cancel = cancel;
+    }
+
+    pub fn cancel(&self) -> Option<&Cancel> {
+        self.cancel.as_ref()
+    }
+
+    fn check_cancel(&self) -> Result<(), EvalError> {
+        match &self.cancel {
+            Some(cancel) if cancel.is_cancelled() => Err(EvalError::with_code(
+                ErrorCode::Cancelled,
+                "evaluation cancelled",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    ///
+    /// Binds `name` to `value`, returning the value it replaced. Only
+    /// [`eval_with`](Eval::eval_with) can read a float variable; the integer
+    /// evaluation of [`eval`](Eval::eval) fails on it.
+    ///
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        self.vars.insert(name.into(), value.into())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.vars.remove(name)
+    }
+
+    fn lookup(&self, name: &str) -> Result<i32, EvalError> {
+        match self.vars.get(name) {
+            Some(value) => int_variable(name, *value),
+            None => Err(undefined_variable(name, self.vars.keys())),
+        }
+    }
+
+    pub fn eval(&mut self, node: &Node) -> Result<i32, EvalError> {
+        self.walk(node, 0, &mut ())
+    }
+
+    ///
+    /// Evaluates `node` as [`eval`](Eval::eval) does, calling `hook` before
+    /// and after each node.
+    ///
+    pub fn eval_hooked(&mut self, node: &Node, hook: &mut impl EvalHook) -> Result<i32, EvalError> {
+        self.walk(node, 0, hook)
+    }
+
+    fn walk(
+        &mut self,
+        node: &Node,
+        depth: usize,
+        hook: &mut impl EvalHook,
+    ) -> Result<i32, EvalError> {
+        self.check_cancel()?;
+        hook.enter(node, depth, &Bindings::new(None, &self.vars));
+        let result = self.step(node, depth, hook);
+        hook.exit(node, depth, result.as_ref().map(|n| Value::Int(*n)));
+        result
+    }
+
+    fn step(
+        &mut self,
+        node: &Node,
+        depth: usize,
+        hook: &mut impl EvalHook,
+    ) -> Result<i32, EvalError> {
+        match node {
+            Node::Number(n) => Ok(*n),
+            Node::Float(Float(x)) => Err(float_literal(*x).at(node)),
+            Node::Identifier(name) => self.lookup(name).map_err(|err| err.at(node)),
+            Node::UnaryExpr { op, child } => {
+                let child = self
+                    .walk(child, depth + 1, hook)
+                    .map_err(|err| err.within(0))?;
+                unary(op, child, self.overflow).map_err(|err| err.at(node))
+            }
+            Node::BinaryExpr { op, lhs, rhs } => {
+                let left_result = self
+                    .walk(lhs, depth + 1, hook)
+                    .map_err(|err| err.within(0))?;
+                let right_result = self
+                    .walk(rhs, depth + 1, hook)
+                    .map_err(|err| err.within(1))?;
+                binary(op, left_result, right_result, self.overflow).map_err(|err| err.at(node))
+            }
+            Node::Assign { name, value } => {
+                let value = self
+                    .walk(value, depth + 1, hook)
+                    .map_err(|err| err.within(0))?;
+                self.vars.insert(name.clone(), Value::Int(value));
+                Ok(value)
+            }
+            Node::Block(stmts) => {
+                let mut last = 0;
+                for (i, stmt) in stmts.iter().enumerate() {
+                    last = self
+                        .walk(stmt, depth + 1, hook)
+                        .map_err(|err| err.within(i))?;
+                }
+                Ok(last)
+            }
+            // Functions are only available through `eval_with`.
+            Node::Call { name, args } => {
+                for (i, arg) in args.iter().enumerate() {
+                    self.walk(arg, depth + 1, hook)
+                        .map_err(|err| err.within(i))?;
+                }
+                Err(undefined_function(name).at(node))
+            }
+            Node::Use(_) => Ok(0),
+        }
+    }
+
+    ///
+    /// Takes one step of evaluating `node` as [`eval`](Eval::eval) would,
+    /// returning the tree with its next operation replaced by its result, or
+    /// `None` once it is a number. Operands are reduced left to right, a
+    /// variable to its value, and an assignment is made when its value is
+    /// reduced, so that repeating the step until `None` gives every
+    /// intermediate state and then the value.
+    ///
+    /// ```
+    /// use rexpr::eval::Eval;
+    /// use rexpr::parser::Node;
+    ///
+    /// let mut eval = Eval::new();
+    /// let mut node: Node = "(1 + 2) * 3 ^ 2".parse().unwrap();
+    /// let mut steps = vec![];
+    /// while let Some(next) = eval.reduce(&node).unwrap() {
+    ///     steps.push(next.to_string());
+    ///     node = next;
+    /// }
+    /// assert_eq!(steps, ["3 * 3 ^ 2", "3 * 9", "27"]);
+    /// ```
+    ///
+    pub fn reduce(&mut self, node: &Node) -> Result<Option<Node>, EvalError> {
+        self.check_cancel()?;
+        let reduced = match node {
+            Node::Number(_) => return Ok(None),
+            Node::Float(Float(x)) => return Err(float_literal(*x).at(node)),
+            Node::Identifier(name) => Node::Number(self.lookup(name).map_err(|err| err.at(node))?),
+            Node::UnaryExpr { op, child } => match **child {
+                Node::Number(n) => {
+                    Node::Number(unary(op, n, self.overflow).map_err(|err| err.at(node))?)
+                }
+                _ => Node::UnaryExpr {
+                    op: op.clone(),
+                    child: Box::new(self.reduced(child, 0)?),
+                },
+            },
+            Node::BinaryExpr { op, lhs, rhs } => match (&**lhs, &**rhs) {
+                (Node::Number(lhs), Node::Number(rhs)) => {
+                    Node::Number(binary(op, *lhs, *rhs, self.overflow).map_err(|err| err.at(node))?)
+                }
+                (Node::Number(_), _) => Node::BinaryExpr {
+                    op: op.clone(),
+                    lhs: lhs.clone(),
+                    rhs: Box::new(self.reduced(rhs, 1)?),
+                },
+                _ => Node::BinaryExpr {
+                    op: op.clone(),
+                    lhs: Box::new(self.reduced(lhs, 0)?),
+                    rhs: rhs.clone(),
+                },
+            },
+            Node::Assign { name, value } => match **value {
+                Node::Number(n) => {
+                    self.vars.insert(name.clone(), Value::Int(n));
+                    Node::Number(n)
+                }
+                _ => Node::Assign {
+                    name: name.clone(),
+                    value: Box::new(self.reduced(value, 0)?),
+                },
+            },
+            Node::Block(stmts) => match stmts
+                .iter()
+                .position(|stmt| !matches!(stmt, Node::Number(_)))
+            {
+                Some(i) => {
+                    let mut stmts = stmts.clone();
+                    stmts[i] = self.reduced(&stmts[i], i)?;
+                    Node::Block(stmts)
+                }
+                None => match stmts.last() {
+                    Some(last) => last.clone(),
+                    None => Node::Number(0),
+                },
+            },
+            Node::Call { .. } | Node::Use(_) => Node::Number(self.eval(node)?),
+        };
+        Ok(Some(reduced))
+    }
+
+    /// `child`, the i-th child of the node being reduced, after a step.
+    fn reduced(&mut self, child: &Node, i: usize) -> Result<Node, EvalError> {
+        match self.reduce(child) {
+            Ok(reduced) => Ok(reduced.unwrap_or_else(|| child.clone())),
+            Err(err) => Err(err.within(i)),
+        }
+    }
+
+    ///
+    /// Evaluates `node` reading variables from `ctx`, then from the
+    /// evaluator's own variables, then from `ctx`'s
+    /// [`on_missing_var`](Context::on_missing_var) callback, and calling the
+    /// functions registered in `ctx`. Assignments stay local to this
+    /// evaluation, so neither `ctx` nor the evaluator is changed;
+    /// [`eval_with_assigning`](Eval::eval_with_assigning) keeps them.
+    ///
+    /// Values may be floats: an operation with a float operand is carried out
+    /// in `f64`, and integer operations follow the evaluator's [`Overflow`].
+    ///
+    /// A `use` directive anywhere in `node` applies to all of it, on top of
+    /// the context's own [`imports`](Context::import).
+    ///
+    pub fn eval_with(&self, node: &Node, ctx: &Context) -> Result<Value, EvalError> {
+        self.eval_with_hooked(node, ctx, &mut ())
+    }
+
+    ///
+    /// Evaluates `node` as [`eval_with`](Eval::eval_with) does, calling
+    /// `hook` before and after each node.
+    ///
+    pub fn eval_with_hooked(
+        &self,
+        node: &Node,
+        ctx: &Context,
+        hook: &mut impl EvalHook,
+    ) -> Result<Value, EvalError> {
+        let imports = node.imports();
+        let scope = Scope {
+            ctx,
+            imports: &imports,
+        };
+        self.eval_scoped(node, scope, &mut Map::new(), 0, hook)
+    }
+
+    ///
+    /// Evaluates `node` as [`eval_with_hooked`](Eval::eval_with_hooked)
+    /// does, then binds the variables it assigned in the evaluator, as
+    /// [`eval`](Eval::eval) would, float values included. Assignments made
+    /// before an error are kept too.
+    ///
+    /// ```
+    /// use rexpr::context::Context;
+    /// use rexpr::eval::{Eval, Value};
+    ///
+    /// let mut eval = Eval::new();
+    /// let node = "x = sqrt(16)".parse().unwrap();
+    /// eval.eval_with_assigning(&node, &Context::stdlib(), &mut ()).unwrap();
+    /// assert_eq!(eval.vars()["x"], Value::Float(4.0));
+    /// ```
+    ///
+    pub fn eval_with_assigning(
+        &mut self,
+        node: &Node,
+        ctx: &Context,
+        hook: &mut impl EvalHook,
+    ) -> Result<Value, EvalError> {
+        let imports = node.imports();
+        let scope = Scope {
+            ctx,
+            imports: &imports,
+        };
+        let mut locals = Map::new();
+        let result = self.eval_scoped(node, scope, &mut locals, 0, hook);
+        for name in node.assigned_variables() {
+            if let Some(value) = locals.remove(&name) {
+                self.vars.insert(name, value);
+            }
+        }
+        result
+    }
+
+    fn eval_scoped(
+        &self,
+        node: &Node,
+        scope: Scope,
+        locals: &mut Map<String, Value>,
+        depth: usize,
+        hook: &mut impl EvalHook,
+    ) -> Result<Value, EvalError> {
+        self.check_cancel()?;
+        hook.enter(node, depth, &Bindings::new(Some(locals), &self.vars));
+        let result = self.scoped_step(node, scope, locals, depth, hook);
+        hook.exit(node, depth, result.as_ref().copied());
+        result
+    }
+
+    fn scoped_step(
+        &self,
+        node: &Node,
+        scope: Scope,
+        locals: &mut Map<String, Value>,
+        depth: usize,
+        hook: &mut impl EvalHook,
+    ) -> Result<Value, EvalError> {
+        let ctx = scope.ctx;
+        match node {
+            Node::Number(n) => Ok(Value::Int(*n)),
+            Node::Float(Float(x)) => Ok(Value::Float(*x)),
+            Node::Identifier(name) => {
+                if let Some(value) = locals.get(name).copied().or_else(|| ctx.get(name)) {
+                    return Ok(value);
+                }
+                if let Some(value) = self.vars.get(name) {
+                    return Ok(*value);
+                }
+                match ctx.resolve_missing(name) {
+                    Some(value) => {
+                        locals.insert(name.clone(), value);
+                        Ok(value)
+                    }
+                    None => {
+                        let known = locals.keys().chain(ctx.var_names());
+                        Err(undefined_variable(name, known.chain(self.vars.keys())).at(node))
+                    }
+                }
+            }
+            Node::UnaryExpr { op, child } => {
+                let child = self
+                    .eval_scoped(child, scope, locals, depth + 1, hook)
+                    .map_err(|err| err.within(0))?;
+                unary_value(op, child, self.overflow).map_err(|err| err.at(node))
+            }
+            Node::BinaryExpr { op, lhs, rhs } => {
+                let lhs = self
+                    .eval_scoped(lhs, scope, locals, depth + 1, hook)
+                    .map_err(|err| err.within(0))?;
+                let rhs = self
+                    .eval_scoped(rhs, scope, locals, depth + 1, hook)
+                    .map_err(|err| err.within(1))?;
+                binary_value(op, lhs, rhs, self.overflow).map_err(|err| err.at(node))
+            }
+            Node::Assign { name, value } => {
+                let value = self
+                    .eval_scoped(value, scope, locals, depth + 1, hook)
+                    .map_err(|err| err.within(0))?;
+                locals.insert(name.clone(), value);
+                Ok(value)
+            }
+            Node::Block(stmts) => {
+                let mut last = Value::Int(0);
+                for (i, stmt) in stmts.iter().enumerate() {
+                    last = self
+                        .eval_scoped(stmt, scope, locals, depth + 1, hook)
+                        .map_err(|err| err.within(i))?;
+                }
+                Ok(last)
+            }
+            Node::Call { name, args } => {
+                let args = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| {
+                        self.eval_scoped(arg, scope, locals, depth + 1, hook)
+                            .map_err(|err| err.within(i))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                ctx.call_importing(name, &args, scope.imports)
+                    .map_err(|err| err.at(node))
+            }
+            Node::Use(_) => Ok(Value::Int(0)),
+        }
+    }
+
+    ///
+    /// Evaluates `node` once for each set of `bindings`, in parallel when the
+    /// `rayon` feature is enabled. Every run sees the evaluator's variables
+    /// overridden by its bindings; assignments stay local to the run, so the
+    /// evaluator itself is left unchanged.
+    ///
+    /// The expression is compiled once and results come back in the order of
+    /// `bindings`, one per set, failed runs included.
+    ///
+    pub fn eval_batch<I>(&self, node: &Node, bindings: I) -> Vec<Result<i32, EvalError>>
+    where
+        I: IntoIterator<Item = Map<String, i32>>,
+    {
+        let program = compile(node);
+        let free = node.free_variables();
+        let run = |vm: &mut Vm, bindings: Map<String, i32>| {
+            let mut vars = Vec::with_capacity(program.bindings().len());
+            for name in program.bindings().names() {
+                let value = match bindings.get(name) {
+                    Some(value) => Some(*value),
+                    None => match self.vars.get(name) {
+                        Some(value) => Some(int_variable(name, *value)?),
+                        None => None,
+                    },
+                };
+                match value {
+                    Some(value) => vars.push(value),
+                    None if free.contains(name) => {
+                        return Err(EvalError::with_code(
+                            ErrorCode::UndefinedVariable,
+                            format!("undefined variable {}", name),
+                        ))
+                    }
+                    // Assigned before it is read, so the initial value is unused.
+                    None => vars.push(0),
+                }
+            }
+            vm.run(&program, &mut vars)
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            let bindings: Vec<_> = bindings.into_iter().collect();
+            bindings
+                .into_par_iter()
+                .map_init(|| Vm::with_overflow(self.overflow), run)
+                .collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            let mut vm = Vm::with_overflow(self.overflow);
+            bindings.into_iter().map(|set| run(&mut vm, set)).collect()
+        }
+    }
+
+    ///
+    /// Evaluates as much of `node` as possible without failing, using the
+    /// evaluator's variables overridden by `bindings`, and returns the
+    /// residual tree in terms of the remaining unbound variables.
+    ///
+    /// Assignments of known values are propagated into later statements and
+    /// dropped, as are other statements whose value is known and unused.
+    /// Operations that would fail, such as `1 / 0`, are left in place so the
+    /// error surfaces when the residual tree is evaluated.
+    ///
+    pub fn partial_eval(&self, node: &Node, bindings: &Map<String, i32>) -> Node {
+        // Float variables stay in the residual tree, which is folded in
+        // integers.
+        let mut known: Map<String, i32> = self
+            .vars
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Int(n) => Some((name.clone(), *n)),
+                Value::Float(_) => None,
+            })
+            .collect();
+        known.extend(bindings.iter().map(|(name, value)| (name.clone(), *value)));
+        let stmts = match node {
+            Node::Block(stmts) => stmts.as_slice(),
+            node => core::slice::from_ref(node),
+        };
+        let mut residual = vec![];
+        for (i, stmt) in stmts.iter().enumerate() {
+            let stmt = self.residual(stmt, &mut known);
+            let last = i + 1 == stmts.len();
+            match stmt {
+                Node::Assign { value, .. } if !last && matches!(*value, Node::Number(_)) => {}
+                Node::Number(_) if !last => {}
+                stmt => residual.push(stmt),
+            }
+        }
+        match residual.len() {
+            0 => Node::Number(0),
+            1 => residual.remove(0),
+            _ => Node::Block(residual),
+        }
     }
 
-    pub fn eval(&self, node: &Node) -> i32 {
+    fn residual(&self, node: &Node, known: &mut Map<String, i32>) -> Node {
         match node {
-            Node::Number(n) => *n,
+            Node::Number(_) | Node::Float(_) | Node::Use(_) => node.clone(),
+            Node::Identifier(name) => match known.get(name) {
+                Some(value) => Node::Number(*value),
+                None => node.clone(),
+            },
             Node::UnaryExpr { op, child } => {
-                let child = self.eval(&child);
-                match op {
-                    UnaryOperator::Neg => -child,
+                let child = self.residual(child, known);
+                match child {
+                    Node::Number(n) => match unary(op, n, self.overflow) {
+                        Ok(value) => Node::Number(value),
+                        Err(_) => Node::unary(op.clone(), child),
+                    },
+                    child => Node::unary(op.clone(), child),
                 }
             }
             Node::BinaryExpr { op, lhs, rhs } => {
-                let left_result = self.eval(&lhs);
-                let right_result = self.eval(&rhs);
+                let lhs = self.residual(lhs, known);
+                let rhs = self.residual(rhs, known);
+                match (&lhs, &rhs) {
+                    (Node::Number(l), Node::Number(r)) => match binary(op, *l, *r, self.overflow) {
+                        Ok(value) => Node::Number(value),
+                        Err(_) => Node::binary(op.clone(), lhs, rhs),
+                    },
+                    _ => Node::binary(op.clone(), lhs, rhs),
+                }
+            }
+            Node::Assign { name, value } => {
+                let value = self.residual(value, known);
+                match value {
+                    Node::Number(n) => {
+                        known.insert(name.clone(), n);
+                    }
+                    _ => {
+                        known.remove(name);
+                    }
+                }
+                Node::assign(name.clone(), value)
+            }
+            Node::Block(_) => self.partial_eval(node, known),
+            Node::Call { .. } => node
+                .clone()
+                .map_children(|child| self.residual(&child, known)),
+        }
+    }
 
-                match op {
-                    BinaryOperator::Plus => left_result + right_result,
-                    BinaryOperator::Minus => left_result - right_result,
-                    BinaryOperator::Mul => left_result * right_result,
-                    BinaryOperator::Div => left_result / right_result,
+    #[cfg(feature = "std")]
+    pub fn eval_arena(&mut self, arena: &ExprArena, id: NodeId) -> Result<i32, EvalError> {
+        match arena.get(id) {
+            ArenaNode::Number(n) => Ok(*n),
+            ArenaNode::Float(Float(x)) => Err(float_literal(*x)),
+            ArenaNode::Identifier(name) => self.lookup(name),
+            ArenaNode::UnaryExpr { op, child } => {
+                let child = self.eval_arena(arena, *child)?;
+                unary(op, child, self.overflow)
+            }
+            ArenaNode::BinaryExpr { op, lhs, rhs } => {
+                let left_result = self.eval_arena(arena, *lhs)?;
+                let right_result = self.eval_arena(arena, *rhs)?;
+                binary(op, left_result, right_result, self.overflow)
+            }
+            ArenaNode::Assign { name, value } => {
+                let value = self.eval_arena(arena, *value)?;
+                self.vars.insert(name.clone(), Value::Int(value));
+                Ok(value)
+            }
+            ArenaNode::Block(stmts) => {
+                let mut last = 0;
+                for stmt in stmts {
+                    last = self.eval_arena(arena, *stmt)?;
+                }
+                Ok(last)
+            }
+            ArenaNode::Call { name, args } => {
+                for arg in args {
+                    self.eval_arena(arena, *arg)?;
                 }
+                Err(undefined_function(name))
             }
+            ArenaNode::Use(_) => Ok(0),
+        }
+    }
+}
+
+/// The value of variable `name` for integer evaluation, which cannot read
+/// a float.
+fn int_variable(name: &str, value: Value) -> Result<i32, EvalError> {
+    match value {
+        Value::Int(n) => Ok(n),
+        Value::Float(_) => Err(EvalError::with_code(
+            ErrorCode::IntegerOperands,
+            format!("variable {} is a float", name),
+        )),
+    }
+}
+
+/// The error of integer evaluation meeting the float literal `x`.
+pub(crate) fn float_literal(x: f64) -> EvalError {
+    EvalError::with_code(
+        ErrorCode::IntegerOperands,
+        format!("{} is a float", Node::Float(Float(x))),
+    )
+}
+
+fn undefined_variable<'a>(name: &str, known: impl Iterator<Item = &'a String>) -> EvalError {
+    let message = format!("undefined variable {}", name);
+    let message = did_you_mean(message, name, known.map(String::as_str));
+    EvalError::with_code(ErrorCode::UndefinedVariable, message)
+}
+
+/// Calls fail outside of `eval_with`, which has functions to call.
+pub(crate) fn undefined_function(name: &str) -> EvalError {
+    let message = format!("undefined function {}", name);
+    EvalError::with_code(ErrorCode::UndefinedFunction, message)
+}
+
+fn overflowed(
+    result: Option<i32>,
+    wrapping: i32,
+    saturating: i32,
+    overflow: Overflow,
+) -> Result<i32, EvalError> {
+    match overflow {
+        Overflow::Checked => result
+            .ok_or_else(|| EvalError::with_code(ErrorCode::IntegerOverflow, "integer overflow")),
+        Overflow::Wrapping => Ok(wrapping),
+        Overflow::Saturating => Ok(saturating),
+    }
+}
+
+pub(crate) fn unary(op: &UnaryOperator, child: i32, overflow: Overflow) -> Result<i32, EvalError> {
+    match op {
+        UnaryOperator::Neg => overflowed(
+            child.checked_neg(),
+            child.wrapping_neg(),
+            child.saturating_neg(),
+            overflow,
+        ),
+        UnaryOperator::Not => Ok((child == 0) as i32),
+    }
+}
+
+pub(crate) fn binary(
+    op: &BinaryOperator,
+    lhs: i32,
+    rhs: i32,
+    overflow: Overflow,
+) -> Result<i32, EvalError> {
+    match op {
+        BinaryOperator::Plus => overflowed(
+            lhs.checked_add(rhs),
+            lhs.wrapping_add(rhs),
+            lhs.saturating_add(rhs),
+            overflow,
+        ),
+        BinaryOperator::Minus => overflowed(
+            lhs.checked_sub(rhs),
+            lhs.wrapping_sub(rhs),
+            lhs.saturating_sub(rhs),
+            overflow,
+        ),
+        BinaryOperator::Mul => overflowed(
+            lhs.checked_mul(rhs),
+            lhs.wrapping_mul(rhs),
+            lhs.saturating_mul(rhs),
+            overflow,
+        ),
+        BinaryOperator::Div if rhs == 0 => Err(EvalError::with_code(
+            ErrorCode::DivisionByZero,
+            "division by zero",
+        )),
+        BinaryOperator::Div => overflowed(
+            lhs.checked_div(rhs),
+            lhs.wrapping_div(rhs),
+            lhs.saturating_div(rhs),
+            overflow,
+        ),
+        BinaryOperator::Pow => {
+            let exp = u32::try_from(rhs).map_err(|_| {
+                EvalError::with_code(ErrorCode::NegativeExponent, "negative exponent")
+            })?;
+            overflowed(
+                lhs.checked_pow(exp),
+                lhs.wrapping_pow(exp),
+                lhs.saturating_pow(exp),
+                overflow,
+            )
+        }
+        BinaryOperator::Shl => {
+            // `x << n` overflows exactly when `x * 2^n` does.
+            let shifted = (lhs as i64) << shift_amount(rhs)?;
+            overflowed(
+                i32::try_from(shifted).ok(),
+                shifted as i32,
+                shifted.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+                overflow,
+            )
         }
+        BinaryOperator::Shr => Ok(lhs >> shift_amount(rhs)?),
+        // Logical operators treat any non-zero operand as true and yield 0 or
+        // 1. Both operands are always evaluated, there is no short-circuit.
+        BinaryOperator::And => Ok((lhs != 0 && rhs != 0) as i32),
+        BinaryOperator::Or => Ok((lhs != 0 || rhs != 0) as i32),
+    }
+}
+
+pub(crate) fn unary_value(
+    op: &UnaryOperator,
+    child: Value,
+    overflow: Overflow,
+) -> Result<Value, EvalError> {
+    match (op, child) {
+        (_, Value::Int(n)) => unary(op, n, overflow).map(Value::Int),
+        (UnaryOperator::Neg, Value::Float(x)) => Ok(Value::Float(-x)),
+        (UnaryOperator::Not, Value::Float(x)) => Ok(Value::Int((x == 0.0) as i32)),
+    }
+}
+
+pub(crate) fn binary_value(
+    op: &BinaryOperator,
+    lhs: Value,
+    rhs: Value,
+    overflow: Overflow,
+) -> Result<Value, EvalError> {
+    let (lhs, rhs) = match (lhs, rhs) {
+        (Value::Int(lhs), Value::Int(rhs)) => {
+            return binary(op, lhs, rhs, overflow).map(Value::Int)
+        }
+        (lhs, rhs) => (lhs.as_f64(), rhs.as_f64()),
+    };
+    match op {
+        BinaryOperator::Plus => Ok(Value::Float(lhs + rhs)),
+        BinaryOperator::Minus => Ok(Value::Float(lhs - rhs)),
+        BinaryOperator::Mul => Ok(Value::Float(lhs * rhs)),
+        BinaryOperator::Div => Ok(Value::Float(lhs / rhs)),
+        BinaryOperator::Pow => Ok(Value::Float(powf(lhs, rhs))),
+        BinaryOperator::Shl | BinaryOperator::Shr => Err(integer_operands(op)),
+        BinaryOperator::And => Ok(Value::Int((lhs != 0.0 && rhs != 0.0) as i32)),
+        BinaryOperator::Or => Ok(Value::Int((lhs != 0.0 || rhs != 0.0) as i32)),
+    }
+}
+
+/// Shifts are only defined on integers.
+pub(crate) fn integer_operands(op: &BinaryOperator) -> EvalError {
+    let message = format!("{} requires integer operands", op);
+    EvalError::with_code(ErrorCode::IntegerOperands, message)
+}
+
+#[cfg(feature = "std")]
+fn powf(x: f64, y: f64) -> f64 {
+    x.powf(y)
+}
+
+/// Float powers come from libm where std's are not available.
+#[cfg(not(feature = "std"))]
+fn powf(x: f64, y: f64) -> f64 {
+    libm::pow(x, y)
+}
+
+fn shift_amount(rhs: i32) -> Result<u32, EvalError> {
+    match u32::try_from(rhs) {
+        Ok(n) if n < i32::BITS => Ok(n),
+        _ => Err(EvalError::with_code(
+            ErrorCode::ShiftOutOfRange,
+            format!("shift amount {} out of range", rhs),
+        )),
     }
 }
 
@@ -39,111 +1119,501 @@ mod test {
 
     #[test]
     fn number() {
-        let mut tokenizer = Tokenizer::new("1");
+        let tokenizer = Tokenizer::new("1");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(1, result)
     }
 
     #[test]
     fn negtive_number() {
-        let mut tokenizer = Tokenizer::new("-1");
+        let tokenizer = Tokenizer::new("-1");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(-1, result)
     }
 
     #[test]
     fn plus() {
-        let mut tokenizer = Tokenizer::new("1 + 2");
+        let tokenizer = Tokenizer::new("1 + 2");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(3, result)
     }
 
     #[test]
     fn minus() {
-        let mut tokenizer = Tokenizer::new("1 - 2");
+        let tokenizer = Tokenizer::new("1 - 2");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(-1, result)
     }
 
     #[test]
     fn mul() {
-        let mut tokenizer = Tokenizer::new("1 * 2");
+        let tokenizer = Tokenizer::new("1 * 2");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(2, result)
     }
 
     #[test]
     fn div() {
-        let mut tokenizer = Tokenizer::new("1 / 2");
+        let tokenizer = Tokenizer::new("1 / 2");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(0, result)
     }
 
     #[test]
     fn normal_expr() {
-        let mut tokenizer = Tokenizer::new("1 + 2 * 3");
+        let tokenizer = Tokenizer::new("1 + 2 * 3");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(7, result)
     }
 
     #[test]
     fn parenthesis_expr() {
-        let mut tokenizer = Tokenizer::new("(1 + 2) * 3");
+        let tokenizer = Tokenizer::new("(1 + 2) * 3");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(9, result)
     }
 
     #[test]
     fn negetive_expr() {
-        let mut tokenizer = Tokenizer::new("-(1 + 2) * 3");
+        let tokenizer = Tokenizer::new("-(1 + 2) * 3");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(-9, result)
     }
 
     #[test]
     fn negetive_number_in_expr() {
-        let mut tokenizer = Tokenizer::new("1 + -2 * 3");
+        let tokenizer = Tokenizer::new("1 + -2 * 3");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
-        let eval = Eval::new();
-        let result = eval.eval(&expr);
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
         assert_eq!(-5, result)
     }
+
+    #[test]
+    fn statements_return_last_value() {
+        let tokenizer = Tokenizer::new("x = 3; y = 4; x * y");
+        let tokens = tokenizer.tokenize().unwrap();
+        let parser = Parser::new(tokens);
+        let expr = parser.parse().unwrap();
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
+        assert_eq!(12, result)
+    }
+
+    #[test]
+    fn variables_persist_between_evals() {
+        let mut eval = Eval::new();
+        for (input, expected) in [("x = 2", 2), ("x * 5", 10)] {
+            let tokenizer = Tokenizer::new(input);
+            let tokens = tokenizer.tokenize().unwrap();
+            let expr = Parser::new(tokens).parse().unwrap();
+            assert_eq!(expected, eval.eval(&expr).unwrap())
+        }
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let tokenizer = Tokenizer::new("x + 1");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        let err = Eval::new().eval(&expr).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined variable x")
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let tokenizer = Tokenizer::new("1 / 0");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        let err = Eval::new().eval(&expr).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: division by zero")
+    }
+
+    #[test]
+    fn left_associative_expr() {
+        let tokenizer = Tokenizer::new("8 - 4 - 2 + 16 / 4 / 2");
+        let tokens = tokenizer.tokenize().unwrap();
+        let parser = Parser::new(tokens);
+        let expr = parser.parse().unwrap();
+        let mut eval = Eval::new();
+        let result = eval.eval(&expr).unwrap();
+        assert_eq!(4, result)
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn eval_arena() {
+        let expr: Node = "x = 6; -(x + 2) * x / 4".parse().unwrap();
+        let (arena, root) = ExprArena::from_node(&expr);
+        let mut eval = Eval::new();
+        assert_eq!(-12, eval.eval_arena(&arena, root).unwrap());
+        assert_eq!(-12, Eval::new().eval(&expr).unwrap())
+    }
+
+    #[test]
+    fn overflow_semantics() {
+        let expr: Node = "2147483647 + 1".parse().unwrap();
+        let err = Eval::new().eval(&expr).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: integer overflow");
+        let mut eval = Eval::with_overflow(Overflow::Wrapping);
+        assert_eq!(i32::MIN, eval.eval(&expr).unwrap());
+        let mut eval = Eval::with_overflow(Overflow::Saturating);
+        assert_eq!(i32::MAX, eval.eval(&expr).unwrap());
+    }
+
+    #[test]
+    fn power_and_shifts() {
+        for (input, expected) in [
+            ("2 ^ 3 ^ 2", 512),
+            ("-2 ^ 2", -4),
+            ("1 + 3 << 2", 16),
+            ("-9 >> 1", -5),
+        ] {
+            let expr: Node = input.parse().unwrap();
+            assert_eq!(expected, Eval::new().eval(&expr).unwrap())
+        }
+        let err = Eval::new().eval(&"2 ^ -1".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: negative exponent");
+        let err = Eval::new().eval(&"1 << 32".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: shift amount 32 out of range");
+        let err = Eval::new().eval(&"1 << 31".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: integer overflow");
+    }
+
+    #[test]
+    fn logical_operators() {
+        for (input, expected) in [
+            ("!0", 1),
+            ("!7", 0),
+            ("3 && -1", 1),
+            ("2 && 0", 0),
+            ("0 || 5", 1),
+            ("0 || 0", 0),
+            ("1 || 0 && 0", 1),
+            ("!(2 || 0) + 1", 1),
+        ] {
+            let expr: Node = input.parse().unwrap();
+            assert_eq!(expected, Eval::new().eval(&expr).unwrap(), "{}", input)
+        }
+        let err = Eval::new()
+            .eval(&"0 && 1 / 0".parse().unwrap())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: division by zero");
+    }
+
+    #[test]
+    fn partial_eval() {
+        let eval = Eval::new();
+        let bindings = Map::from([("rate".to_string(), 3)]);
+        let residual = |text: &str| {
+            let node: Node = text.parse().unwrap();
+            eval.partial_eval(&node, &bindings).to_string()
+        };
+        assert_eq!(residual("price * (rate + 1) - 2 * 5"), "price * 4 - 10");
+        assert_eq!(residual("k = rate * 2; base + k"), "base + 6");
+        assert_eq!(residual("k = base * 2; k + rate"), "k = base * 2; k + 3");
+        assert_eq!(residual("rate / (rate - 3) + x"), "3 / 0 + x");
+        assert_eq!(residual("rate = 1; rate"), "1");
+        assert_eq!(residual("total = rate * 2"), "total = 6")
+    }
+
+    #[test]
+    fn eval_batch() {
+        let mut eval = Eval::new();
+        eval.eval(&"rate = 3".parse().unwrap()).unwrap();
+        let node: Node = "total = price * rate; total / qty".parse().unwrap();
+        let rows =
+            (0..100).map(|i| Map::from([("price".to_string(), i), ("qty".to_string(), i % 4)]));
+        let results = eval.eval_batch(&node, rows);
+        assert_eq!(results.len(), 100);
+        assert_eq!(results[5].as_ref().unwrap(), &15);
+        assert_eq!(
+            results[4].as_ref().unwrap_err().to_string(),
+            "EvalError: division by zero"
+        );
+        assert!(eval.vars().get("total").is_none());
+
+        let missing = eval.eval_batch(&node, [Map::from([("qty".to_string(), 1)])]);
+        assert_eq!(
+            missing[0].as_ref().unwrap_err().to_string(),
+            "EvalError: undefined variable price"
+        )
+    }
+
+    #[test]
+    fn eval_with_context() {
+        let mut eval = Eval::new();
+        eval.eval(&"fee = 2; price = 1".parse().unwrap()).unwrap();
+        let mut ctx = Context::new();
+        ctx.set("price", 10.5);
+        ctx.set("qty", 4);
+        let node: Node = "total = price * qty; total + fee".parse().unwrap();
+        assert_eq!(eval.eval_with(&node, &ctx).unwrap(), Value::Float(44.0));
+        assert_eq!(eval.vars().get("total"), None);
+        assert_eq!(ctx.get("total"), None);
+
+        let node: Node = "qty << 1 && !missing".parse().unwrap();
+        let err = eval.eval_with(&node, &ctx).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: undefined variable missing");
+        ctx.set("qty", 0.5);
+        let err = eval
+            .eval_with(&"qty << 1".parse().unwrap(), &ctx)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: << requires integer operands")
+    }
+
+    #[test]
+    fn eval_with_assigning() {
+        let mut eval = Eval::new();
+        let mut ctx = Context::new();
+        ctx.set("rate", 0.5);
+        let node: Node = "half = 3 * rate; n = 2; n / 0".parse().unwrap();
+        assert!(eval.eval_with_assigning(&node, &ctx, &mut ()).is_err());
+        assert_eq!(eval.vars().get("half"), Some(&Value::Float(1.5)));
+        assert_eq!(eval.vars().get("n"), Some(&Value::Int(2)));
+        assert_eq!(eval.vars().get("rate"), None);
+        assert_eq!(eval.eval(&"n + 1".parse().unwrap()).unwrap(), 3);
+        let err = eval.eval(&"half + 1".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: variable half is a float")
+    }
+
+    #[test]
+    fn float_literals() {
+        let node: Node = "x = 7 / 2.0; x * 2".parse().unwrap();
+        let ctx = Context::new();
+        assert_eq!(
+            Eval::new().eval_with(&node, &ctx).unwrap(),
+            Value::Float(7.0)
+        );
+        let err = Eval::new().eval(&node).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::IntegerOperands);
+        assert_eq!(err.to_string(), "EvalError: 2.0 is a float");
+        assert!(Eval::new().reduce(&node).is_err());
+        let runs = Eval::new().eval_batch(&node, [Map::new()]);
+        assert_eq!(
+            runs[0].as_ref().unwrap_err().code(),
+            ErrorCode::IntegerOperands
+        )
+    }
+
+    #[test]
+    fn locate_errors() {
+        let error = |text: &str| {
+            let parser = Parser::from_lexer(crate::tokenizer::Lexer::new(text));
+            let (node, spans) = parser.parse_spanned().unwrap();
+            let mut err = Eval::new().eval(&node).unwrap_err();
+            err.locate(&spans);
+            err
+        };
+        let err = error("x = 3;\ny = 6 / (x - x)");
+        assert_eq!(err.expression(), Some("6 / (x - x)"));
+        assert_eq!(
+            err.to_string(),
+            "EvalError: division by zero in `6 / (x - x)` at line 2, col 5"
+        );
+        let err = error("-(2147483647 + 1) * 2");
+        assert_eq!(err.code(), ErrorCode::IntegerOverflow);
+        assert_eq!(
+            err.span().map(|span| (span.offset, span.len)),
+            Some((1, 16))
+        );
+        let mut eval = Eval::new();
+        eval.eval(&"price = 3".parse().unwrap()).unwrap();
+        let err = eval.eval(&"prise * 2".parse().unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EvalError: undefined variable prise, did you mean price?"
+        );
+        let err = error("1 + f(2, -rate)");
+        assert_eq!(err.expression(), Some("rate"));
+        assert_eq!(
+            err.to_string(),
+            "EvalError: undefined variable rate in `rate` at col 11"
+        );
+
+        let err = Eval::new().eval(&"1 / 0".parse().unwrap()).unwrap_err();
+        assert_eq!(err.expression(), Some("1 / 0"));
+        assert_eq!(err.to_string(), "EvalError: division by zero")
+    }
+
+    #[test]
+    fn partial_eval_uses_variables() {
+        let mut eval = Eval::new();
+        eval.eval(&"scale = 10".parse().unwrap()).unwrap();
+        let node: Node = "x * scale + y".parse().unwrap();
+        let bindings = Map::from([("y".to_string(), 1)]);
+        let residual = eval.partial_eval(&node, &bindings);
+        assert_eq!(residual.to_string(), "x * 10 + 1");
+        assert_eq!(residual.free_variables(), vec!["x"])
+    }
+
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl EvalHook for Trace {
+        fn enter(&mut self, node: &Node, depth: usize, bindings: &Bindings) {
+            let mut vars: Vec<String> = bindings
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            vars.sort();
+            self.0
+                .push(format!("{} {} [{}]", depth, node, vars.join(" ")))
+        }
+
+        fn exit(&mut self, node: &Node, depth: usize, result: Result<Value, &EvalError>) {
+            let result = result.map_or_else(|err| err.to_string(), |value| value.to_string());
+            self.0.push(format!("{} {} = {}", depth, node, result))
+        }
+    }
+
+    #[test]
+    fn hook_evaluation() {
+        let mut eval = Eval::new();
+        eval.set("x", 1);
+        let node: Node = "x = x + 1; sqrt(x)".parse().unwrap();
+        let mut ctx = Context::new();
+        ctx.register_fn("sqrt", 1, |args| Ok(Value::Float(args[0].as_f64().sqrt())));
+        let mut trace = Trace::default();
+        let value = eval.eval_with_hooked(&node, &ctx, &mut trace);
+        assert_eq!(value.unwrap().as_f64(), 2f64.sqrt());
+        assert_eq!(
+            trace.0[..8],
+            [
+                "0 x = x + 1; sqrt(x) [x=1]",
+                "1 x = x + 1 [x=1]",
+                "2 x + 1 [x=1]",
+                "3 x [x=1]",
+                "3 x = 1",
+                "3 1 [x=1]",
+                "3 1 = 1",
+                "2 x + 1 = 2",
+            ]
+        );
+        assert_eq!(trace.0[9], "1 sqrt(x) [x=2]");
+        let mut trace = Trace::default();
+        let err = eval.eval_hooked(&"1 / (x - 1)".parse().unwrap(), &mut trace);
+        assert!(err.is_err());
+        assert_eq!(
+            trace.0.last().unwrap(),
+            "0 1 / (x - 1) = EvalError: division by zero"
+        )
+    }
+
+    #[test]
+    fn report_counts() {
+        let mut report = EvalReport::default();
+        let eval = Eval::new();
+        let node: Node = "max(1, -2) + !0 * 3 - 1".parse().unwrap();
+        let mut ctx = Context::new();
+        ctx.register_fn("max", 2, |args| {
+            let larger = args[0].as_f64() < args[1].as_f64();
+            Ok(if larger { args[1] } else { args[0] })
+        });
+        eval.eval_with_hooked(&node, &ctx, &mut report).unwrap();
+        assert_eq!(report.nodes, 11);
+        assert_eq!(report.calls, 1);
+        assert_eq!(report.max_depth, 4);
+        assert_eq!(report.unary.len(), 2);
+        let ops = [
+            (BinaryOperator::Plus, 1),
+            (BinaryOperator::Minus, 1),
+            (BinaryOperator::Mul, 1),
+        ];
+        assert_eq!(report.binary, ops.into_iter().collect());
+        let failing: Node = "1 / 0 + 2".parse().unwrap();
+        assert!(eval
+            .eval_with_hooked(&failing, &Context::new(), &mut report)
+            .is_err());
+        assert_eq!(report.nodes, 15);
+        assert_eq!(report.binary[&BinaryOperator::Plus], 2);
+        report.reset();
+        assert_eq!(report, EvalReport::default())
+    }
+
+    #[test]
+    fn reduce_steps() {
+        let mut eval = Eval::new();
+        let mut node: Node = "x = -(2 * 3); y = x + 1; y << 2".parse().unwrap();
+        let mut steps = vec![];
+        while let Some(next) = eval.reduce(&node).unwrap() {
+            steps.push(next.to_string());
+            node = next;
+        }
+        assert_eq!(
+            steps,
+            [
+                "x = -6; y = x + 1; y << 2",
+                "x = -6; y = x + 1; y << 2",
+                "-6; y = x + 1; y << 2",
+                "-6; y = -6 + 1; y << 2",
+                "-6; y = -5; y << 2",
+                "-6; -5; y << 2",
+                "-6; -5; -5 << 2",
+                "-6; -5; -20",
+                "-20",
+            ]
+        );
+        assert_eq!(eval.vars().get("y"), Some(&Value::Int(-5)));
+        let node: Node = "1 + 2 / (3 - 3)".parse().unwrap();
+        let node = eval.reduce(&node).unwrap().unwrap();
+        assert_eq!(node.to_string(), "1 + 2 / 0");
+        let err = eval.reduce(&node).unwrap_err();
+        assert_eq!(err.expression(), Some("2 / 0"))
+    }
+
+    #[test]
+    fn cancel_evaluation() {
+        let cancel = Cancel::new();
+        let mut eval = Eval::new();
+        eval.set_cancel(Some(cancel.clone()));
+        let node: Node = "x = 1 + 2".parse().unwrap();
+        cancel.cancel();
+        let err = eval.eval(&node).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Cancelled);
+        assert_eq!(err.to_string(), "EvalError: evaluation cancelled");
+        assert!(eval.vars().is_empty());
+        let err = eval.eval_with(&node, &Context::new()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Cancelled);
+        cancel.reset();
+        assert_eq!(eval.eval(&node).unwrap(), 3)
+    }
 }
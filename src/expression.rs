@@ -0,0 +1,223 @@
+//!
+//! A compiled expression with named parameters, for evaluating the same
+//! formula many times from host code:
+//!
+//! ```
+//! use rexpr::eval::Value;
+//! use rexpr::expression::Expression;
+//!
+//! let line = Expression::compile("a * x + b").unwrap();
+//! assert_eq!(line.variables(), ["a", "x", "b"]);
+//! let y = line.eval(&[("a", 2.0), ("x", 3.0), ("b", 0.5)]).unwrap();
+//! assert_eq!(y, Value::Float(6.5));
+//! ```
+//!
+//! Placeholders `$1`, `$2`, ... are bound by position instead, for templates
+//! applied to rows of input:
+//!
+//! ```
+//! use rexpr::eval::Value;
+//! use rexpr::expression::Expression;
+//!
+//! let template = Expression::compile("$1 * 2 + $2").unwrap();
+//! assert_eq!(template.arity(), 2);
+//! assert_eq!(template.eval_args(&[5, 1]).unwrap(), Value::Int(11));
+//! ```
+//!
+use crate::closure::Closure;
+use crate::compile::Bindings;
+use crate::diagnostic::did_you_mean;
+use crate::error::{ErrorCode, RexprError};
+use crate::eval::{EvalError, Value};
+use crate::parser::Node;
+use std::fmt::Display;
+
+/// The position of a placeholder `$n`, counting from 1.
+fn position(name: &str) -> Option<usize> {
+    name.strip_prefix('$')?.parse().ok()
+}
+
+fn invalid_binding(message: String) -> EvalError {
+    EvalError::with_code(ErrorCode::InvalidBinding, message)
+}
+
+pub struct Expression {
+    node: Node,
+    bindings: Bindings,
+    closure: Closure,
+}
+
+impl Expression {
+    /// Parses `source` and compiles it, taking its free variables as the
+    /// parameters.
+    pub fn compile(source: &str) -> Result<Expression, RexprError> {
+        Ok(Expression::from_node(source.parse()?))
+    }
+
+    pub fn from_node(node: Node) -> Expression {
+        let bindings: Bindings = node.free_variables().into_iter().collect();
+        let closure = node.to_closure(&bindings);
+        Expression {
+            node,
+            bindings,
+            closure,
+        }
+    }
+
+    /// The parameters, in order of first use.
+    pub fn variables(&self) -> &[String] {
+        self.bindings.names()
+    }
+
+    /// The number of positional arguments: the highest placeholder used, so
+    /// `$2 + 1` takes two arguments and ignores the first.
+    pub fn arity(&self) -> usize {
+        self.variables()
+            .iter()
+            .filter_map(|name| position(name))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    ///
+    /// Evaluates the expression with each parameter bound by name. Every
+    /// parameter must be bound exactly once, and no other names are accepted.
+    ///
+    pub fn eval<V: Into<Value> + Copy>(&self, bindings: &[(&str, V)]) -> Result<Value, EvalError> {
+        let mut args = vec![None; self.bindings.len()];
+        for (name, value) in bindings {
+            let slot = self.bindings.get(name).ok_or_else(|| {
+                let message = format!("unknown variable {}", name);
+                let known = self.variables().iter().map(String::as_str);
+                invalid_binding(did_you_mean(message, name, known))
+            })?;
+            if args[slot].replace((*value).into()).is_some() {
+                return Err(invalid_binding(format!("variable {} bound twice", name)));
+            }
+        }
+        let args = args
+            .into_iter()
+            .zip(self.bindings.names())
+            .map(|(arg, name)| {
+                arg.ok_or_else(|| invalid_binding(format!("missing variable {}", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        (self.closure)(&args)
+    }
+
+    ///
+    /// Evaluates the expression with `args[n - 1]` bound to each placeholder
+    /// `$n`. Exactly `arity()` arguments must be given, and the expression
+    /// must have no named parameters.
+    ///
+    pub fn eval_args<V: Into<Value> + Copy>(&self, args: &[V]) -> Result<Value, EvalError> {
+        if args.len() != self.arity() {
+            return Err(EvalError::with_code(
+                ErrorCode::ArgumentCount,
+                format!("expected {} arguments, got {}", self.arity(), args.len()),
+            ));
+        }
+        let args = self
+            .variables()
+            .iter()
+            .map(|name| match position(name) {
+                Some(n) => Ok(args[n - 1].into()),
+                None => Err(invalid_binding(format!("missing variable {}", name))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        (self.closure)(&args)
+    }
+}
+
+impl std::fmt::Debug for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Expression")
+            .field("node", &self.node)
+            .field("variables", &self.variables())
+            .finish()
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repeated_evaluation() {
+        let expr = Expression::compile("rate = 2; base + rate * hours").unwrap();
+        assert_eq!(expr.variables(), ["base", "hours"]);
+        for hours in 0..5 {
+            let value = expr.eval(&[("hours", hours), ("base", 10)]).unwrap();
+            assert_eq!(value, Value::Int(10 + 2 * hours))
+        }
+        assert_eq!(expr.to_string(), "rate = 2; base + rate * hours")
+    }
+
+    #[test]
+    fn validate_bindings() {
+        let expr = Expression::compile("x / y").unwrap();
+        let message = |bindings: &[(&str, i32)]| expr.eval(bindings).unwrap_err().to_string();
+        assert_eq!(message(&[("x", 1)]), "EvalError: missing variable y");
+        assert_eq!(
+            message(&[("x", 1), ("y", 2), ("z", 3)]),
+            "EvalError: unknown variable z"
+        );
+        assert_eq!(
+            message(&[("x", 1), ("x", 2), ("y", 3)]),
+            "EvalError: variable x bound twice"
+        );
+        assert_eq!(
+            message(&[("x", 1), ("yy", 2)]),
+            "EvalError: unknown variable yy, did you mean y?"
+        );
+        assert_eq!(
+            message(&[("x", 1), ("y", 0)]),
+            "EvalError: division by zero"
+        )
+    }
+
+    #[test]
+    fn positional_placeholders() {
+        let expr = Expression::compile("total = $2 * $1; total - $1").unwrap();
+        assert_eq!(expr.arity(), 2);
+        assert_eq!(expr.eval_args(&[3, 4]).unwrap(), Value::Int(9));
+        assert_eq!(expr.eval(&[("$1", 3), ("$2", 4)]).unwrap(), Value::Int(9));
+        let err = expr.eval_args(&[3]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: expected 2 arguments, got 1");
+
+        let expr = Expression::compile("$3 + offset").unwrap();
+        assert_eq!(expr.arity(), 3);
+        let err = expr.eval_args(&[1.0, 2.0, 3.0]).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: missing variable offset")
+    }
+
+    #[test]
+    fn evaluate_concurrently() {
+        let expr = Expression::compile("a * x + b").unwrap();
+        let results: Vec<Value> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|x| {
+                    let expr = &expr;
+                    s.spawn(move || expr.eval(&[("a", 3), ("x", x), ("b", 1)]).unwrap())
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        assert_eq!(results, [1, 4, 7, 10].map(Value::Int))
+    }
+
+    #[test]
+    fn reject_invalid_source() {
+        assert!(Expression::compile("a *").is_err())
+    }
+}
@@ -0,0 +1,139 @@
+//!
+//! A C interface to the evaluator, built with the `ffi` feature and declared
+//! in `include/rexpr.h`:
+//!
+//! ```c
+//! double value;
+//! char *err;
+//! if (rexpr_eval("x = 6; x * 7", &value, &err) != 0) {
+//!     fprintf(stderr, "%s\n", err);
+//!     rexpr_free_string(err);
+//! }
+//! ```
+//!
+//! Expressions are evaluated with the functions and constants of the
+//! standard library groups built in, as by [`Eval::eval_with`], so values
+//! are integers unless a float enters, as in `7 / 2.0` or `sqrt(2)`.
+//!
+//! The crate builds the shared library to link against, `librexpr.so` or
+//! its counterpart, next to the Rust one:
+//!
+//! ```sh
+//! cargo build --lib --release --features ffi
+//! ```
+//!
+use crate::context::Context;
+use crate::eval::Eval;
+use crate::parser::Parser;
+use crate::tokenizer::Lexer;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use std::sync::OnceLock;
+
+/// The standard library, registered on the first evaluation.
+static STDLIB: OnceLock<Context> = OnceLock::new();
+
+fn report(err: *mut *mut c_char, message: String) -> c_int {
+    if !err.is_null() {
+        // Messages come from Rust strings, which have no interior NULs.
+        let message = CString::new(message).unwrap_or_default();
+        unsafe { *err = message.into_raw() };
+    }
+    1
+}
+
+///
+/// Evaluates the NUL-terminated `source` in a fresh session. On success
+/// stores the value in `out` and returns 0. On failure returns 1 and, unless
+/// `err` is null, stores in it a message to be freed with
+/// [`rexpr_free_string`].
+///
+/// # Safety
+///
+/// `source` must be a valid NUL-terminated string, `out` must be valid for
+/// a write, and `err` must be null or valid for a write.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rexpr_eval(
+    source: *const c_char,
+    out: *mut f64,
+    err: *mut *mut c_char,
+) -> c_int {
+    if !err.is_null() {
+        *err = ptr::null_mut();
+    }
+    if source.is_null() || out.is_null() {
+        return report(err, "null argument".to_string());
+    }
+    let source = match CStr::from_ptr(source).to_str() {
+        Ok(source) => source,
+        Err(_) => return report(err, "source is not valid UTF-8".to_string()),
+    };
+    let (node, spans) = match Parser::from_lexer(Lexer::new(source)).parse_spanned() {
+        Ok(parsed) => parsed,
+        Err(e) => return report(err, e.to_string()),
+    };
+    match Eval::new().eval_with(&node, STDLIB.get_or_init(Context::stdlib)) {
+        Ok(value) => {
+            *out = value.as_f64();
+            0
+        }
+        Err(mut e) => {
+            e.locate(&spans);
+            report(err, e.to_string())
+        }
+    }
+}
+
+///
+/// Frees a message returned by [`rexpr_eval`]. Does nothing for null.
+///
+/// # Safety
+///
+/// `s` must be null or a string from this library not already freed.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rexpr_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval(source: &str) -> Result<f64, String> {
+        let source = CString::new(source).unwrap();
+        let (mut out, mut err) = (0.0, ptr::null_mut());
+        unsafe {
+            if rexpr_eval(source.as_ptr(), &mut out, &mut err) == 0 {
+                assert!(err.is_null());
+                return Ok(out);
+            }
+            let message = CStr::from_ptr(err).to_str().unwrap().to_string();
+            rexpr_free_string(err);
+            Err(message)
+        }
+    }
+
+    #[test]
+    fn evaluate_through_c_interface() {
+        assert_eq!(eval("x = 6; x * 7"), Ok(42.0));
+        assert_eq!(eval("7 / 2 + 1 / 4.0"), Ok(3.25));
+        assert_eq!(
+            eval("1 / 0"),
+            Err("EvalError: division by zero in `1 / 0` at col 1".to_string())
+        );
+        let mut out = 0.0;
+        let source = CString::new("2 +").unwrap();
+        assert_eq!(
+            unsafe { rexpr_eval(source.as_ptr(), &mut out, ptr::null_mut()) },
+            1
+        );
+        assert_eq!(
+            unsafe { rexpr_eval(ptr::null(), &mut out, ptr::null_mut()) },
+            1
+        )
+    }
+}
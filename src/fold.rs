@@ -0,0 +1,183 @@
+//!
+//! Tree transformations: bottom-up folds and rewrites, and substitution.
+//!
+use crate::parser::*;
+use crate::prelude::*;
+use core::convert::Infallible;
+
+impl Node {
+    /// Rebuilds the node with `f` applied to each direct child.
+    pub fn try_map_children<E>(
+        self,
+        mut f: impl FnMut(Node) -> Result<Node, E>,
+    ) -> Result<Node, E> {
+        Ok(match self {
+            Node::Number(_) | Node::Float(_) | Node::Identifier(_) | Node::Use(_) => self,
+            Node::UnaryExpr { op, child } => Node::UnaryExpr {
+                op,
+                child: Box::new(f(*child)?),
+            },
+            Node::BinaryExpr { op, lhs, rhs } => Node::BinaryExpr {
+                op,
+                lhs: Box::new(f(*lhs)?),
+                rhs: Box::new(f(*rhs)?),
+            },
+            Node::Assign { name, value } => Node::Assign {
+                name,
+                value: Box::new(f(*value)?),
+            },
+            Node::Block(stmts) => Node::Block(stmts.into_iter().map(f).collect::<Result<_, _>>()?),
+            Node::Call { name, args } => Node::Call {
+                name,
+                args: args.into_iter().map(f).collect::<Result<_, _>>()?,
+            },
+        })
+    }
+
+    pub fn map_children(self, mut f: impl FnMut(Node) -> Node) -> Node {
+        match self.try_map_children(|child| Ok::<_, Infallible>(f(child))) {
+            Ok(node) => node,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Applies `f` to every node bottom-up: children are folded first and the
+    /// rebuilt parent is then handed to `f`.
+    pub fn try_fold<E>(self, f: &mut impl FnMut(Node) -> Result<Node, E>) -> Result<Node, E> {
+        let node = self.try_map_children(|child| child.try_fold(f))?;
+        f(node)
+    }
+
+    pub fn fold(self, f: &mut impl FnMut(Node) -> Node) -> Node {
+        let node = self.map_children(|child| child.fold(f));
+        f(node)
+    }
+
+    /// Applies the rule `f` bottom-up until it no longer matches anywhere.
+    /// `f` returns `None` when it does not apply to a node; a replacement is
+    /// itself rewritten again, so rules must make progress to terminate.
+    pub fn rewrite(self, f: &mut impl FnMut(&Node) -> Option<Node>) -> Node {
+        let node = self.map_children(|child| child.rewrite(f));
+        match f(&node) {
+            Some(replacement) => replacement.rewrite(f),
+            None => node,
+        }
+    }
+
+    ///
+    /// Replaces every read of the variable `var` with `replacement`.
+    ///
+    /// Reads after a statement assigning `var` see the assigned value rather
+    /// than the input, so they are left alone, as is the assigned name itself.
+    ///
+    pub fn substitute(&self, var: &str, replacement: &Node) -> Node {
+        match self {
+            Node::Identifier(name) if name == var => replacement.clone(),
+            Node::Block(stmts) => {
+                let mut assigned = false;
+                let stmts = stmts.iter().map(|stmt| {
+                    if assigned {
+                        return stmt.clone();
+                    }
+                    assigned = matches!(stmt, Node::Assign { name, .. } if name == var);
+                    stmt.substitute(var, replacement)
+                });
+                Node::Block(stmts.collect())
+            }
+            node => node
+                .clone()
+                .map_children(|child| child.substitute(var, replacement)),
+        }
+    }
+
+    /// Replaces every subtree equal to `pattern` with `replacement`, outermost
+    /// first; the inserted replacements are not searched again.
+    pub fn substitute_subtree(&self, pattern: &Node, replacement: &Node) -> Node {
+        if self == pattern {
+            return replacement.clone();
+        }
+        self.clone()
+            .map_children(|child| child.substitute_subtree(pattern, replacement))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn parse(text: &str) -> Node {
+        let tokenizer = Tokenizer::new(text);
+        let tokens = tokenizer.tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn fold_replaces_operators() {
+        let expr = parse("a / b + c / (d / e)").fold(&mut |node| match node {
+            Node::BinaryExpr {
+                op: BinaryOperator::Div,
+                lhs,
+                rhs,
+            } => Node::BinaryExpr {
+                op: BinaryOperator::Mul,
+                lhs,
+                rhs,
+            },
+            node => node,
+        });
+        assert_eq!(expr.to_string(), "a * b + c * (d * e)")
+    }
+
+    #[test]
+    fn fold_is_bottom_up() {
+        let mut visited = vec![];
+        parse("1 + 2 * 3").fold(&mut |node| {
+            visited.push(node.to_string());
+            node
+        });
+        assert_eq!(visited, vec!["1", "2", "3", "2 * 3", "1 + 2 * 3"])
+    }
+
+    #[test]
+    fn try_fold_stops_on_error() {
+        let result = parse("x + (y * z)").try_fold(&mut |node| match node {
+            Node::Identifier(name) if name == "y" => Err(name),
+            node => Ok(node),
+        });
+        assert_eq!(result, Err("y".to_string()))
+    }
+
+    #[test]
+    fn substitute_variable() {
+        let expr = parse("x * 2 + y").substitute("x", &parse("a + b"));
+        assert_eq!(expr.to_string(), "(a + b) * 2 + y");
+        let expr = parse("x = x + 1; y = x; x * y").substitute("x", &parse("n"));
+        assert_eq!(expr.to_string(), "x = n + 1; y = x; x * y")
+    }
+
+    #[test]
+    fn substitute_subtree() {
+        let expr = parse("(x + 1) * (x + 1) - x").substitute_subtree(&parse("x + 1"), &parse("t"));
+        assert_eq!(expr.to_string(), "t * t - x");
+        let expr = parse("x + 1").substitute_subtree(&parse("x"), &parse("x + 1"));
+        assert_eq!(expr.to_string(), "x + 1 + 1")
+    }
+
+    #[test]
+    fn rewrite_until_fixpoint() {
+        let expr = parse("x * 1 * 1 + --y").rewrite(&mut |node| match node {
+            Node::BinaryExpr {
+                op: BinaryOperator::Mul,
+                lhs,
+                rhs,
+            } if **rhs == Node::Number(1) => Some((**lhs).clone()),
+            Node::UnaryExpr { child, .. } => match &**child {
+                Node::UnaryExpr { child, .. } => Some((**child).clone()),
+                _ => None,
+            },
+            _ => None,
+        });
+        assert_eq!(expr.to_string(), "x + y")
+    }
+}
@@ -0,0 +1,498 @@
+//!
+//! Formatting of results for display: integers in binary, octal, decimal or
+//! hexadecimal, and floats to a given precision, in fixed or scientific
+//! notation, with digits optionally grouped by a separator and the decimal
+//! point of a [`Locale`]. [`Value`]'s `Display` is the default.
+//!
+//! ```
+//! use rexpr::eval::Value;
+//! use rexpr::format::{FloatFormat, Radix, ValueFormat};
+//!
+//! let hex = ValueFormat::new().with_radix(Radix::Hexadecimal);
+//! assert_eq!(hex.format(&Value::Int(255)), "0xff");
+//! assert_eq!(hex.format(&Value::Int(-16)), "-0x10");
+//! assert_eq!(hex.format(&Value::Float(0.5)), "0.5");
+//!
+//! let mut fixed = ValueFormat::new();
+//! fixed.set_format(FloatFormat::Fixed(4));
+//! assert_eq!(fixed.format(&Value::Float(2.0 / 3.0)), "0.6667");
+//! assert_eq!(fixed.format(&Value::Float(0.5)), "0.5000");
+//! fixed.set_trailing_zeros(false);
+//! assert_eq!(fixed.format(&Value::Float(0.5)), "0.5");
+//!
+//! let grouped = ValueFormat::new().with_separator(Some(','));
+//! assert_eq!(grouped.format(&Value::Int(4294967)), "4,294,967");
+//!
+//! let german = ValueFormat::new().with_locale("de_DE.UTF-8".parse().unwrap());
+//! assert_eq!(german.format(&Value::Float(1234.5)), "1.234,5");
+//! ```
+//!
+//! Negative integers keep their sign rather than showing the bits of their
+//! two's complement, and floats are always shown in decimal.
+//!
+use crate::eval::Value;
+use crate::prelude::*;
+use core::fmt::Display;
+use core::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Radix {
+    Binary,
+    Octal,
+    #[default]
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    pub const ALL: [Radix; 4] = [
+        Radix::Decimal,
+        Radix::Hexadecimal,
+        Radix::Octal,
+        Radix::Binary,
+    ];
+
+    /// The prefix of integers in this radix, as in Rust literals.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+            Radix::Decimal => "",
+            Radix::Hexadecimal => "0x",
+        }
+    }
+
+    /// A short name, also accepted by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Radix::Binary => "bin",
+            Radix::Octal => "oct",
+            Radix::Decimal => "dec",
+            Radix::Hexadecimal => "hex",
+        }
+    }
+
+    /// The digits between separators: thousands in decimal and octal, and
+    /// 16 bits in hexadecimal and binary.
+    pub fn group_size(self) -> usize {
+        match self {
+            Radix::Binary | Radix::Hexadecimal => 4,
+            Radix::Octal | Radix::Decimal => 3,
+        }
+    }
+
+    /// `n` in this radix, with its prefix after any sign.
+    pub fn format_int(self, n: i32) -> String {
+        self.format_grouped(n, None)
+    }
+
+    /// `n` in this radix, with `separator` between groups of digits.
+    pub fn format_grouped(self, n: i32, separator: Option<char>) -> String {
+        let sign = if n < 0 { "-" } else { "" };
+        let magnitude = n.unsigned_abs();
+        let digits = match self {
+            Radix::Binary => format!("{:b}", magnitude),
+            Radix::Octal => format!("{:o}", magnitude),
+            Radix::Decimal => format!("{}", magnitude),
+            Radix::Hexadecimal => format!("{:x}", magnitude),
+        };
+        let digits = match separator {
+            Some(separator) => group(&digits, self.group_size(), separator),
+            None => digits,
+        };
+        format!("{}{}{}", sign, self.prefix(), digits)
+    }
+}
+
+impl Display for Radix {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct RadixError {
+    message: String,
+}
+
+impl Display for RadixError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "RadixError: {}", self.message)
+    }
+}
+
+impl core::error::Error for RadixError {}
+
+impl FromStr for Radix {
+    type Err = RadixError;
+
+    /// A radix by its name, such as `hex`, or its base, such as `16`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bin" | "2" => Ok(Radix::Binary),
+            "oct" | "8" => Ok(Radix::Octal),
+            "dec" | "10" => Ok(Radix::Decimal),
+            "hex" | "16" => Ok(Radix::Hexadecimal),
+            _ => Err(RadixError {
+                message: format!("unknown radix {}, expected bin, oct, dec or hex", s),
+            }),
+        }
+    }
+}
+
+/// `digits` with `separator` between groups of `size`, counted from the
+/// right.
+fn group(digits: &str, size: usize, separator: char) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(size) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+///
+/// The decimal point and digit separator of a region, as POSIX's
+/// `LC_NUMERIC` sets them.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Locale {
+    pub decimal_point: char,
+    pub separator: Option<char>,
+}
+
+impl Locale {
+    /// The `C` locale: a decimal point and no grouping.
+    pub const C: Locale = Locale {
+        decimal_point: '.',
+        separator: None,
+    };
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::C
+    }
+}
+
+#[derive(Debug)]
+pub struct LocaleError {
+    message: String,
+}
+
+impl Display for LocaleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "LocaleError: {}", self.message)
+    }
+}
+
+impl core::error::Error for LocaleError {}
+
+impl FromStr for Locale {
+    type Err = LocaleError;
+
+    ///
+    /// A locale by its POSIX name, such as `de_DE.UTF-8` or `fr_FR@euro`.
+    /// Only the language and territory are looked at, and only languages
+    /// whose conventions are known are accepted.
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.split(['.', '@']).next().unwrap_or(s);
+        let (language, territory) = name.split_once('_').unwrap_or((name, ""));
+        let (decimal_point, separator) = match (language, territory) {
+            ("C" | "POSIX", _) | ("", _) => return Ok(Locale::C),
+            ("de" | "it", "CH") => ('.', '\''),
+            (
+                "da" | "de" | "el" | "es" | "hr" | "id" | "it" | "nl" | "pt" | "ro" | "sl" | "sr"
+                | "tr",
+                _,
+            ) => (',', '.'),
+            (
+                "bg" | "cs" | "et" | "fi" | "fr" | "hu" | "lt" | "lv" | "nb" | "nn" | "no" | "pl"
+                | "ru" | "sk" | "sv" | "uk",
+                _,
+            ) => (',', '\u{a0}'),
+            ("en" | "he" | "hi" | "ja" | "ko" | "th" | "zh", _) => ('.', ','),
+            _ => {
+                return Err(LocaleError {
+                    message: format!("unknown locale {}", s),
+                })
+            }
+        };
+        Ok(Locale {
+            decimal_point,
+            separator: Some(separator),
+        })
+    }
+}
+
+/// How to show floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FloatFormat {
+    /// The fewest digits that read back as the same float, as in `1.5` or
+    /// `0.1`.
+    #[default]
+    Shortest,
+    /// This many digits after the point, as in `1.5000`.
+    Fixed(usize),
+    /// This many digits after the point of the mantissa, as in `1.5000e3`.
+    Scientific(usize),
+}
+
+impl FloatFormat {
+    /// The digits after the point, unless shortest.
+    pub fn precision(self) -> Option<usize> {
+        match self {
+            FloatFormat::Shortest => None,
+            FloatFormat::Fixed(digits) | FloatFormat::Scientific(digits) => Some(digits),
+        }
+    }
+}
+
+/// Removes the zeros at the end of the fraction of `number`, and the point
+/// if none are left, keeping any exponent.
+fn trim_zeros(number: &str) -> String {
+    let (mantissa, exponent) = match number.find('e') {
+        Some(i) => number.split_at(i),
+        None => (number, ""),
+    };
+    let mantissa = match mantissa.contains('.') {
+        true => mantissa.trim_end_matches('0').trim_end_matches('.'),
+        false => mantissa,
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+/// How to show values, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueFormat {
+    radix: Radix,
+    float: FloatFormat,
+    trailing_zeros: bool,
+    separator: Option<char>,
+    decimal_point: char,
+}
+
+impl Default for ValueFormat {
+    fn default() -> Self {
+        ValueFormat {
+            radix: Radix::default(),
+            float: FloatFormat::default(),
+            trailing_zeros: true,
+            separator: None,
+            decimal_point: '.',
+        }
+    }
+}
+
+impl ValueFormat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows integers in `radix`.
+    pub fn with_radix(mut self, radix: Radix) -> Self {
+        self.radix = radix;
+        self
+    }
+
+    pub fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    /// Shows floats as `float` says.
+    pub fn with_format(mut self, float: FloatFormat) -> Self {
+        self.float = float;
+        self
+    }
+
+    pub fn set_format(&mut self, float: FloatFormat) {
+        self.float = float;
+    }
+
+    pub fn float_format(&self) -> FloatFormat {
+        self.float
+    }
+
+    ///
+    /// Whether fixed and scientific floats keep the zeros that pad them to
+    /// their precision, as in `0.5000`, or drop them, as in `0.5`. They are
+    /// kept by default.
+    ///
+    pub fn with_trailing_zeros(mut self, keep: bool) -> Self {
+        self.trailing_zeros = keep;
+        self
+    }
+
+    pub fn set_trailing_zeros(&mut self, keep: bool) {
+        self.trailing_zeros = keep;
+    }
+
+    pub fn trailing_zeros(&self) -> bool {
+        self.trailing_zeros
+    }
+
+    ///
+    /// Puts `separator`, such as `_` or `,`, between groups of digits of
+    /// integers and of the integer part of floats, or nothing for `None`,
+    /// the default.
+    ///
+    pub fn with_separator(mut self, separator: Option<char>) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    pub fn set_separator(&mut self, separator: Option<char>) {
+        self.separator = separator;
+    }
+
+    pub fn separator(&self) -> Option<char> {
+        self.separator
+    }
+
+    /// Shows floats with the decimal point of `locale`, and groups digits
+    /// with its separator.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.set_locale(locale);
+        self
+    }
+
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.decimal_point = locale.decimal_point;
+        self.separator = locale.separator;
+    }
+
+    pub fn decimal_point(&self) -> char {
+        self.decimal_point
+    }
+
+    pub fn format(&self, value: &Value) -> String {
+        match value {
+            Value::Int(n) => self.radix.format_grouped(*n, self.separator),
+            Value::Float(x) => self.format_float(*x),
+        }
+    }
+
+    pub fn format_float(&self, x: f64) -> String {
+        let number = match self.float {
+            FloatFormat::Shortest => Value::Float(x).to_string(),
+            _ if !x.is_finite() => format!("{}", x),
+            FloatFormat::Fixed(digits) => format!("{:.*}", digits, x),
+            FloatFormat::Scientific(digits) => format!("{:.*e}", digits, x),
+        };
+        let number = match self.trailing_zeros || self.float == FloatFormat::Shortest {
+            true => number,
+            false => trim_zeros(&number),
+        };
+        let number = match self.decimal_point {
+            '.' => number,
+            point => number.replacen('.', point.encode_utf8(&mut [0; 4]), 1),
+        };
+        match self.separator {
+            Some(separator) => {
+                let sign = if number.starts_with('-') { 1 } else { 0 };
+                let end = number[sign..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or(number.len(), |i| sign + i);
+                let integer = group(&number[sign..end], 3, separator);
+                format!("{}{}{}", &number[..sign], integer, &number[end..])
+            }
+            None => number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_in_each_radix() {
+        let formats: Vec<String> = Radix::ALL
+            .iter()
+            .map(|radix| radix.format_int(10))
+            .collect();
+        assert_eq!(formats, ["10", "0xa", "0o12", "0b1010"]);
+        assert_eq!(Radix::Hexadecimal.format_int(i32::MIN), "-0x80000000");
+        assert_eq!(Radix::Binary.format_int(0), "0b0");
+        let bin = ValueFormat::new().with_radix(Radix::Binary);
+        assert_eq!(bin.format(&Value::Int(-5)), "-0b101");
+        assert_eq!(bin.format(&Value::Float(2.0)), "2.0")
+    }
+
+    #[test]
+    fn format_floats() {
+        let format = |float, keep, x| {
+            ValueFormat::new()
+                .with_format(float)
+                .with_trailing_zeros(keep)
+                .format_float(x)
+        };
+        assert_eq!(format(FloatFormat::Shortest, false, 2.0), "2.0");
+        assert_eq!(format(FloatFormat::Fixed(2), true, 2.0), "2.00");
+        assert_eq!(format(FloatFormat::Fixed(2), false, 2.0), "2");
+        assert_eq!(format(FloatFormat::Fixed(0), false, 250.0), "250");
+        assert_eq!(format(FloatFormat::Fixed(3), true, -1.0 / 3.0), "-0.333");
+        assert_eq!(format(FloatFormat::Scientific(3), true, 1234.5), "1.234e3");
+        assert_eq!(format(FloatFormat::Scientific(3), false, 1500.0), "1.5e3");
+        assert_eq!(format(FloatFormat::Scientific(2), false, 0.0), "0e0");
+        assert_eq!(format(FloatFormat::Fixed(2), true, f64::NAN), "NaN");
+        assert_eq!(format(FloatFormat::Fixed(2), true, -f64::INFINITY), "-inf");
+        let hex = ValueFormat::new()
+            .with_radix(Radix::Hexadecimal)
+            .with_format(FloatFormat::Fixed(1));
+        assert_eq!(hex.format(&Value::Int(10)), "0xa");
+        assert_eq!(hex.format(&Value::Float(10.0)), "10.0")
+    }
+
+    #[test]
+    fn group_digits() {
+        let underscores = |radix: Radix, n| radix.format_grouped(n, Some('_'));
+        assert_eq!(underscores(Radix::Decimal, 1_000_000), "1_000_000");
+        assert_eq!(underscores(Radix::Decimal, -12345), "-12_345");
+        assert_eq!(underscores(Radix::Decimal, 999), "999");
+        assert_eq!(underscores(Radix::Hexadecimal, -1 << 20), "-0x10_0000");
+        assert_eq!(underscores(Radix::Binary, 10), "0b1010");
+        assert_eq!(underscores(Radix::Octal, 4096), "0o10_000");
+        let commas = ValueFormat::new().with_separator(Some(','));
+        assert_eq!(commas.format_float(-1234567.25), "-1,234,567.25");
+        assert_eq!(commas.format_float(1e21), "1e21");
+        assert_eq!(commas.format_float(f64::INFINITY), "inf");
+        let fixed = commas.with_format(FloatFormat::Fixed(1));
+        assert_eq!(fixed.format_float(4294967296.0), "4,294,967,296.0")
+    }
+
+    #[test]
+    fn format_in_locales() {
+        let locale = |name: &str| ValueFormat::new().with_locale(name.parse().unwrap());
+        let german = locale("de_DE.UTF-8");
+        assert_eq!(german.format(&Value::Int(-1234567)), "-1.234.567");
+        assert_eq!(german.format_float(1234567.25), "1.234.567,25");
+        let sci = german.with_format(FloatFormat::Scientific(2));
+        assert_eq!(sci.format_float(1500.0), "1,50e3");
+        assert_eq!(locale("fr_FR@euro").format_float(1e4), "10\u{a0}000,0");
+        assert_eq!(locale("de_CH").format_float(1e4), "10'000.0");
+        assert_eq!(locale("en_GB").format_float(1e4), "10,000.0");
+        assert_eq!(locale("C.UTF-8").format_float(1e4), "10000.0");
+        assert_eq!(
+            "tlh".parse::<Locale>().unwrap_err().to_string(),
+            "LocaleError: unknown locale tlh"
+        )
+    }
+
+    #[test]
+    fn parse_radix() {
+        assert_eq!("hex".parse::<Radix>().unwrap(), Radix::Hexadecimal);
+        assert_eq!("8".parse::<Radix>().unwrap(), Radix::Octal);
+        assert_eq!(
+            "12".parse::<Radix>().unwrap_err().to_string(),
+            "RadixError: unknown radix 12, expected bin, oct, dec or hex"
+        )
+    }
+}
@@ -0,0 +1,241 @@
+//!
+//! New syntax for the parser, added without changing it: prefix, infix and
+//! postfix operators, each made of a symbol, a precedence and a function
+//! building the tree it stands for out of existing nodes.
+//!
+//! ```
+//! use rexpr::grammar::Grammar;
+//! use rexpr::parser::Node;
+//!
+//! let mut grammar = Grammar::new();
+//! grammar.register_infix("..", 1, |lhs, rhs| Node::call("range", [lhs, rhs]));
+//! grammar.register_delimited("|", "|", |child| Node::call("abs", [child]));
+//! grammar.register_postfix("!", 9, |child| Node::call("fact", [child]));
+//!
+//! let node = grammar.parser("|x - 1| .. n! + 1").parse().unwrap();
+//! assert_eq!(node.to_string(), "range(abs(x - 1), fact(n) + 1)");
+//! ```
+//!
+//! Precedences are those of the built-in operators, from 1, looser than
+//! `||`, through 2 for `||`, 3 for `&&`, 4 for shifts, 5 for `+` and `-` and
+//! 6 for `*` and `/`, to 7 for unary operators, 8 for `^` and 9 for atoms.
+//! Infix operators are left associative and bind at 1 to 6; higher
+//! precedences are taken as 6. Postfix operators at 7 or higher apply to
+//! atoms directly.
+//!
+//! The spans of the operands become the children of the span tree of the
+//! result, so constructors should keep the operands as its direct children,
+//! in order, as the arguments of a call do.
+//!
+use crate::parser::{Node, Parser};
+use crate::prelude::*;
+use crate::tokenizer::Lexer;
+use alloc::sync::Arc;
+
+/// Builds the tree for a prefix, postfix or delimited operator.
+pub type UnaryFn = Arc<dyn Fn(Node) -> Node + Send + Sync>;
+
+/// Builds the tree for an infix operator.
+pub type BinaryFn = Arc<dyn Fn(Node, Node) -> Node + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) struct Prefix {
+    /// How tightly the operand binds, or `None` for an operand running up to
+    /// the closing symbol.
+    pub(crate) precedence: Option<u8>,
+    pub(crate) close: Option<&'static str>,
+    pub(crate) build: UnaryFn,
+}
+
+#[derive(Clone)]
+pub(crate) struct Operator<F> {
+    pub(crate) precedence: u8,
+    pub(crate) build: F,
+}
+
+/// The operators added to the parser, see the [module docs](self).
+#[derive(Clone, Default)]
+pub struct Grammar {
+    /// Every symbol used, for the lexer.
+    symbols: Vec<&'static str>,
+    prefix: Map<&'static str, Prefix>,
+    infix: Map<&'static str, Operator<BinaryFn>>,
+    postfix: Map<&'static str, Operator<UnaryFn>>,
+}
+
+type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+impl Grammar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Makes `symbol` lexable. Symbols are made of punctuation: the lexer
+    /// emits one wherever it is longer than the built-in token at the same
+    /// place, so `==` wins over `=` but `|` leaves `||` alone.
+    ///
+    fn symbol(&mut self, symbol: &'static str) {
+        if !self.symbols.contains(&symbol) {
+            self.symbols.push(symbol);
+        }
+    }
+
+    /// Parses `symbol operand`, with the operand binding at `precedence`.
+    pub fn register_prefix(
+        &mut self,
+        symbol: &'static str,
+        precedence: u8,
+        build: impl Fn(Node) -> Node + Send + Sync + 'static,
+    ) {
+        self.symbol(symbol);
+        let prefix = Prefix {
+            precedence: Some(precedence),
+            close: None,
+            build: Arc::new(build),
+        };
+        self.prefix.insert(symbol, prefix);
+    }
+
+    /// Parses `open expression close`, such as `|x|`.
+    pub fn register_delimited(
+        &mut self,
+        open: &'static str,
+        close: &'static str,
+        build: impl Fn(Node) -> Node + Send + Sync + 'static,
+    ) {
+        self.symbol(open);
+        self.symbol(close);
+        let prefix = Prefix {
+            precedence: None,
+            close: Some(close),
+            build: Arc::new(build),
+        };
+        self.prefix.insert(open, prefix);
+    }
+
+    /// Parses `lhs symbol rhs` at `precedence`.
+    pub fn register_infix(
+        &mut self,
+        symbol: &'static str,
+        precedence: u8,
+        build: impl Fn(Node, Node) -> Node + Send + Sync + 'static,
+    ) {
+        self.symbol(symbol);
+        let build: BinaryFn = Arc::new(build);
+        let precedence = precedence.clamp(1, 6);
+        self.infix.insert(symbol, Operator { precedence, build });
+    }
+
+    /// Parses `operand symbol`, where the operand is an expression at
+    /// `precedence` or tighter.
+    pub fn register_postfix(
+        &mut self,
+        symbol: &'static str,
+        precedence: u8,
+        build: impl Fn(Node) -> Node + Send + Sync + 'static,
+    ) {
+        self.symbol(symbol);
+        let build: UnaryFn = Arc::new(build);
+        let precedence = if precedence > 6 { 9 } else { precedence.max(1) };
+        self.postfix.insert(symbol, Operator { precedence, build });
+    }
+
+    /// The symbols of every operator, to pass to [`Lexer::with_symbols`].
+    pub fn symbols(&self) -> &[&'static str] {
+        &self.symbols
+    }
+
+    /// A parser for `text` that knows the operators of this grammar.
+    pub fn parser<'a>(&'a self, text: &'a str) -> Parser<'a> {
+        Parser::from_lexer(Lexer::new(text).with_symbols(&self.symbols)).with_grammar(self)
+    }
+
+    pub(crate) fn prefix(&self, symbol: &str) -> Option<&Prefix> {
+        self.prefix.get(symbol)
+    }
+
+    pub(crate) fn infix(&self, symbol: &str, precedence: u8) -> Option<&BinaryFn> {
+        self.infix
+            .get(symbol)
+            .filter(|infix| infix.precedence == precedence)
+            .map(|infix| &infix.build)
+    }
+
+    pub(crate) fn postfix(&self, symbol: &str, precedence: u8) -> Option<&UnaryFn> {
+        self.postfix
+            .get(symbol)
+            .filter(|postfix| postfix.precedence == precedence)
+            .map(|postfix| &postfix.build)
+    }
+}
+
+impl core::fmt::Debug for Grammar {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Grammar")
+            .field("prefix", &self.prefix.keys().collect::<Vec<_>>())
+            .field("infix", &self.infix.keys().collect::<Vec<_>>())
+            .field("postfix", &self.postfix.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Span;
+
+    fn grammar() -> Grammar {
+        let mut grammar = Grammar::new();
+        grammar.register_infix("..", 1, |lhs, rhs| Node::call("range", [lhs, rhs]));
+        grammar.register_infix("==", 2, |lhs, rhs| Node::call("eq", [lhs, rhs]));
+        grammar.register_infix("%", 6, |lhs, rhs| Node::call("rem", [lhs, rhs]));
+        grammar.register_prefix("+", 7, |child| child);
+        grammar.register_prefix("√", 9, |child| Node::call("sqrt", [child]));
+        grammar.register_delimited("|", "|", |child| Node::call("abs", [child]));
+        grammar.register_postfix("!", 9, |child| Node::call("fact", [child]));
+        grammar.register_postfix("?", 1, |child| Node::call("check", [child]));
+        grammar
+    }
+
+    fn parse(text: &str) -> String {
+        grammar().parser(text).parse().unwrap().to_string()
+    }
+
+    #[test]
+    fn parse_added_operators() {
+        assert_eq!(parse("1 .. 2 + 3 * 4"), "range(1, 2 + 3 * 4)");
+        assert_eq!(parse("a == b || c"), "eq(a, b) || c");
+        assert_eq!(parse("a + b % c % d"), "a + rem(rem(b, c), d)");
+        assert_eq!(parse("+x ^ 2 - √y * 2"), "x ^ 2 - sqrt(y) * 2");
+        assert_eq!(parse("|a - |b| | + 1"), "abs(a - abs(b)) + 1");
+        assert_eq!(parse("-n! ^ 2"), "-fact(n) ^ 2");
+        assert_eq!(parse("x = a || b?; !x"), "x = check(a || b); !x");
+        assert_eq!(parse("a || b && c"), "a || b && c")
+    }
+
+    #[test]
+    fn added_operator_spans() {
+        let (_, spans) = grammar().parser("|x| .. n!").parse_spanned().unwrap();
+        let span = |offset, len| Span {
+            line: 1,
+            col: offset as u32 + 1,
+            offset,
+            len,
+        };
+        assert_eq!(spans.span, span(0, 9));
+        assert_eq!(spans.get(&[0]).unwrap().span, span(0, 3));
+        assert_eq!(spans.get(&[0, 0]).unwrap().span, span(1, 1));
+        assert_eq!(spans.get(&[1]).unwrap().span, span(7, 2))
+    }
+
+    #[test]
+    fn reject_without_grammar() {
+        let err = grammar().parser("|x + 1").parse().unwrap_err();
+        assert_eq!(err.to_string(), "ParserError: unclosed |");
+        assert_eq!(err.hint(), Some("expected `|`"));
+        assert!(grammar().parser("1 ..").parse().is_err());
+        assert!("1 .. 2".parse::<Node>().is_err());
+        assert!("n!".parse::<Node>().is_err())
+    }
+}
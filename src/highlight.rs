@@ -0,0 +1,170 @@
+//!
+//! Classification of source text for syntax highlighting. Every token is
+//! given a [`TokenClass`] and its span, so editors, the language server and
+//! the REPL color input the same way.
+//!
+//! ```
+//! use rexpr::highlight::{classify, TokenClass};
+//!
+//! let classes: Vec<TokenClass> = classify("x = sqrt(2) // root")
+//!     .iter()
+//!     .map(|token| token.class)
+//!     .collect();
+//! assert_eq!(
+//!     classes,
+//!     [
+//!         TokenClass::Identifier,
+//!         TokenClass::Operator,
+//!         TokenClass::Function,
+//!         TokenClass::Paren,
+//!         TokenClass::Number,
+//!         TokenClass::Paren,
+//!         TokenClass::Comment,
+//!     ]
+//! );
+//! ```
+//!
+//! Classification only looks at neighbouring tokens, not at the parse, so it
+//! works on incomplete and invalid input as it is typed.
+//!
+use crate::prelude::*;
+use crate::tokenizer::{Lexer, Span, SpannedToken, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Number,
+    /// Arithmetic, logical and assignment operators, and the symbols of a
+    /// grammar.
+    Operator,
+    Paren,
+    /// Variables and placeholders.
+    Identifier,
+    /// An identifier followed by `(`.
+    Function,
+    /// `use` at the start of a directive, and the keywords given to the
+    /// lexer.
+    Keyword,
+    Comment,
+    /// `;` and `,`.
+    Punctuation,
+    /// A character the lexer does not accept.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub class: TokenClass,
+    pub span: Span,
+}
+
+/// Classifies every token of `text`, comments and errors included.
+pub fn classify(text: &str) -> Vec<SemanticToken> {
+    classify_lexer(Lexer::new(text))
+}
+
+///
+/// Classifies the tokens of `lexer`, which is set to keep comments and
+/// recover from errors. Use it to pass keywords or the symbols of a
+/// [`Grammar`](crate::grammar::Grammar).
+///
+pub fn classify_lexer(lexer: Lexer) -> Vec<SemanticToken> {
+    let results: Vec<_> = lexer.keep_comments(true).recover(true).collect();
+    // The next token that is not a comment, for each token.
+    let mut next = vec![None; results.len()];
+    let mut following: Option<Token> = None;
+    for (i, result) in results.iter().enumerate().rev() {
+        next[i] = following;
+        match result {
+            Ok(SpannedToken {
+                token: Token::Comment(_),
+                ..
+            }) => {}
+            Ok(token) => following = Some(token.token),
+            Err(_) => following = None,
+        }
+    }
+    results
+        .iter()
+        .zip(next)
+        .map(|(result, next)| match result {
+            Ok(token) => SemanticToken {
+                class: class(token.token, next),
+                span: token.span,
+            },
+            Err(err) => SemanticToken {
+                class: TokenClass::Error,
+                span: err.span(),
+            },
+        })
+        .collect()
+}
+
+fn class(token: Token, next: Option<Token>) -> TokenClass {
+    match (token, next) {
+        (Token::Number(_), _) => TokenClass::Number,
+        (Token::Identifier(_), Some(Token::LParen)) => TokenClass::Function,
+        (Token::Identifier("use"), Some(Token::Identifier(path))) if path.contains("::") => {
+            TokenClass::Keyword
+        }
+        (Token::Identifier(_), _) => TokenClass::Identifier,
+        (Token::Keyword(_), _) => TokenClass::Keyword,
+        (Token::Comment(_), _) => TokenClass::Comment,
+        (Token::LParen | Token::RParen, _) => TokenClass::Paren,
+        (Token::Semicolon | Token::Comma | Token::Space, _) => TokenClass::Punctuation,
+        _ => TokenClass::Operator,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::grammar::Grammar;
+
+    fn classes(tokens: Vec<SemanticToken>) -> Vec<(TokenClass, usize, usize)> {
+        tokens
+            .iter()
+            .map(|token| (token.class, token.span.offset, token.span.len))
+            .collect()
+    }
+
+    #[test]
+    fn classify_tokens() {
+        use TokenClass::*;
+        assert_eq!(
+            classes(classify("use math::*;\nf /* call */ (a, $1) << 2 @")),
+            [
+                (Keyword, 0, 3),
+                (Identifier, 4, 7),
+                (Punctuation, 11, 1),
+                (Function, 13, 1),
+                (Comment, 15, 10),
+                (Paren, 26, 1),
+                (Identifier, 27, 1),
+                (Punctuation, 28, 1),
+                (Identifier, 30, 2),
+                (Paren, 32, 1),
+                (Operator, 34, 2),
+                (Number, 37, 1),
+                (Error, 39, 1),
+            ]
+        );
+        assert_eq!(
+            classes(classify("use + 1")),
+            [(Identifier, 0, 3), (Operator, 4, 1), (Number, 6, 1)]
+        )
+    }
+
+    #[test]
+    fn classify_grammar_symbols() {
+        let mut grammar = Grammar::new();
+        grammar.register_infix("..", 1, |lhs, _| lhs);
+        let lexer = Lexer::new("1 .. n").with_symbols(grammar.symbols());
+        let tokens: Vec<TokenClass> = classify_lexer(lexer)
+            .iter()
+            .map(|token| token.class)
+            .collect();
+        use TokenClass::*;
+        assert_eq!(tokens, [Number, Operator, Identifier]);
+        assert_eq!(classify("1 .. n")[1].class, Error)
+    }
+}
@@ -0,0 +1,328 @@
+//!
+//! Incremental parsing for editors. A [`Document`] keeps the tree of each
+//! statement and, after an edit, reparses only from the first statement the
+//! edit touches until the statements line up with the old ones again. The
+//! rest are reused, with their spans moved by the edit.
+//!
+//! ```
+//! use rexpr::incremental::Document;
+//!
+//! let mut doc = Document::new("a = 1;\nb = a * 2;\nb + 1");
+//! // Only `a = 1;` is reparsed.
+//! assert_eq!(doc.edit(4..5, "10"), 1);
+//! let (node, spans) = doc.parse().unwrap();
+//! assert_eq!(node.to_string(), "a = 10; b = a * 2; b + 1");
+//! assert_eq!(spans.get(&[2]).unwrap().span.offset, 19);
+//! ```
+//!
+use crate::parser::{block, Node, Parsed, Parser, ParserError, SpanTree};
+use crate::prelude::*;
+use crate::tokenizer::{Lexer, Span, Token};
+use core::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    line: u32,
+    col: u32,
+    offset: usize,
+}
+
+const START: Position = Position {
+    line: 1,
+    col: 1,
+    offset: 0,
+};
+
+impl Position {
+    /// The position of `offset` in `text`, counting from `self`.
+    fn advance(self, text: &str, offset: usize) -> Position {
+        let skipped = &text[self.offset..offset];
+        match skipped.rfind('\n') {
+            Some(i) => Position {
+                line: self.line + skipped.matches('\n').count() as u32,
+                col: skipped[i + 1..].chars().count() as u32 + 1,
+                offset,
+            },
+            None => Position {
+                col: self.col + skipped.chars().count() as u32,
+                offset,
+                ..self
+            },
+        }
+    }
+}
+
+/// A statement together with the whitespace and comments before it and the
+/// `;` after it, so that consecutive statements cover the whole text.
+#[derive(Debug, Clone)]
+struct Statement {
+    start: Position,
+    /// The offset just past the `;`, or the end of the text.
+    end: usize,
+    /// `None` for the blank text after a final `;`.
+    parsed: Option<Result<Parsed, Vec<ParserError>>>,
+}
+
+/// Lexes and parses the statement at `start`, also returning where the next
+/// one starts if it ends with a `;`.
+fn statement(text: &str, start: Position) -> (Statement, Option<Position>) {
+    let rest = &text[start.offset..];
+    let mut lexer = Lexer::with_position(rest, start.line, start.col, start.offset).recover(true);
+    let mut tokens = vec![];
+    let mut errors: Vec<ParserError> = vec![];
+    let mut closed = false;
+    for token in lexer.by_ref() {
+        match token {
+            Ok(token) => {
+                tokens.push(token);
+                if token.token == Token::Semicolon {
+                    closed = true;
+                    break;
+                }
+            }
+            Err(err) => errors.push(err.into()),
+        }
+    }
+    let (line, col, end) = lexer.position();
+    let parsed = if tokens.is_empty() && errors.is_empty() && start.offset > 0 {
+        None
+    } else {
+        match Parser::new(tokens).parse_spanned() {
+            Ok(parsed) if errors.is_empty() => Some(Ok(parsed)),
+            Ok(_) => Some(Err(errors)),
+            Err(err) => {
+                errors.push(err);
+                errors.sort_by_key(|err| err.span().map_or(usize::MAX, |span| span.offset));
+                Some(Err(errors))
+            }
+        }
+    };
+    let next = closed.then_some(Position {
+        line,
+        col,
+        offset: end,
+    });
+    (Statement { start, end, parsed }, next)
+}
+
+/// How an edit moves the text after it: from the end of the replaced range
+/// in the old text to the end of the replacement in the new one.
+struct Move {
+    from: Position,
+    to: Position,
+}
+
+impl Move {
+    fn offset(&self, offset: usize) -> usize {
+        offset - self.from.offset + self.to.offset
+    }
+
+    /// Moves a position after the edit.
+    fn position(&self, position: Position) -> Position {
+        let col = match position.line == self.from.line {
+            true => position.col - self.from.col + self.to.col,
+            false => position.col,
+        };
+        Position {
+            line: position.line - self.from.line + self.to.line,
+            col,
+            offset: self.offset(position.offset),
+        }
+    }
+
+    fn span(&self, span: &mut Span) {
+        let Position { line, col, offset } = self.position(Position {
+            line: span.line,
+            col: span.col,
+            offset: span.offset,
+        });
+        *span = Span {
+            line,
+            col,
+            offset,
+            len: span.len,
+        };
+    }
+
+    fn spans(&self, tree: &mut SpanTree) {
+        self.span(&mut tree.span);
+        tree.children.iter_mut().for_each(|child| self.spans(child));
+    }
+
+    fn statement(&self, mut stmt: Statement) -> Statement {
+        stmt.start = self.position(stmt.start);
+        stmt.end = self.offset(stmt.end);
+        match &mut stmt.parsed {
+            Some(Ok((_, spans))) => self.spans(spans),
+            Some(Err(errors)) => errors
+                .iter_mut()
+                .filter_map(ParserError::span_mut)
+                .for_each(|span| self.span(span)),
+            None => {}
+        }
+        stmt
+    }
+}
+
+/// Source text kept parsed across edits.
+#[derive(Debug, Clone)]
+pub struct Document {
+    text: String,
+    stmts: Vec<Statement>,
+}
+
+impl Document {
+    pub fn new(text: impl Into<String>) -> Self {
+        let mut doc = Document {
+            text: text.into(),
+            stmts: vec![],
+        };
+        let mut start = Some(START);
+        while let Some(at) = start {
+            let (stmt, next) = statement(&doc.text, at);
+            doc.stmts.push(stmt);
+            start = next;
+        }
+        doc
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    ///
+    /// Replaces the bytes in `range` with `text` and reparses what changed,
+    /// returning the number of statements parsed again. Both ends of
+    /// `range` must lie on character boundaries of the current text.
+    ///
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> usize {
+        // Statements before the edit keep their positions.
+        let first = self
+            .stmts
+            .iter()
+            .position(|stmt| stmt.end >= range.start)
+            .unwrap_or(self.stmts.len() - 1);
+        let last = self
+            .stmts
+            .iter()
+            .rposition(|stmt| stmt.start.offset <= range.end)
+            .unwrap_or(first);
+        let from = self.stmts[last].start.advance(&self.text, range.end);
+        self.text.replace_range(range.clone(), text);
+        let mut start = Some(self.stmts[first].start);
+        let moved = Move {
+            from,
+            to: self.stmts[first]
+                .start
+                .advance(&self.text, range.start + text.len()),
+        };
+
+        let old = self.stmts.split_off(first);
+        let mut reparsed = 0;
+        while let Some(at) = start {
+            let (stmt, next) = statement(&self.text, at);
+            let end = stmt.end;
+            self.stmts.push(stmt);
+            reparsed += 1;
+            start = next;
+            if end < moved.to.offset {
+                continue;
+            }
+            // The first statement is never reused, since it is the only one
+            // that is an error when blank.
+            let old_start = |stmt: &Statement| {
+                stmt.start.offset >= range.end.max(1) && moved.offset(stmt.start.offset) == end
+            };
+            if let Some(i) = old.iter().position(old_start) {
+                let rest = old.into_iter().skip(i);
+                self.stmts.extend(rest.map(|stmt| moved.statement(stmt)));
+                break;
+            }
+        }
+        reparsed
+    }
+
+    /// The tree of the whole text, as [`Parser::parse_recovering`] would
+    /// give it.
+    pub fn parse(&self) -> Result<(Node, SpanTree), Vec<ParserError>> {
+        let mut stmts = vec![];
+        let mut errors = vec![];
+        for parsed in self.stmts.iter().filter_map(|stmt| stmt.parsed.as_ref()) {
+            match parsed {
+                Ok(parsed) => stmts.push(parsed.clone()),
+                Err(errs) => errors.extend(errs.iter().cloned()),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(block(stmts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn full(text: &str) -> Result<(Node, SpanTree), Vec<ParserError>> {
+        Parser::from_lexer(Lexer::new(text).recover(true)).parse_recovering()
+    }
+
+    /// Replaces `old`, the first match of `before` followed by `old`, with
+    /// `new`.
+    fn replace(doc: &mut Document, before: &str, old: &str, new: &str) -> usize {
+        let start = doc.text().find(&format!("{}{}", before, old)).unwrap() + before.len();
+        let reparsed = doc.edit(start..start + old.len(), new);
+        assert_eq!(doc.parse().ok(), full(doc.text()).ok());
+        reparsed
+    }
+
+    #[test]
+    fn edits_match_full_parse() {
+        let mut doc = Document::new("a = 1;\n/* two */ b = a * 2;\n  c = b - a;\nc + 1;\n");
+        assert_eq!(replace(&mut doc, "a = ", "1", "(1 + 2)"), 1);
+        assert_eq!(replace(&mut doc, "/* ", "two", "three"), 1);
+        assert_eq!(replace(&mut doc, "", "  c", "\n\n  c"), 1);
+        assert_eq!(replace(&mut doc, "", "a = (", "z = 0; a = ("), 2);
+        assert_eq!(replace(&mut doc, "b - a", "", "; d = 4"), 2);
+        assert_eq!(replace(&mut doc, "", "z = 0; ", ""), 1);
+        assert_eq!(replace(&mut doc, "c + 1", ";", ""), 1);
+        assert_eq!(
+            doc.parse().unwrap().0.to_string(),
+            "a = 1 + 2; b = a * 2; c = b - a; d = 4; c + 1"
+        )
+    }
+
+    #[test]
+    fn every_small_edit_matches_full_parse() {
+        let text = "a = 1;\n b = a /* ; */ * 2;\n\tc = b - a; c";
+        for i in 0..=text.len() {
+            for insert in [";", "\n", "x", "*/", "/*", "1 +"] {
+                let mut doc = Document::new(text);
+                doc.edit(i..i, insert);
+                assert_eq!(doc.parse().ok(), full(doc.text()).ok(), "{:?}", doc.text());
+                doc.edit(i..i + insert.len(), "");
+                assert_eq!(doc.parse().ok(), full(text).ok(), "{:?}", doc.text());
+            }
+        }
+    }
+
+    #[test]
+    fn reparse_after_comments_and_errors() {
+        let mut doc = Document::new("x = 1; y = 2; x + y");
+        assert_eq!(replace(&mut doc, "x = 1; ", "", "/* "), 1);
+        assert_eq!(replace(&mut doc, "y = 2;", "", " */"), 1);
+        assert_eq!(doc.parse().unwrap().0.to_string(), "x = 1; x + y");
+
+        assert_eq!(replace(&mut doc, "x = 1", "", " +"), 1);
+        let errors = doc.parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "ParserError: unexpected token ;");
+        assert_eq!(replace(&mut doc, "x = 1 +", "", " 1"), 1);
+
+        let mut doc = Document::new("");
+        assert!(doc.parse().is_err());
+        doc.edit(0..0, "1;");
+        assert_eq!(doc.parse().unwrap().0, Node::Number(1))
+    }
+}
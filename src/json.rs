@@ -0,0 +1,630 @@
+//!
+//! Minimal JSON support and the JSON form of the AST.
+//!
+//! Every node is an object with a `type` field:
+//!
+//! | type         | fields                                                                |
+//! |--------------|-----------------------------------------------------------------------|
+//! | `number`     | `value`: integer                                                      |
+//! | `float`      | `value`: number                                                       |
+//! | `identifier` | `name`: string                                                        |
+//! | `unary`      | `op`: `"-" "!"`, `child`: node                                        |
+//! | `binary`     | `op`: `"+" "-" "*" "/" "^" "<<" ">>" "&&" "\|\|"`, `lhs`, `rhs`: node |
+//! | `assign`     | `name`: string, `value`: node                                         |
+//! | `block`      | `body`: array of nodes                                                |
+//! | `call`       | `name`: string, `args`: array of nodes                                |
+//! | `use`        | `path`: string                                                        |
+//!
+//! Names are read as the tokenizer reads identifiers: a word such as `x`, a
+//! placeholder such as `$1` or a path such as `math::sin`. Placeholders are
+//! not assigned or called, and `use` takes a path, which may end in `*`.
+//!
+//! Unknown fields are ignored when reading, so the schema can grow without
+//! breaking older readers.
+//!
+//! Errors are objects with a `code` such as `"E0102"`, the `message`, and
+//! the `span` in the source as an object with `line`, `col`, `offset` and
+//! `len`, or `null` when it is not known. A `hint` and the failed
+//! `expression` are added when there are any.
+//!
+use crate::error::RexprError;
+use crate::parser::*;
+use crate::tokenizer::{Lexer, Span, SpannedToken, Token};
+use std::error::Error;
+use std::fmt::Display;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    message: String,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JsonError: {}", self.message)
+    }
+}
+
+impl Error for JsonError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, JsonError> {
+    Err(JsonError {
+        message: message.into(),
+    })
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut chars = text.chars().peekable();
+        let json = parse_value(&mut chars, 0)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(c) => error(format!("unexpected character {}", c)),
+            None => Ok(json),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+    while matches!(chars.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+        chars.next();
+    }
+}
+
+fn expect_word(chars: &mut Peekable<Chars<'_>>, word: &str, json: Json) -> Result<Json, JsonError> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return error(format!("expected {}", word));
+        }
+    }
+    Ok(json)
+}
+
+/// How deeply arrays and objects may nest in parsed text. Values are parsed
+/// recursively, so the limit keeps untrusted input from overflowing the
+/// stack.
+pub const MAX_NESTING: usize = 1_000;
+
+/// Parses a value nested in `depth` arrays and objects.
+fn parse_value(chars: &mut Peekable<Chars<'_>>, depth: usize) -> Result<Json, JsonError> {
+    skip_whitespace(chars);
+    if let Some('[' | '{') = chars.peek() {
+        if depth == MAX_NESTING {
+            return error(format!(
+                "arrays and objects nested more than {} deep",
+                MAX_NESTING
+            ));
+        }
+    }
+    match chars.peek() {
+        Some('n') => expect_word(chars, "null", Json::Null),
+        Some('t') => expect_word(chars, "true", Json::Bool(true)),
+        Some('f') => expect_word(chars, "false", Json::Bool(false)),
+        Some('"') => Ok(Json::String(parse_string(chars)?)),
+        Some('[') => {
+            chars.next();
+            let mut items = vec![];
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars, depth + 1)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Ok(Json::Array(items)),
+                    _ => return error("expected , or ]"),
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut fields = vec![];
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                if chars.peek() != Some(&'"') {
+                    return error("expected object key");
+                }
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next() != Some(':') {
+                    return error("expected :");
+                }
+                fields.push((key, parse_value(chars, depth + 1)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return error("expected , or }"),
+                }
+            }
+        }
+        Some(c) if *c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match number.parse::<f64>() {
+                Ok(n) => Ok(Json::Number(n)),
+                Err(_) => error(format!("invalid number {}", number)),
+            }
+        }
+        Some(c) => error(format!("unexpected character {}", c)),
+        None => error("unexpected end of input"),
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars<'_>>) -> Result<u32, JsonError> {
+    let mut code = 0;
+    for _ in 0..4 {
+        match chars.next().and_then(|c| c.to_digit(16)) {
+            Some(digit) => code = code * 16 + digit,
+            None => return error("invalid unicode escape"),
+        }
+    }
+    Ok(code)
+}
+
+fn parse_string(chars: &mut Peekable<Chars<'_>>) -> Result<String, JsonError> {
+    chars.next();
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let mut code = parse_hex4(chars)?;
+                    if (0xd800..0xdc00).contains(&code) {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return error("unpaired surrogate");
+                        }
+                        let low = parse_hex4(chars)?;
+                        code =
+                            0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                    }
+                    match char::from_u32(code) {
+                        Some(c) => s.push(c),
+                        None => return error("invalid unicode escape"),
+                    }
+                }
+                _ => return error("invalid escape"),
+            },
+            Some(c) => s.push(c),
+            None => return error("unterminated string"),
+        }
+    }
+}
+
+pub(crate) fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+fn field<'j>(json: &'j Json, key: &str) -> Result<&'j Json, JsonError> {
+    match json.get(key) {
+        Some(value) => Ok(value),
+        None => error(format!("missing field {}", key)),
+    }
+}
+
+fn str_field<'j>(json: &'j Json, key: &str) -> Result<&'j str, JsonError> {
+    match field(json, key)?.as_str() {
+        Some(s) => Ok(s),
+        None => error(format!("field {} must be a string", key)),
+    }
+}
+
+/// The name in field `key`, which must lex as one identifier that `valid`
+/// accepts.
+fn name_field(json: &Json, key: &str, valid: impl Fn(&str) -> bool) -> Result<String, JsonError> {
+    let name = str_field(json, key)?;
+    let mut lexer = Lexer::new(name);
+    match (lexer.next(), lexer.next()) {
+        (
+            Some(Ok(SpannedToken {
+                token: Token::Identifier(word),
+                ..
+            })),
+            None,
+        ) if word == name && valid(name) => Ok(name.to_string()),
+        _ => error(format!(
+            "field {} must be a valid name, got {:?}",
+            key, name
+        )),
+    }
+}
+
+/// Names of variables and functions: globs are only imported.
+fn plain(name: &str) -> bool {
+    !name.ends_with("::*")
+}
+
+fn node_field(json: &Json, key: &str) -> Result<Box<Node>, JsonError> {
+    Ok(Box::new(Node::from_json_value(field(json, key)?)?))
+}
+
+fn nodes_field(json: &Json, key: &str) -> Result<Vec<Node>, JsonError> {
+    match field(json, key)? {
+        Json::Array(items) => items.iter().map(Node::from_json_value).collect(),
+        _ => error(format!("field {} must be an array", key)),
+    }
+}
+
+impl Node {
+    pub fn to_json_value(&self) -> Json {
+        match self {
+            Node::Number(n) => object(vec![
+                ("type", Json::String("number".to_string())),
+                ("value", Json::Number(*n as f64)),
+            ]),
+            // JSON has no infinity or NaN, so those go as the divisions they
+            // print as.
+            Node::Float(Float(x)) if !x.is_finite() => normalize(self).to_json_value(),
+            Node::Float(Float(x)) => object(vec![
+                ("type", Json::String("float".to_string())),
+                ("value", Json::Number(*x)),
+            ]),
+            Node::Identifier(name) => object(vec![
+                ("type", Json::String("identifier".to_string())),
+                ("name", Json::String(name.clone())),
+            ]),
+            Node::UnaryExpr { op, child } => object(vec![
+                ("type", Json::String("unary".to_string())),
+                ("op", Json::String(op.to_string())),
+                ("child", child.to_json_value()),
+            ]),
+            Node::BinaryExpr { op, lhs, rhs } => object(vec![
+                ("type", Json::String("binary".to_string())),
+                ("op", Json::String(op.to_string())),
+                ("lhs", lhs.to_json_value()),
+                ("rhs", rhs.to_json_value()),
+            ]),
+            Node::Assign { name, value } => object(vec![
+                ("type", Json::String("assign".to_string())),
+                ("name", Json::String(name.clone())),
+                ("value", value.to_json_value()),
+            ]),
+            Node::Block(stmts) => object(vec![
+                ("type", Json::String("block".to_string())),
+                (
+                    "body",
+                    Json::Array(stmts.iter().map(Node::to_json_value).collect()),
+                ),
+            ]),
+            Node::Call { name, args } => object(vec![
+                ("type", Json::String("call".to_string())),
+                ("name", Json::String(name.clone())),
+                (
+                    "args",
+                    Json::Array(args.iter().map(Node::to_json_value).collect()),
+                ),
+            ]),
+            Node::Use(path) => object(vec![
+                ("type", Json::String("use".to_string())),
+                ("path", Json::String(path.clone())),
+            ]),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    pub fn from_json_value(json: &Json) -> Result<Node, JsonError> {
+        match str_field(json, "type")? {
+            "number" => match field(json, "value")?.as_f64() {
+                Some(n) if n.fract() == 0.0 && n >= i32::MIN as f64 && n <= i32::MAX as f64 => {
+                    Ok(Node::Number(n as i32))
+                }
+                _ => error("field value must be a 32-bit integer"),
+            },
+            "float" => match field(json, "value")?.as_f64() {
+                Some(x) => Ok(Node::Float(Float(x))),
+                None => error("field value must be a number"),
+            },
+            "identifier" => Ok(Node::Identifier(name_field(json, "name", plain)?)),
+            "unary" => match str_field(json, "op")? {
+                "-" => Ok(Node::UnaryExpr {
+                    op: UnaryOperator::Neg,
+                    child: node_field(json, "child")?,
+                }),
+                "!" => Ok(Node::UnaryExpr {
+                    op: UnaryOperator::Not,
+                    child: node_field(json, "child")?,
+                }),
+                other => error(format!("unknown unary operator {}", other)),
+            },
+            "binary" => {
+                let op = match str_field(json, "op")? {
+                    "+" => BinaryOperator::Plus,
+                    "-" => BinaryOperator::Minus,
+                    "*" => BinaryOperator::Mul,
+                    "/" => BinaryOperator::Div,
+                    "^" => BinaryOperator::Pow,
+                    "<<" => BinaryOperator::Shl,
+                    ">>" => BinaryOperator::Shr,
+                    "&&" => BinaryOperator::And,
+                    "||" => BinaryOperator::Or,
+                    other => return error(format!("unknown binary operator {}", other)),
+                };
+                Ok(Node::BinaryExpr {
+                    op,
+                    lhs: node_field(json, "lhs")?,
+                    rhs: node_field(json, "rhs")?,
+                })
+            }
+            "assign" => Ok(Node::Assign {
+                name: name_field(json, "name", |name| plain(name) && !name.starts_with('$'))?,
+                value: node_field(json, "value")?,
+            }),
+            "block" => Ok(Node::Block(nodes_field(json, "body")?)),
+            "call" => Ok(Node::Call {
+                name: name_field(json, "name", |name| plain(name) && !name.starts_with('$'))?,
+                args: nodes_field(json, "args")?,
+            }),
+            "use" => Ok(Node::Use(name_field(json, "path", |path| {
+                path.contains("::")
+            })?)),
+            other => error(format!("unknown node type {}", other)),
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Node, JsonError> {
+        Node::from_json_value(&Json::parse(text)?)
+    }
+}
+
+fn span_value(span: Option<Span>) -> Json {
+    match span {
+        Some(span) => object(vec![
+            ("line", Json::Number(span.line as f64)),
+            ("col", Json::Number(span.col as f64)),
+            ("offset", Json::Number(span.offset as f64)),
+            ("len", Json::Number(span.len as f64)),
+        ]),
+        None => Json::Null,
+    }
+}
+
+impl RexprError {
+    pub fn to_json_value(&self) -> Json {
+        let (message, hint, expression) = match self {
+            RexprError::Tokenizer(err) => (err.message.as_str(), None, None),
+            RexprError::Parser(err) => (err.message(), err.hint(), None),
+            RexprError::Eval(err) => (err.message(), None, err.expression()),
+        };
+        let mut fields = vec![
+            ("code", Json::String(self.code().to_string())),
+            ("message", Json::String(message.to_string())),
+            ("span", span_value(self.span())),
+        ];
+        if let Some(hint) = hint {
+            fields.push(("hint", Json::String(hint.to_string())));
+        }
+        if let Some(expression) = expression {
+            fields.push(("expression", Json::String(expression.to_string())));
+        }
+        object(fields)
+    }
+
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn parse_json_values() {
+        let json = Json::parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\"é😀"} "#).unwrap();
+        assert_eq!(
+            json,
+            Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number(1.0),
+                        Json::Number(-25.0),
+                        Json::Bool(true),
+                        Json::Null
+                    ])
+                ),
+                ("b".to_string(), Json::String("x\"é😀".to_string())),
+            ])
+        )
+    }
+
+    #[test]
+    fn reject_invalid_json() {
+        assert!(Json::parse("{\"a\": }").is_err());
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("1 2").is_err());
+        let nested = |n| format!("{}{}", "[".repeat(n), "]".repeat(n));
+        assert!(Json::parse(&nested(MAX_NESTING)).is_ok());
+        let err = Json::parse(&nested(200_000)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "JsonError: arrays and objects nested more than 1000 deep"
+        );
+    }
+
+    #[test]
+    fn node_to_json() {
+        let tokenizer = Tokenizer::new("x = -1 + 2");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            expr.to_json(),
+            concat!(
+                r#"{"type":"assign","name":"x","value":{"type":"binary","op":"+","#,
+                r#""lhs":{"type":"unary","op":"-","child":{"type":"number","value":1}},"#,
+                r#""rhs":{"type":"number","value":2}}}"#
+            )
+        )
+    }
+
+    #[test]
+    fn node_json_round_trip() {
+        let tokenizer = Tokenizer::new("a = 2.5; b = a * (3 - a) / 4; -b || !a && max(b, 1)");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(Node::from_json(&expr.to_json()).unwrap(), expr);
+        let infinity = Node::Float(Float(f64::INFINITY));
+        assert_eq!(
+            Node::from_json(&infinity.to_json()).unwrap(),
+            normalize(&infinity)
+        )
+    }
+
+    #[test]
+    fn error_to_json() {
+        let err = "1 + (2".parse::<Node>().unwrap_err();
+        assert_eq!(
+            err.to_json(),
+            r#"{"code":"E0001","message":"unknow token","span":{"line":1,"col":7,"offset":6,"len":0},"hint":"expected `)`"}"#
+        );
+        let err = crate::eval_str("x = 0; 1 / x").unwrap_err();
+        let json = err.to_json_value();
+        assert_eq!(json.get("code").and_then(Json::as_str), Some("E0102"));
+        assert_eq!(json.get("expression").and_then(Json::as_str), Some("1 / x"));
+        let json = crate::eval_str("a @").unwrap_err().to_json_value();
+        assert_eq!(json.get("code").and_then(Json::as_str), Some("E0006"));
+        let err = crate::eval_str("y").unwrap_err();
+        assert_eq!(
+            err.to_json(),
+            r#"{"code":"E0101","message":"undefined variable y","span":{"line":1,"col":1,"offset":0,"len":1},"expression":"y"}"#
+        )
+    }
+
+    #[test]
+    fn reject_invalid_node() {
+        assert!(Node::from_json(r#"{"type":"number","value":1.5}"#).is_err());
+        assert!(Node::from_json(r#"{"type":"binary","op":"%","lhs":{},"rhs":{}}"#).is_err());
+        assert!(Node::from_json(r#"{"value":1}"#).is_err());
+        let err = Node::from_json(r#"{"type":"identifier","name":"x + 1"}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"JsonError: field name must be a valid name, got "x + 1""#
+        );
+        for name in ["", "1x", "x y", " x", "$0", "math::", "math::*", "x.y"] {
+            let json = format!(r#"{{"type":"identifier","name":"{}"}}"#, name);
+            assert!(Node::from_json(&json).is_err(), "{}", name);
+        }
+        let value = r#""value":{"type":"number","value":1}"#;
+        assert!(Node::from_json(&format!(r#"{{"type":"assign","name":"$1",{}}}"#, value)).is_err());
+        assert!(Node::from_json(r#"{"type":"call","name":"$1","args":[]}"#).is_err());
+        assert!(Node::from_json(r#"{"type":"use","path":"math"}"#).is_err());
+        let path = Node::from_json(r#"{"type":"use","path":"math::*"}"#).unwrap();
+        assert_eq!(path, Node::Use("math::*".to_string()));
+        let name = Node::from_json(r#"{"type":"identifier","name":"$2"}"#).unwrap();
+        assert_eq!(name, Node::Identifier("$2".to_string()))
+    }
+}
@@ -0,0 +1,206 @@
+//!
+//! LaTeX rendering of the AST, e.g. `\frac{1}{2} \cdot x`.
+//!
+use crate::parser::*;
+
+fn group(node: &Node, parenthesize: bool) -> String {
+    if parenthesize {
+        format!("\\left({}\\right)", node.to_latex())
+    } else {
+        node.to_latex()
+    }
+}
+
+fn is_or(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::BinaryExpr {
+            op: BinaryOperator::Or,
+            ..
+        }
+    )
+}
+
+/// A float in scientific notation when it prints with an exponent.
+fn float(x: f64) -> String {
+    if x.is_nan() {
+        return "\\mathrm{NaN}".to_string();
+    }
+    if x.is_infinite() {
+        return if x < 0.0 { "-\\infty" } else { "\\infty" }.to_string();
+    }
+    let text = format!("{:?}", x);
+    match text.split_once('e') {
+        Some((mantissa, exponent)) => format!("{} \\times 10^{{{}}}", mantissa, exponent),
+        None => text,
+    }
+}
+
+fn identifier(name: &str) -> String {
+    let name = name.replace('_', "\\_").replace('$', "\\$");
+    if name.chars().count() == 1 {
+        name
+    } else {
+        format!("\\mathrm{{{}}}", name)
+    }
+}
+
+impl Node {
+    pub fn to_latex(&self) -> String {
+        match self {
+            Node::Number(n) => n.to_string(),
+            Node::Float(Float(x)) => float(*x),
+            Node::Identifier(name) => identifier(name),
+            Node::UnaryExpr { op, child } => match op {
+                UnaryOperator::Neg => {
+                    format!(
+                        "-{}",
+                        group(
+                            child,
+                            child.is_additive() || child.is_shift() || child.is_logical()
+                        )
+                    )
+                }
+                UnaryOperator::Not => format!(
+                    "\\lnot {}",
+                    group(
+                        child,
+                        child.is_additive() || child.is_shift() || child.is_logical()
+                    )
+                ),
+            },
+            Node::BinaryExpr { op, lhs, rhs } => match op {
+                BinaryOperator::Plus => format!(
+                    "{} + {}",
+                    group(lhs, lhs.is_shift() || lhs.is_logical()),
+                    group(rhs, rhs.is_negation() || rhs.is_shift() || rhs.is_logical())
+                ),
+                BinaryOperator::Minus => format!(
+                    "{} - {}",
+                    group(lhs, lhs.is_shift() || lhs.is_logical()),
+                    group(
+                        rhs,
+                        rhs.is_additive()
+                            || rhs.is_negation()
+                            || rhs.is_shift()
+                            || rhs.is_logical()
+                    )
+                ),
+                BinaryOperator::Mul => format!(
+                    "{} \\cdot {}",
+                    group(lhs, lhs.is_additive() || lhs.is_shift() || lhs.is_logical()),
+                    group(
+                        rhs,
+                        rhs.is_additive()
+                            || rhs.is_negation()
+                            || rhs.is_shift()
+                            || rhs.is_logical()
+                    )
+                ),
+                BinaryOperator::Div => {
+                    format!("\\frac{{{}}}{{{}}}", lhs.to_latex(), rhs.to_latex())
+                }
+                BinaryOperator::Pow => {
+                    format!("{}^{{{}}}", group(lhs, !lhs.is_atom()), rhs.to_latex())
+                }
+                BinaryOperator::Shl => {
+                    format!(
+                        "{} \\ll {}",
+                        group(lhs, lhs.is_logical()),
+                        group(rhs, rhs.is_shift() || rhs.is_logical())
+                    )
+                }
+                BinaryOperator::Shr => {
+                    format!(
+                        "{} \\gg {}",
+                        group(lhs, lhs.is_logical()),
+                        group(rhs, rhs.is_shift() || rhs.is_logical())
+                    )
+                }
+                BinaryOperator::And => format!(
+                    "{} \\land {}",
+                    group(lhs, is_or(lhs)),
+                    group(rhs, rhs.is_logical())
+                ),
+                BinaryOperator::Or => {
+                    format!("{} \\lor {}", lhs.to_latex(), group(rhs, is_or(rhs)))
+                }
+            },
+            Node::Assign { name, value } => format!("{} = {}", identifier(name), value.to_latex()),
+            Node::Block(stmts) => stmts
+                .iter()
+                .map(Node::to_latex)
+                .collect::<Vec<_>>()
+                .join(";\\quad "),
+            Node::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(Node::to_latex).collect();
+                format!(
+                    "\\operatorname{{{}}}\\left({}\\right)",
+                    name.replace('_', "\\_"),
+                    args.join(", ")
+                )
+            }
+            Node::Use(path) => format!("\\text{{use {}}}", path.replace('_', "\\_")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn latex(text: &str) -> String {
+        let tokenizer = Tokenizer::new(text);
+        let tokens = tokenizer.tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap().to_latex()
+    }
+
+    #[test]
+    fn fraction_and_product() {
+        assert_eq!(latex("1 / 2 * x"), "\\frac{1}{2} \\cdot x")
+    }
+
+    #[test]
+    fn parenthesize_by_precedence() {
+        assert_eq!(
+            latex("(1 + 2) * -(3 - x)"),
+            "\\left(1 + 2\\right) \\cdot \\left(-\\left(3 - x\\right)\\right)"
+        );
+        assert_eq!(latex("1 - (2 + 3)"), "1 - \\left(2 + 3\\right)");
+        assert_eq!(latex("(1 + 2) / (3 * 4)"), "\\frac{1 + 2}{3 \\cdot 4}");
+        assert_eq!(latex("(x << 1) * 2"), "\\left(x \\ll 1\\right) \\cdot 2");
+    }
+
+    #[test]
+    fn powers() {
+        assert_eq!(latex("x ^ (n + 1)"), "x^{n + 1}");
+        assert_eq!(latex("(-x) ^ 2 ^ y"), "\\left(-x\\right)^{2^{y}}");
+        assert_eq!(
+            latex("1.5 ^ 2 * 2.5e-7"),
+            "\\left(1.5\\right)^{2} \\cdot 2.5 \\times 10^{-7}"
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(
+            latex("!(a || b) && c || !d"),
+            "\\lnot \\left(a \\lor b\\right) \\land c \\lor \\lnot d"
+        );
+        assert_eq!(latex("a && (b || c)"), "a \\land \\left(b \\lor c\\right)")
+    }
+
+    #[test]
+    fn identifiers_and_statements() {
+        assert_eq!(
+            latex("unit_price = 3; unit_price * n"),
+            "\\mathrm{unit\\_price} = 3;\\quad \\mathrm{unit\\_price} \\cdot n"
+        );
+        assert_eq!(latex("$1 + x"), "\\mathrm{\\$1} + x");
+        assert_eq!(
+            latex("net_price(p, 2) ^ 2"),
+            "\\operatorname{net\\_price}\\left(p, 2\\right)^{2}"
+        )
+    }
+}
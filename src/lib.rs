@@ -1,3 +1,130 @@
+//!
+//! The tokenizer, parser and evaluator build without `std`, on `core` and
+//! `alloc`, when the default `std` feature is off; the other modules and the
+//! REPL need `std`, as does the `cdylib` the crate also builds, so build the
+//! library alone then with `cargo rustc --lib --crate-type lib`.
+//!
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "quickcheck")]
+pub mod arbitrary;
+#[cfg(feature = "std")]
+pub mod arena;
+#[cfg(feature = "std")]
+pub mod boolean;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod calculus;
+#[cfg(feature = "std")]
+pub mod canonical;
+#[cfg(feature = "std")]
+pub mod closure;
+#[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
+pub mod columnar;
+pub mod compile;
+#[cfg(feature = "std")]
+pub mod config;
+pub mod context;
+pub mod diagnostic;
+#[cfg(feature = "std")]
+pub mod dot;
+#[cfg(feature = "std")]
+pub mod equivalence;
+pub mod error;
 pub mod eval;
+#[cfg(feature = "std")]
+pub mod expression;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fold;
+pub mod format;
+pub mod grammar;
+pub mod highlight;
+pub mod incremental;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod latex;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+#[cfg(feature = "std")]
+pub mod mathml;
+#[cfg(feature = "std")]
+pub mod memory;
+pub mod ops;
+#[cfg(feature = "std")]
+pub mod optimize;
 pub mod parser;
+#[cfg(feature = "std")]
+pub mod plot;
+#[cfg(feature = "plugin")]
+pub mod plugin;
+#[cfg(feature = "std")]
+pub mod polynomial;
+#[cfg(feature = "std")]
+pub mod rpc;
+#[cfg(feature = "std")]
+pub mod sexpr;
+pub mod stdlib;
 pub mod tokenizer;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod wat;
+
+/// What the std prelude adds to core's, for the modules that build without
+/// std.
+mod prelude {
+    pub(crate) use alloc::boxed::Box;
+    pub(crate) use alloc::format;
+    pub(crate) use alloc::string::{String, ToString};
+    pub(crate) use alloc::vec;
+    pub(crate) use alloc::vec::Vec;
+}
+
+/// The map holding variables and functions: a `HashMap` with std and a
+/// `BTreeMap` without.
+#[cfg(feature = "std")]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+use error::RexprError;
+use eval::{Eval, Value};
+use parser::Parser;
+use tokenizer::Lexer;
+
+///
+/// Tokenizes, parses and evaluates `source` in a fresh session, so that any
+/// failure comes back as one error type. Evaluation errors point at the
+/// subexpression that failed:
+///
+/// ```
+/// use rexpr::error::RexprError;
+/// use rexpr::eval::Value;
+///
+/// assert_eq!(rexpr::eval_str("x = 6; x * 7").unwrap(), Value::Int(42));
+/// assert!(matches!(rexpr::eval_str("1 +"), Err(RexprError::Parser(_))));
+/// let err = rexpr::eval_str("x = 1; 6 / (x - x)").unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "EvalError: division by zero in `6 / (x - x)` at col 8"
+/// );
+/// ```
+///
+pub fn eval_str(source: &str) -> Result<Value, RexprError> {
+    let (node, spans) = Parser::from_lexer(Lexer::new(source)).parse_spanned()?;
+    match Eval::new().eval(&node) {
+        Ok(value) => Ok(Value::Int(value)),
+        Err(mut err) => {
+            err.locate(&spans);
+            Err(err.into())
+        }
+    }
+}
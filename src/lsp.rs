@@ -0,0 +1,709 @@
+//!
+//! A language server for `.rx` expression files, built with the `lsp`
+//! feature and run by the `rexpr-lsp` binary. It speaks the Language Server
+//! Protocol over stdin and stdout and provides:
+//!
+//! - diagnostics for syntax errors, updated as the file is edited
+//! - hover evaluation of the sub-expression under the cursor, with the
+//!   statements before it and the standard library in scope
+//! - completion of functions and variables
+//! - formatting, one statement per line
+//! - semantic tokens, classified by [`classify`]
+//!
+//! Documents are kept as [`Document`]s, so each edit only reparses the
+//! statements it touches.
+//!
+use crate::context::Context;
+use crate::eval::Eval;
+use crate::highlight::{classify, TokenClass};
+use crate::incremental::Document;
+use crate::json::{object, Json};
+use crate::parser::{Node, ParserError, SpanTree};
+use crate::tokenizer::{Lexer, Span, Token};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+// Kinds of completion items in the protocol.
+const FUNCTION: f64 = 3.0;
+const VARIABLE: f64 = 6.0;
+
+/// The semantic token types in the legend, indexed by the tokens sent.
+const TOKEN_TYPES: &[&str] = &[
+    "number", "operator", "variable", "function", "keyword", "comment",
+];
+
+fn token_type(class: TokenClass) -> Option<usize> {
+    match class {
+        TokenClass::Number => Some(0),
+        TokenClass::Operator => Some(1),
+        TokenClass::Identifier => Some(2),
+        TokenClass::Function => Some(3),
+        TokenClass::Keyword => Some(4),
+        TokenClass::Comment => Some(5),
+        TokenClass::Paren | TokenClass::Punctuation | TokenClass::Error => None,
+    }
+}
+
+///
+/// Reads one message, framed by a `Content-Length` header. Returns `None`
+/// at the end of the input.
+///
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes one message with its `Content-Length` header.
+pub fn write_message(writer: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// The byte offset of an LSP position, whose character counts UTF-16 code
+/// units, clamped to the line and the text.
+fn offset(text: &str, position: &Json) -> Option<usize> {
+    let line = position.get("line")?.as_f64()? as usize;
+    let character = position.get("character")?.as_f64()? as usize;
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return Some(text.len()),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+/// The LSP position of a byte offset.
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset];
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    object(vec![
+        ("line", Json::Number(before.matches('\n').count() as f64)),
+        (
+            "character",
+            Json::Number(utf16_len(&before[start..]) as f64),
+        ),
+    ])
+}
+
+/// The end of `span` in `text`, widened to a whole character since the
+/// span of a lexer error is one byte long.
+fn end(text: &str, span: Span) -> usize {
+    let mut end = (span.offset + span.len).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    end
+}
+
+fn range(text: &str, span: Span) -> Json {
+    object(vec![
+        ("start", position(text, span.offset.min(text.len()))),
+        ("end", position(text, end(text, span))),
+    ])
+}
+
+fn diagnostic(text: &str, err: &ParserError) -> Json {
+    let span = err.span().unwrap_or(Span {
+        line: 0,
+        col: 0,
+        offset: text.len(),
+        len: 0,
+    });
+    let mut message = err.message().to_string();
+    if let Some(hint) = err.hint() {
+        message = format!("{}: {}", message, hint);
+    }
+    object(vec![
+        ("range", range(text, span)),
+        ("severity", Json::Number(1.0)),
+        ("code", Json::String(err.code().to_string())),
+        ("source", Json::String("rexpr".to_string())),
+        ("message", Json::String(message)),
+    ])
+}
+
+/// The statements of a parsed document, with their spans.
+fn statements((node, spans): (Node, SpanTree)) -> Vec<(Node, SpanTree)> {
+    match node {
+        Node::Block(stmts) => stmts.into_iter().zip(spans.children).collect(),
+        stmt => vec![(stmt, spans)],
+    }
+}
+
+/// The `i`th child of `node`, in the order of its span tree.
+fn child(node: &Node, i: usize) -> Option<&Node> {
+    match node {
+        Node::UnaryExpr { child, .. } if i == 0 => Some(child),
+        Node::BinaryExpr { lhs, .. } if i == 0 => Some(lhs),
+        Node::BinaryExpr { rhs, .. } if i == 1 => Some(rhs),
+        Node::Assign { value, .. } if i == 0 => Some(value),
+        Node::Block(nodes) | Node::Call { args: nodes, .. } => nodes.get(i),
+        _ => None,
+    }
+}
+
+/// The innermost node of `node` whose span contains `offset`.
+fn innermost<'n>(mut node: &'n Node, mut spans: &'n SpanTree, offset: usize) -> (&'n Node, Span) {
+    'descend: loop {
+        for (i, spans_i) in spans.children.iter().enumerate() {
+            let span = spans_i.span;
+            if span.offset <= offset && offset < span.offset + span.len {
+                if let Some(child) = child(node, i) {
+                    node = child;
+                    spans = spans_i;
+                    continue 'descend;
+                }
+            }
+        }
+        return (node, spans.span);
+    }
+}
+
+fn assigned(node: &Node, names: &mut Vec<String>) {
+    if let Node::Assign { name, .. } = node {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+}
+
+/// The state of the server: the open documents and the functions and
+/// constants available to them.
+pub struct Server {
+    documents: HashMap<String, Document>,
+    ctx: Context,
+    shutdown: bool,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    /// A server with the standard library in scope.
+    pub fn new() -> Self {
+        Server::with_context(Context::stdlib())
+    }
+
+    pub fn with_context(ctx: Context) -> Self {
+        Server {
+            documents: HashMap::new(),
+            ctx,
+            shutdown: false,
+        }
+    }
+
+    ///
+    /// Serves requests from `reader` until the client sends `exit`, writing
+    /// responses and notifications to `writer`.
+    ///
+    pub fn run(&mut self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        while let Some(body) = read_message(&mut reader)? {
+            let messages = match Json::parse(&body) {
+                Ok(message) => {
+                    if message.get("method").and_then(Json::as_str) == Some("exit") {
+                        return Ok(());
+                    }
+                    self.handle(&message)
+                }
+                Err(err) => vec![error(Json::Null, PARSE_ERROR, err.to_string())],
+            };
+            for message in &messages {
+                write_message(&mut writer, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Handles one request or notification, returning the messages to send
+    /// back: the response to a request, and any diagnostics it publishes.
+    ///
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            // Notifications get no response.
+            None => return self.notify(method, params),
+        };
+        if self.shutdown && method != "shutdown" {
+            return vec![error(id, INVALID_PARAMS, "the server is shutting down")];
+        }
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "textDocument/hover" => self.hover(params).or(Some(Json::Null)),
+            "textDocument/completion" => Some(self.completion(params)),
+            "textDocument/formatting" => self.formatting(params).or(Some(Json::Null)),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(params).or(Some(Json::Null)),
+            _ => None,
+        };
+        match result {
+            Some(result) => vec![object(vec![
+                ("jsonrpc", Json::String("2.0".to_string())),
+                ("id", id),
+                ("result", result),
+            ])],
+            None => vec![error(
+                id,
+                METHOD_NOT_FOUND,
+                format!("unknown method {}", method),
+            )],
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = match uri(params) {
+            Some(uri) => uri.to_string(),
+            None => return vec![],
+        };
+        let document = params.get("textDocument").unwrap_or(&Json::Null);
+        match method {
+            "textDocument/didOpen" => {
+                let text = document.get("text").and_then(Json::as_str).unwrap_or("");
+                self.documents.insert(uri.clone(), Document::new(text));
+            }
+            "textDocument/didChange" => {
+                let changes = match params.get("contentChanges") {
+                    Some(Json::Array(changes)) => changes,
+                    _ => return vec![],
+                };
+                for change in changes {
+                    self.change(&uri, change);
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, vec![])];
+            }
+            _ => return vec![],
+        }
+        vec![self.diagnostics(&uri)]
+    }
+
+    /// Applies a change, either to a range or to the whole text.
+    fn change(&mut self, uri: &str, change: &Json) {
+        let text = change.get("text").and_then(Json::as_str).unwrap_or("");
+        let document = self.documents.get_mut(uri);
+        match (document, change.get("range")) {
+            (Some(document), Some(range)) => {
+                let start = range
+                    .get("start")
+                    .and_then(|start| offset(document.text(), start));
+                let end = range
+                    .get("end")
+                    .and_then(|end| offset(document.text(), end));
+                if let (Some(start), Some(end)) = (start, end) {
+                    document.edit(start..end.max(start), text);
+                }
+            }
+            _ => {
+                self.documents.insert(uri.to_string(), Document::new(text));
+            }
+        }
+    }
+
+    fn diagnostics(&self, uri: &str) -> Json {
+        let diagnostics = match self.documents.get(uri) {
+            Some(document) => match document.parse() {
+                Ok(_) => vec![],
+                Err(errors) => errors
+                    .iter()
+                    .map(|err| diagnostic(document.text(), err))
+                    .collect(),
+            },
+            None => vec![],
+        };
+        publish(uri, diagnostics)
+    }
+
+    ///
+    /// The value of the innermost sub-expression at the cursor, evaluated
+    /// after the statements before it. `use` directives apply to the whole
+    /// file, as when it is evaluated.
+    ///
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let document = self.documents.get(uri(params)?)?;
+        let text = document.text();
+        let offset = offset(text, params.get("position")?)?;
+        let stmts = statements(document.parse().ok()?);
+        let i = stmts.iter().position(|(_, spans)| {
+            spans.span.offset <= offset && offset < spans.span.offset + spans.span.len
+        })?;
+        let (node, span) = innermost(&stmts[i].0, &stmts[i].1, offset);
+        let imports = stmts[i + 1..]
+            .iter()
+            .filter(|(stmt, _)| matches!(stmt, Node::Use(_)));
+        let program: Vec<Node> = stmts[..i]
+            .iter()
+            .chain(imports)
+            .map(|(stmt, _)| stmt.clone())
+            .chain([node.clone()])
+            .collect();
+        let value = match Eval::new().eval_with(&Node::Block(program), &self.ctx) {
+            Ok(value) => format!("{} = {}", node, value),
+            Err(err) => format!("{}: {}", node, err.message()),
+        };
+        Some(object(vec![
+            ("contents", Json::String(value)),
+            ("range", range(text, span)),
+        ]))
+    }
+
+    ///
+    /// The functions and constants of the context and the variables assigned
+    /// in the document. Functions in an imported namespace are offered both
+    /// qualified and by their short name.
+    ///
+    fn completion(&self, params: &Json) -> Json {
+        let mut items = vec![];
+        let globs: Vec<&str> = self
+            .ctx
+            .imports()
+            .filter_map(|path| path.strip_suffix("::*"))
+            .collect();
+        let mut functions: Vec<&str> = self.ctx.functions().collect();
+        functions.sort();
+        for name in functions {
+            if let Some((namespace, short)) = name.rsplit_once("::") {
+                if globs.contains(&namespace) {
+                    items.push(item(short, FUNCTION, name));
+                }
+            }
+            items.push(item(name, FUNCTION, name));
+        }
+        let mut vars: Vec<String> = self.ctx.var_names().cloned().collect();
+        vars.sort();
+        let document = uri(params).and_then(|uri| self.documents.get(uri));
+        if let Some(Ok(parsed)) = document.map(Document::parse) {
+            for (stmt, _) in statements(parsed) {
+                assigned(&stmt, &mut vars);
+            }
+        }
+        for name in &vars {
+            items.push(item(name, VARIABLE, "variable"));
+        }
+        Json::Array(items)
+    }
+
+    ///
+    /// Rewrites the document with one statement per line and the spacing of
+    /// [`Node`]'s `Display`. Documents with comments or errors are left as
+    /// they are, since formatting would lose the comments.
+    ///
+    fn formatting(&self, params: &Json) -> Option<Json> {
+        let document = self.documents.get(uri(params)?)?;
+        let text = document.text();
+        let commented = Lexer::new(text)
+            .keep_comments(true)
+            .any(|token| matches!(token, Ok(token) if matches!(token.token, Token::Comment(_))));
+        if commented {
+            return None;
+        }
+        let stmts = statements(document.parse().ok()?);
+        let formatted: String = stmts
+            .iter()
+            .map(|(stmt, _)| format!("{};\n", stmt))
+            .collect();
+        let whole = Span {
+            line: 1,
+            col: 1,
+            offset: 0,
+            len: text.len(),
+        };
+        Some(Json::Array(vec![object(vec![
+            ("range", range(text, whole)),
+            ("newText", Json::String(formatted)),
+        ])]))
+    }
+
+    ///
+    /// The classified tokens of the document, each as the line and UTF-16
+    /// character of its start relative to the previous token, its length and
+    /// its type. Tokens spanning lines, such as block comments, are split
+    /// into one per line.
+    ///
+    fn semantic_tokens(&self, params: &Json) -> Option<Json> {
+        let text = self.documents.get(uri(params)?)?.text();
+        let mut data = vec![];
+        let (mut line, mut character) = (0, 0);
+        for token in classify(text) {
+            let ty = match token_type(token.class) {
+                Some(ty) => ty,
+                None => continue,
+            };
+            let mut start = token.span.offset;
+            let pieces = text[start..end(text, token.span)].split('\n');
+            for (token_line, piece) in (token.span.line as usize - 1..).zip(pieces) {
+                let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+                let token_character = utf16_len(&text[line_start..start]);
+                if !piece.is_empty() {
+                    let delta = match token_line == line {
+                        true => token_character - character,
+                        false => token_character,
+                    };
+                    data.extend([token_line - line, delta, utf16_len(piece), ty, 0]);
+                    (line, character) = (token_line, token_character);
+                }
+                start += piece.len() + 1;
+            }
+        }
+        let data = data.into_iter().map(|n| Json::Number(n as f64)).collect();
+        Some(object(vec![("data", Json::Array(data))]))
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+fn uri(params: &Json) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+fn capabilities() -> Json {
+    object(vec![(
+        "capabilities",
+        object(vec![
+            // Incremental changes.
+            ("textDocumentSync", Json::Number(2.0)),
+            ("hoverProvider", Json::Bool(true)),
+            ("completionProvider", object(vec![])),
+            ("documentFormattingProvider", Json::Bool(true)),
+            (
+                "semanticTokensProvider",
+                object(vec![
+                    (
+                        "legend",
+                        object(vec![
+                            (
+                                "tokenTypes",
+                                Json::Array(
+                                    TOKEN_TYPES
+                                        .iter()
+                                        .map(|ty| Json::String(ty.to_string()))
+                                        .collect(),
+                                ),
+                            ),
+                            ("tokenModifiers", Json::Array(vec![])),
+                        ]),
+                    ),
+                    ("full", Json::Bool(true)),
+                ]),
+            ),
+        ]),
+    )])
+}
+
+fn item(label: &str, kind: f64, detail: &str) -> Json {
+    object(vec![
+        ("label", Json::String(label.to_string())),
+        ("kind", Json::Number(kind)),
+        ("detail", Json::String(detail.to_string())),
+    ])
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    object(vec![
+        ("jsonrpc", Json::String("2.0".to_string())),
+        (
+            "method",
+            Json::String("textDocument/publishDiagnostics".to_string()),
+        ),
+        (
+            "params",
+            object(vec![
+                ("uri", Json::String(uri.to_string())),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn error(id: Json, code: i32, message: impl Into<String>) -> Json {
+    object(vec![
+        ("jsonrpc", Json::String("2.0".to_string())),
+        ("id", id),
+        (
+            "error",
+            object(vec![
+                ("code", Json::Number(code as f64)),
+                ("message", Json::String(message.into())),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const URI: &str = "file:///tmp/prices.rx";
+
+    fn request(server: &mut Server, method: &str, params: &str) -> Vec<Json> {
+        let message = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"{}","params":{}}}"#,
+            method, params
+        );
+        server.handle(&Json::parse(&message).unwrap())
+    }
+
+    fn notify(server: &mut Server, method: &str, params: &str) -> Vec<Json> {
+        let message = format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#,
+            method, params
+        );
+        server.handle(&Json::parse(&message).unwrap())
+    }
+
+    fn open(server: &mut Server, text: &str) -> Vec<Json> {
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"{}","languageId":"rexpr","version":1,"text":{}}}}}"#,
+            URI,
+            Json::String(text.to_string())
+        );
+        notify(server, "textDocument/didOpen", &params)
+    }
+
+    fn at(method: &str, server: &mut Server, line: u32, character: u32) -> Json {
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}}}}"#,
+            URI, line, character
+        );
+        let mut messages = request(server, method, &params);
+        messages.remove(0).get("result").unwrap().clone()
+    }
+
+    #[test]
+    fn frame_messages() {
+        let mut out = vec![];
+        write_message(&mut out, &Json::Bool(true)).unwrap();
+        assert_eq!(out, b"Content-Length: 4\r\n\r\ntrue");
+        let input = b"Content-Length: 4\r\nContent-Type: x\r\n\r\nnull";
+        let mut reader = &input[..];
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), "null");
+        assert_eq!(read_message(&mut reader).unwrap(), None)
+    }
+
+    #[test]
+    fn publish_diagnostics_on_change() {
+        let mut server = Server::new();
+        let messages = open(&mut server, "a = 1;\nb = a +;\n");
+        let diagnostics = messages[0].get("params").unwrap().get("diagnostics");
+        assert_eq!(
+            diagnostics.unwrap().to_string(),
+            r#"[{"range":{"start":{"line":1,"character":7},"end":{"line":1,"character":8}},"severity":1,"code":"E0002","source":"rexpr","message":"unexpected token ;: expected a number, a variable or `(`"}]"#
+        );
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"{}","version":2}},"contentChanges":[{{"range":{{"start":{{"line":1,"character":7}},"end":{{"line":1,"character":7}}}},"text":" 2"}}]}}"#,
+            URI
+        );
+        let messages = notify(&mut server, "textDocument/didChange", &params);
+        let diagnostics = messages[0].get("params").unwrap().get("diagnostics");
+        assert_eq!(diagnostics.unwrap().to_string(), "[]");
+        assert_eq!(server.documents[URI].text(), "a = 1;\nb = a + 2;\n")
+    }
+
+    #[test]
+    fn hover_sub_expressions() {
+        let mut server = Server::new();
+        open(
+            &mut server,
+            "r = 3;\narea = pi * r ^ 2;\nx = w + sqrt(r * 3)",
+        );
+        let hover = at("textDocument/hover", &mut server, 1, 14);
+        assert_eq!(hover.get("contents").unwrap().as_str(), Some("r ^ 2 = 9"));
+        let hover = at("textDocument/hover", &mut server, 2, 9);
+        assert_eq!(
+            hover.get("contents").unwrap().as_str(),
+            Some("sqrt(r * 3) = 3.0")
+        );
+        let hover = at("textDocument/hover", &mut server, 2, 4);
+        assert_eq!(
+            hover.get("contents").unwrap().as_str(),
+            Some("w: undefined variable w")
+        )
+    }
+
+    #[test]
+    fn complete_and_format() {
+        let mut server = Server::new();
+        open(&mut server, "total = 1 +   2;rate=3\n");
+        let labels: Vec<String> = match at("textDocument/completion", &mut server, 0, 0) {
+            Json::Array(items) => items
+                .iter()
+                .map(|item| item.get("label").unwrap().to_string())
+                .collect(),
+            other => panic!("{}", other),
+        };
+        for label in ["sqrt", "math::sqrt", "mean", "pi", "total", "rate"] {
+            assert!(labels.contains(&format!("\"{}\"", label)), "{}", label);
+        }
+        let params = format!(r#"{{"textDocument":{{"uri":"{}"}},"options":{{}}}}"#, URI);
+        let edits = request(&mut server, "textDocument/formatting", &params);
+        let edit = edits[0].get("result").unwrap().to_string();
+        assert!(
+            edit.ends_with(r#""newText":"total = 1 + 2;\nrate = 3;\n"}]"#),
+            "{}",
+            edit
+        );
+
+        open(&mut server, "// keep me\n1+1");
+        let edits = request(&mut server, "textDocument/formatting", &params);
+        assert_eq!(edits[0].get("result"), Some(&Json::Null))
+    }
+
+    #[test]
+    fn semantic_tokens() {
+        let mut server = Server::new();
+        open(&mut server, "x = sqrt(2);\n/* a\nb */ é + x");
+        let params = format!(r#"{{"textDocument":{{"uri":"{}"}}}}"#, URI);
+        let tokens = request(&mut server, "textDocument/semanticTokens/full", &params);
+        assert_eq!(
+            tokens[0].get("result").unwrap().to_string(),
+            r#"{"data":[0,0,1,2,0,0,2,1,1,0,0,2,4,3,0,0,5,1,0,0,1,0,4,5,0,1,0,4,5,0,0,7,1,1,0,0,2,1,2,0]}"#
+        )
+    }
+}
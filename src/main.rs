@@ -1,42 +1,3964 @@
-use rexpr::eval::Eval;
-use rexpr::parser::Parser;
-use rexpr::tokenizer::Tokenizer;
+use rexpr::boolean::truth_table;
+use rexpr::calculus::Solver;
+use rexpr::compile::{compile, Vm};
+use rexpr::config::Config;
+use rexpr::context::Context;
+use rexpr::diagnostic::{suggest, Diagnostic};
+use rexpr::error::{ErrorCode, RexprError};
+use rexpr::eval::{Bindings, Cancel, Eval, EvalError, EvalHook, EvalReport, Value};
+use rexpr::format::{FloatFormat, Locale, Radix, ValueFormat};
+use rexpr::highlight::{classify, SemanticToken, TokenClass};
+use rexpr::json::Json;
+use rexpr::memory::{format_bytes, parse_size, Tracking};
+use rexpr::optimize::simplify;
+use rexpr::parser::{Float, Node, Parser, ParserError};
+use rexpr::plot::plot;
+use rexpr::polynomial::{collect, expand};
+use rexpr::rpc;
+use rexpr::stdlib::Angle;
+use rexpr::tokenizer::{Lexer, Span, SpannedToken};
+use rexpr::visit::Visitor;
+use rexpr::Map;
+use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
-use rustyline::{Editor, Result};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{ColorMode, CompletionType, Editor, Helper, Result};
+use std::borrow::Cow;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: rexpr [options] [script]
+
+Without a script or -e, starts an interactive session. A script is a file
+of statements separated by `;`, whose values are printed in turn.
+
+options:
+  -e, --eval <expr>    evaluate <expr> and print its value; may be repeated,
+                       evaluating each in turn in the same session
+  -q, --quiet          print only the last value, of the last -e or of the
+                       last statement of a script
+  --format <format>    print results and errors as `plain` text, the
+                       default, as `json` objects or as `tsv` rows, one per
+                       line and all on stdout
+  --output-base <base> show integer results in plain text and in the
+                       interactive session in `bin`, `oct`, `dec` or `hex`
+  --locale <name>      show plain results with the decimal point and digit
+                       grouping of a locale such as `de_DE`, instead of
+                       that of LC_ALL or LC_NUMERIC
+  --preload <path>     run the script at <path> first, in the same session;
+                       may be repeated, after the scripts of the config file
+  --config <path>      read defaults from <path> instead of
+                       $XDG_CONFIG_HOME/rexpr/config.toml or
+                       ~/.config/rexpr/config.toml
+  --persist            restore the variables of the last interactive session
+                       on startup and save them on exit
+  --time               print how long tokenizing, parsing and evaluating
+                       each -e or interactive line took, on stderr
+  --color <when>       color the interactive session `auto`, the default,
+                       when on a terminal and NO_COLOR is not set,
+                       `always` or `never`; also --color=<when>
+  --memory-limit <size>
+                       warn on stderr once the memory in use passes <size>,
+                       such as `512M` or `2G`, and fail lines parsed past it;
+                       needs the `memory` feature
+  --serve-jsonrpc      answer line-delimited JSON requests on stdin
+  -h, --help           print this message
+
+exit status:
+  0  success
+  1  a script or the config file could not be read
+  2  invalid arguments
+  3  an invalid character or token
+  4  a syntax error
+  5  an evaluation error";
+
+/// Counts the memory in use, for `:memory` and `--memory-limit`. It only
+/// sees allocations in builds with the `memory` feature.
+#[cfg_attr(feature = "memory", global_allocator)]
+static ALLOCATOR: Tracking = Tracking::new();
+
+const UNTRACKED: &str = "memory tracking needs a build with the `memory` feature";
+
+const EXIT_READ: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_TOKENIZER: i32 = 3;
+const EXIT_PARSER: i32 = 4;
+const EXIT_EVAL: i32 = 5;
+
+/// Errors rendered against their source, with the exit status they give.
+#[derive(Debug)]
+struct Failure {
+    status: i32,
+    message: String,
+    errors: Vec<RexprError>,
+}
+
+impl Failure {
+    /// Syntax errors, whose status is that of the first.
+    fn syntax(errors: &[ParserError], source: &str) -> Self {
+        let status = match errors.first().map(ParserError::code) {
+            Some(
+                ErrorCode::UnknownSymbol
+                | ErrorCode::InvalidPlaceholder
+                | ErrorCode::UnterminatedComment
+                | ErrorCode::Read,
+            ) => EXIT_TOKENIZER,
+            _ => EXIT_PARSER,
+        };
+        let rendered: Vec<String> = errors
+            .iter()
+            .map(|err| Diagnostic::from(err).render(source))
+            .collect();
+        Failure {
+            status,
+            message: rendered.join("\n"),
+            errors: errors.iter().cloned().map(RexprError::from).collect(),
+        }
+    }
+
+    fn eval(err: EvalError, source: &str) -> Self {
+        Failure {
+            status: EXIT_EVAL,
+            message: Diagnostic::from(&err).render(source),
+            errors: vec![err.into()],
+        }
+    }
+}
+
+/// How results and errors are printed outside the interactive session.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Format {
+    #[default]
+    Plain,
+    /// An object per result, with the `expr`, its `value` and `type` and the
+    /// time taken in `micros`, and an object per error with the `error` in
+    /// the form of `rexpr::json`.
+    Json,
+    ///
+    /// A row per result, `ok`, the expression, value, type and time taken
+    /// in microseconds, and a row per error, `error`, the code, message,
+    /// line and column. Tabs and newlines in fields become spaces.
+    ///
+    Tsv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(format!("unknown format {}, expected plain, json or tsv", s)),
+        }
+    }
+}
+
+/// Whether to color the interactive session, from `--color`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Color {
+    /// When stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err(format!(
+                "unknown color {}, expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+impl Color {
+    fn enabled(self) -> bool {
+        match self {
+            Color::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && io::stdout().is_terminal()
+            }
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "int",
+        Value::Float(_) => "float",
+    }
+}
+
+fn tsv_field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+impl Format {
+    /// A result, shown in plain text as `values` says.
+    fn result(self, expr: &str, value: &Value, elapsed: Duration, values: &ValueFormat) -> String {
+        match self {
+            Format::Plain => values.format(value),
+            Format::Json => {
+                let fields = vec![
+                    ("expr".to_string(), Json::String(expr.trim().to_string())),
+                    ("value".to_string(), Json::Number(value.as_f64())),
+                    (
+                        "type".to_string(),
+                        Json::String(type_name(value).to_string()),
+                    ),
+                    (
+                        "micros".to_string(),
+                        Json::Number(elapsed.as_micros() as f64),
+                    ),
+                ];
+                Json::Object(fields).to_string()
+            }
+            Format::Tsv => format!(
+                "ok\t{}\t{}\t{}\t{}",
+                tsv_field(expr.trim()),
+                value,
+                type_name(value),
+                elapsed.as_micros()
+            ),
+        }
+    }
+
+    fn errors(self, failure: &Failure) -> Vec<String> {
+        match self {
+            Format::Plain => vec![failure.message.clone()],
+            Format::Json => failure
+                .errors
+                .iter()
+                .map(|err| {
+                    let fields = vec![("error".to_string(), err.to_json_value())];
+                    Json::Object(fields).to_string()
+                })
+                .collect(),
+            Format::Tsv => failure
+                .errors
+                .iter()
+                .map(|err| {
+                    let message = match err {
+                        RexprError::Tokenizer(err) => err.message.as_str(),
+                        RexprError::Parser(err) => err.message(),
+                        RexprError::Eval(err) => err.message(),
+                    };
+                    let (line, col) = err.span().map_or((0, 0), |span| (span.line, span.col));
+                    format!(
+                        "error\t{}\t{}\t{}\t{}",
+                        err.code(),
+                        tsv_field(message),
+                        line,
+                        col
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Prints results as they come, or only the last one when quiet.
+struct Output {
+    format: Format,
+    values: ValueFormat,
+    quiet: bool,
+    last: Option<String>,
+}
+
+impl Output {
+    fn result(&mut self, expr: &str, value: &Value, elapsed: Duration) {
+        let line = self.format.result(expr, value, elapsed, &self.values);
+        if self.quiet {
+            self.last = Some(line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    fn finish(self) {
+        if let Some(line) = self.last {
+            println!("{}", line);
+        }
+    }
+
+    /// Prints the errors, on stderr as plain text, and exits.
+    fn fail(&self, failure: Failure) -> ! {
+        for line in self.format.errors(&failure) {
+            match self.format {
+                Format::Plain => eprintln!("{}", line),
+                Format::Json | Format::Tsv => println!("{}", line),
+            }
+        }
+        std::process::exit(failure.status)
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    /// The expressions given with `-e`, in order.
+    exprs: Vec<String>,
+    /// The scripts given with `--preload`, in order.
+    preload: Vec<String>,
+    /// The config file given with `--config`.
+    config: Option<String>,
+    script: Option<String>,
+    quiet: bool,
+    format: Format,
+    radix: Radix,
+    locale: Option<Locale>,
+    time: bool,
+    color: Color,
+    memory_limit: Option<usize>,
+    persist: bool,
+    serve_jsonrpc: bool,
+    help: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> std::result::Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-e" | "--eval" => match args.next() {
+                    Some(expr) => options.exprs.push(expr),
+                    None => return Err(format!("{} needs an expression", arg)),
+                },
+                "-q" | "--quiet" => options.quiet = true,
+                "--format" => match args.next() {
+                    Some(format) => options.format = format.parse()?,
+                    None => return Err("--format needs a format".to_string()),
+                },
+                "--output-base" => match args.next() {
+                    Some(radix) => {
+                        options.radix = radix.parse().map_err(|err| format!("{}", err))?
+                    }
+                    None => return Err("--output-base needs a base".to_string()),
+                },
+                "--locale" => match args.next() {
+                    Some(locale) => {
+                        options.locale = Some(locale.parse().map_err(|err| format!("{}", err))?)
+                    }
+                    None => return Err("--locale needs a locale name".to_string()),
+                },
+                "--preload" => match args.next() {
+                    Some(path) => options.preload.push(path),
+                    None => return Err("--preload needs a path".to_string()),
+                },
+                "--config" => match args.next() {
+                    Some(path) => options.config = Some(path),
+                    None => return Err("--config needs a path".to_string()),
+                },
+                "--time" => options.time = true,
+                "--color" => match args.next() {
+                    Some(color) => options.color = color.parse()?,
+                    None => return Err("--color needs auto, always or never".to_string()),
+                },
+                _ if arg.starts_with("--color=") => {
+                    options.color = arg["--color=".len()..].parse()?
+                }
+                "--memory-limit" => match args.next() {
+                    Some(size) => match parse_size(&size) {
+                        Some(limit) => options.memory_limit = Some(limit),
+                        None => return Err(format!("invalid size {}", size)),
+                    },
+                    None => return Err("--memory-limit needs a size".to_string()),
+                },
+                "--persist" => options.persist = true,
+                "--serve-jsonrpc" => options.serve_jsonrpc = true,
+                "-h" | "--help" => options.help = true,
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown argument {}", arg))
+                }
+                _ if options.script.is_some() => {
+                    return Err(format!("unexpected argument {}", arg))
+                }
+                _ => options.script = Some(arg),
+            }
+        }
+        if options.script.is_some() && !options.exprs.is_empty() {
+            return Err("a script cannot be combined with -e".to_string());
+        }
+        Ok(options)
+    }
+}
+
+///
+/// The locale of `LC_ALL` or else `LC_NUMERIC`, as the C library picks it.
+/// `LANG` is not looked at, so that results are only grouped when asked
+/// for. Unknown locales are ignored.
+///
+fn env_locale() -> Option<Locale> {
+    ["LC_ALL", "LC_NUMERIC"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| value.parse().ok())
+}
+
+///
+/// The config file given with `--config`, which must exist, or else the
+/// one at [`Config::path`], if any. Exits if it cannot be read or parsed.
+///
+fn load_config(path: Option<&str>) -> Config {
+    let result = match path.map(PathBuf::from).or_else(Config::path) {
+        Some(file) if path.is_some() && !file.exists() => {
+            Err(format!("cannot read {}: no such file", file.display()))
+        }
+        Some(file) => Config::load(&file).map_err(|err| err.to_string()),
+        None => Ok(Config::default()),
+    };
+    result.unwrap_or_else(|message| {
+        eprintln!("rexpr: {}", message);
+        std::process::exit(EXIT_READ);
+    })
+}
+
+/// `path` with a leading `~/` replaced by the home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) if !home.is_empty() => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
 
 fn main() -> Result<()> {
-    let mut rl = Editor::<()>::new()?;
-    if rl.load_history("history.txt").is_err() {
-        println!("No previous history.");
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rexpr: {}\n\n{}", message, USAGE);
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    if options.serve_jsonrpc {
+        let base = Arc::new(Context::stdlib());
+        return Ok(rpc::serve(base, io::stdin().lock(), io::stdout().lock())?);
+    }
+    if options.memory_limit.is_some() {
+        if !cfg!(feature = "memory") {
+            eprintln!("rexpr: {}", UNTRACKED);
+            std::process::exit(EXIT_USAGE);
+        }
+        ALLOCATOR.set_limit(options.memory_limit);
+    }
+    let config = load_config(options.config.as_deref());
+    let locale = options.locale.or_else(env_locale).unwrap_or_default();
+    let mut values = ValueFormat::new()
+        .with_radix(options.radix)
+        .with_locale(locale);
+    if let Some(precision) = config.precision {
+        values.set_format(precision);
+    }
+    let mut output = Output {
+        format: options.format,
+        values: values.clone(),
+        quiet: options.quiet,
+        last: None,
+    };
+    let mut session = Session::new(Shown {
+        values,
+        all_radixes: false,
+    });
+    session.time = options.time;
+    if let Some(prompt) = &config.prompt {
+        session.prompt = prompt.clone();
+    }
+    if let Some(angle) = config.angle {
+        session.set_angle(angle);
+    }
+    if options.color.enabled() {
+        let name = config.theme.as_deref().unwrap_or(DEFAULT_THEME);
+        session.theme = Some(Theme::named(name).unwrap_or_else(|| {
+            eprintln!("rexpr: {}", unknown_theme(name));
+            Theme::named(DEFAULT_THEME).unwrap()
+        }));
+    }
+    let Session {
+        eval, functions, ..
+    } = &mut session;
+    let preload = config.preload.iter().map(|path| expand_home(path));
+    for path in preload.chain(options.preload.iter().cloned()) {
+        let text = read_script(&path).unwrap_or_else(|err| {
+            eprintln!("rexpr: cannot read {}: {}", path, err);
+            std::process::exit(EXIT_READ);
+        });
+        if let Err(failure) = run_script(&text, eval, functions, &mut |_, _, _| {}) {
+            output.fail(failure);
+        }
+    }
+    if !options.exprs.is_empty() {
+        for expr in &options.exprs {
+            let start = Instant::now();
+            let (result, timing) = timed_line(expr, eval, functions, &mut ());
+            let elapsed = start.elapsed();
+            if options.time {
+                eprintln!("{}", timing);
+            }
+            match result {
+                Ok(value) => output.result(expr, &value, elapsed),
+                Err(failure) => output.fail(failure),
+            }
+            warn_memory();
+        }
+        output.finish();
+        return Ok(());
+    }
+    if let Some(path) = &options.script {
+        let text = read_script(path).unwrap_or_else(|err| {
+            eprintln!("rexpr: cannot read {}: {}", path, err);
+            std::process::exit(EXIT_READ);
+        });
+        let mut print =
+            |expr: &str, value: &Value, elapsed: Duration| output.result(expr, value, elapsed);
+        if let Err(failure) = run_script(&text, eval, functions, &mut print) {
+            output.fail(failure);
+        }
+        output.finish();
+        return Ok(());
+    }
+    if options.persist {
+        if let Err(message) = session.restore(DEFAULT_SESSION, false) {
+            eprintln!("rexpr: {}", message);
+        }
+    }
+    repl(session, options.persist, &config)
+}
+
+/// The workspace `--persist` and `:session` use unless given a name.
+const DEFAULT_SESSION: &str = "default";
+
+///
+/// Where the interactive session keeps its history: the `history_file` of
+/// the config, or `history.txt` in the data directory.
+///
+fn history_path(config: &Config) -> Option<PathBuf> {
+    match &config.history_file {
+        Some(path) => Some(PathBuf::from(expand_home(path))),
+        None => Some(data_dir()?.join("history.txt")),
+    }
+}
+
+///
+/// Where rexpr keeps its data: `$XDG_DATA_HOME/rexpr`, or
+/// `~/.local/share/rexpr` when that is not set to an absolute path.
+///
+fn data_dir() -> Option<PathBuf> {
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            let home = std::env::var_os("HOME").filter(|home| !home.is_empty())?;
+            Some(PathBuf::from(home).join(".local").join("share"))
+        })?;
+    Some(data.join("rexpr"))
+}
+
+/// The file of the named workspace, if `name` is a plain name.
+fn session_path(name: &str) -> std::result::Result<PathBuf, String> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(plain) {
+        return Err(format!("invalid session name {}", name));
+    }
+    match data_dir() {
+        Some(dir) => Ok(dir.join("sessions").join(format!("{}.rx", name))),
+        None => Err("no data directory, set XDG_DATA_HOME or HOME".to_string()),
+    }
+}
+
+/// The text of the script at `path`, or of stdin for `-`.
+fn read_script(path: &str) -> io::Result<String> {
+    match path {
+        "-" => io::read_to_string(io::stdin()),
+        path => std::fs::read_to_string(path),
+    }
+}
+
+/// How the interactive session shows results, set with `:hex` and the like.
+struct Shown {
+    values: ValueFormat,
+    /// Integers in every radix at once, after `:bases`.
+    all_radixes: bool,
+}
+
+impl Shown {
+    fn show(&self, value: &Value) -> String {
+        match value {
+            Value::Int(n) if self.all_radixes => {
+                let shown: Vec<String> = Radix::ALL
+                    .iter()
+                    .map(|radix| radix.format_grouped(*n, self.values.separator()))
+                    .collect();
+                shown.join(" = ")
+            }
+            value => self.values.format(value),
+        }
+    }
+
+    /// The settings listed by `:set`, as `key` and `value`.
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let float = self.values.float_format();
+        let base = match self.all_radixes {
+            true => "all",
+            false => self.values.radix().name(),
+        };
+        let precision = match float.precision() {
+            Some(digits) => digits.to_string(),
+            None => "auto".to_string(),
+        };
+        let notation = match float {
+            FloatFormat::Shortest => "auto",
+            FloatFormat::Fixed(_) => "fixed",
+            FloatFormat::Scientific(_) => "sci",
+        };
+        let zeros = if self.values.trailing_zeros() {
+            "keep"
+        } else {
+            "trim"
+        };
+        let group = match self.values.separator() {
+            Some(separator) => separator.to_string(),
+            None => "off".to_string(),
+        };
+        vec![
+            ("base", base.to_string()),
+            ("precision", precision),
+            ("notation", notation.to_string()),
+            ("zeros", zeros.to_string()),
+            ("group", group),
+        ]
+    }
+
+    /// Changes the setting `key`, or returns its usage.
+    fn set(&mut self, key: &str, value: &str) -> std::result::Result<(), String> {
+        let float = self.values.float_format();
+        // Six digits, as C's printf, when switching notation from shortest.
+        let digits = float.precision().unwrap_or(6);
+        match (key, value) {
+            ("base", "all") => self.all_radixes = true,
+            ("base", radix) => match radix.parse() {
+                Ok(radix) => {
+                    self.values.set_radix(radix);
+                    self.all_radixes = false;
+                }
+                Err(_) => return Err("usage: :set base=bin|oct|dec|hex|all".to_string()),
+            },
+            ("precision", "auto") => self.values.set_format(FloatFormat::Shortest),
+            ("precision", digits) => match (digits.parse(), float) {
+                (Ok(digits), FloatFormat::Scientific(_)) => {
+                    self.values.set_format(FloatFormat::Scientific(digits))
+                }
+                (Ok(digits), _) => self.values.set_format(FloatFormat::Fixed(digits)),
+                (Err(_), _) => return Err("usage: :precision <digits>|auto".to_string()),
+            },
+            ("notation", "fixed") => self.values.set_format(FloatFormat::Fixed(digits)),
+            ("notation", "sci") => self.values.set_format(FloatFormat::Scientific(digits)),
+            ("notation", _) => return Err("usage: :notation fixed|sci".to_string()),
+            ("zeros", "keep") => self.values.set_trailing_zeros(true),
+            ("zeros", "trim") => self.values.set_trailing_zeros(false),
+            ("zeros", _) => return Err("usage: :zeros keep|trim".to_string()),
+            ("group", "off") => self.values.set_separator(None),
+            ("group", "_" | ",") => self.values.set_separator(value.chars().next()),
+            ("group", _) => return Err("usage: :group _|,|off".to_string()),
+            ("locale", "") => return Err("usage: :locale <name>".to_string()),
+            ("locale", name) => match name.parse() {
+                Ok(locale) => self.values.set_locale(locale),
+                Err(err) => return Err(format!("{}", err)),
+            },
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+
+    ///
+    /// Handles the commands changing how results are shown, returning
+    /// whether `command` is one of them:
+    ///
+    /// - `:hex`, `:bin`, `:oct` and `:dec` show integers in that radix, and
+    ///   `:bases` in all of them
+    /// - `:precision <digits>|auto` sets the digits after the point of
+    ///   floats, or shows as few as needed
+    /// - `:notation fixed|sci` switches floats between `1500.0` and `1.5e3`
+    /// - `:zeros keep|trim` keeps or drops the zeros padding floats to their
+    ///   precision
+    /// - `:group _|,|off` separates groups of digits, as in `1_000_000`
+    /// - `:locale <name>` uses the decimal point and grouping of a locale
+    ///
+    fn command(&mut self, command: &str) -> bool {
+        let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+        let result = match (name, args.trim()) {
+            ("bases", _) => self.set("base", "all"),
+            ("hex" | "bin" | "oct" | "dec", _) => self.set("base", name),
+            ("precision", "") => {
+                match self.values.float_format().precision() {
+                    Some(digits) => println!("precision {}", digits),
+                    None => println!("precision auto"),
+                }
+                Ok(())
+            }
+            ("precision" | "notation" | "zeros" | "group" | "locale", args) => self.set(name, args),
+            _ => return false,
+        };
+        if let Err(usage) = result {
+            println!("{}", usage);
+        }
+        true
+    }
+}
+
+/// A `:command` of the interactive session.
+struct Command {
+    name: &'static str,
+    /// Its arguments, as shown by `:help`.
+    args: &'static str,
+    help: &'static str,
+    /// Runs the command, given the whole line after the `:`.
+    run: fn(&mut Session, &str),
+}
+
+/// The arguments of a command line, after its name.
+fn args(command: &str) -> &str {
+    command.split_once(' ').map_or("", |(_, args)| args.trim())
+}
+
+fn show_command(session: &mut Session, command: &str) {
+    session.shown.command(command);
+}
+
+fn tool_command(session: &mut Session, command: &str) {
+    run_command(command, &mut session.eval, &mut session.functions)
+}
+
+/// The commands of the interactive session, in the order `:help` lists them.
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: "[<command>]",
+        help: "list the commands, or describe one",
+        run: |_, command| print!("{}", help(args(command))),
+    },
+    Command {
+        name: "quit",
+        args: "",
+        help: "end the session",
+        run: |session, _| session.quit = true,
+    },
+    Command {
+        name: "mode",
+        args: "[eval|lisp|simplify|expand|truth|dot]",
+        help: "give plain lines to a command instead of evaluating them",
+        run: |session, command| match args(command) {
+            "" => println!("mode {}", session.mode.unwrap_or("eval")),
+            mode => session.set("mode", mode),
+        },
+    },
+    Command {
+        name: "set",
+        args: "[<key>=<value>]",
+        help: "change a setting, or list them",
+        run: |session, command| match args(command).split_once('=') {
+            Some((key, value)) => session.set(key.trim(), value.trim()),
+            None if args(command).is_empty() => {
+                for (key, value) in session.settings() {
+                    println!("{}={}", key, value);
+                }
+            }
+            None => println!("usage: :set <key>=<value>"),
+        },
+    },
+    Command {
+        name: "unset",
+        args: "<name>...",
+        help: "remove variables from the session",
+        run: |session, command| {
+            for name in args(command).split_whitespace() {
+                if session.eval.remove(name).is_none() {
+                    println!("no variable {}", name);
+                }
+            }
+        },
+    },
+    Command {
+        name: "undo",
+        args: "",
+        help: "undo the bindings of the last line that changed any",
+        run: |session, _| match session.undo.pop() {
+            Some(changes) => {
+                for (name, value) in changes {
+                    match value {
+                        Some(value) => session.eval.set(name, value),
+                        None => session.eval.remove(&name),
+                    };
+                }
+            }
+            None => println!("nothing to undo"),
+        },
+    },
+    Command {
+        name: "reset",
+        args: "",
+        help: "clear the variables, imports and plugins of the session",
+        run: |session, _| {
+            session.eval = Eval::with_overflow(session.eval.overflow());
+            session.functions = Context::stdlib();
+            session.set_angle(session.angle);
+            session.undo.clear();
+            session.watches.clear();
+        },
+    },
+    Command {
+        name: "time",
+        args: "<expr>",
+        help: "evaluate an expression, timing each phase",
+        run: |session, command| session.time(args(command)),
+    },
+    Command {
+        name: "bench",
+        args: "<expr> [runs]",
+        help: "compare the latency of the tree-walker and the bytecode machine",
+        run: |session, command| match bench(args(command), &session.eval, &session.functions) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(message) => println!("{}", message),
+        },
+    },
+    Command {
+        name: "memory",
+        args: "[<expr>]",
+        help: "show the memory in use, or what the tree of an expression takes",
+        run: |_, command| println!("{}", memory(args(command))),
+    },
+    Command {
+        name: "stats",
+        args: "last",
+        help: "count the nodes, operations and calls of the last line evaluated",
+        run: |session, command| match session.stats(args(command)) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(message) => println!("{}", message),
+        },
+    },
+    Command {
+        name: "watch",
+        args: "[<expr>]",
+        help: "reprint an expression whenever a variable it reads changes",
+        run: |session, command| session.watch(args(command)),
+    },
+    Command {
+        name: "unwatch",
+        args: "[<n>]",
+        help: "stop watching the n-th watched expression, or all",
+        run: |session, command| session.unwatch(args(command)),
+    },
+    Command {
+        name: "jobs",
+        args: "",
+        help: "list the evaluations started with a trailing `&`",
+        run: |session, _| match session.jobs.is_empty() {
+            true => println!("no jobs"),
+            false => session.jobs().iter().for_each(|line| println!("{}", line)),
+        },
+    },
+    Command {
+        name: "result",
+        args: "<job>",
+        help: "print the result of a finished job, forgetting the job",
+        run: |session, command| session.job_result(args(command)),
+    },
+    Command {
+        name: "load",
+        args: "<path>",
+        help: "run a script in the session, keeping its variables and imports",
+        run: |session, command| session.load(args(command)),
+    },
+    Command {
+        name: "save",
+        args: "[--script] <path>",
+        help: "write the transcript, or the expressions that evaluated",
+        run: |session, command| session.save(args(command)),
+    },
+    Command {
+        name: "session",
+        args: "save|load [<name>] | list",
+        help: "save or restore the variables and imports of a workspace",
+        run: |session, command| session.session(args(command)),
+    },
+    Command {
+        name: "vars",
+        args: "[all]",
+        help: "list the variables of the session, or also the library's",
+        run: |session, command| page(&session.vars(args(command) == "all")),
+    },
+    Command {
+        name: "funcs",
+        args: "[<prefix>]",
+        help: "list the functions and their arities",
+        run: |session, command| page(&session.funcs(args(command))),
+    },
+    Command {
+        name: "bases",
+        args: "",
+        help: "show integers in every radix",
+        run: show_command,
+    },
+    Command {
+        name: "bin",
+        args: "",
+        help: "show integers in binary",
+        run: show_command,
+    },
+    Command {
+        name: "oct",
+        args: "",
+        help: "show integers in octal",
+        run: show_command,
+    },
+    Command {
+        name: "dec",
+        args: "",
+        help: "show integers in decimal",
+        run: show_command,
+    },
+    Command {
+        name: "hex",
+        args: "",
+        help: "show integers in hexadecimal",
+        run: show_command,
+    },
+    Command {
+        name: "precision",
+        args: "[<digits>|auto]",
+        help: "set the digits after the point of floats",
+        run: show_command,
+    },
+    Command {
+        name: "notation",
+        args: "fixed|sci",
+        help: "show floats in fixed or scientific notation",
+        run: show_command,
+    },
+    Command {
+        name: "zeros",
+        args: "keep|trim",
+        help: "keep or drop the zeros padding floats",
+        run: show_command,
+    },
+    Command {
+        name: "group",
+        args: "_|,|off",
+        help: "separate groups of digits",
+        run: show_command,
+    },
+    Command {
+        name: "locale",
+        args: "<name>",
+        help: "use the decimal point and grouping of a locale",
+        run: show_command,
+    },
+    Command {
+        name: "explain",
+        args: "<expr>",
+        help: "print each step of evaluating an expression",
+        run: tool_command,
+    },
+    Command {
+        name: "debug",
+        args: "<expr>",
+        help: "step through the evaluation of an expression",
+        run: tool_command,
+    },
+    Command {
+        name: "ast",
+        args: "[json] <expr>",
+        help: "print the syntax tree of an expression, indented or as JSON",
+        run: tool_command,
+    },
+    Command {
+        name: "tokens",
+        args: "<expr>",
+        help: "print the tokens of an expression with their byte ranges",
+        run: tool_command,
+    },
+    Command {
+        name: "dot",
+        args: "<expr>",
+        help: "print the syntax tree of an expression for Graphviz",
+        run: tool_command,
+    },
+    Command {
+        name: "lisp",
+        args: "<sexpr>",
+        help: "evaluate an expression written as an s-expression",
+        run: tool_command,
+    },
+    Command {
+        name: "simplify",
+        args: "<expr>",
+        help: "simplify an expression",
+        run: tool_command,
+    },
+    Command {
+        name: "expand",
+        args: "<expr>",
+        help: "expand a polynomial",
+        run: tool_command,
+    },
+    Command {
+        name: "collect",
+        args: "<var> <expr>",
+        help: "collect the terms of a polynomial in <var>",
+        run: tool_command,
+    },
+    Command {
+        name: "solve",
+        args: "<var> <guess> <expr>",
+        help: "find a root of an expression near <guess>",
+        run: tool_command,
+    },
+    Command {
+        name: "plot",
+        args: "<expr>, <var> = <from>..<to>",
+        help: "plot an expression over a range of a variable",
+        run: tool_command,
+    },
+    Command {
+        name: "truth",
+        args: "<expr>",
+        help: "print the truth table of a boolean expression",
+        run: tool_command,
+    },
+    Command {
+        name: "plugin",
+        args: "load <path>",
+        help: "load the functions of a plugin library",
+        run: tool_command,
+    },
+];
+
+/// The commands `:mode` can give plain lines to.
+const MODES: [&str; 5] = ["lisp", "simplify", "expand", "truth", "dot"];
+
+/// `:help` for `name`, or for every command if it is empty.
+fn help(name: &str) -> String {
+    let usage = |command: &Command| match command.args {
+        "" => format!(":{}", command.name),
+        args => format!(":{} {}", command.name, args),
+    };
+    if name.is_empty() {
+        let width = COMMANDS.iter().map(|command| usage(command).len()).max();
+        return COMMANDS
+            .iter()
+            .map(|command| {
+                let usage = usage(command);
+                format!(
+                    "  {:width$}  {}\n",
+                    usage,
+                    command.help,
+                    width = width.unwrap()
+                )
+            })
+            .collect();
+    }
+    let name = name.strip_prefix(':').unwrap_or(name);
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => format!("{}\n  {}\n", usage(command), command.help),
+        None => format!("{}\n", unknown_command(name)),
+    }
+}
+
+fn unknown_command(name: &str) -> String {
+    let names = COMMANDS.iter().map(|command| command.name);
+    match suggest(name, names) {
+        Some(command) => format!("unknown command :{}, did you mean :{}?", name, command),
+        None => format!("unknown command :{}, see :help", name),
+    }
+}
+
+/// The state of an interactive session.
+struct Session {
+    eval: Eval,
+    /// The standard library and functions loaded from plugins.
+    functions: Context,
+    shown: Shown,
+    /// The command given plain lines by `:mode`, if not evaluating them.
+    mode: Option<&'static str>,
+    /// For each line that changed variables, their values before it, or
+    /// `None` for those it bound first, for `:undo`.
+    undo: Vec<Vec<(String, Option<Value>)>>,
+    /// Whether to time every line, as with `:time`.
+    time: bool,
+    /// The unit of the trigonometric functions.
+    angle: Angle,
+    /// The prompt outside of modes.
+    prompt: String,
+    /// The colors of results, errors and input, if colored.
+    theme: Option<Theme>,
+    transcript: Vec<Entry>,
+    /// The output and, if it evaluated, the expression of the line being
+    /// run, for its entry in the transcript.
+    result: Option<(Option<String>, Option<String>)>,
+    /// What evaluating the last line did, for `:stats last`.
+    report: Option<EvalReport>,
+    watches: Vec<Watch>,
+    jobs: Vec<Job>,
+    /// The number of the next job.
+    next_job: usize,
+    quit: bool,
+}
+
+/// An expression given to `:watch`.
+struct Watch {
+    expr: String,
+    node: Node,
+    /// The variables it reads, whose changes evaluate it again.
+    inputs: Vec<String>,
+}
+
+///
+/// An expression evaluated on its own thread after a trailing `&`, against
+/// a copy of the session: its assignments are not seen by the session.
+///
+struct Job {
+    id: usize,
+    expr: String,
+    handle: JoinHandle<std::result::Result<Value, Failure>>,
+    /// Whether the session said that it finished.
+    reported: bool,
+}
+
+/// A line of the session, for `:save`.
+struct Entry {
+    input: String,
+    /// The value or error printed, for evaluated lines.
+    output: Option<String>,
+    /// The expression evaluated, if it did without error.
+    script: Option<String>,
+}
+
+impl Session {
+    fn new(shown: Shown) -> Self {
+        Session {
+            eval: Eval::new(),
+            functions: Context::stdlib(),
+            shown,
+            mode: None,
+            undo: vec![],
+            time: false,
+            angle: Angle::Radians,
+            prompt: "rexpr> ".to_string(),
+            theme: None,
+            transcript: vec![],
+            result: None,
+            report: None,
+            watches: vec![],
+            jobs: vec![],
+            next_job: 1,
+            quit: false,
+        }
+    }
+
+    /// The prompt, with the mode before its `>`, as in `rexpr[lisp]> `.
+    fn prompt(&self) -> String {
+        let Some(mode) = self.mode else {
+            return self.prompt.clone();
+        };
+        let prompt = self.prompt.trim_end();
+        match prompt.strip_suffix('>') {
+            Some(stem) => format!("{}[{}]> ", stem, mode),
+            None => format!("{}[{}] ", prompt, mode),
+        }
+    }
+
+    /// Registers the trigonometric functions for angles in `angle`.
+    fn set_angle(&mut self, angle: Angle) {
+        self.angle = angle;
+        #[cfg(feature = "math")]
+        rexpr::stdlib::trigonometry(&mut self.functions, angle);
+    }
+
+    ///
+    /// Runs a line: a `:command`, or an expression for the current mode,
+    /// adding it to the transcript and remembering the variables it changes
+    /// unless it is `:undo` or `:reset`.
+    ///
+    fn line(&mut self, line: &str) {
+        let before = self.eval.vars().clone();
+        self.run(line);
+        self.run_watches(&before);
+        let (output, script) = self.result.take().unwrap_or_default();
+        self.transcript.push(Entry {
+            input: line.to_string(),
+            output,
+            script,
+        });
+        if let Some("undo" | "reset") = line.trim().strip_prefix(':') {
+            return;
+        }
+        let changes = changes(&before, self.eval.vars());
+        if !changes.is_empty() {
+            self.undo.push(changes);
+        }
+        warn_memory();
+    }
+
+    fn run(&mut self, line: &str) {
+        if let Some(command) = line.trim().strip_prefix(':') {
+            return self.command(command);
+        }
+        if let Some(mode) = self.mode {
+            return self.command(&format!("{} {}", mode, line));
+        }
+        if let Some(expr) = line.trim_end().strip_suffix('&') {
+            if !expr.ends_with('&') {
+                return self.spawn(expr.trim());
+            }
+        }
+        if self.time {
+            return self.time(line);
+        }
+        let mut report = EvalReport::default();
+        let result = evaluate_line(line, &mut self.eval, &mut self.functions, &mut report);
+        self.report = Some(report);
+        self.print_result(line, result);
+    }
+
+    /// Prints the value of `expr` or why it failed, keeping both for the
+    /// transcript.
+    fn print_result(&mut self, expr: &str, result: std::result::Result<Value, Failure>) {
+        let (output, script) = match result {
+            Ok(value) => (self.shown.show(&value), Some(expr.to_string())),
+            Err(failure) => (failure.message, None),
+        };
+        match (&self.theme, script.is_some()) {
+            (Some(theme), true) => println!("{}{}{}", theme.result, output, RESET),
+            (Some(theme), false) => println!("{}", theme.paint_error(&output)),
+            (None, _) => println!("{}", output),
+        }
+        self.result = Some((Some(output), script));
+    }
+
+    ///
+    /// `:save [--script] <path>`: writes the transcript, or with `--script`
+    /// the lines that evaluated without error, one statement each, so that
+    /// the file can be given to `:load` or run.
+    ///
+    fn save(&self, args: &str) {
+        let (script, path) = match args.strip_prefix("--script") {
+            Some(path) => (true, path.trim()),
+            None => (false, args),
+        };
+        if path.is_empty() {
+            return println!("usage: :save [--script] <path>");
+        }
+        let text = match script {
+            true => self.script(),
+            false => self.transcript(),
+        };
+        match std::fs::write(path, text) {
+            Ok(()) => println!("saved {}", path),
+            Err(err) => println!("cannot write {}: {}", path, err),
+        }
+    }
+
+    /// Each line after a prompt, followed by its value or error. The output
+    /// of commands is not kept.
+    fn transcript(&self) -> String {
+        let mut text = String::new();
+        for entry in &self.transcript {
+            text.push_str(&format!("rexpr> {}\n", entry.input));
+            if let Some(output) = &entry.output {
+                text.push_str(&format!("{}\n", output));
+            }
+        }
+        text
+    }
+
+    /// The expressions that evaluated without error, printed back from
+    /// their trees so that comments cannot swallow the `;` after them.
+    fn script(&self) -> String {
+        let exprs = self
+            .transcript
+            .iter()
+            .filter_map(|entry| entry.script.as_ref());
+        exprs
+            .filter_map(|expr| expr.parse::<Node>().ok())
+            .map(|node| format!("{};\n", node))
+            .collect()
+    }
+
+    /// `:load`: runs the script at `path` without printing its values.
+    fn load(&mut self, path: &str) {
+        if path.is_empty() {
+            return println!("usage: :load <path>");
+        }
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => return println!("cannot read {}: {}", path, err),
+        };
+        let result = run_script(
+            &text,
+            &mut self.eval,
+            &mut self.functions,
+            &mut |_, _, _| {},
+        );
+        match result {
+            Ok(_) => println!("loaded {}", path),
+            Err(failure) => println!("in {}:\n{}", path, failure.message),
+        }
+    }
+
+    ///
+    /// The variables of the session and the paths it imported with `use`,
+    /// as a script that restores them.
+    ///
+    fn bindings(&self) -> String {
+        let mut text = String::new();
+        let library = Context::stdlib();
+        let library: Vec<&str> = library.imports().collect();
+        for path in self
+            .functions
+            .imports()
+            .filter(|path| !library.contains(path))
+        {
+            text.push_str(&format!("use {};\n", path));
+        }
+        let mut vars: Vec<(&String, &Value)> = self.eval.vars().iter().collect();
+        vars.sort_by_key(|(name, _)| *name);
+        for (name, value) in vars {
+            match *value {
+                // Too large to write as a literal.
+                Value::Int(i32::MIN) => text.push_str(&format!("{} = -2147483647 - 1;\n", name)),
+                Value::Int(n) => text.push_str(&format!("{} = {};\n", name, n)),
+                Value::Float(x) => {
+                    text.push_str(&format!("{} = {};\n", name, Node::Float(Float(x))))
+                }
+            }
+        }
+        text
+    }
+
+    /// Saves the bindings to the named workspace, returning where.
+    fn persist(&self, name: &str) -> std::result::Result<PathBuf, String> {
+        let path = session_path(name)?;
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(&path, self.bindings()));
+        match written {
+            Ok(()) => Ok(path),
+            Err(err) => Err(format!("cannot write {}: {}", path.display(), err)),
+        }
+    }
+
+    ///
+    /// Replaces the bindings with those of the named workspace. A missing
+    /// workspace is an error only if `required`, so that the first
+    /// `--persist` session starts empty.
+    ///
+    fn restore(&mut self, name: &str, required: bool) -> std::result::Result<(), String> {
+        let path = session_path(name)?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => return Ok(()),
+            Err(err) => return Err(format!("cannot read {}: {}", path.display(), err)),
+        };
+        self.eval = Eval::with_overflow(self.eval.overflow());
+        self.functions = Context::stdlib();
+        self.set_angle(self.angle);
+        run_script(
+            &text,
+            &mut self.eval,
+            &mut self.functions,
+            &mut |_, _, _| {},
+        )
+        .map(|_| ())
+        .map_err(|failure| format!("in {}:\n{}", path.display(), failure.message))
+    }
+
+    /// `:session save|load [<name>]` and `:session list`.
+    fn session(&mut self, args: &str) {
+        let (action, name) = args.split_once(' ').unwrap_or((args, DEFAULT_SESSION));
+        let name = name.trim();
+        let result = match action {
+            "save" => self
+                .persist(name)
+                .map(|path| format!("saved {}", path.display())),
+            "load" => self
+                .restore(name, true)
+                .map(|()| format!("loaded {}", name)),
+            "list" => session_path(DEFAULT_SESSION).map(|path| {
+                let dir = path.parent().and_then(|dir| std::fs::read_dir(dir).ok());
+                let mut names: Vec<String> = dir
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| {
+                        let path = entry.ok()?.path();
+                        let name = path.file_name()?.to_str()?.strip_suffix(".rx")?;
+                        Some(name.to_string())
+                    })
+                    .collect();
+                names.sort();
+                names.join("\n")
+            }),
+            _ => Err("usage: :session save|load [<name>], :session list".to_string()),
+        };
+        match result {
+            Ok(message) if message.is_empty() => {}
+            Ok(message) => println!("{}", message),
+            Err(message) => println!("{}", message),
+        }
+    }
+
+    ///
+    /// `:stats last`: how many nodes evaluating the last line visited, how
+    /// deep it went, and the calls and operations it made, up to the error
+    /// if it failed.
+    ///
+    fn stats(&self, args: &str) -> std::result::Result<Vec<String>, String> {
+        if args != "last" {
+            return Err("usage: :stats last".to_string());
+        }
+        let report = self.report.as_ref().ok_or("no line evaluated yet")?;
+        let mut binary: Vec<_> = report.binary.iter().collect();
+        binary.sort();
+        let mut unary: Vec<_> = report.unary.iter().collect();
+        unary.sort();
+        let mut ops: Vec<String> = binary
+            .iter()
+            .map(|(op, count)| format!("{} {}", op, count))
+            .collect();
+        ops.extend(unary.iter().map(|(op, count)| format!("{}x {}", op, count)));
+        if ops.is_empty() {
+            ops.push("none".to_string());
+        }
+        Ok(vec![
+            format!("nodes       {}", report.nodes),
+            format!("max depth   {}", report.max_depth),
+            format!("calls       {}", report.calls),
+            format!("operations  {}", ops.join(", ")),
+        ])
+    }
+
+    ///
+    /// `:watch <expr>` watches an expression, printing its value now and
+    /// whenever a variable it reads changes; without one, lists them.
+    ///
+    fn watch(&mut self, expr: &str) {
+        if expr.is_empty() {
+            for (i, watch) in self.watches.iter().enumerate() {
+                println!("{}  {}", i + 1, watch.expr);
+            }
+            return;
+        }
+        let node: Node = match expr.parse() {
+            Ok(node) => node,
+            Err(err) => return println!("{}", err),
+        };
+        let watch = Watch {
+            expr: expr.to_string(),
+            inputs: node.free_variables(),
+            node,
+        };
+        self.watches.push(watch);
+        let before = self.eval.vars().clone();
+        self.reevaluate(self.watches.len() - 1);
+        self.run_watches(&before);
+    }
+
+    /// `:unwatch [<n>]`: stops watching the n-th expression, or all.
+    fn unwatch(&mut self, args: &str) {
+        if args.is_empty() {
+            return self.watches.clear();
+        }
+        match args.parse::<usize>() {
+            Ok(n) if (1..=self.watches.len()).contains(&n) => {
+                self.watches.remove(n - 1);
+            }
+            _ => println!("usage: :unwatch [<n>], n from 1 to {}", self.watches.len()),
+        }
+    }
+
+    /// Prints the value of the i-th watched expression, indented.
+    fn reevaluate(&mut self, i: usize) {
+        let watch = &self.watches[i];
+        let label = match &watch.node {
+            Node::Assign { name, .. } => name.clone(),
+            _ => watch.expr.clone(),
+        };
+        match evaluate(&watch.node, &mut self.eval, &mut self.functions, &mut ()) {
+            Ok(value) => println!("  {} = {}", label, self.shown.show(&value)),
+            Err(err) => println!("  {}: {}", label, err.message()),
+        }
+    }
+
+    ///
+    /// Evaluates again the watched expressions reading variables that
+    /// changed since `before`, and those reading the variables they assign
+    /// in turn, each at most once.
+    ///
+    fn run_watches(&mut self, before: &Map<String, Value>) {
+        let mut changed: Vec<String> = changes(before, self.eval.vars())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let mut ran = vec![false; self.watches.len()];
+        while let Some(i) = (0..self.watches.len()).find(|i| {
+            !ran[*i]
+                && self.watches[*i]
+                    .inputs
+                    .iter()
+                    .any(|name| changed.contains(name))
+        }) {
+            ran[i] = true;
+            let before = self.eval.vars().clone();
+            self.reevaluate(i);
+            let assigned = changes(&before, self.eval.vars());
+            changed.extend(assigned.into_iter().map(|(name, _)| name));
+        }
+    }
+
+    /// Starts evaluating `expr` in the background, as job `[n]`.
+    fn spawn(&mut self, expr: &str) {
+        let mut eval = scratch(&self.eval);
+        // Ctrl-C cancels the line in the foreground, not jobs.
+        eval.set_cancel(None);
+        let mut functions = self.functions.clone();
+        let line = expr.to_string();
+        let handle = worker()
+            .spawn(move || evaluate_line(&line, &mut eval, &mut functions, &mut ()))
+            .expect("failed to spawn a job thread");
+        let id = self.next_job;
+        self.next_job += 1;
+        self.jobs.push(Job {
+            id,
+            expr: expr.to_string(),
+            handle,
+            reported: false,
+        });
+        println!("[{}] {}", id, expr);
+    }
+
+    /// `:jobs`: the number, state and expression of each job.
+    fn jobs(&self) -> Vec<String> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                let state = match job.handle.is_finished() {
+                    true => "done",
+                    false => "running",
+                };
+                format!("[{}] {:7}  {}", job.id, state, job.expr)
+            })
+            .collect()
+    }
+
+    /// Says which jobs finished since it was last called, before a prompt.
+    fn report_jobs(&mut self) {
+        for job in &mut self.jobs {
+            if !job.reported && job.handle.is_finished() {
+                println!("[{}] done  {}", job.id, job.expr);
+                job.reported = true;
+            }
+        }
+    }
+
+    /// `:result <job>`: prints the result of a finished job.
+    fn job_result(&mut self, args: &str) {
+        let Ok(id) = args
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+        else {
+            return println!("usage: :result <job>");
+        };
+        let Some(i) = self.jobs.iter().position(|job| job.id == id) else {
+            return println!("no job [{}]", id);
+        };
+        if !self.jobs[i].handle.is_finished() {
+            return println!("job [{}] is still running", id);
+        }
+        let job = self.jobs.remove(i);
+        match job.handle.join() {
+            Ok(result) => self.print_result(&job.expr, result),
+            Err(_) => println!("job [{}] panicked", id),
+        }
+    }
+
+    /// Runs `line`, then prints how long each phase took.
+    fn time(&mut self, line: &str) {
+        let mut report = EvalReport::default();
+        let (result, timing) = timed_line(line, &mut self.eval, &mut self.functions, &mut report);
+        self.report = Some(report);
+        self.print_result(line, result);
+        eprintln!("{}", timing);
+    }
+
+    fn command(&mut self, command: &str) {
+        let name = command.split(' ').next().unwrap_or(command);
+        match COMMANDS.iter().find(|known| known.name == name) {
+            Some(known) => (known.run)(self, command),
+            None => println!("{}", unknown_command(name)),
+        }
+    }
+
+    ///
+    /// `:vars`: each variable of the session and its value, sorted by name,
+    /// followed by the constants of the library that are not shadowed if
+    /// `all` is set.
+    ///
+    fn vars(&self, all: bool) -> Vec<String> {
+        let mut vars: Vec<(&str, Value)> = self
+            .eval
+            .vars()
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        if all {
+            let library = self.functions.vars().iter();
+            let library = library.filter(|(name, _)| !self.eval.vars().contains_key(*name));
+            vars.extend(library.map(|(name, value)| (name.as_str(), *value)));
+        }
+        vars.sort_by_key(|(name, _)| *name);
+        let width = vars.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        vars.iter()
+            .map(|(name, value)| format!("{:width$} = {}", name, self.shown.show(value)))
+            .collect()
+    }
+
+    /// `:funcs`: each function whose name starts with `prefix` and the
+    /// number of arguments it takes, sorted by name.
+    fn funcs(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<&str> = self
+            .functions
+            .functions()
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort_unstable();
+        let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+        names
+            .iter()
+            .map(|name| match self.functions.arity(name) {
+                Some(arity) => format!("{:width$}  {}", name, arity),
+                None => format!("{:width$}  variadic", name),
+            })
+            .collect()
+    }
+
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = self.shown.settings();
+        settings.push(("mode", self.mode.unwrap_or("eval").to_string()));
+        let time = if self.time { "on" } else { "off" };
+        settings.push(("time", time.to_string()));
+        settings.push(("angle", self.angle.name().to_string()));
+        let memory = ALLOCATOR.limit().map_or("off".to_string(), size_setting);
+        settings.push(("memory", memory));
+        settings
+    }
+
+    /// Changes a setting for `:set`, printing its usage if `value` is not
+    /// one of its values.
+    fn set(&mut self, key: &str, value: &str) {
+        let result = match (key, value) {
+            ("time", "on" | "off") => {
+                self.time = value == "on";
+                Ok(())
+            }
+            ("time", _) => Err("usage: :set time=on|off".to_string()),
+            ("memory", "off") => {
+                ALLOCATOR.set_limit(None);
+                Ok(())
+            }
+            ("memory", _) if !cfg!(feature = "memory") => Err(UNTRACKED.to_string()),
+            ("memory", size) => match parse_size(size) {
+                Some(limit) => {
+                    ALLOCATOR.set_limit(Some(limit));
+                    Ok(())
+                }
+                None => Err("usage: :set memory=<size>|off, as 512M".to_string()),
+            },
+            ("angle", angle) => match angle.parse() {
+                Ok(angle) => {
+                    self.set_angle(angle);
+                    Ok(())
+                }
+                Err(_) => Err("usage: :set angle=rad|deg".to_string()),
+            },
+            ("mode", "eval") => {
+                self.mode = None;
+                Ok(())
+            }
+            ("mode", mode) => match MODES.iter().find(|known| **known == mode) {
+                Some(known) => {
+                    self.mode = Some(known);
+                    Ok(())
+                }
+                None => Err("usage: :mode eval|lisp|simplify|expand|truth|dot".to_string()),
+            },
+            (key, value) => self.shown.set(key, value),
+        };
+        if let Err(usage) = result {
+            println!("{}", usage);
+        }
+    }
+}
+
+///
+/// Prints `lines` a screen at a time when stdout is a terminal, waiting for
+/// Enter between screens, or `q` to stop. The screen is `LINES` high, or 24
+/// lines.
+///
+fn page(lines: &[String]) {
+    let height = std::env::var("LINES")
+        .ok()
+        .and_then(|lines| lines.parse().ok())
+        .filter(|height: &usize| *height > 1)
+        .unwrap_or(24);
+    if !io::stdout().is_terminal() || lines.len() < height {
+        lines.iter().for_each(|line| println!("{}", line));
+        return;
+    }
+    for (i, screen) in lines.chunks(height - 1).enumerate() {
+        if i > 0 {
+            print!("-- more --");
+            let _ = io::stdout().flush();
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer).is_err() || answer.trim() == "q" {
+                return;
+            }
+        }
+        screen.iter().for_each(|line| println!("{}", line));
+    }
+}
+
+/// The most nodes evaluated for the hint of a line.
+const HINT_NODES: usize = 10_000;
+
+/// Cancels an evaluation before it goes through more than a number of nodes.
+struct Budget {
+    nodes: usize,
+    cancel: Cancel,
+}
+
+impl Budget {
+    fn new(nodes: usize) -> Self {
+        Budget {
+            nodes,
+            cancel: Cancel::new(),
+        }
+    }
+}
+
+impl EvalHook for Budget {
+    fn enter(&mut self, _node: &Node, _depth: usize, _bindings: &Bindings) {
+        self.nodes = self.nodes.saturating_sub(1);
+        if self.nodes == 0 {
+            self.cancel.cancel()
+        }
+    }
+}
+
+/// What the line editor knows of the session, to complete and highlight
+/// names and show the value of the line as it is typed.
+#[derive(Default)]
+struct LineHelper {
+    /// The names functions can be called by, sorted.
+    functions: Vec<String>,
+    /// The variables of the session and the library constants, sorted.
+    vars: Vec<String>,
+    /// Whether lines are evaluated, rather than shown in a mode, so that
+    /// names must be defined.
+    evaluating: bool,
+    /// The lines read so far of an input continued at `....> `.
+    pending: String,
+    /// A copy of the variables of the session, to evaluate hints in.
+    eval: Eval,
+    /// The standard library with the imports and constants of the session,
+    /// but none of the functions of its plugins.
+    library: Context,
+    values: ValueFormat,
+    theme: Theme,
+}
+
+impl LineHelper {
+    /// Takes the names of `session`, before each line.
+    fn refresh(&mut self, session: &Session) {
+        self.functions = session.functions.callable().map(String::from).collect();
+        self.functions.sort_unstable();
+        self.functions.dedup();
+        let vars = session.eval.vars().keys();
+        self.vars = vars
+            .chain(session.functions.vars().keys())
+            .cloned()
+            .collect();
+        self.vars.sort_unstable();
+        self.vars.dedup();
+        self.evaluating = session.mode.is_none();
+        self.eval = scratch(&session.eval);
+        // Plugin functions may be slow or act on the world, so hints, which
+        // run on every keystroke, never call them.
+        self.library = Context::stdlib();
+        #[cfg(feature = "math")]
+        rexpr::stdlib::trigonometry(&mut self.library, session.angle);
+        for path in session.functions.imports() {
+            self.library.import(path);
+        }
+        for (name, value) in session.functions.vars() {
+            self.library.set(name.clone(), *value);
+        }
+        self.values = session.shown.values.clone();
+        self.theme = session.theme.unwrap_or_default();
+    }
+
+    ///
+    /// The value of `line` as it would be shown, if it parses and evaluates
+    /// within [`HINT_NODES`] nodes without calling functions of plugins.
+    /// The session is left as it is. Commands, and lines that are already
+    /// their value, have none.
+    ///
+    fn result(&self, line: &str) -> Option<String> {
+        let (input, _) = self.input(line);
+        if !self.evaluating || input.trim_start().starts_with(':') {
+            return None;
+        }
+        let node: Node = input.parse().ok()?;
+        let mut eval = scratch(&self.eval);
+        let mut budget = Budget::new(HINT_NODES);
+        eval.set_cancel(Some(budget.cancel.clone()));
+        let value = if needs_context(&node, &eval, &self.library) {
+            eval.eval_with_hooked(&node, &self.library, &mut budget)
+        } else {
+            eval.eval_hooked(&node, &mut budget).map(Value::Int)
+        };
+        let shown = self.values.format(&value.ok()?);
+        (shown != input.trim()).then_some(shown)
+    }
+
+    /// The whole input with `line` last, and where `line` starts in it.
+    fn input<'l>(&self, line: &'l str) -> (Cow<'l, str>, usize) {
+        match self.pending.is_empty() {
+            true => (Cow::Borrowed(line), 0),
+            false => (
+                Cow::Owned(format!("{}\n{}", self.pending, line)),
+                self.pending.len() + 1,
+            ),
+        }
+    }
+
+    ///
+    /// Whether the name `token` of `line` is neither known to the session
+    /// nor assigned on the line. Paths imported with `use` and placeholders
+    /// are not looked up.
+    ///
+    fn unknown(
+        &self,
+        line: &str,
+        token: &SemanticToken,
+        previous: Option<TokenClass>,
+        assigned: &[&str],
+    ) -> bool {
+        let name = &line[token.span.offset..token.span.offset + token.span.len];
+        let known = |names: &[String]| names.binary_search_by(|known| known.as_str().cmp(name));
+        match token.class {
+            _ if !self.evaluating || name.starts_with('$') => false,
+            TokenClass::Function => known(&self.functions).is_err(),
+            TokenClass::Identifier => {
+                previous != Some(TokenClass::Keyword)
+                    && !assigned.contains(&name)
+                    && known(&self.vars).is_err()
+            }
+            _ => false,
+        }
+    }
+
+    ///
+    /// `line` with its tokens colored: unknown names and parentheses
+    /// without a match in red, and the pair of parentheses at `pos` in
+    /// bold. Parentheses are matched and names looked up across the lines
+    /// of a continued input. Commands are left as they are.
+    ///
+    fn highlight_line(&self, line: &str, pos: usize) -> String {
+        let (input, offset) = self.input(line);
+        if input.starts_with(':') {
+            return line.to_string();
+        }
+        let (line, pos) = (input.as_ref(), pos + offset);
+        let tokens = classify(line);
+        let parens = match_parens(line, &tokens);
+        let at_cursor = tokens.iter().position(|token| {
+            token.class == TokenClass::Paren
+                && (token.span.offset == pos || token.span.offset + token.span.len == pos)
+        });
+        let bold = at_cursor.and_then(|i| Some((i, parens[i]?)));
+        let assigned: Vec<&str> = tokens
+            .iter()
+            .filter(|token| token.class == TokenClass::Identifier)
+            .map(|token| {
+                let end = token.span.offset + token.span.len;
+                (&line[token.span.offset..end], line[end..].trim_start())
+            })
+            .filter(|(_, rest)| rest.starts_with('=') && !rest.starts_with("=="))
+            .map(|(name, _)| name)
+            .collect();
+        let mut text = String::with_capacity(line.len() * 2);
+        let mut end = offset;
+        let mut previous = None;
+        for (i, token) in tokens.iter().enumerate() {
+            if token.span.offset + token.span.len <= offset {
+                if token.class != TokenClass::Comment {
+                    previous = Some(token.class);
+                }
+                continue;
+            }
+            // Only the part on this line of a comment spanning lines.
+            let start = token.span.offset.max(offset);
+            let len = token.span.offset + token.span.len - start;
+            text.push_str(&line[end..start]);
+            let style = match token.class {
+                TokenClass::Paren if parens[i].is_none() => Some(self.theme.error),
+                TokenClass::Paren if bold.is_some_and(|(a, b)| i == a || i == b) => Some(BOLD),
+                _ if self.unknown(line, token, previous, &assigned) => Some(self.theme.error),
+                class => self.theme.token(class),
+            };
+            match style {
+                Some(style) => {
+                    text.push_str(&format!("{}{}{}", style, &line[start..start + len], RESET))
+                }
+                None => text.push_str(&line[start..start + len]),
+            }
+            end = start + len;
+            if token.class != TokenClass::Comment {
+                previous = Some(token.class);
+            }
+        }
+        text.push_str(&line[end..]);
+        text
+    }
+
+    ///
+    /// Where the word before `pos` starts and its completions: the names of
+    /// commands after a leading `:`, and otherwise those of variables,
+    /// constants and functions, with a `(` after functions unless one
+    /// follows already.
+    ///
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let text = &line[..pos];
+        let pair = |name: &str, replacement: String| Pair {
+            display: name.to_string(),
+            replacement,
+        };
+        if let Some(prefix) = text.strip_prefix(':').filter(|text| !text.contains(' ')) {
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| command.name.starts_with(prefix));
+            let commands = commands.map(|command| pair(command.name, command.name.to_string()));
+            return (1, commands.collect());
+        }
+        let word = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+        let start = text
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !word(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let start = pos - text[start..].trim_start_matches(':').len();
+        let prefix = &text[start..];
+        if prefix.is_empty() {
+            return (pos, vec![]);
+        }
+        let paren = if line[pos..].starts_with('(') {
+            ""
+        } else {
+            "("
+        };
+        let vars = self.vars.iter().filter(|name| name.starts_with(prefix));
+        let functions = self
+            .functions
+            .iter()
+            .filter(|name| name.starts_with(prefix));
+        let candidates = vars
+            .map(|name| pair(name, name.clone()))
+            .chain(functions.map(|name| pair(name, format!("{}{}", name, paren))));
+        (start, candidates.collect())
+    }
+}
+
+impl Completer for LineHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> Result<(usize, Vec<Pair>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+/// The value of the line, shown dim after it but never inserted.
+struct ResultHint(String);
+
+impl Hint for ResultHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for LineHelper {
+    type Hint = ResultHint;
+
+    fn hint(&self, line: &str, pos: usize, _: &rustyline::Context<'_>) -> Option<ResultHint> {
+        if pos < line.len() {
+            return None;
+        }
+        let value = self.result(line)?;
+        Some(ResultHint(format!(" = {}", value)))
+    }
+}
+
+impl Highlighter for LineHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Cow::Owned(self.highlight_line(line, pos))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{}{}{}", self.theme.hint, hint, RESET))
+    }
+
+    fn highlight_char(&self, _: &str, _: usize) -> bool {
+        // Moving the cursor can change the pair of parentheses in bold.
+        true
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+
+/// The theme unless the config file picks another.
+const DEFAULT_THEME: &str = "dark";
+
+/// The ANSI styles of the interactive session, picked by `theme` in the
+/// config file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Theme {
+    name: &'static str,
+    number: &'static str,
+    operator: &'static str,
+    function: &'static str,
+    keyword: &'static str,
+    comment: &'static str,
+    /// Characters the lexer rejects.
+    invalid: &'static str,
+    /// Unknown names, parentheses without a match and `error:`.
+    error: &'static str,
+    result: &'static str,
+    hint: &'static str,
+    /// The `-->` and `|` of diagnostics.
+    gutter: &'static str,
+}
+
+const THEMES: [Theme; 2] = [
+    Theme {
+        name: "dark",
+        number: "\x1b[36m",
+        operator: "\x1b[33m",
+        function: "\x1b[34m",
+        keyword: "\x1b[35m",
+        comment: "\x1b[90m",
+        invalid: "\x1b[31m",
+        error: "\x1b[1;31m",
+        result: "\x1b[1;32m",
+        hint: "\x1b[2m",
+        gutter: "\x1b[94m",
+    },
+    // Darker colors, for light backgrounds.
+    Theme {
+        name: "light",
+        number: "\x1b[34m",
+        operator: "\x1b[35m",
+        function: "\x1b[32m",
+        keyword: "\x1b[1;35m",
+        comment: "\x1b[2m",
+        invalid: "\x1b[31m",
+        error: "\x1b[1;31m",
+        result: "\x1b[1;34m",
+        hint: "\x1b[2m",
+        gutter: "\x1b[34m",
+    },
+];
+
+fn unknown_theme(name: &str) -> String {
+    let names: Vec<&str> = THEMES.iter().map(|theme| theme.name).collect();
+    format!("unknown theme {}, expected {}", name, names.join(" or "))
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        THEMES[0]
+    }
+}
+
+impl Theme {
+    fn named(name: &str) -> Option<Theme> {
+        THEMES.iter().find(|theme| theme.name == name).copied()
+    }
+
+    /// The style of a kind of token at the prompt, if any.
+    fn token(&self, class: TokenClass) -> Option<&'static str> {
+        match class {
+            TokenClass::Number => Some(self.number),
+            TokenClass::Operator => Some(self.operator),
+            TokenClass::Function => Some(self.function),
+            TokenClass::Keyword => Some(self.keyword),
+            TokenClass::Comment => Some(self.comment),
+            TokenClass::Error => Some(self.invalid),
+            _ => None,
+        }
     }
-    let eval = Eval::new();
-    loop {
-        let readline = rl.readline("rexpr> ");
+
+    ///
+    /// Colors the errors of `message`, as [`Diagnostic::render`] lays them
+    /// out: `error:` and the carets under the span as errors, and the
+    /// location and gutter of the source line as such.
+    ///
+    fn paint_error(&self, message: &str) -> String {
+        let lines = message.lines().map(|line| {
+            if let Some(rest) = line.strip_prefix("error:") {
+                return format!("{}error:{}{}", self.error, RESET, rest);
+            }
+            if line.trim_start().starts_with("--> ") {
+                return format!("{}{}{}", self.gutter, line, RESET);
+            }
+            let Some((gutter, text)) = line.split_once(" |") else {
+                return line.to_string();
+            };
+            if gutter.is_empty() || !gutter.chars().all(|c| c == ' ' || c.is_ascii_digit()) {
+                return line.to_string();
+            }
+            let text = text.strip_prefix(' ').unwrap_or(text);
+            let carets = text.trim_start();
+            let text = match gutter.trim().is_empty() && carets.starts_with('^') {
+                true => {
+                    let indent = &text[..text.len() - carets.len()];
+                    let hint = carets.trim_start_matches('^');
+                    let carets = &carets[..carets.len() - hint.len()];
+                    format!("{}{}{}{}{}", indent, self.error, carets, RESET, hint)
+                }
+                false => text.to_string(),
+            };
+            format!("{}{} |{} {}", self.gutter, gutter, RESET, text)
+                .trim_end()
+                .to_string()
+        });
+        lines.collect::<Vec<String>>().join("\n")
+    }
+}
+
+///
+/// Whether `text` stops in the middle of an expression, with parentheses
+/// left open or after an operator, so that the prompt asks for more.
+/// Commands, and input the lexer rejects, are complete.
+///
+fn incomplete(text: &str) -> bool {
+    if text.trim_start().starts_with(':') {
+        return false;
+    }
+    let tokens = classify(text);
+    let mut depth = 0;
+    for token in &tokens {
+        match token.class {
+            TokenClass::Error => return false,
+            TokenClass::Paren if text[token.span.offset..].starts_with('(') => depth += 1,
+            TokenClass::Paren => depth -= 1,
+            _ => {}
+        }
+    }
+    let last = tokens
+        .iter()
+        .rev()
+        .find(|token| token.class != TokenClass::Comment);
+    depth > 0 || last.is_some_and(|token| token.class == TokenClass::Operator)
+}
+
+/// The prompt of the lines continuing an incomplete input.
+const CONTINUATION: &str = "....> ";
+
+///
+/// Reads an input at `prompt`, continuing it on further lines while it is
+/// incomplete.
+///
+fn read_input(rl: &mut Editor<LineHelper>, prompt: &str) -> Result<String> {
+    let mut input = rl.readline(prompt);
+    while let Ok(text) = &input {
+        if !incomplete(text) {
+            break;
+        }
+        if let Some(helper) = rl.helper_mut() {
+            helper.pending = text.clone();
+        }
+        let line = rl.readline(CONTINUATION);
+        input = line.map(|line| format!("{}\n{}", text, line));
+    }
+    if let Some(helper) = rl.helper_mut() {
+        helper.pending.clear();
+    }
+    input
+}
+
+///
+/// For each of `tokens`, the index of the parenthesis matching it, or
+/// `None` for parentheses without a match and other tokens.
+///
+fn match_parens(line: &str, tokens: &[SemanticToken]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; tokens.len()];
+    let mut open = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if token.class != TokenClass::Paren {
+            continue;
+        }
+        if line[token.span.offset..].starts_with('(') {
+            open.push(i);
+        } else if let Some(j) = open.pop() {
+            matches[i] = Some(j);
+            matches[j] = Some(i);
+        }
+    }
+    matches
+}
+
+impl Validator for LineHelper {}
+
+impl Helper for LineHelper {}
+
+/// Set on Ctrl-C while a line runs, outside of the line editor.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+///
+/// Makes Ctrl-C set [`INTERRUPTED`] instead of ending rexpr. The line
+/// editor reads Ctrl-C itself, so this only applies while a line runs.
+///
+#[cfg(unix)]
+fn catch_interrupts() {
+    let handler: extern "C" fn(libc::c_int) = interrupt;
+    // SAFETY: the handler only stores to an atomic, which is signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupts() {}
+
+/// The stack of threads evaluating lines, which recurse once per level of
+/// the expression: enough for any the parser accepts, up to
+/// [`rexpr::parser::MAX_DEPTH`] deep.
+const WORKER_STACK: usize = 64 << 20;
+
+fn worker() -> std::thread::Builder {
+    std::thread::Builder::new().stack_size(WORKER_STACK)
+}
+
+///
+/// Runs `line` in the session on a worker thread, cancelling its
+/// evaluation if Ctrl-C is pressed meanwhile, so that the session goes on.
+///
+fn run_interruptible(session: &mut Session, line: &str) {
+    let cancel = Cancel::new();
+    session.eval.set_cancel(Some(cancel.clone()));
+    INTERRUPTED.store(false, Ordering::Relaxed);
+    let waiting = std::thread::current();
+    std::thread::scope(|scope| {
+        let worker = worker()
+            .spawn_scoped(scope, || {
+                session.line(line);
+                waiting.unpark();
+            })
+            .expect("failed to spawn the worker thread");
+        while !worker.is_finished() {
+            if INTERRUPTED.swap(false, Ordering::Relaxed) {
+                cancel.cancel();
+            }
+            std::thread::park_timeout(Duration::from_millis(20));
+        }
+    });
+}
+
+///
+/// Runs the interactive session, with the history and its size set in
+/// `config`, saving its variables on exit if `persist`. Consecutive
+/// duplicate lines are kept once in the history.
+///
+fn repl(mut session: Session, persist: bool, config: &Config) -> Result<()> {
+    let color = match session.theme {
+        Some(_) => ColorMode::Forced,
+        None => ColorMode::Disabled,
+    };
+    let mut editor = rustyline::Config::builder()
+        .history_ignore_dups(true)
+        .completion_type(CompletionType::List)
+        .color_mode(color);
+    if let Some(size) = config.history_size {
+        editor = editor.max_history_size(size);
+    }
+    let mut rl = Editor::<LineHelper>::with_config(editor.build())?;
+    rl.set_helper(Some(LineHelper::default()));
+    catch_interrupts();
+    let history = history_path(config);
+    if let Some(path) = &history {
+        // A missing history is that of a first session.
+        let _ = rl.load_history(path);
+    }
+    while !session.quit {
+        session.report_jobs();
+        if let Some(helper) = rl.helper_mut() {
+            helper.refresh(&session);
+        }
+        let readline = read_input(&mut rl, &session.prompt());
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
-                let mut tokenizer = Tokenizer::new(&line);
-                match tokenizer.tokenize() {
-                    Ok(tokens) => match Parser::new(tokens).parse() {
-                        Ok(node) => println!("{}", eval.eval(&node)),
-                        Err(err) => println!("{:?}", err),
-                    },
-                    Err(err) => println!("{:?}", err),
-                }
-            }
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
+                run_interruptible(&mut session, &line);
             }
+            // Ctrl-C at the prompt drops the line.
+            Err(ReadlineError::Interrupted) => continue,
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");
                 break;
             }
             Err(err) => {
-                println!("Error: {:?}", err);
+                println!("Error: {}", err);
                 break;
             }
         }
     }
-    rl.save_history("history.txt")
+    if persist {
+        if let Err(message) = session.persist(DEFAULT_SESSION) {
+            println!("{}", message);
+        }
+    }
+    match history {
+        Some(path) => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            rl.save_history(&path)
+        }
+        None => Ok(()),
+    }
+}
+
+///
+/// Parses and evaluates `line` in the session, rendering any errors against
+/// it, one after the other.
+///
+fn evaluate_line(
+    line: &str,
+    eval: &mut Eval,
+    functions: &mut Context,
+    hook: &mut impl EvalHook,
+) -> std::result::Result<Value, Failure> {
+    let lexer = Lexer::new(line).recover(true);
+    match Parser::from_lexer(lexer).parse_recovering() {
+        Ok((node, spans)) => evaluate(&node, eval, functions, hook).map_err(|mut err| {
+            err.locate(&spans);
+            Failure::eval(err, line)
+        }),
+        Err(errors) => Err(Failure::syntax(&errors, line)),
+    }
+}
+
+/// How long each phase of running a line took.
+#[derive(Debug, Default)]
+struct Timing {
+    tokenize: Duration,
+    parse: Duration,
+    eval: Duration,
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tokenize {:?}, parse {:?}, eval {:?}",
+            self.tokenize, self.parse, self.eval
+        )
+    }
+}
+
+///
+/// Like [`evaluate_line`], timing tokenizing, parsing and evaluating
+/// separately. Phases after a failing one take no time.
+///
+fn timed_line(
+    line: &str,
+    eval: &mut Eval,
+    functions: &mut Context,
+    hook: &mut impl EvalHook,
+) -> (std::result::Result<Value, Failure>, Timing) {
+    let mut timing = Timing::default();
+    let start = Instant::now();
+    let tokens: std::result::Result<Vec<SpannedToken>, _> = Lexer::new(line).collect();
+    timing.tokenize = start.elapsed();
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        // Lexed again to report every error, as without timing.
+        Err(_) => return (evaluate_line(line, eval, functions, hook), timing),
+    };
+    let start = Instant::now();
+    let parsed = Parser::new(tokens).parse_recovering();
+    timing.parse = start.elapsed();
+    let (node, spans) = match parsed {
+        Ok(parsed) => parsed,
+        Err(errors) => return (Err(Failure::syntax(&errors, line)), timing),
+    };
+    let start = Instant::now();
+    let value = evaluate(&node, eval, functions, hook).map_err(|mut err| {
+        err.locate(&spans);
+        Failure::eval(err, line)
+    });
+    timing.eval = start.elapsed();
+    (value, timing)
+}
+
+///
+/// Runs the statements of a script one by one, passing the source, value
+/// and evaluation time of each to `print`, except for `use` directives, and
+/// returns the last value. The script stops at the first error; syntax
+/// errors are all reported before anything runs.
+///
+fn run_script(
+    text: &str,
+    eval: &mut Eval,
+    functions: &mut Context,
+    print: &mut dyn FnMut(&str, &Value, Duration),
+) -> std::result::Result<Option<Value>, Failure> {
+    let lexer = Lexer::new(text).recover(true);
+    let (node, spans) = match Parser::from_lexer(lexer).parse_recovering() {
+        Ok(parsed) => parsed,
+        Err(errors) => return Err(Failure::syntax(&errors, text)),
+    };
+    // A machine-generated script may take most of the memory once parsed.
+    check_memory().map_err(|err| Failure::eval(err, text))?;
+    // `use` applies to the whole script.
+    for path in node.imports() {
+        functions.import(path);
+    }
+    let stmts = match node {
+        Node::Block(stmts) => stmts.into_iter().zip(spans.children).collect(),
+        stmt => vec![(stmt, spans)],
+    };
+    let mut last = None;
+    for (stmt, spans) in stmts {
+        if let Node::Use(_) = stmt {
+            continue;
+        }
+        let start = Instant::now();
+        let value = evaluate(&stmt, eval, functions, &mut ()).map_err(|mut err| {
+            err.locate(&spans);
+            Failure::eval(err, text)
+        })?;
+        let source = &text[spans.span.offset..spans.span.offset + spans.span.len];
+        print(source, &value, start.elapsed());
+        warn_memory();
+        last = Some(value);
+    }
+    Ok(last)
+}
+
+/// Finds out whether an expression calls any function or has a float
+/// literal.
+#[derive(Default)]
+struct CallsOrFloats(bool);
+
+impl Visitor for CallsOrFloats {
+    fn visit_float(&mut self, _x: f64) {
+        self.0 = true
+    }
+
+    fn visit_call(&mut self, _name: &str, _args: &[Node]) {
+        self.0 = true
+    }
+}
+
+///
+/// Evaluates a line in the session, calling `hook` around each node. Lines
+/// calling functions, with float literals, reading library constants or
+/// reading float variables are evaluated against `functions`, the others in
+/// integers; either way assignments are kept. `use` directives stay in effect
+/// for the rest of the session. Nothing is evaluated while the memory in use
+/// is over the limit.
+///
+fn evaluate(
+    node: &Node,
+    eval: &mut Eval,
+    functions: &mut Context,
+    hook: &mut impl EvalHook,
+) -> std::result::Result<Value, EvalError> {
+    check_memory()?;
+    for path in node.imports() {
+        functions.import(path);
+    }
+    if needs_context(node, eval, functions) {
+        eval.eval_with_assigning(node, functions, hook)
+    } else {
+        eval.eval_hooked(node, hook).map(Value::Int)
+    }
+}
+
+/// Whether `node` calls functions, has float literals, reads library
+/// constants or reads float variables, so that it must be evaluated against
+/// `functions`.
+fn needs_context(node: &Node, eval: &Eval, functions: &Context) -> bool {
+    let mut calls = CallsOrFloats::default();
+    node.accept(&mut calls);
+    calls.0
+        || node
+            .free_variables()
+            .iter()
+            .any(|name| match eval.vars().get(name) {
+                Some(value) => matches!(value, Value::Float(_)),
+                None => functions.get(name).is_some(),
+            })
+}
+
+/// The runs `:bench` makes unless told otherwise.
+const BENCH_RUNS: usize = 10_000;
+
+/// What `:bench` reports of the times of the runs of one backend.
+#[derive(Debug, PartialEq)]
+struct Latency {
+    mean: Duration,
+    median: Duration,
+    p99: Duration,
+}
+
+impl Latency {
+    /// From the time of each run, of which there must be at least one.
+    fn new(mut times: Vec<Duration>) -> Self {
+        times.sort_unstable();
+        // The nearest rank: the smallest time at least `percent` of the runs
+        // take no longer than.
+        let percentile = |percent: usize| times[(times.len() * percent).div_ceil(100) - 1];
+        Latency {
+            mean: times.iter().sum::<Duration>() / times.len() as u32,
+            median: percentile(50),
+            p99: percentile(99),
+        }
+    }
+}
+
+impl std::fmt::Display for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mean {:?}, median {:?}, p99 {:?}",
+            self.mean, self.median, self.p99
+        )
+    }
+}
+
+/// Times `runs` calls of `run`, stopping at the first error.
+fn measure<T>(
+    runs: usize,
+    mut run: impl FnMut() -> std::result::Result<T, EvalError>,
+) -> std::result::Result<Latency, EvalError> {
+    let mut times = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        run()?;
+        times.push(start.elapsed());
+    }
+    Ok(Latency::new(times))
+}
+
+///
+/// `:bench <expr> [runs]`: the latency of evaluating `expr`, parsed once,
+/// with the tree-walking evaluator and with the bytecode machine. The runs
+/// start from the session's variables, on copies, and assignments carry
+/// over from one run to the next.
+///
+fn bench(args: &str, eval: &Eval, functions: &Context) -> std::result::Result<Vec<String>, String> {
+    // A trailing count is only split off if the whole is no expression, so
+    // that `x * 1000` is benchmarked as is.
+    let (expr, runs) = match args.rsplit_once(' ') {
+        Some((expr, runs)) if args.parse::<Node>().is_err() => match runs.parse() {
+            Ok(runs) if runs > 0 => (expr, runs),
+            _ => (args, BENCH_RUNS),
+        },
+        _ => (args, BENCH_RUNS),
+    };
+    if expr.trim().is_empty() {
+        return Err("usage: :bench <expr> [runs]".to_string());
+    }
+    let node: Node = expr.parse().map_err(|err: RexprError| err.to_string())?;
+    let mut lines = vec![format!("{} runs", runs)];
+    if needs_context(&node, eval, functions) {
+        let tree = measure(runs, || eval.eval_with(&node, functions));
+        lines.push(format!("tree  {}", tree.map_err(|err| err.to_string())?));
+        lines.push("vm    unavailable: the machine cannot call functions".to_string());
+        return Ok(lines);
+    }
+    let mut scratch = scratch(eval);
+    let tree = measure(runs, || scratch.eval(&node));
+    lines.push(format!("tree  {}", tree.map_err(|err| err.to_string())?));
+    let program = compile(&node);
+    // Variables the session lacks are assigned before they are read, or the
+    // tree-walker would have failed.
+    let mut vars: Vec<i32> = program
+        .bindings()
+        .names()
+        .iter()
+        .map(|name| match eval.vars().get(name) {
+            Some(Value::Int(n)) => *n,
+            _ => 0,
+        })
+        .collect();
+    let mut vm = Vm::with_overflow(eval.overflow());
+    let machine = measure(runs, || vm.run(&program, &mut vars));
+    lines.push(format!("vm    {}", machine.map_err(|err| err.to_string())?));
+    Ok(lines)
+}
+
+///
+/// The variables that differ between `before` and `after`, with their
+/// value before, or `None` for those bound since.
+///
+fn changes(
+    before: &Map<String, Value>,
+    after: &Map<String, Value>,
+) -> Vec<(String, Option<Value>)> {
+    let mut changes: Vec<(String, Option<Value>)> = before
+        .iter()
+        .filter(|(name, value)| after.get(*name) != Some(*value))
+        .map(|(name, value)| (name.clone(), Some(*value)))
+        .collect();
+    let bound = after.keys().filter(|name| !before.contains_key(*name));
+    changes.extend(bound.map(|name| (name.clone(), None)));
+    changes
+}
+
+///
+/// `:memory`: the memory in use, the most in use at once and the limit set
+/// with `--memory-limit` or `:set memory`; with an expression, the bytes its
+/// tree takes.
+///
+fn memory(expr: &str) -> String {
+    if expr.is_empty() {
+        if !cfg!(feature = "memory") {
+            return UNTRACKED.to_string();
+        }
+        let limit = ALLOCATOR.limit().map_or("none".to_string(), format_bytes);
+        return format!(
+            "in use {}, peak {}, limit {}",
+            format_bytes(ALLOCATOR.allocated()),
+            format_bytes(ALLOCATOR.peak()),
+            limit
+        );
+    }
+    match expr.parse::<Node>() {
+        Ok(node) => format_bytes(node.deep_size()),
+        Err(err) => err.to_string(),
+    }
+}
+
+///
+/// Fails while the memory in use is over the limit, as it is after parsing
+/// a line whose tree does not fit, so that the line is not evaluated. The
+/// warning is taken, as the error tells the same.
+///
+fn check_memory() -> std::result::Result<(), EvalError> {
+    match ALLOCATOR.limit() {
+        Some(limit) if ALLOCATOR.is_over() => {
+            let peak = ALLOCATOR.take_warning().unwrap_or_else(|| ALLOCATOR.peak());
+            Err(EvalError::new(format!(
+                "memory in use reached {}, over the limit of {}",
+                format_bytes(peak),
+                format_bytes(limit)
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Warns on stderr if the memory in use went over the limit.
+fn warn_memory() {
+    if let (Some(peak), Some(limit)) = (ALLOCATOR.take_warning(), ALLOCATOR.limit()) {
+        eprintln!(
+            "warning: memory in use reached {}, over the limit of {}",
+            format_bytes(peak),
+            format_bytes(limit)
+        );
+    }
+}
+
+/// `bytes` as `:set memory` takes it, in the largest unit that is exact.
+fn size_setting(bytes: usize) -> String {
+    match bytes.trailing_zeros() {
+        30.. => format!("{}G", bytes >> 30),
+        20.. => format!("{}M", bytes >> 20),
+        10.. => format!("{}K", bytes >> 10),
+        _ => bytes.to_string(),
+    }
+}
+
+/// A copy of `eval`, to evaluate in without changing the session.
+fn scratch(eval: &Eval) -> Eval {
+    let mut scratch = Eval::with_overflow(eval.overflow());
+    for (name, value) in eval.vars() {
+        scratch.set(name.clone(), *value);
+    }
+    scratch.set_cancel(eval.cancel().cloned());
+    scratch
+}
+
+fn run_command(command: &str, eval: &mut Eval, functions: &mut Context) {
+    let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        "ast" => match args.trim_start().split_once(' ') {
+            Some(("json", expr)) => match Parser::from_lexer(Lexer::new(expr)).parse() {
+                Ok(node) => println!("{}", node.to_json()),
+                Err(err) => println!("{}", err),
+            },
+            _ => match Parser::from_lexer(Lexer::new(args)).parse() {
+                Ok(node) => print!("{}", node.to_tree()),
+                Err(err) => println!("{}", err),
+            },
+        },
+        "tokens" => print!("{}", tokens(args.trim_start())),
+        "debug" => debug(
+            args,
+            eval,
+            functions,
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+        ),
+        "explain" => explain(args, eval)
+            .iter()
+            .for_each(|line| println!("{}", line)),
+        "dot" => match Parser::from_lexer(Lexer::new(args)).parse() {
+            Ok(node) => print!("{}", node.to_dot()),
+            Err(err) => println!("{}", err),
+        },
+        "lisp" => match Node::from_sexpr(args) {
+            Ok(node) => match eval.eval(&node) {
+                Ok(value) => println!("{}", value),
+                Err(err) => println!("{}", err),
+            },
+            Err(err) => println!("{}", err),
+        },
+        "simplify" => match Parser::from_lexer(Lexer::new(args)).parse() {
+            Ok(node) => println!("{}", simplify(&node)),
+            Err(err) => println!("{}", err),
+        },
+        "solve" => solve(args, eval),
+        "plot" => match plot_range(args, eval, functions) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(message) => println!("{}", message),
+        },
+        "plugin" => match args.trim_start().split_once(' ') {
+            Some(("load", path)) => load_plugin(path.trim(), functions),
+            _ => println!("usage: :plugin load <path>"),
+        },
+        "expand" => match Parser::from_lexer(Lexer::new(args)).parse() {
+            Ok(node) => match expand(&node) {
+                Ok(node) => println!("{}", node),
+                Err(err) => println!("{}", err),
+            },
+            Err(err) => println!("{}", err),
+        },
+        "collect" => match args.trim_start().split_once(' ') {
+            Some((var, expr)) => match Parser::from_lexer(Lexer::new(expr)).parse() {
+                Ok(node) => match collect(&node, var) {
+                    Ok(node) => println!("{}", node),
+                    Err(err) => println!("{}", err),
+                },
+                Err(err) => println!("{}", err),
+            },
+            None => println!("usage: :collect <var> <expr>"),
+        },
+        "truth" => match Parser::from_lexer(Lexer::new(args)).parse() {
+            Ok(node) => match truth_table(&node) {
+                Ok(table) => print!("{}", table),
+                Err(err) => println!("{}", err),
+            },
+            Err(err) => println!("{}", err),
+        },
+        _ => println!("unknown command :{}", name),
+    }
+}
+
+///
+/// `:explain <expr>`: `expr`, then each step of evaluating it down to its
+/// value, or to the error that stopped it. Assignments are made on a copy of
+/// the session's variables.
+///
+fn explain(expr: &str, eval: &Eval) -> Vec<String> {
+    let mut node = match expr.parse::<Node>() {
+        Ok(node) => node,
+        Err(err) => return vec![err.to_string()],
+    };
+    let mut eval = scratch(eval);
+    let mut lines = vec![node.to_string()];
+    let mut last = node.to_string();
+    loop {
+        match eval.reduce(&node) {
+            Ok(Some(next)) => node = next,
+            Ok(None) => return lines,
+            Err(err) => {
+                lines.push(err.to_string());
+                return lines;
+            }
+        }
+        // Steps that only change how a number is held, as `-(6)` to `-6`,
+        // are not shown.
+        let text = node.to_string();
+        if text != last {
+            lines.push(format!("→ {}", text));
+            last = text;
+        }
+    }
+}
+
+/// How `:debug` goes on from a stop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resume {
+    /// Stops before the next node.
+    Step,
+    /// Stops before the next node at most this deep, past the children of
+    /// the node stopped at.
+    Next(usize),
+    /// Runs to the end.
+    Continue,
+}
+
+/// The hook of `:debug`, which stops before nodes for a command from
+/// `input`.
+struct Debugger<'a, R, W> {
+    input: &'a mut R,
+    out: &'a mut W,
+    resume: Resume,
+    /// The last command, which an empty line repeats.
+    last: String,
+    /// Whether each node being evaluated was stopped at, innermost last, so
+    /// that its result is shown too.
+    stopped: Vec<bool>,
+    /// The variables last shown, shown again once they change.
+    vars: Vec<String>,
+}
+
+impl<R: BufRead, W: Write> Debugger<'_, R, W> {
+    /// Reads commands until one resumes the evaluation, stopped `depth`
+    /// levels deep. The end of input continues.
+    fn command(&mut self, depth: usize) -> Resume {
+        loop {
+            let _ = write!(self.out, "debug> ");
+            let _ = self.out.flush();
+            let mut line = String::new();
+            if !matches!(self.input.read_line(&mut line), Ok(n) if n > 0) {
+                return Resume::Continue;
+            }
+            let command = match line.trim() {
+                "" => self.last.clone(),
+                command => command.to_string(),
+            };
+            let resume = match command.as_str() {
+                "s" | "step" => Resume::Step,
+                "n" | "next" => Resume::Next(depth),
+                "c" | "continue" => Resume::Continue,
+                _ => {
+                    let _ = writeln!(self.out, "commands: step (s), next (n), continue (c)");
+                    continue;
+                }
+            };
+            self.last = command;
+            return resume;
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> EvalHook for Debugger<'_, R, W> {
+    fn enter(&mut self, node: &Node, depth: usize, bindings: &Bindings) {
+        let stop = match self.resume {
+            Resume::Step => true,
+            Resume::Next(deepest) => depth <= deepest,
+            Resume::Continue => false,
+        };
+        self.stopped.push(stop);
+        if !stop {
+            return;
+        }
+        let indent = "  ".repeat(depth);
+        let _ = writeln!(self.out, "{}{}", indent, node);
+        let mut vars: Vec<String> = bindings
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        vars.sort();
+        if vars != self.vars {
+            let _ = writeln!(self.out, "{}  with {}", indent, vars.join(", "));
+            self.vars = vars;
+        }
+        self.resume = self.command(depth);
+    }
+
+    fn exit(&mut self, _node: &Node, depth: usize, result: std::result::Result<Value, &EvalError>) {
+        // The value of the root is shown once the evaluation ends.
+        if self.stopped.pop() != Some(true) || self.resume == Resume::Continue || depth == 0 {
+            return;
+        }
+        let indent = "  ".repeat(depth);
+        let _ = match result {
+            Ok(value) => writeln!(self.out, "{}= {}", indent, value),
+            Err(err) => writeln!(self.out, "{}! {}", indent, err),
+        };
+    }
+}
+
+///
+/// `:debug <expr>` evaluates `expr` a node at a time, showing each node
+/// before it is evaluated with the variables bound so far, and its value
+/// after, and waits at each for `step` into it, `next` to go past its
+/// children, or `continue` to the end. Assignments are made on a copy of
+/// the session's variables.
+///
+fn debug(
+    expr: &str,
+    eval: &Eval,
+    functions: &Context,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) {
+    let node = match expr.parse::<Node>() {
+        Ok(node) => node,
+        Err(err) => {
+            let _ = writeln!(out, "{}", err);
+            return;
+        }
+    };
+    let mut debugger = Debugger {
+        input,
+        out,
+        resume: Resume::Step,
+        last: "step".to_string(),
+        stopped: vec![],
+        vars: vec![],
+    };
+    let result = if needs_context(&node, eval, functions) {
+        eval.eval_with_hooked(&node, functions, &mut debugger)
+    } else {
+        scratch(eval)
+            .eval_hooked(&node, &mut debugger)
+            .map(Value::Int)
+    };
+    let _ = match result {
+        Ok(value) => writeln!(debugger.out, "{}", value),
+        Err(err) => writeln!(debugger.out, "{}", err),
+    };
+}
+
+/// `:tokens <expr>`: each token of `expr`, comments and errors included,
+/// after the byte range it spans.
+fn tokens(expr: &str) -> String {
+    let lexer = Lexer::new(expr).keep_comments(true).recover(true);
+    let lines: Vec<(String, String)> = lexer
+        .map(|token| match token {
+            Ok(token) => (range(token.span), format!("{:?}", token.token)),
+            Err(err) => (range(err.span()), err.to_string()),
+        })
+        .collect();
+    let width = lines
+        .iter()
+        .map(|(range, _)| range.len())
+        .max()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|(range, token)| format!("{:width$}  {}\n", range, token, width = width))
+        .collect()
+}
+
+fn range(span: Span) -> String {
+    format!("{}..{}", span.offset, span.offset + span.len)
+}
+
+/// `:plugin load <path>` makes the functions of a plugin library callable.
+#[cfg(feature = "plugin")]
+fn load_plugin(path: &str, functions: &mut Context) {
+    // Loading runs code from the library: the user asked for it by path.
+    match unsafe { rexpr::plugin::Plugin::load(path) } {
+        Ok(plugin) => {
+            plugin.register(functions);
+            let names: Vec<&str> = plugin.functions().collect();
+            println!("loaded {}: {}", plugin.name(), names.join(", "))
+        }
+        Err(err) => println!("{}", err),
+    }
+}
+
+#[cfg(not(feature = "plugin"))]
+fn load_plugin(_path: &str, _functions: &mut Context) {
+    println!("plugins need a build with the `plugin` feature")
+}
+
+/// `:solve <var> <guess> <expr>` finds a root of `expr` near `guess`, with the
+/// session's variables in scope.
+fn solve(args: &str, eval: &Eval) {
+    let mut parts = args.trim_start().splitn(3, ' ');
+    let (var, guess, expr) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(var), Some(guess), Some(expr)) => match guess.parse::<f64>() {
+            Ok(guess) => (var, guess, expr),
+            Err(_) => return println!("invalid guess {}", guess),
+        },
+        _ => return println!("usage: :solve <var> <guess> <expr>"),
+    };
+    let node = match Parser::from_lexer(Lexer::new(expr)).parse() {
+        Ok(node) => node,
+        Err(err) => return println!("{}", err),
+    };
+    let vars = eval
+        .vars()
+        .iter()
+        .map(|(name, value)| (name.clone(), value.as_f64()))
+        .collect();
+    match Solver::default().solve(&node, var, guess, &vars) {
+        Ok(root) => println!("{} = {}", var, root),
+        Err(err) => println!("{}", err),
+    }
+}
+
+/// The characters across the plot of `:plot`, each two samples wide.
+const PLOT_COLUMNS: usize = 60;
+/// The lines down the plot of `:plot`.
+const PLOT_ROWS: usize = 12;
+
+///
+/// `:plot <expr>, <var> = <from>..<to>` samples `expr` across the range of
+/// `var`, with the session's variables in scope, and plots it between the
+/// lowest and highest values on the left and the range below. Points where
+/// it fails are left out.
+///
+fn plot_range(
+    args: &str,
+    eval: &Eval,
+    functions: &Context,
+) -> std::result::Result<Vec<String>, String> {
+    let usage = || "usage: :plot <expr>, <var> = <from>..<to>".to_string();
+    let (expr, range) = args.rsplit_once(',').ok_or_else(usage)?;
+    let (var, range) = range.split_once('=').ok_or_else(usage)?;
+    let (from, to) = range.split_once("..").ok_or_else(usage)?;
+    let parse = |expr: &str| {
+        expr.parse::<Node>()
+            .map_err(|err: RexprError| err.to_string())
+    };
+    // The bounds may be decimals, which expressions cannot write.
+    let bound = |expr: &str| match expr.trim().parse::<f64>() {
+        Ok(x) => Ok(x),
+        Err(_) => {
+            let value = eval.eval_with(&parse(expr)?, functions);
+            value.map(Value::as_f64).map_err(|err| err.to_string())
+        }
+    };
+    let (var, node, from, to) = (var.trim(), parse(expr)?, bound(from)?, bound(to)?);
+    if !(from.is_finite() && to.is_finite() && from < to) {
+        return Err(format!("empty range {}..{}", from, to));
+    }
+    let mut ctx = Context::extend(Arc::new(functions.clone()));
+    let mut failure = None;
+    let last = (PLOT_COLUMNS * 2 - 1) as f64;
+    let samples: Vec<f64> = (0..PLOT_COLUMNS * 2)
+        .map(|i| {
+            ctx.set(var, from + (to - from) * i as f64 / last);
+            eval.eval_with(&node, &ctx)
+                .map(Value::as_f64)
+                .unwrap_or_else(|err| {
+                    failure.get_or_insert(err);
+                    f64::NAN
+                })
+        })
+        .collect();
+    if let (true, Some(err)) = (samples.iter().all(|y| !y.is_finite()), failure) {
+        return Err(err.to_string());
+    }
+    let plot = plot(&samples, PLOT_ROWS);
+    let (high, low) = (label(plot.high), label(plot.low));
+    let width = high.len().max(low.len());
+    let mut lines: Vec<String> = plot
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => format!("{:>width$} ┤{}", high, line),
+            i if i == PLOT_ROWS - 1 => format!("{:>width$} ┤{}", low, line),
+            _ => format!("{:width$} │{}", "", line),
+        })
+        .collect();
+    let from = label(from);
+    let pad = PLOT_COLUMNS.saturating_sub(from.len());
+    lines.push(format!("{:width$}  {}{:>pad$}", "", from, label(to)));
+    Ok(lines)
+}
+
+/// `x` for the axes of a plot, to at most three decimals.
+fn label(x: f64) -> String {
+    let text = format!("{:.3}", x);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn parse(args: &[&str]) -> std::result::Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_eval_flags() {
+        let options = parse(&["-e", "x = 2", "--eval", "x * 3"]).unwrap();
+        assert_eq!(options.exprs, ["x = 2", "x * 3"]);
+        assert!(parse(&["--serve-jsonrpc"]).unwrap().serve_jsonrpc);
+        assert_eq!(parse(&["-e"]).unwrap_err(), "-e needs an expression");
+        assert_eq!(parse(&["-x"]).unwrap_err(), "unknown argument -x")
+    }
+
+    #[test]
+    fn parse_script_arguments() {
+        let options = parse(&["--quiet", "prices.rx"]).unwrap();
+        assert_eq!(options.script.as_deref(), Some("prices.rx"));
+        assert!(options.quiet);
+        assert_eq!(parse(&["-"]).unwrap().script.as_deref(), Some("-"));
+        assert!(parse(&["a.rx", "b.rx"]).is_err());
+        assert!(parse(&["a.rx", "-e", "1"]).is_err())
+    }
+
+    #[test]
+    fn run_scripts() {
+        let script = "\
+# Prices with tax.
+use math::*;
+
+rate = 20;
+price = 150;  // before tax
+
+price + price * rate / 100;
+round(sqrt(price))
+";
+        let mut values = vec![];
+        let last = run_script(
+            script,
+            &mut Eval::new(),
+            &mut Context::stdlib(),
+            &mut |_, value, _| values.push(*value),
+        );
+        assert_eq!(last.ok(), Some(Some(Value::Float(12.0))));
+        assert_eq!(
+            values,
+            [
+                Value::Int(20),
+                Value::Int(150),
+                Value::Int(180),
+                Value::Float(12.0)
+            ]
+        );
+
+        let err = run_script(
+            "x = 1;\ny = x / 0;\nz = 2",
+            &mut Eval::new(),
+            &mut Context::new(),
+            &mut |_, _, _| {},
+        );
+        let failure = err.unwrap_err();
+        assert_eq!(failure.status, EXIT_EVAL);
+        assert!(failure.message.contains("2 | y = x / 0;"));
+
+        let mut values = vec![];
+        let last = run_script(
+            "a = max(1, 2);\nb = pi;\na + 1;\nb * 2",
+            &mut Eval::new(),
+            &mut Context::stdlib(),
+            &mut |_, value, _| values.push(*value),
+        );
+        assert_eq!(
+            last.ok(),
+            Some(Some(Value::Float(2.0 * std::f64::consts::PI)))
+        );
+        assert_eq!(values[2], Value::Int(3));
+    }
+
+    #[test]
+    fn evaluate_in_one_session() {
+        let mut eval = Eval::new();
+        let mut functions = Context::stdlib();
+        let mut run = |line: &str| evaluate_line(line, &mut eval, &mut functions, &mut ());
+        assert_eq!(run("x = 2").ok(), Some(Value::Int(2)));
+        assert_eq!(run("x * 3").ok(), Some(Value::Int(6)));
+        assert_eq!(run("sqrt(x * 8)").ok(), Some(Value::Float(4.0)));
+        assert_eq!(run("x = sqrt(16)").ok(), Some(Value::Float(4.0)));
+        assert_eq!(run("x").ok(), Some(Value::Float(4.0)));
+        assert_eq!(run("y = pi; floor(y)").ok(), Some(Value::Float(3.0)));
+        assert_eq!(run("y").ok(), Some(Value::Float(std::f64::consts::PI)));
+        assert!(run("x +").unwrap_err().message.starts_with("error: "))
+    }
+
+    #[test]
+    fn exit_status_by_stage() {
+        let mut eval = Eval::new();
+        let mut functions = Context::stdlib();
+        let mut status = |line: &str| {
+            evaluate_line(line, &mut eval, &mut functions, &mut ())
+                .unwrap_err()
+                .status
+        };
+        assert_eq!(status("1 @ 2"), EXIT_TOKENIZER);
+        assert_eq!(status("/* 1"), EXIT_TOKENIZER);
+        assert_eq!(status("(1 + 2"), EXIT_PARSER);
+        assert_eq!(status("1 + x"), EXIT_EVAL);
+        assert_eq!(status("sqrt(1, 2)"), EXIT_EVAL)
+    }
+
+    #[test]
+    fn format_results_and_errors() {
+        let micros = Duration::from_micros(12);
+        let hex = ValueFormat::new().with_radix(Radix::Hexadecimal);
+        assert_eq!(
+            Format::Plain.result("", &Value::Int(6), micros, &hex),
+            "0x6"
+        );
+        assert_eq!(
+            Format::Json.result("x * 2 ", &Value::Int(6), micros, &hex),
+            r#"{"expr":"x * 2","value":6,"type":"int","micros":12}"#
+        );
+        assert_eq!(
+            Format::Tsv.result("a\t+ b", &Value::Float(0.5), micros, &hex),
+            "ok\ta + b\t0.5\tfloat\t12"
+        );
+        assert_eq!(
+            "csv".parse::<Format>(),
+            Err("unknown format csv, expected plain, json or tsv".to_string())
+        );
+        assert_eq!(parse(&["--format", "tsv"]).unwrap().format, Format::Tsv);
+
+        let mut eval = Eval::new();
+        let mut functions = Context::stdlib();
+        let failure = evaluate_line("1 @ 2; 3 +", &mut eval, &mut functions, &mut ()).unwrap_err();
+        assert_eq!(
+            Format::Tsv.errors(&failure),
+            [
+                "error\tE0006\tUnknow symbol\t1\t3",
+                "error\tE0003\texpected factor\t1\t11"
+            ]
+        );
+        let failure = evaluate_line("y + 1", &mut eval, &mut functions, &mut ()).unwrap_err();
+        assert_eq!(
+            Format::Json.errors(&failure),
+            [
+                r#"{"error":{"code":"E0101","message":"undefined variable y","span":{"line":1,"col":1,"offset":0,"len":1},"expression":"y"}}"#
+            ]
+        )
+    }
+
+    #[test]
+    fn show_radixes() {
+        let mut shown = Shown {
+            values: ValueFormat::new(),
+            all_radixes: false,
+        };
+        assert!(shown.command("hex"));
+        assert_eq!(shown.show(&Value::Int(255)), "0xff");
+        assert!(shown.command("bases"));
+        assert_eq!(shown.show(&Value::Int(-5)), "-5 = -0x5 = -0o5 = -0b101");
+        assert_eq!(shown.show(&Value::Float(1.5)), "1.5");
+        assert!(shown.command("dec"));
+        assert_eq!(shown.show(&Value::Int(255)), "255");
+        assert!(!shown.command("16"));
+        assert!(!shown.command("ast json 1"));
+        let options = parse(&["--output-base", "oct"]).unwrap();
+        assert_eq!(options.radix, Radix::Octal)
+    }
+
+    #[test]
+    fn show_floats() {
+        let mut shown = Shown {
+            values: ValueFormat::new(),
+            all_radixes: false,
+        };
+        let third = Value::Float(1.0 / 3.0);
+        assert!(shown.command("precision 3"));
+        assert_eq!(shown.show(&third), "0.333");
+        assert!(shown.command("notation sci"));
+        assert_eq!(shown.show(&Value::Float(1500.0)), "1.500e3");
+        assert!(shown.command("zeros trim"));
+        assert_eq!(shown.show(&Value::Float(1500.0)), "1.5e3");
+        assert!(shown.command("precision 1"));
+        assert_eq!(shown.values.float_format(), FloatFormat::Scientific(1));
+        assert!(shown.command("precision auto"));
+        assert_eq!(shown.show(&third), "0.3333333333333333");
+        assert!(shown.command("notation fixed"));
+        assert_eq!(shown.values.float_format(), FloatFormat::Fixed(6))
+    }
+
+    fn session() -> Session {
+        Session::new(Shown {
+            values: ValueFormat::new(),
+            all_radixes: false,
+        })
+    }
+
+    #[test]
+    fn run_commands() {
+        let mut session = session();
+        session.line(":set base=hex");
+        session.line(":set precision = 2");
+        session.line(":mode simplify");
+        assert_eq!(session.prompt(), "rexpr[simplify]> ");
+        let settings: Vec<String> = session
+            .settings()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        assert_eq!(
+            settings,
+            [
+                "base=hex",
+                "precision=2",
+                "notation=fixed",
+                "zeros=keep",
+                "group=off",
+                "mode=simplify",
+                "time=off",
+                "angle=rad",
+                "memory=off",
+            ]
+        );
+        session.line(":set mode=eval");
+        session.line(":set mode=graph");
+        session.line("x = 1");
+        assert_eq!(session.eval.vars().get("x"), Some(&Value::Int(1)));
+        session.line(":quit");
+        assert!(session.quit)
+    }
+
+    #[test]
+    fn print_tokens() {
+        assert_eq!(
+            tokens("x1 = 10 /* ten */ @"),
+            concat!(
+                "0..2    Identifier(\"x1\")\n",
+                "3..4    Assign\n",
+                "5..7    Number(\"10\")\n",
+                "8..17   Comment(\"/* ten */\")\n",
+                "18..19  Unknow symbol at Line: 1, Column 19\n",
+            )
+        )
+    }
+
+    #[test]
+    fn list_bindings() {
+        let mut session = session();
+        session.line("rate = 20; base = 150");
+        session.line(":hex");
+        assert_eq!(session.vars(false), ["base = 0x96", "rate = 0x14"]);
+        session.line("pi = 3");
+        let all = session.vars(true);
+        let pi: Vec<&String> = all.iter().filter(|line| line.starts_with("pi ")).collect();
+        assert!(pi.len() == 1 && pi[0].ends_with(" = 0x3"));
+        assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(session.funcs("math::sq"), ["math::sqrt  1"]);
+        assert_eq!(
+            session.funcs("stats::m"),
+            ["stats::mean    variadic", "stats::median  variadic"]
+        );
+        assert!(session.funcs("nothing").is_empty())
+    }
+
+    #[test]
+    fn unset_and_undo() {
+        let mut session = session();
+        session.line("x = 1; y = 2");
+        session.line("x = 10; z = 3");
+        session.line(":unset y");
+        assert_eq!(session.vars(false), ["x = 10", "z = 3"]);
+        session.line(":undo");
+        assert_eq!(session.vars(false), ["x = 10", "y = 2", "z = 3"]);
+        session.line(":undo");
+        assert_eq!(session.vars(false), ["x = 1", "y = 2"]);
+        session.line("use math::sin");
+        session.line(":reset");
+        assert!(session.vars(false).is_empty());
+        let imports = Context::stdlib().imports().count();
+        assert_eq!(session.functions.imports().count(), imports);
+        assert!(session.undo.is_empty())
+    }
+
+    #[test]
+    fn time_phases() {
+        let mut eval = Eval::new();
+        let mut functions = Context::stdlib();
+        let (value, timing) = timed_line("x = max(2, 3) * 4", &mut eval, &mut functions, &mut ());
+        assert_eq!(value.ok(), Some(Value::Int(12)));
+        assert!(timing.to_string().starts_with("tokenize "));
+        let (value, timing) = timed_line("1 + @", &mut eval, &mut functions, &mut ());
+        assert_eq!(value.unwrap_err().status, EXIT_TOKENIZER);
+        assert_eq!(timing.parse + timing.eval, Duration::ZERO);
+        let (value, _) = timed_line("(1", &mut eval, &mut functions, &mut ());
+        assert_eq!(value.unwrap_err().status, EXIT_PARSER);
+        assert!(parse(&["--time", "-e", "1"]).unwrap().time)
+    }
+
+    #[test]
+    fn bench_backends() {
+        let ms = Duration::from_millis;
+        let times = (1..=200).map(ms).collect();
+        assert_eq!(
+            Latency::new(times),
+            Latency {
+                mean: Duration::from_micros(100_500),
+                median: ms(100),
+                p99: ms(198),
+            }
+        );
+        let mut eval = Eval::new();
+        eval.set("x", 6);
+        let functions = Context::stdlib();
+        let lines = bench("x * 7 + y 50", &eval, &functions).unwrap_err();
+        assert_eq!(lines, "EvalError: undefined variable y");
+        let lines = bench("y = x * 7; y 50", &eval, &functions).unwrap();
+        assert_eq!(lines[0], "50 runs");
+        assert!(lines[1].starts_with("tree  mean "));
+        assert!(lines[2].starts_with("vm    mean "));
+        let lines = bench("max(x, 1000)", &eval, &functions).unwrap();
+        assert_eq!(lines[0], "10000 runs");
+        assert!(lines[2].starts_with("vm    unavailable"));
+        assert!(bench("x * (", &eval, &functions).is_err());
+        assert!(bench("", &eval, &functions).is_err())
+    }
+
+    #[test]
+    fn load_scripts() {
+        let path = std::env::temp_dir().join(format!("rexpr-load-{}.rx", std::process::id()));
+        std::fs::write(
+            &path,
+            "use math::sin;\nrate = 20;\nbase = 150 // before tax\n",
+        )
+        .unwrap();
+        let mut session = session();
+        session.line(&format!(":load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(session.vars(false), ["base = 150", "rate = 20"]);
+        assert!(session.functions.imports().any(|path| path == "math::sin"));
+        session.line(":undo");
+        assert!(session.vars(false).is_empty());
+        session.line(&format!(":load {}", path.display()));
+        assert!(session.vars(false).is_empty());
+        let options = parse(&["--preload", "a.rx", "--preload", "b.rx", "-e", "1"]).unwrap();
+        assert_eq!(options.preload, ["a.rx", "b.rx"])
+    }
+
+    #[test]
+    fn save_transcripts() {
+        let mut session = session();
+        session.line("x = 2 // two");
+        session.line(":hex");
+        session.line("x * 300");
+        session.line("x +");
+        session.line(":time y = x");
+        assert_eq!(session.script(), "x = 2;\nx * 300;\ny = x;\n");
+        let transcript = session.transcript();
+        assert!(transcript.starts_with(
+            "rexpr> x = 2 // two\n2\nrexpr> :hex\nrexpr> x * 300\n0x258\nrexpr> x +\nerror: "
+        ));
+        assert!(transcript.ends_with("rexpr> :time y = x\n0x2\n"));
+        let path = std::env::temp_dir().join(format!("rexpr-save-{}.rx", std::process::id()));
+        session.line(&format!(":save --script {}", path.display()));
+        let mut replay = self::session();
+        replay.line(&format!(":load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.vars(false), ["x = 2", "y = 2"])
+    }
+
+    #[test]
+    fn keep_assignments_of_calls() {
+        let mut eval = Eval::new();
+        let mut functions = Context::new();
+        functions.register_fn("half", 1, |args| Ok(Value::Float(args[0].as_f64() / 2.0)));
+        let mut run = |line: &str| evaluate_line(line, &mut eval, &mut functions, &mut ()).ok();
+        assert_eq!(run("r = half(3); n = 2"), Some(Value::Int(2)));
+        assert_eq!(run("r"), Some(Value::Float(1.5)));
+        assert_eq!(run("r * n"), Some(Value::Float(3.0)));
+        assert_eq!(run("n << 1"), Some(Value::Int(4)));
+        assert_eq!(eval.vars().get("n"), Some(&Value::Int(2)))
+    }
+
+    #[test]
+    fn persist_sessions() {
+        let data = std::env::temp_dir().join(format!("rexpr-data-{}", std::process::id()));
+        std::env::set_var("XDG_DATA_HOME", &data);
+        let mut session = session();
+        session.line("use math::sin; x = 2; y = -2147483647 - 1; z = 1 / 4.0; w = -1e300 * 1e9");
+        assert_eq!(
+            session.bindings(),
+            "use math::sin;\nw = (-1.0 / 0.0);\nx = 2;\ny = -2147483647 - 1;\nz = 0.25;\n"
+        );
+        session.line(":session save work");
+        session.line(":unset x");
+        session.line(":session load work");
+        assert_eq!(
+            session.vars(false),
+            ["w = -inf", "x = 2", "y = -2147483648", "z = 0.25"]
+        );
+        assert!(session.restore("other", false).is_ok());
+        assert!(session.restore("other", true).is_err());
+        assert!(session_path("../work").is_err());
+        std::fs::remove_dir_all(&data).unwrap();
+        assert!(parse(&["--persist"]).unwrap().persist)
+    }
+
+    #[test]
+    fn help_and_suggestions() {
+        assert!(help("").lines().all(|line| line.starts_with("  :")));
+        assert_eq!(help("").lines().count(), COMMANDS.len());
+        assert_eq!(
+            help(":set"),
+            ":set [<key>=<value>]\n  change a setting, or list them\n"
+        );
+        assert_eq!(
+            unknown_command("halp"),
+            "unknown command :halp, did you mean :help?"
+        );
+        assert_eq!(
+            unknown_command("frobnicate"),
+            "unknown command :frobnicate, see :help"
+        )
+    }
+
+    #[test]
+    fn show_groups() {
+        let mut shown = Shown {
+            values: ValueFormat::new(),
+            all_radixes: false,
+        };
+        assert!(shown.command("group ,"));
+        assert_eq!(shown.show(&Value::Int(i32::MAX)), "2,147,483,647");
+        assert_eq!(shown.show(&Value::Float(4294967296.0)), "4,294,967,296.0");
+        assert!(shown.command("group _"));
+        assert!(shown.command("bases"));
+        assert_eq!(
+            shown.show(&Value::Int(65535)),
+            "65_535 = 0xffff = 0o177_777 = 0b1111_1111_1111_1111"
+        );
+        assert!(shown.command("group off"));
+        assert_eq!(shown.values.separator(), None);
+        assert!(shown.command("locale de_DE.UTF-8"));
+        assert_eq!(shown.show(&Value::Float(-1234.5)), "-1.234,5");
+        let options = parse(&["--locale", "fr_FR"]).unwrap();
+        assert_eq!(options.locale.unwrap().decimal_point, ',');
+        assert!(parse(&["--locale", "xx"]).is_err())
+    }
+
+    #[test]
+    fn complete_names() {
+        let mut session = session();
+        session.line("budget = 1");
+        let mut helper = LineHelper::default();
+        helper.refresh(&session);
+        let complete = |line: &str, pos: usize| {
+            let (start, candidates) = helper.candidates(line, pos);
+            let replacements: Vec<String> = candidates
+                .into_iter()
+                .map(|pair| pair.replacement)
+                .collect();
+            (start, replacements)
+        };
+        assert_eq!(complete("1 + bud", 7), (4, vec!["budget".to_string()]));
+        assert_eq!(complete("ta", 2), (0, vec!["tan(".to_string()]));
+        assert_eq!(
+            complete("math::ta()", 8),
+            (0, vec!["math::tan".to_string()])
+        );
+        assert_eq!(
+            complete(":un", 3),
+            (
+                1,
+                vec![
+                    "unset".to_string(),
+                    "undo".to_string(),
+                    "unwatch".to_string()
+                ]
+            )
+        );
+        assert_eq!(complete(":unset bu", 9), (7, vec!["budget".to_string()]));
+        assert_eq!(complete("1 + ", 4), (4, vec![]))
+    }
+
+    #[test]
+    fn highlight_input() {
+        let mut session = session();
+        session.line("a = 2");
+        let mut helper = LineHelper::default();
+        helper.refresh(&session);
+        assert_eq!(
+            helper.highlight_line("sqrt(a) + b", 4),
+            "\x1b[34msqrt\x1b[0m\x1b[1m(\x1b[0ma\x1b[1m)\x1b[0m \x1b[33m+\x1b[0m \x1b[1;31mb\x1b[0m"
+        );
+        assert_eq!(
+            helper.highlight_line("(b = 1; b", 6),
+            "\x1b[1;31m(\x1b[0mb \x1b[33m=\x1b[0m \x1b[36m1\x1b[0m; b"
+        );
+        assert_eq!(helper.highlight_line(":vars", 0), ":vars");
+        session.line(":mode simplify");
+        helper.refresh(&session);
+        assert_eq!(helper.highlight_line("b)", 0), "b\x1b[1;31m)\x1b[0m")
+    }
+
+    #[test]
+    fn hint_results() {
+        let mut session = session();
+        session.line("a = 2");
+        session.line(":hex");
+        let mut helper = LineHelper::default();
+        helper.refresh(&session);
+        assert_eq!(helper.result("a * 8").as_deref(), Some("0x10"));
+        assert_eq!(helper.result("b = a + 1").as_deref(), Some("0x3"));
+        assert_eq!(helper.result("sqrt(a * 8)").as_deref(), Some("4.0"));
+        assert_eq!(helper.result("0x10"), None);
+        assert_eq!(helper.result("a +"), None);
+        assert_eq!(helper.result("a / 0"), None);
+        assert_eq!(helper.result(":hex"), None);
+        assert_eq!(session.eval.vars().get("b"), None);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        session.functions.register_fn("plugin::tick", 0, move |_| {
+            Ok(Value::Int(counted.fetch_add(1, Ordering::Relaxed) as i32))
+        });
+        helper.refresh(&session);
+        assert_eq!(helper.result("plugin::tick() + a"), None);
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        assert_eq!(helper.result(&("1; ".repeat(HINT_NODES - 1) + "a")), None);
+        let long = "1; ".repeat(HINT_NODES - 2) + "a";
+        assert_eq!(helper.result(&long).as_deref(), Some("0x2"))
+    }
+
+    #[test]
+    fn continue_input() {
+        assert!(incomplete("sqrt(2 *"));
+        assert!(incomplete("x = (1 + 2) * // next\n"));
+        assert!(incomplete("max(1,\n2"));
+        assert!(!incomplete("max(1,\n2)"));
+        assert!(!incomplete("1 + 2 // done"));
+        assert!(!incomplete("1 + 2)"));
+        assert!(!incomplete("1 + @"));
+        assert!(!incomplete(":ast 1 +"));
+        assert!(!incomplete(""));
+        let mut helper = LineHelper {
+            pending: "a = (1 +".to_string(),
+            evaluating: true,
+            ..LineHelper::default()
+        };
+        assert_eq!(helper.highlight_line("2)", 0), "\x1b[36m2\x1b[0m)");
+        assert_eq!(helper.result("2)").as_deref(), Some("3"));
+        helper.pending.clear();
+        assert_eq!(
+            helper.highlight_line("2)", 0),
+            "\x1b[36m2\x1b[0m\x1b[1;31m)\x1b[0m"
+        )
+    }
+
+    #[test]
+    fn paint_errors() {
+        let theme = Theme::named("light").unwrap();
+        let failure = evaluate_line("1 + ) * 2", &mut Eval::new(), &mut Context::new(), &mut ());
+        let message = failure.unwrap_err().message;
+        assert_eq!(
+            theme.paint_error(&message),
+            [
+                "\x1b[1;31merror:\x1b[0m unexpected token )",
+                "\x1b[34m --> line 1, col 5\x1b[0m",
+                "\x1b[34m  |\x1b[0m",
+                "\x1b[34m1 |\x1b[0m 1 + ) * 2",
+                "\x1b[34m  |\x1b[0m     \x1b[1;31m^\x1b[0m expected a number, a variable or `(`",
+            ]
+            .join("\n")
+        );
+        assert_eq!(theme.paint_error("nothing to undo"), "nothing to undo");
+        assert_eq!(
+            unknown_theme("solarized"),
+            "unknown theme solarized, expected dark or light"
+        );
+        assert_eq!(parse(&["--color=never"]).unwrap().color, Color::Never);
+        assert_eq!(parse(&["--color", "always"]).unwrap().color, Color::Always);
+        assert!(parse(&["--color=rainbow"]).is_err())
+    }
+
+    #[test]
+    fn interrupt_lines() {
+        let mut session = session();
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        run_interruptible(&mut session, "x = 6 * 7");
+        assert_eq!(session.eval.vars().get("x"), Some(&Value::Int(42)));
+        let cancel = session.eval.cancel().unwrap().clone();
+        cancel.cancel();
+        session.line("y = 1");
+        let transcript = session.transcript();
+        assert!(transcript.ends_with("error: evaluation cancelled\n"));
+        assert_eq!(session.eval.vars().get("y"), None);
+        let bench = bench("1 + 1", &session.eval, &session.functions);
+        assert_eq!(bench.unwrap_err(), "EvalError: evaluation cancelled")
+    }
+
+    #[test]
+    fn interrupt_deep_lines() {
+        let mut session = session();
+        let n = 5000;
+        let line = format!("x = {}1{}", "1 + (".repeat(n), ")".repeat(n));
+        run_interruptible(&mut session, &line);
+        assert_eq!(session.eval.vars().get("x"), Some(&Value::Int(5001)));
+    }
+
+    #[test]
+    fn run_jobs() {
+        let mut session = session();
+        session.line("x = 6");
+        session.line("y = x * 7 &");
+        session.line("x && 1 &");
+        session.line("x &&");
+        assert_eq!(session.jobs.len(), 2);
+        while session.jobs.iter().any(|job| !job.handle.is_finished()) {
+            std::thread::yield_now();
+        }
+        assert_eq!(
+            session.jobs(),
+            ["[1] done     y = x * 7", "[2] done     x && 1"]
+        );
+        session.line(":result 1");
+        assert_eq!(
+            session.transcript.last().unwrap().output.as_deref(),
+            Some("42")
+        );
+        assert_eq!(session.eval.vars().get("y"), None);
+        session.line(":result 1");
+        session.line(":result [2]");
+        assert!(session.jobs.is_empty())
+    }
+
+    #[test]
+    fn watch_expressions() {
+        let mut session = session();
+        session.line("price = 3");
+        session.line(":watch total = price * qty");
+        session.line(":watch double = total * 2");
+        session.line("qty = 10");
+        assert_eq!(session.eval.vars().get("total"), Some(&Value::Int(30)));
+        assert_eq!(session.eval.vars().get("double"), Some(&Value::Int(60)));
+        session.line("price = 4");
+        assert_eq!(session.eval.vars().get("double"), Some(&Value::Int(80)));
+        session.line(":undo");
+        assert_eq!(session.eval.vars().get("price"), Some(&Value::Int(3)));
+        assert_eq!(session.eval.vars().get("double"), Some(&Value::Int(60)));
+        session.line(":unwatch 2");
+        assert_eq!(session.watches.len(), 1);
+        session.line("price = 5");
+        assert_eq!(session.eval.vars().get("double"), Some(&Value::Int(60)));
+        session.line(":unwatch");
+        session.line("qty = 1");
+        assert_eq!(session.eval.vars().get("total"), Some(&Value::Int(50)))
+    }
+
+    #[test]
+    fn plot_expressions() {
+        let mut eval = Eval::new();
+        eval.set("a", 2);
+        let functions = Context::stdlib();
+        let lines = plot_range("a * sin(x), x = 0..2*pi", &eval, &functions).unwrap();
+        assert_eq!(lines.len(), PLOT_ROWS + 1);
+        assert!(lines[0].starts_with(" 2 ┤"));
+        assert!(lines[PLOT_ROWS - 1].starts_with("-2 ┤"));
+        assert!(lines[PLOT_ROWS].starts_with("    0 ") && lines[PLOT_ROWS].ends_with(" 6.283"));
+        assert_eq!(
+            plot_range("x * rate, x = 0..1", &eval, &functions).unwrap_err(),
+            "EvalError: undefined variable rate"
+        );
+        let lines = plot_range("sin(x), x = 0..6.28", &eval, &functions).unwrap();
+        assert!(lines[PLOT_ROWS].ends_with(" 6.28"));
+        assert_eq!(
+            plot_range("x, x = 1..1", &eval, &functions).unwrap_err(),
+            "empty range 1..1"
+        );
+        assert_eq!(
+            plot_range("sin(x)", &eval, &functions).unwrap_err(),
+            "usage: :plot <expr>, <var> = <from>..<to>"
+        )
+    }
+
+    #[test]
+    fn explain_steps() {
+        let mut eval = Eval::new();
+        eval.set("n", 2);
+        assert_eq!(
+            explain("(1+2)*3^n", &eval),
+            [
+                "(1 + 2) * 3 ^ n",
+                "→ 3 * 3 ^ n",
+                "→ 3 * 3 ^ 2",
+                "→ 3 * 9",
+                "→ 27"
+            ]
+        );
+        assert_eq!(
+            explain("n = -(n * 4); n / (n + 8)", &eval),
+            [
+                "n = -(n * 4); n / (n + 8)",
+                "→ n = -(2 * 4); n / (n + 8)",
+                "→ n = -8; n / (n + 8)",
+                "→ -8; n / (n + 8)",
+                "→ -8; -8 / (n + 8)",
+                "→ -8; -8 / (-8 + 8)",
+                "→ -8; -8 / 0",
+                "EvalError: division by zero",
+            ]
+        );
+        assert_eq!(eval.vars().get("n"), Some(&Value::Int(2)))
+    }
+
+    #[test]
+    fn debug_steps() {
+        let mut eval = Eval::new();
+        eval.set("n", 3);
+        let functions = Context::stdlib();
+        let run = |expr: &str, input: &str| {
+            let mut out = vec![];
+            debug(expr, &eval, &functions, &mut input.as_bytes(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            run("(1 + 2) * n", "s\nn\nhelp\n\nc\n"),
+            "(1 + 2) * n\n  with n = 3\ndebug>   1 + 2\ndebug>   = 3\n  n\n\
+             debug> commands: step (s), next (n), continue (c)\ndebug>   = 3\n9\n"
+        );
+        assert_eq!(
+            run("x = 2; sqrt(x * n)", "s\nn\ns\nn\n"),
+            "x = 2; sqrt(x * n)\n  with n = 3\ndebug>   x = 2\ndebug>   = 2\n\
+             \x20 sqrt(x * n)\n    with n = 3, x = 2\ndebug>     x * n\ndebug>     = 6\n\
+             \x20 = 2.449489742783178\n2.449489742783178\n"
+        );
+        assert_eq!(
+            run("1 / (n - 3)", ""),
+            "1 / (n - 3)\n  with n = 3\ndebug> EvalError: division by zero\n"
+        );
+        assert_eq!(eval.vars().get("x"), None)
+    }
+
+    #[test]
+    fn stats_last() {
+        let mut session = session();
+        assert_eq!(session.stats("last").unwrap_err(), "no line evaluated yet");
+        session.line("x = 2");
+        session.line("-max(x, 3) * 2 + x * x");
+        session.line(":stats last");
+        assert_eq!(
+            session.stats("last").unwrap(),
+            [
+                "nodes       10",
+                "max depth   4",
+                "calls       1",
+                "operations  + 1, * 2, -x 1",
+            ]
+        );
+        session.line("1 / 0");
+        assert_eq!(session.stats("last").unwrap()[3], "operations  / 1");
+        assert_eq!(session.stats("").unwrap_err(), "usage: :stats last")
+    }
+
+    #[test]
+    fn memory_sizes() {
+        assert_eq!(
+            parse(&["--memory-limit", "64M"]).unwrap().memory_limit,
+            Some(64 << 20)
+        );
+        assert_eq!(
+            parse(&["--memory-limit", "lots"]).unwrap_err(),
+            "invalid size lots"
+        );
+        assert_eq!(size_setting(3 << 30), "3G");
+        assert_eq!(size_setting(3072), "3K");
+        assert_eq!(size_setting(1000), "1000");
+        let size = std::mem::size_of::<Node>();
+        assert_eq!(memory("1 + 2"), format_bytes(3 * size));
+        let usage = if cfg!(feature = "memory") {
+            "in use "
+        } else {
+            UNTRACKED
+        };
+        assert!(memory("").starts_with(usage))
+    }
+
+    #[test]
+    fn apply_config() {
+        let dir = std::env::temp_dir().join(format!("rexpr-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.toml");
+        std::fs::write(&file, "angle = \"deg\"\nprompt = \"calc> \"\n").unwrap();
+        let options = parse(&["--config", file.to_str().unwrap()]).unwrap();
+        let config = load_config(options.config.as_deref());
+        let mut session = session();
+        session.prompt = config.prompt.unwrap();
+        session.set_angle(config.angle.unwrap());
+        session.line(":mode lisp");
+        assert_eq!(session.prompt(), "calc[lisp]> ");
+        let sin = |session: &mut Session| {
+            let (result, _) = timed_line(
+                "sin(90)",
+                &mut session.eval,
+                &mut session.functions,
+                &mut (),
+            );
+            result.ok().unwrap().as_f64()
+        };
+        session.line(":reset");
+        assert_eq!(sin(&mut session), 1.0);
+        session.line(":set angle=rad");
+        assert_eq!(sin(&mut session), 90f64.sin());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(expand_home("a/~/b.rx"), "a/~/b.rx");
+        let config = Config {
+            history_file: Some("h.txt".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(history_path(&config), Some(PathBuf::from("h.txt")))
+    }
 }
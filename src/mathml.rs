@@ -0,0 +1,278 @@
+//!
+//! Presentation MathML output of the AST.
+//!
+use crate::parser::*;
+
+fn group(node: &Node, parenthesize: bool) -> String {
+    if parenthesize {
+        format!("<mrow><mo>(</mo>{}<mo>)</mo></mrow>", node.mathml())
+    } else {
+        node.mathml()
+    }
+}
+
+/// `text` with the characters that are markup in XML escaped, as names may
+/// come from trees built by hand or read from JSON.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn is_or(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::BinaryExpr {
+            op: BinaryOperator::Or,
+            ..
+        }
+    )
+}
+
+fn number(n: i32) -> String {
+    if n < 0 {
+        format!("<mrow><mo>-</mo><mn>{}</mn></mrow>", n.unsigned_abs())
+    } else {
+        format!("<mn>{}</mn>", n)
+    }
+}
+
+/// A float in scientific notation when it prints with an exponent.
+fn float(x: f64) -> String {
+    if x.is_nan() {
+        return "<mi>NaN</mi>".to_string();
+    }
+    if x.is_infinite() {
+        let sign = if x < 0.0 { "<mo>-</mo>" } else { "" };
+        return format!("<mrow>{}<mi>&#x221E;</mi></mrow>", sign);
+    }
+    let text = format!("{:?}", x.abs());
+    let digits = match text.split_once('e') {
+        Some((mantissa, exponent)) => format!(
+            "<mrow><mn>{}</mn><mo>&#xD7;</mo><msup><mn>10</mn><mn>{}</mn></msup></mrow>",
+            mantissa, exponent
+        ),
+        None => format!("<mn>{}</mn>", text),
+    };
+    if x.is_sign_negative() {
+        format!("<mrow><mo>-</mo>{}</mrow>", digits)
+    } else {
+        digits
+    }
+}
+
+impl Node {
+    fn mathml(&self) -> String {
+        match self {
+            Node::Number(n) => number(*n),
+            Node::Float(Float(x)) => float(*x),
+            Node::Identifier(name) => format!("<mi>{}</mi>", escape(name)),
+            Node::UnaryExpr { op, child } => match op {
+                UnaryOperator::Neg => {
+                    format!(
+                        "<mrow><mo>-</mo>{}</mrow>",
+                        group(
+                            child,
+                            child.is_additive() || child.is_shift() || child.is_logical()
+                        )
+                    )
+                }
+                UnaryOperator::Not => {
+                    format!(
+                        "<mrow><mo>&#xAC;</mo>{}</mrow>",
+                        group(
+                            child,
+                            child.is_additive() || child.is_shift() || child.is_logical()
+                        )
+                    )
+                }
+            },
+            Node::BinaryExpr { op, lhs, rhs } => match op {
+                BinaryOperator::Plus => format!(
+                    "<mrow>{}<mo>+</mo>{}</mrow>",
+                    group(lhs, lhs.is_shift() || lhs.is_logical()),
+                    group(rhs, rhs.is_negation() || rhs.is_shift() || rhs.is_logical())
+                ),
+                BinaryOperator::Minus => format!(
+                    "<mrow>{}<mo>-</mo>{}</mrow>",
+                    group(lhs, lhs.is_shift() || lhs.is_logical()),
+                    group(
+                        rhs,
+                        rhs.is_additive()
+                            || rhs.is_negation()
+                            || rhs.is_shift()
+                            || rhs.is_logical()
+                    )
+                ),
+                BinaryOperator::Mul => format!(
+                    "<mrow>{}<mo>&#x22C5;</mo>{}</mrow>",
+                    group(lhs, lhs.is_additive() || lhs.is_shift() || lhs.is_logical()),
+                    group(
+                        rhs,
+                        rhs.is_additive()
+                            || rhs.is_negation()
+                            || rhs.is_shift()
+                            || rhs.is_logical()
+                    )
+                ),
+                BinaryOperator::Div => {
+                    format!("<mfrac>{}{}</mfrac>", lhs.mathml(), rhs.mathml())
+                }
+                BinaryOperator::Pow => {
+                    format!(
+                        "<msup>{}{}</msup>",
+                        group(lhs, !lhs.is_atom()),
+                        rhs.mathml()
+                    )
+                }
+                BinaryOperator::Shl => format!(
+                    "<mrow>{}<mo>&lt;&lt;</mo>{}</mrow>",
+                    group(lhs, lhs.is_logical()),
+                    group(rhs, rhs.is_shift() || rhs.is_logical())
+                ),
+                BinaryOperator::Shr => format!(
+                    "<mrow>{}<mo>&gt;&gt;</mo>{}</mrow>",
+                    group(lhs, lhs.is_logical()),
+                    group(rhs, rhs.is_shift() || rhs.is_logical())
+                ),
+                BinaryOperator::And => format!(
+                    "<mrow>{}<mo>&#x2227;</mo>{}</mrow>",
+                    group(lhs, is_or(lhs)),
+                    group(rhs, rhs.is_logical())
+                ),
+                BinaryOperator::Or => format!(
+                    "<mrow>{}<mo>&#x2228;</mo>{}</mrow>",
+                    lhs.mathml(),
+                    group(rhs, is_or(rhs))
+                ),
+            },
+            Node::Assign { name, value } => {
+                let name = escape(name);
+                format!("<mrow><mi>{}</mi><mo>=</mo>{}</mrow>", name, value.mathml())
+            }
+            Node::Block(stmts) => {
+                let stmts: Vec<String> = stmts.iter().map(Node::mathml).collect();
+                format!("<mrow>{}</mrow>", stmts.join("<mo>;</mo>"))
+            }
+            Node::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(Node::mathml).collect();
+                format!(
+                    "<mrow><mi>{}</mi><mo>&#x2061;</mo>\
+                     <mrow><mo>(</mo>{}<mo>)</mo></mrow></mrow>",
+                    escape(name),
+                    args.join("<mo>,</mo>")
+                )
+            }
+            Node::Use(path) => format!("<mtext>use {}</mtext>", escape(path)),
+        }
+    }
+
+    pub fn to_mathml(&self) -> String {
+        format!(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>",
+            self.mathml()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn mathml(text: &str) -> String {
+        let tokenizer = Tokenizer::new(text);
+        let tokens = tokenizer.tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap().to_mathml()
+    }
+
+    #[test]
+    fn fraction() {
+        assert_eq!(
+            mathml("x / 2"),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mfrac><mi>x</mi><mn>2</mn></mfrac></math>"
+        )
+    }
+
+    #[test]
+    fn parenthesize_by_precedence() {
+        assert_eq!(
+            mathml("(1 + 2) * -x"),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>\
+             <mrow><mo>(</mo><mrow><mn>1</mn><mo>+</mo><mn>2</mn></mrow><mo>)</mo></mrow>\
+             <mo>&#x22C5;</mo>\
+             <mrow><mo>(</mo><mrow><mo>-</mo><mi>x</mi></mrow><mo>)</mo></mrow>\
+             </mrow></math>"
+        )
+    }
+
+    #[test]
+    fn superscript() {
+        assert_eq!(
+            mathml("(x + 1) ^ 2"),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><msup>\
+             <mrow><mo>(</mo><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow><mo>)</mo></mrow>\
+             <mn>2</mn></msup></math>"
+        )
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(
+            mathml("!a && (b || c)"),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>\
+             <mrow><mo>&#xAC;</mo><mi>a</mi></mrow><mo>&#x2227;</mo>\
+             <mrow><mo>(</mo><mrow><mi>b</mi><mo>&#x2228;</mo><mi>c</mi></mrow><mo>)</mo></mrow>\
+             </mrow></math>"
+        )
+    }
+
+    #[test]
+    fn function_call() {
+        assert_eq!(
+            "max(a, 1)".parse::<Node>().unwrap().mathml(),
+            "<mrow><mi>max</mi><mo>&#x2061;</mo>\
+             <mrow><mo>(</mo><mi>a</mi><mo>,</mo><mn>1</mn><mo>)</mo></mrow></mrow>"
+        )
+    }
+
+    #[test]
+    fn negative_literal() {
+        let node = Node::Number(-3);
+        assert_eq!(node.mathml(), "<mrow><mo>-</mo><mn>3</mn></mrow>");
+        let node = Node::Float(Float(-2.5e-7));
+        assert_eq!(
+            node.mathml(),
+            "<mrow><mo>-</mo><mrow><mn>2.5</mn><mo>&#xD7;</mo>\
+             <msup><mn>10</mn><mn>-7</mn></msup></mrow></mrow>"
+        )
+    }
+
+    #[test]
+    fn escape_names() {
+        let node = Node::Assign {
+            name: "a<b>".to_string(),
+            value: Box::new(Node::Identifier("x&\"y".to_string())),
+        };
+        assert_eq!(
+            node.mathml(),
+            "<mrow><mi>a&lt;b&gt;</mi><mo>=</mo><mi>x&amp;&quot;y</mi></mrow>"
+        );
+        let node = Node::Call {
+            name: "</mi>".to_string(),
+            args: vec![],
+        };
+        assert!(node.mathml().starts_with("<mrow><mi>&lt;/mi&gt;</mi>"));
+        let node = Node::Use("<m>".to_string());
+        assert_eq!(node.mathml(), "<mtext>use &lt;m&gt;</mtext>")
+    }
+}
@@ -0,0 +1,247 @@
+//!
+//! Accounting of heap memory, for programs handling expressions too large
+//! to trust: [`Tracking`] is a global allocator that counts the bytes in
+//! use and raises a warning once they pass a limit, and
+//! [`Node::deep_size`](crate::parser::Node::deep_size) tells what a tree
+//! takes.
+//!
+//! ```
+//! use rexpr::memory::Tracking;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: Tracking = Tracking::new();
+//!
+//! ALLOCATOR.set_limit(Some(1 << 20));
+//! let big = vec![0u8; 2 << 20];
+//! assert!(ALLOCATOR.allocated() >= big.len());
+//! drop(big);
+//! assert!(ALLOCATOR.take_warning().is_some());
+//! assert!(ALLOCATOR.take_warning().is_none());
+//! ```
+//!
+//! The counts are kept with relaxed atomics, so they are exact once the
+//! threads allocating are done, and close while they run.
+//!
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// No limit.
+const UNLIMITED: usize = usize::MAX;
+
+///
+/// An allocator counting the bytes allocated through it, on top of `A`,
+/// the system allocator unless given. A warning is raised when the bytes in
+/// use go over the [limit](Tracking::set_limit), and raised again only after
+/// they went back under it.
+///
+pub struct Tracking<A = System> {
+    inner: A,
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+    limit: AtomicUsize,
+    /// Whether the bytes in use are over the limit.
+    over: AtomicBool,
+    /// Whether a warning was raised and not yet taken.
+    warning: AtomicBool,
+}
+
+impl Tracking {
+    pub const fn new() -> Self {
+        Tracking::with(System)
+    }
+}
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> Tracking<A> {
+    pub const fn with(inner: A) -> Self {
+        Tracking {
+            inner,
+            allocated: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            limit: AtomicUsize::new(UNLIMITED),
+            over: AtomicBool::new(false),
+            warning: AtomicBool::new(false),
+        }
+    }
+
+    /// The bytes in use.
+    pub fn allocated(&self) -> usize {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    /// The most bytes in use at once since the start or the last
+    /// [`reset_peak`](Tracking::reset_peak).
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_peak(&self) {
+        self.peak.store(self.allocated(), Ordering::Relaxed)
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        Some(self.limit.load(Ordering::Relaxed)).filter(|limit| *limit != UNLIMITED)
+    }
+
+    /// Warns once more than `limit` bytes are in use, or never for `None`.
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit
+            .store(limit.unwrap_or(UNLIMITED), Ordering::Relaxed);
+        self.over.store(false, Ordering::Relaxed);
+        self.warning.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether more bytes than the limit are in use.
+    pub fn is_over(&self) -> bool {
+        self.over.load(Ordering::Relaxed)
+    }
+
+    /// The peak, if the limit was passed since the last call.
+    pub fn take_warning(&self) -> Option<usize> {
+        self.warning
+            .swap(false, Ordering::Relaxed)
+            .then(|| self.peak())
+    }
+
+    fn add(&self, size: usize) {
+        let allocated = self.allocated.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(allocated, Ordering::Relaxed);
+        if allocated > self.limit.load(Ordering::Relaxed)
+            && !self.over.swap(true, Ordering::Relaxed)
+        {
+            self.warning.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn remove(&self, size: usize) {
+        let allocated = self.allocated.fetch_sub(size, Ordering::Relaxed) - size;
+        if allocated <= self.limit.load(Ordering::Relaxed) {
+            self.over.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+// Counting does not allocate, so it cannot recurse into the allocator.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Tracking<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.remove(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = self.inner.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            self.remove(layout.size());
+            self.add(new_size);
+        }
+        new
+    }
+}
+
+/// `bytes` in the largest binary unit it makes at least one of, as `1.5 MiB`.
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+///
+/// A size as written by a user: bytes, or a number of `K`, `M` or `G`,
+/// binary units which may be followed by `iB` or `B`, as in `512M` or
+/// `2 GiB`.
+///
+pub fn parse_size(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+    let number: usize = number.parse().ok()?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let shift = match unit {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn count_allocations() {
+        let tracking = Tracking::new();
+        tracking.set_limit(Some(100));
+        let small = Layout::from_size_align(60, 8).unwrap();
+        unsafe {
+            let a = tracking.alloc(small);
+            assert_eq!(tracking.take_warning(), None);
+            let b = tracking.alloc_zeroed(small);
+            assert_eq!(tracking.allocated(), 120);
+            assert_eq!(tracking.take_warning(), Some(120));
+            assert!(tracking.is_over());
+            let b = tracking.realloc(b, small, 30);
+            assert!(!tracking.is_over());
+            assert_eq!((tracking.allocated(), tracking.peak()), (90, 120));
+            let c = tracking.alloc(small);
+            assert_eq!(tracking.take_warning(), Some(150));
+            tracking.dealloc(c, small);
+            tracking.dealloc(b, Layout::from_size_align(30, 8).unwrap());
+            tracking.dealloc(a, small);
+        }
+        assert_eq!(tracking.allocated(), 0);
+        tracking.reset_peak();
+        assert_eq!(tracking.peak(), 0);
+        assert_eq!(tracking.limit(), Some(100))
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+        assert_eq!(parse_size("512M"), Some(512 << 20));
+        assert_eq!(parse_size("2 GiB"), Some(2 << 30));
+        assert_eq!(parse_size("64kb"), Some(64 << 10));
+        assert_eq!(parse_size("1000"), Some(1000));
+        assert_eq!(parse_size("1.5G"), None);
+        assert_eq!(parse_size("M"), None)
+    }
+}
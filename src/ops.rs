@@ -0,0 +1,151 @@
+//!
+//! Building expressions from host code with the usual operators:
+//!
+//! ```
+//! use rexpr::parser::Node;
+//!
+//! let x = Node::var("x");
+//! let expr = Node::num(1) + Node::num(2) * x - 3;
+//! assert_eq!(expr.to_string(), "1 + 2 * x - 3");
+//! ```
+//!
+use crate::parser::*;
+use crate::prelude::*;
+use core::ops::{Add, Div, Mul, Neg, Not, Sub};
+
+impl Node {
+    pub fn num(n: i32) -> Node {
+        Node::Number(n)
+    }
+
+    pub fn var(name: impl Into<String>) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    pub fn assign(name: impl Into<String>, value: impl Into<Node>) -> Node {
+        Node::Assign {
+            name: name.into(),
+            value: Box::new(value.into()),
+        }
+    }
+
+    pub fn unary(op: UnaryOperator, child: impl Into<Node>) -> Node {
+        Node::UnaryExpr {
+            op,
+            child: Box::new(child.into()),
+        }
+    }
+
+    pub fn binary(op: BinaryOperator, lhs: impl Into<Node>, rhs: impl Into<Node>) -> Node {
+        Node::BinaryExpr {
+            op,
+            lhs: Box::new(lhs.into()),
+            rhs: Box::new(rhs.into()),
+        }
+    }
+
+    pub fn block(stmts: impl IntoIterator<Item = Node>) -> Node {
+        Node::Block(stmts.into_iter().collect())
+    }
+
+    pub fn call(name: impl Into<String>, args: impl IntoIterator<Item = Node>) -> Node {
+        Node::Call {
+            name: name.into(),
+            args: args.into_iter().collect(),
+        }
+    }
+}
+
+impl From<i32> for Node {
+    fn from(n: i32) -> Self {
+        Node::Number(n)
+    }
+}
+
+impl<T: Into<Node>> Add<T> for Node {
+    type Output = Node;
+
+    fn add(self, rhs: T) -> Node {
+        Node::binary(BinaryOperator::Plus, self, rhs)
+    }
+}
+
+impl<T: Into<Node>> Sub<T> for Node {
+    type Output = Node;
+
+    fn sub(self, rhs: T) -> Node {
+        Node::binary(BinaryOperator::Minus, self, rhs)
+    }
+}
+
+impl<T: Into<Node>> Mul<T> for Node {
+    type Output = Node;
+
+    fn mul(self, rhs: T) -> Node {
+        Node::binary(BinaryOperator::Mul, self, rhs)
+    }
+}
+
+impl<T: Into<Node>> Div<T> for Node {
+    type Output = Node;
+
+    fn div(self, rhs: T) -> Node {
+        Node::binary(BinaryOperator::Div, self, rhs)
+    }
+}
+
+impl Neg for Node {
+    type Output = Node;
+
+    fn neg(self) -> Node {
+        Node::unary(UnaryOperator::Neg, self)
+    }
+}
+
+impl Not for Node {
+    type Output = Node;
+
+    fn not(self) -> Node {
+        Node::unary(UnaryOperator::Not, self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn parse(text: &str) -> Node {
+        let tokenizer = Tokenizer::new(text);
+        let tokens = tokenizer.tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn build_matches_parse() {
+        let x = Node::var("x");
+        let expr = -(Node::num(1) + Node::num(2) * x.clone()) / (x - 4);
+        assert_eq!(expr, parse("-(1 + 2 * x) / (x - 4)"))
+    }
+
+    #[test]
+    fn build_assignment() {
+        let expr = Node::assign("y", Node::var("x") * 2);
+        assert_eq!(expr, parse("y = x * 2"))
+    }
+
+    #[test]
+    fn build_logical() {
+        let expr = Node::binary(BinaryOperator::And, !Node::var("a"), Node::var("b"));
+        assert_eq!(expr, parse("!a && b"))
+    }
+
+    #[test]
+    fn build_block_and_call() {
+        let expr = Node::block([
+            Node::assign("r", 2),
+            Node::call("area", [Node::var("r"), Node::num(3)]),
+        ]);
+        assert_eq!(expr, parse("r = 2; area(r, 3)"))
+    }
+}
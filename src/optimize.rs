@@ -0,0 +1,229 @@
+//!
+//! AST optimization passes, run before evaluation or export.
+//!
+use crate::eval::{binary, unary, Overflow};
+use crate::parser::*;
+
+///
+/// Replaces every operation on literal operands with its result, bottom-up,
+/// so `2 * 3 + x` becomes `6 + x`.
+///
+/// Results are computed with the evaluator's own arithmetic under
+/// `overflow`, so folding never changes what the tree evaluates to. An
+/// operation that would fail at runtime, such as `1 / 0` or an overflow under
+/// `Overflow::Checked`, is left in place for the evaluator to report.
+///
+pub fn fold_constants(node: &Node, overflow: Overflow) -> Node {
+    node.clone().fold(&mut |node| match &node {
+        Node::UnaryExpr { op, child } => match **child {
+            Node::Number(n) => unary(op, n, overflow).map_or(node, Node::Number),
+            _ => node,
+        },
+        Node::BinaryExpr { op, lhs, rhs } => match (&**lhs, &**rhs) {
+            (Node::Number(l), Node::Number(r)) => {
+                binary(op, *l, *r, overflow).map_or(node, Node::Number)
+            }
+            _ => node,
+        },
+        _ => node,
+    })
+}
+
+/// Whether `node` evaluates to an integer if at all: it has no variables,
+/// calls or floats, whose values may be floats.
+fn integral(node: &Node) -> bool {
+    match node {
+        Node::Number(_) => true,
+        Node::UnaryExpr { child, .. } => integral(child),
+        Node::BinaryExpr { lhs, rhs, .. } => integral(lhs) && integral(rhs),
+        _ => false,
+    }
+}
+
+fn strength_rule(node: Node) -> Node {
+    match node {
+        Node::BinaryExpr { op, lhs, rhs } => match (op, *lhs, *rhs) {
+            (BinaryOperator::Mul, x, Node::Number(n))
+            | (BinaryOperator::Mul, Node::Number(n), x)
+                if n > 1 && (n as u32).is_power_of_two() && integral(&x) =>
+            {
+                Node::binary(BinaryOperator::Shl, x, n.trailing_zeros() as i32)
+            }
+            (
+                BinaryOperator::Pow,
+                x @ (Node::Identifier(_) | Node::Number(_) | Node::Float(_)),
+                Node::Number(2),
+            ) => x.clone() * x,
+            (BinaryOperator::Pow | BinaryOperator::Div, x, Node::Number(1)) => x,
+            (op, lhs, rhs) => Node::binary(op, lhs, rhs),
+        },
+        node => node,
+    }
+}
+
+///
+/// Replaces operations with cheaper equivalents: multiplying an integer
+/// operand by a power of two becomes a left shift (`(1 - n) * 2` to
+/// `1 - n << 1` for a literal `n`), squaring a variable or literal becomes a
+/// product (`x ^ 2` to `x * x`), and `x / 1` and `x ^ 1` become `x`.
+///
+/// The rules keep values and errors: `x << n` overflows exactly when
+/// `x * 2^n` does, which is why shifts are kept to operands without
+/// variables or calls, which may hold floats. Only operands evaluated in one
+/// step are squared, so the tree never grows by more than a leaf per rule,
+/// and no rule drops an operand that could fail to evaluate.
+///
+pub fn reduce_strength(node: &Node) -> Node {
+    node.clone().fold(&mut strength_rule)
+}
+
+/// Runs all optimization passes over `node`: constant folding, then strength
+/// reduction.
+pub fn optimize(node: &Node, overflow: Overflow) -> Node {
+    reduce_strength(&fold_constants(node, overflow))
+}
+
+pub(crate) fn simplify_rule(node: &Node) -> Option<Node> {
+    let zero = Node::Number(0);
+    let one = Node::Number(1);
+    match node {
+        Node::UnaryExpr { op, child } => match &**child {
+            Node::Number(n) => unary(op, *n, Overflow::Checked).ok().map(Node::Number),
+            Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child,
+            } if *op == UnaryOperator::Neg => Some((**child).clone()),
+            _ => None,
+        },
+        Node::BinaryExpr { op, lhs, rhs } => {
+            let (lhs, rhs) = (&**lhs, &**rhs);
+            match (op, lhs, rhs) {
+                (_, Node::Number(l), Node::Number(r)) => {
+                    binary(op, *l, *r, Overflow::Checked).ok().map(Node::Number)
+                }
+                (BinaryOperator::Plus, x, z) | (BinaryOperator::Plus, z, x) if *z == zero => {
+                    Some(x.clone())
+                }
+                (BinaryOperator::Minus, x, z) if *z == zero => Some(x.clone()),
+                (BinaryOperator::Minus, z, x) if *z == zero => Some(-x.clone()),
+                (BinaryOperator::Minus, x, y) if x == y => Some(zero),
+                (BinaryOperator::Mul, x, o) | (BinaryOperator::Mul, o, x) if *o == one => {
+                    Some(x.clone())
+                }
+                (BinaryOperator::Mul, _, z) | (BinaryOperator::Mul, z, _) if *z == zero => {
+                    Some(zero)
+                }
+                (BinaryOperator::Div, x, o) if *o == one => Some(x.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+///
+/// Applies algebraic identities until none match: `x * 1`, `x + 0`, `x - 0`
+/// and `x / 1` become `x`, `0 - x` becomes `-x`, `--x` becomes `x`, `x - x`
+/// and `x * 0` become `0`, and literal operations are folded.
+///
+/// Unlike `fold_constants` this is not exact: a rule that discards an operand
+/// assumes it would have evaluated without error, so `y - y` simplifies to
+/// `0` even when `y` is undefined.
+///
+pub fn simplify(node: &Node) -> Node {
+    node.clone().rewrite(&mut simplify_rule)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    fn fold(text: &str) -> String {
+        let node: Node = text.parse().unwrap();
+        fold_constants(&node, Overflow::Checked).to_string()
+    }
+
+    #[test]
+    fn fold_literals() {
+        assert_eq!(fold("2 * 3 + x"), "6 + x");
+        assert_eq!(fold("-(4 - 6) * y / (8 / 2)"), "2 * y / 4");
+        assert_eq!(fold("x = 1 + 1; x * (3 - 1)"), "x = 2; x * 2")
+    }
+
+    #[test]
+    fn keep_runtime_errors() {
+        assert_eq!(fold("x + 1 / 0"), "x + 1 / 0");
+        assert_eq!(fold("2147483647 + 1"), "2147483647 + 1");
+        let node: Node = "2147483647 + 1".parse().unwrap();
+        let folded = fold_constants(&node, Overflow::Wrapping);
+        assert_eq!(folded, Node::Number(i32::MIN))
+    }
+
+    #[test]
+    fn folding_preserves_value() {
+        for text in ["x = 7; (x + 3 * 4) / -(2 - 5)", "8 - 4 - 2 + 16 / 4 / 2"] {
+            let node: Node = text.parse().unwrap();
+            let folded = optimize(&node, Overflow::Checked);
+            assert_eq!(
+                Eval::new().eval(&node).unwrap(),
+                Eval::new().eval(&folded).unwrap()
+            )
+        }
+    }
+
+    fn simplified(text: &str) -> String {
+        simplify(&text.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn simplify_identities() {
+        assert_eq!(simplified("x * 1 + 0"), "x");
+        assert_eq!(simplified("1 * (0 + y) / 1 - 0"), "y");
+        assert_eq!(simplified("0 - x"), "-x");
+        assert_eq!(simplified("--x * ---y"), "x * -y");
+        assert_eq!(simplified("!!x + !-x"), "!!x + !-x");
+        assert_eq!(simplified("z = (a + b) - (a + b)"), "z = 0")
+    }
+
+    #[test]
+    fn simplify_cascades() {
+        assert_eq!(simplified("(x - x) * y + 2 * 3"), "6");
+        assert_eq!(simplified("x * (y - y + 1)"), "x");
+        assert_eq!(simplified("x / (1 - 1)"), "x / 0")
+    }
+
+    fn reduced(text: &str) -> String {
+        optimize(&text.parse().unwrap(), Overflow::Checked).to_string()
+    }
+
+    #[test]
+    fn strength_reduction() {
+        let reduced = |text: &str| reduce_strength(&text.parse().unwrap()).to_string();
+        assert_eq!(reduced("(1 + 2) * 2"), "1 + 2 << 1");
+        assert_eq!(reduced("x * 2"), "x * 2");
+        assert_eq!(reduced("2.5 * 4"), "2.5 * 4");
+        assert_eq!(reduced("x ^ 2 + 3 ^ 2"), "x * x + 3 * 3");
+        assert_eq!(reduced("(x + 1) ^ 2"), "(x + 1) ^ 2");
+    }
+
+    #[test]
+    fn optimization() {
+        assert_eq!(reduced("4 * 2 * y"), "8 * y");
+        assert_eq!(reduced("x / 1 + y ^ 1"), "x + y");
+        assert_eq!(reduced("x * 6 + x / 2"), "x * 6 + x / 2")
+    }
+
+    #[test]
+    fn strength_reduction_preserves_value() {
+        for overflow in [Overflow::Checked, Overflow::Wrapping, Overflow::Saturating] {
+            for x in [0, -7, 1 << 29, i32::MAX, i32::MIN + 1] {
+                let node: Node = format!("x = {0}; {0} * 4 + -x ^ 2 / 1", x).parse().unwrap();
+                let reduced = reduce_strength(&node);
+                assert_ne!(node, reduced);
+                let eval = |node: &Node| Eval::with_overflow(overflow).eval(node).ok();
+                assert_eq!(eval(&node), eval(&reduced))
+            }
+        }
+    }
+}
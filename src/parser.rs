@@ -1,25 +1,85 @@
+use crate::error::{ErrorCode, RexprError};
+use crate::grammar::{BinaryFn, Grammar, Prefix, UnaryFn};
+use crate::prelude::*;
 use crate::tokenizer::*;
-use std::error::Error;
-use std::fmt::Display;
-use std::iter::Peekable;
-use std::slice::Iter;
+use core::error::Error;
+use core::fmt::Display;
+use core::str::FromStr;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperator {
     Plus,
     Minus,
     Mul,
     Div,
+    Pow,
+    Shl,
+    Shr,
+    And,
+    Or,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOperator {
     Neg,
+    Not,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BinaryOperator::Plus => f.write_str("+"),
+            BinaryOperator::Minus => f.write_str("-"),
+            BinaryOperator::Mul => f.write_str("*"),
+            BinaryOperator::Div => f.write_str("/"),
+            BinaryOperator::Pow => f.write_str("^"),
+            BinaryOperator::Shl => f.write_str("<<"),
+            BinaryOperator::Shr => f.write_str(">>"),
+            BinaryOperator::And => f.write_str("&&"),
+            BinaryOperator::Or => f.write_str("||"),
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            UnaryOperator::Neg => f.write_str("-"),
+            UnaryOperator::Not => f.write_str("!"),
+        }
+    }
+}
+
+/// The value of a float literal. Literals compare and hash by their bits, so
+/// that [`Node`] stays `Eq` and `Hash`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl core::hash::Hash for Float {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Node {
     Number(i32),
+    /// A float literal such as `2.5` or `1e-3`. Only the float-capable
+    /// evaluations accept it; integer evaluation rejects it.
+    Float(Float),
+    Identifier(String),
     BinaryExpr {
         op: BinaryOperator,
         lhs: Box<Node>,
@@ -29,156 +89,1199 @@ pub enum Node {
         op: UnaryOperator,
         child: Box<Node>,
     },
+    Assign {
+        name: String,
+        value: Box<Node>,
+    },
+    Block(Vec<Node>),
+    /// A call of a function supplied by the host, see `Context::register_fn`.
+    /// The name may be qualified with a namespace, as in `math::sin`.
+    Call {
+        name: String,
+        args: Vec<Node>,
+    },
+    /// A `use` directive: a namespace glob such as `math::*`, or a single
+    /// function such as `math::sin`, that unqualified calls anywhere in the
+    /// program may refer to. It evaluates to 0.
+    Use(String),
+}
+
+impl Node {
+    fn precedence(&self) -> u8 {
+        match self {
+            Node::Block(_) => 0,
+            Node::Assign { .. } | Node::Use(_) => 1,
+            Node::BinaryExpr {
+                op: BinaryOperator::Or,
+                ..
+            } => 2,
+            Node::BinaryExpr {
+                op: BinaryOperator::And,
+                ..
+            } => 3,
+            Node::BinaryExpr {
+                op: BinaryOperator::Shl | BinaryOperator::Shr,
+                ..
+            } => 4,
+            Node::BinaryExpr {
+                op: BinaryOperator::Plus | BinaryOperator::Minus,
+                ..
+            } => 5,
+            Node::BinaryExpr {
+                op: BinaryOperator::Mul | BinaryOperator::Div,
+                ..
+            } => 6,
+            Node::UnaryExpr { .. } => 7,
+            Node::Number(n) if *n < 0 => 7,
+            Node::Float(Float(x)) if x.is_finite() && x.is_sign_negative() => 7,
+            Node::BinaryExpr {
+                op: BinaryOperator::Pow,
+                ..
+            } => 8,
+            Node::Number(_) | Node::Float(_) | Node::Identifier(_) | Node::Call { .. } => 9,
+        }
+    }
+
+    fn fmt_operand(
+        &self,
+        f: &mut core::fmt::Formatter<'_>,
+        parenthesize: bool,
+    ) -> core::fmt::Result {
+        if parenthesize {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+///
+/// Prints infix source text using only the parentheses required by precedence
+/// and associativity (`^` is right associative, every other operator left).
+/// For every tree in the form returned by [`normalize`], parsing the printed
+/// text yields a structurally equal tree.
+///
+impl Display for Node {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Node::Number(n) => write!(f, "{}", n),
+            Node::Float(Float(x)) if x.is_nan() => f.write_str("(0.0 / 0.0)"),
+            Node::Float(Float(x)) if x.is_infinite() && *x < 0.0 => f.write_str("(-1.0 / 0.0)"),
+            Node::Float(Float(x)) if x.is_infinite() => f.write_str("(1.0 / 0.0)"),
+            Node::Float(Float(x)) => write!(f, "{:?}", x),
+            Node::Identifier(name) => f.write_str(name),
+            Node::UnaryExpr { op, child } => {
+                write!(f, "{}", op)?;
+                child.fmt_operand(f, child.precedence() < self.precedence())
+            }
+            Node::BinaryExpr {
+                op: BinaryOperator::Pow,
+                lhs,
+                rhs,
+            } => {
+                lhs.fmt_operand(f, lhs.precedence() <= self.precedence())?;
+                f.write_str(" ^ ")?;
+                rhs.fmt_operand(f, rhs.precedence() < self.precedence())
+            }
+            Node::BinaryExpr { op, lhs, rhs } => {
+                lhs.fmt_operand(f, lhs.precedence() < self.precedence())?;
+                write!(f, " {} ", op)?;
+                rhs.fmt_operand(f, rhs.precedence() <= self.precedence())
+            }
+            Node::Assign { name, value } => write!(f, "{} = {}", name, value),
+            Node::Use(path) => write!(f, "use {}", path),
+            Node::Block(stmts) => {
+                for (i, stmt) in stmts.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    write!(f, "{}", stmt)?;
+                }
+                Ok(())
+            }
+            Node::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Shapes the printers in the std-only modules parenthesize by.
+#[cfg(feature = "std")]
+impl Node {
+    pub(crate) fn is_additive(&self) -> bool {
+        matches!(
+            self,
+            Node::BinaryExpr {
+                op: BinaryOperator::Plus | BinaryOperator::Minus,
+                ..
+            }
+        )
+    }
+
+    pub(crate) fn is_shift(&self) -> bool {
+        matches!(
+            self,
+            Node::BinaryExpr {
+                op: BinaryOperator::Shl | BinaryOperator::Shr,
+                ..
+            }
+        )
+    }
+
+    pub(crate) fn is_logical(&self) -> bool {
+        matches!(
+            self,
+            Node::BinaryExpr {
+                op: BinaryOperator::And | BinaryOperator::Or,
+                ..
+            }
+        )
+    }
+
+    pub(crate) fn is_atom(&self) -> bool {
+        matches!(self, Node::Identifier(_) | Node::Call { .. })
+            || matches!(self, Node::Number(n) if *n >= 0)
+    }
+
+    pub(crate) fn is_negation(&self) -> bool {
+        matches!(self, Node::UnaryExpr { .. })
+    }
+}
+
+///
+/// Rewrites `node` into the shape the parser produces, so that printing and
+/// re-parsing it round-trips exactly:
+///
+/// - negative literals become negations of positive literals
+///   (`i32::MIN` becomes `-2147483647 - 1`),
+/// - infinite and NaN floats become the divisions they print as, such as
+///   `1.0 / 0.0`,
+/// - nested blocks are flattened, single-statement blocks are unwrapped and an
+///   empty block becomes `0`, the value it evaluates to,
+/// - assignments, imports and blocks nested inside an expression, which the
+///   grammar only allows as statements, are lifted into the statements before
+///   it: `1 + (x = 2)` becomes `x = 2; 1 + x` and `f((a; b))` becomes
+///   `a; f(b)`.
+///
+/// Lifting runs the lifted statements before the rest of the expression, so
+/// the value only changes when an operand to the left of an assignment reads
+/// the name it assigns, as in `x + (x = 2)`.
+///
+pub fn normalize(node: &Node) -> Node {
+    let mut stmts = vec![];
+    statement(node, &mut stmts);
+    match stmts.len() {
+        1 => stmts.remove(0),
+        _ => Node::Block(stmts),
+    }
+}
+
+/// Normalizes `node` as a statement, appending it to `stmts`.
+fn statement(node: &Node, stmts: &mut Vec<Node>) {
+    match node {
+        Node::Block(inner) if inner.is_empty() => stmts.push(Node::Number(0)),
+        Node::Block(inner) => inner.iter().for_each(|stmt| statement(stmt, stmts)),
+        Node::Assign { name, value } => {
+            let value = lift(value, stmts);
+            stmts.push(Node::Assign {
+                name: name.clone(),
+                value: Box::new(value),
+            })
+        }
+        Node::Use(_) => stmts.push(node.clone()),
+        _ => {
+            let expr = lift(node, stmts);
+            stmts.push(expr)
+        }
+    }
 }
 
-#[derive(Debug)]
+/// Normalizes `node` as an expression, moving the statements nested in it to
+/// `stmts` and leaving behind what they evaluate to.
+fn lift(node: &Node, stmts: &mut Vec<Node>) -> Node {
+    match node {
+        Node::Number(i32::MIN) => Node::BinaryExpr {
+            op: BinaryOperator::Minus,
+            lhs: Box::new(normalize(&Node::Number(-i32::MAX))),
+            rhs: Box::new(Node::Number(1)),
+        },
+        Node::Number(n) if *n < 0 => Node::UnaryExpr {
+            op: UnaryOperator::Neg,
+            child: Box::new(Node::Number(-n)),
+        },
+        Node::Float(Float(x)) if !x.is_finite() => {
+            let float = |x| Box::new(Node::Float(Float(x)));
+            let lhs = match (x.is_nan(), *x < 0.0) {
+                (true, _) => float(0.0),
+                (false, true) => Box::new(normalize(&Node::Float(Float(-1.0)))),
+                (false, false) => float(1.0),
+            };
+            Node::BinaryExpr {
+                op: BinaryOperator::Div,
+                lhs,
+                rhs: float(0.0),
+            }
+        }
+        Node::Float(Float(x)) if x.is_sign_negative() => Node::UnaryExpr {
+            op: UnaryOperator::Neg,
+            child: Box::new(Node::Float(Float(-x))),
+        },
+        Node::Number(_) | Node::Float(_) | Node::Identifier(_) => node.clone(),
+        Node::UnaryExpr { op, child } => Node::UnaryExpr {
+            op: op.clone(),
+            child: Box::new(lift(child, stmts)),
+        },
+        Node::BinaryExpr { op, lhs, rhs } => {
+            let lhs = lift(lhs, stmts);
+            Node::BinaryExpr {
+                op: op.clone(),
+                lhs: Box::new(lhs),
+                rhs: Box::new(lift(rhs, stmts)),
+            }
+        }
+        Node::Assign { name, .. } => {
+            statement(node, stmts);
+            Node::Identifier(name.clone())
+        }
+        Node::Use(_) => {
+            stmts.push(node.clone());
+            Node::Number(0)
+        }
+        Node::Block(inner) => match inner.split_last() {
+            Some((last, init)) => {
+                init.iter().for_each(|stmt| statement(stmt, stmts));
+                lift(last, stmts)
+            }
+            None => Node::Number(0),
+        },
+        Node::Call { name, args } => Node::Call {
+            name: name.clone(),
+            args: args.iter().map(|arg| lift(arg, stmts)).collect(),
+        },
+    }
+}
+
+impl FromStr for Node {
+    type Err = RexprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = Tokenizer::new(s).tokenize()?;
+        Ok(Parser::new(tokens).parse()?)
+    }
+}
+
+impl TryFrom<&str> for Node {
+    type Error = RexprError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ParserError {
+    code: ErrorCode,
     message: String,
+    span: Option<Span>,
+    hint: Option<String>,
+}
+
+impl ParserError {
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Where in the source the error was found, if anywhere.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// What was expected instead, when that is clear.
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub(crate) fn span_mut(&mut self) -> Option<&mut Span> {
+        self.span.as_mut()
+    }
+
+    fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
 }
 
 impl Display for ParserError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "ParserError: {}", self.message)
     }
 }
 
 impl Error for ParserError {}
 
-pub struct Parser {
-    tokens: Vec<Token>,
+impl From<TokenizerError> for ParserError {
+    fn from(err: TokenizerError) -> Self {
+        ParserError {
+            code: err.code,
+            span: Some(err.span()),
+            message: err.message,
+            hint: None,
+        }
+    }
+}
+
+///
+/// Where each node of a parsed tree came from, from
+/// [`Parser::parse_spanned`]. The children mirror those of the node: the
+/// operand of a unary expression, both sides of a binary one, the value of
+/// an assignment, the statements of a block and the arguments of a call.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens }
+impl SpanTree {
+    fn leaf(span: Span) -> Self {
+        SpanTree {
+            span,
+            children: vec![],
+        }
+    }
+
+    /// The spans of the node reached by following `path`, a child index per
+    /// level.
+    pub fn get(&self, path: &[usize]) -> Option<&SpanTree> {
+        path.iter().try_fold(self, |tree, &i| tree.children.get(i))
+    }
+}
+
+pub(crate) type Parsed = (Node, SpanTree);
+
+const OPERAND: &str = "expected a number, a variable or `(`";
+const STATEMENT_END: &str = "separate statements with `;`";
+
+/// The loosest level of infix operators, below `||`, used only by operators
+/// added with a [`Grammar`].
+const LOOSEST: u8 = 1;
+/// The tightest level of infix operators, that of `*` and `/`.
+const TIGHTEST: u8 = 6;
+/// The level of atoms.
+const ATOM: u8 = 9;
+/// How deeply parentheses, calls, grammar prefixes, unary operators and
+/// powers may nest unless set with [`Parser::with_max_depth`], which bounds
+/// the memory parsing takes and the depth of the trees later passes recurse
+/// over.
+pub const MAX_DEPTH: usize = 10_000;
+
+/// An infix operator, built in or added with a [`Grammar`].
+enum Infix<'a> {
+    Builtin(BinaryOperator),
+    Custom(&'a BinaryFn),
+}
+
+/// An operator that may follow an operand.
+enum Operator<'a> {
+    Infix(Infix<'a>),
+    Postfix(&'a UnaryFn),
+}
+
+/// An operator waiting for the factor it applies to.
+enum Factor {
+    Unary(UnaryOperator, Span),
+    /// `^` after its base.
+    Pow(Parsed),
+}
+
+/// A construct waiting for the expression inside it: the span where it
+/// starts, and for a call the arguments so far.
+enum Group<'a> {
+    Paren(Span),
+    Call {
+        name: String,
+        start: Span,
+        args: Vec<Parsed>,
+    },
+    Prefix(&'a str, &'a Prefix, Span),
+}
+
+/// What an atom position holds.
+enum Atom<'a> {
+    Done(Parsed),
+    /// A group whose contents bind at the given level or tighter.
+    Open(Group<'a>, u8),
+}
+
+/// A chain of operands at `precedence` or tighter being parsed.
+struct Chain<'a> {
+    precedence: u8,
+    /// Operands with the infix operator after them and its level, loosest
+    /// first.
+    operands: Vec<(Parsed, Infix<'a>, u8)>,
+    /// The operators waiting for the operand being parsed.
+    factors: Vec<Factor>,
+}
+
+impl<'a> Chain<'a> {
+    fn new(precedence: u8) -> Self {
+        Chain {
+            precedence,
+            operands: vec![],
+            factors: vec![],
+        }
+    }
+
+    /// Joins the operands waiting with an operator at `level` or tighter
+    /// onto `rhs`.
+    fn reduce(&mut self, level: u8, mut rhs: Parsed) -> Parsed {
+        while self
+            .operands
+            .last()
+            .is_some_and(|(_, _, top)| *top >= level)
+        {
+            let (lhs, op, _) = self.operands.pop().unwrap();
+            rhs = combine(op, lhs, rhs);
+        }
+        rhs
+    }
+}
+
+/// The built-in infix operator `token` stands for at `precedence`.
+fn infix(token: Token, precedence: u8) -> Option<BinaryOperator> {
+    match (precedence, token) {
+        (2, Token::Or) => Some(BinaryOperator::Or),
+        (3, Token::And) => Some(BinaryOperator::And),
+        (4, Token::Shl) => Some(BinaryOperator::Shl),
+        (4, Token::Shr) => Some(BinaryOperator::Shr),
+        (5, Token::Plus) => Some(BinaryOperator::Plus),
+        (5, Token::Minus) => Some(BinaryOperator::Minus),
+        (6, Token::Mul) => Some(BinaryOperator::Mul),
+        (6, Token::Div) => Some(BinaryOperator::Div),
+        _ => None,
+    }
+}
+
+/// The text a [`Grammar`] operator is looked up by.
+fn symbol<'a>(token: Token<'a>) -> Option<&'a str> {
+    match token {
+        Token::Symbol(symbol) | Token::Keyword(symbol) => Some(symbol),
+        Token::Space | Token::Number(_) | Token::Identifier(_) | Token::Comment(_) => None,
+        Token::Plus => Some("+"),
+        Token::Minus => Some("-"),
+        Token::Mul => Some("*"),
+        Token::Div => Some("/"),
+        Token::Pow => Some("^"),
+        Token::Shl => Some("<<"),
+        Token::Shr => Some(">>"),
+        Token::And => Some("&&"),
+        Token::Or => Some("||"),
+        Token::Not => Some("!"),
+        Token::LParen => Some("("),
+        Token::RParen => Some(")"),
+        Token::Assign => Some("="),
+        Token::Semicolon => Some(";"),
+        Token::Comma => Some(","),
+    }
+}
+
+type TokenStream<'a> = Box<dyn Iterator<Item = Result<SpannedToken<'a>, TokenizerError>> + 'a>;
+
+pub struct Parser<'a> {
+    tokens: TokenStream<'a>,
+    lookahead: Option<SpannedToken<'a>>,
+    /// The span of the most recently consumed token.
+    last: Option<Span>,
+    /// Whether to collect tokenizer errors in `errors` and keep going.
+    recovering: bool,
+    errors: Vec<ParserError>,
+    /// Whether a tokenizer error was collected in the statement being
+    /// parsed, after which its syntax errors are follow-on ones.
+    lexer_failed: bool,
+    grammar: Option<&'a Grammar>,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<SpannedToken<'a>>) -> Self {
+        Parser {
+            tokens: Box::new(tokens.into_iter().map(Ok)),
+            lookahead: None,
+            last: None,
+            recovering: false,
+            errors: vec![],
+            lexer_failed: false,
+            grammar: None,
+            max_depth: MAX_DEPTH,
+        }
+    }
+
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
+        Parser {
+            tokens: Box::new(lexer),
+            lookahead: None,
+            last: None,
+            recovering: false,
+            errors: vec![],
+            lexer_failed: false,
+            grammar: None,
+            max_depth: MAX_DEPTH,
+        }
     }
 
     ///
-    /// expr -> term ord_1_op expr | term
-    /// term -> factor ord_2_op term | factor
-    /// factor -> number | neg factor | lparen expr rparen
+    /// Also parses the operators of `grammar`. Their symbols must come from
+    /// a lexer given [`Grammar::symbols`]; [`Grammar::parser`] sets up both.
+    ///
+    pub fn with_grammar(mut self, grammar: &'a Grammar) -> Self {
+        self.grammar = Some(grammar);
+        self
+    }
+
+    /// Fails on expressions nested more than `depth` deep, rather than
+    /// [`MAX_DEPTH`], as for input from untrusted sources.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    ///
+    /// program -> stmt (semicolon stmt)* semicolon?
+    /// stmt -> use path | identifier assign or | or
+    /// or -> or lor and | and
+    /// and -> and land shift | shift
+    /// shift -> shift ord_0_op expr | expr
+    /// expr -> expr ord_1_op term | term
+    /// term -> term ord_2_op factor | factor
+    /// factor -> neg factor | not factor | atom pow factor | atom
+    /// atom -> number | call | identifier | lparen or rparen
+    /// call -> identifier lparen (or (comma or)*)? rparen
+    /// identifier -> name (:: name)*
+    /// path -> name (:: name)* (:: \*)?
     /// neg -> -
+    /// not -> !
+    /// pow -> ^
+    /// lor -> ||
+    /// land -> &&
+    /// ord_0_op -> << | >>
     /// ord_1_op -> + | -
     /// ord_2_op -> * | /
     /// lparen -> (
     /// rparen -> )
+    /// comma -> ,
+    /// assign -> =
+    /// semicolon -> ;
+    ///
+    /// A program with a single statement parses to that statement, otherwise
+    /// the statements are wrapped in a `Node::Block`. Operators of a
+    /// [`Grammar`] slot in at the level of their precedence.
     ///
-    pub fn parse(&self) -> Result<Node, ParserError> {
-        self.parse_expr(&mut self.tokens.iter().peekable())
+    pub fn parse(self) -> Result<Node, ParserError> {
+        self.parse_spanned().map(|(node, _)| node)
     }
 
-    fn parse_expr(&self, tokens: &mut Peekable<Iter<Token>>) -> Result<Node, ParserError> {
-        let term = self.parse_term(tokens)?;
-        let bop = match tokens.peek() {
-            Some(op) => match op {
-                Token::Plus => {
-                    tokens.next();
-                    BinaryOperator::Plus
+    /// Parses like [`Parser::parse`], also returning where in the source
+    /// each node of the tree came from.
+    pub fn parse_spanned(mut self) -> Result<(Node, SpanTree), ParserError> {
+        let mut stmts = vec![self.parse_stmt()?];
+        while self.peek()? == Some(Token::Semicolon) {
+            self.next()?;
+            if self.peek()?.is_none() {
+                break;
+            }
+            stmts.push(self.parse_stmt()?);
+        }
+        if let Some(token) = self.peek()? {
+            let err = self.failure(
+                ErrorCode::UnexpectedToken,
+                format!("unexpected token {}", token),
+            );
+            return Err(err.with_hint(STATEMENT_END));
+        }
+        Ok(block(stmts))
+    }
+
+    ///
+    /// Parses like [`Parser::parse_spanned`], but after a syntax error skips to the
+    /// end of the statement and carries on, so that every error in the input
+    /// is reported, in source order. Give it a lexer with
+    /// [`recover`](Lexer::recover) set to also get past tokenizer errors; a
+    /// statement with one is skipped without reporting the syntax errors the
+    /// missing token causes.
+    ///
+    pub fn parse_recovering(mut self) -> Result<(Node, SpanTree), Vec<ParserError>> {
+        self.recovering = true;
+        let mut stmts = vec![];
+        loop {
+            match self.parse_stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => {
+                    if !self.lexer_failed {
+                        self.errors.push(err);
+                    }
+                    self.synchronize();
                 }
-                Token::Minus => {
-                    tokens.next();
-                    BinaryOperator::Minus
+            }
+            // Tokenizer errors are collected while recovering, so peeking
+            // cannot fail.
+            match self.peek().unwrap_or(None) {
+                Some(Token::Semicolon) | None => {}
+                Some(_) if self.lexer_failed => self.synchronize(),
+                Some(token) => {
+                    let message = format!("unexpected token {}", token);
+                    let err = self.failure(ErrorCode::UnexpectedToken, message);
+                    self.errors.push(err.with_hint(STATEMENT_END));
+                    self.synchronize();
                 }
-                _ => return Ok(term),
-            },
-            None => return Ok(term),
+            }
+            self.lexer_failed = false;
+            let _ = self.next();
+            if self.peek().unwrap_or(None).is_none() {
+                break;
+            }
+        }
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        Ok(block(stmts))
+    }
+
+    /// Skips to the next semicolon or the end of the input.
+    fn synchronize(&mut self) {
+        loop {
+            match self.peek() {
+                Ok(None) | Ok(Some(Token::Semicolon)) => return,
+                _ => {
+                    let _ = self.next();
+                }
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<Token<'a>>, ParserError> {
+        while self.lookahead.is_none() {
+            match self.tokens.next().transpose() {
+                Ok(Some(SpannedToken {
+                    token: Token::Comment(_),
+                    ..
+                })) => continue,
+                Ok(Some(token)) => self.lookahead = Some(token),
+                Ok(None) => break,
+                Err(err) if self.recovering => {
+                    self.errors.push(err.into());
+                    self.lexer_failed = true;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(self.lookahead.map(|t| t.token))
+    }
+
+    fn next(&mut self) -> Result<Option<Token<'a>>, ParserError> {
+        let token = self.peek()?;
+        if let Some(lookahead) = self.lookahead.take() {
+            self.last = Some(lookahead.span);
+        }
+        Ok(token)
+    }
+
+    /// The span of the token just peeked.
+    fn peeked_span(&self) -> Span {
+        self.lookahead.expect("a peeked token").span
+    }
+
+    /// The span from `start` to the end of the last consumed token.
+    fn since(&self, start: Span) -> Span {
+        start.to(self.last.expect("a consumed token"))
+    }
+
+    /// An error at the next token or, at the end of the input, just after
+    /// the last one.
+    fn failure(&self, code: ErrorCode, message: impl Into<String>) -> ParserError {
+        let span = match (self.lookahead, self.last) {
+            (Some(token), _) => Some(token.span),
+            (None, Some(last)) => Some(Span {
+                line: last.line,
+                col: last.col + last.len as u32,
+                offset: last.offset + last.len,
+                len: 0,
+            }),
+            (None, None) => None,
+        };
+        ParserError {
+            code,
+            message: message.into(),
+            span,
+            hint: None,
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Parsed, ParserError> {
+        let (expr, spans) = self.parse_or()?;
+        // An identifier cannot be followed by another, so `use` stays an
+        // ordinary variable name everywhere else.
+        if let (Node::Identifier(name), Some(Token::Identifier(path))) = (&expr, self.peek()?) {
+            if name == "use" {
+                if !path.contains("::") || path.starts_with('$') {
+                    let message = format!("cannot use {}", path);
+                    let err = self.failure(ErrorCode::UnexpectedToken, message);
+                    return Err(err.with_hint("expected a path such as `math::*` or `math::sin`"));
+                }
+                self.next()?;
+                let span = self.since(spans.span);
+                return Ok((Node::Use(path.to_string()), SpanTree::leaf(span)));
+            }
+        }
+        match expr {
+            Node::Identifier(name) if self.peek()? == Some(Token::Assign) => {
+                if name.starts_with('$') {
+                    let message = format!("cannot assign to placeholder {}", name);
+                    return Err(self.failure(ErrorCode::AssignToPlaceholder, message));
+                }
+                self.next()?;
+                let (value, value_spans) = self.parse_or()?;
+                Ok((
+                    Node::Assign {
+                        name,
+                        value: Box::new(value),
+                    },
+                    SpanTree {
+                        span: spans.span.to(value_spans.span),
+                        children: vec![value_spans],
+                    },
+                ))
+            }
+            expr => Ok((expr, spans)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Parsed, ParserError> {
+        self.parse_infix(LOOSEST)
+    }
+
+    ///
+    /// Parses an operand binding at `precedence` or tighter: a chain of left
+    /// associative infix operators at the levels up to [`TIGHTEST`], a factor
+    /// at those of unary operators and `^`, or an atom at [`ATOM`].
+    ///
+    /// The parser climbs precedence on an explicit stack rather than by
+    /// recursion. Operands wait in a [`Chain`] with the operator after them
+    /// until one at their level or looser shows up, and every parenthesis,
+    /// call, grammar prefix, unary operator and power waits as a frame until
+    /// what it applies to is parsed. Nesting thus costs no stack, and past
+    /// the maximum depth parsing fails instead.
+    ///
+    fn parse_infix(&mut self, precedence: u8) -> Result<Parsed, ParserError> {
+        let mut groups: Vec<(Group<'a>, Chain<'a>)> = vec![];
+        let mut chain = Chain::new(precedence);
+        let mut depth = 0;
+        'operand: loop {
+            let mut operand = loop {
+                let op = match self.peek()? {
+                    Some(Token::Minus) if chain.precedence < ATOM => UnaryOperator::Neg,
+                    Some(Token::Not) if chain.precedence < ATOM => UnaryOperator::Not,
+                    _ => match self.parse_atom()? {
+                        Atom::Done(atom) => break atom,
+                        Atom::Open(group, precedence) => {
+                            self.deeper(&mut depth)?;
+                            groups.push((
+                                group,
+                                core::mem::replace(&mut chain, Chain::new(precedence)),
+                            ));
+                            continue;
+                        }
+                    },
+                };
+                let start = self.peeked_span();
+                self.next()?;
+                self.deeper(&mut depth)?;
+                chain.factors.push(Factor::Unary(op, start));
+            };
+            loop {
+                operand = self.parse_postfix(operand)?;
+                if chain.precedence < ATOM {
+                    self.juxtaposed(&operand)?;
+                    if self.peek()? == Some(Token::Pow) {
+                        self.next()?;
+                        self.deeper(&mut depth)?;
+                        chain.factors.push(Factor::Pow(operand));
+                        continue 'operand;
+                    }
+                }
+                while let Some(factor) = chain.factors.pop() {
+                    depth -= 1;
+                    operand = match factor {
+                        Factor::Unary(op, start) => unary(op, start, operand),
+                        Factor::Pow(base) => binary(BinaryOperator::Pow, base, operand),
+                    };
+                }
+                while let Some((op, level)) = self.infix_operator(chain.precedence)? {
+                    operand = chain.reduce(level, operand);
+                    self.next()?;
+                    match op {
+                        Operator::Postfix(build) => operand = self.postfix(build, operand),
+                        Operator::Infix(op) => {
+                            chain.operands.push((operand, op, level));
+                            continue 'operand;
+                        }
+                    }
+                }
+                let expr = chain.reduce(0, operand);
+                let (group, outer) = match groups.pop() {
+                    Some(group) => group,
+                    None => return Ok(expr),
+                };
+                match self.close(group, expr)? {
+                    Ok(atom) => {
+                        depth -= 1;
+                        chain = outer;
+                        operand = atom;
+                    }
+                    Err(group) => {
+                        groups.push((group, outer));
+                        chain = Chain::new(LOOSEST);
+                        continue 'operand;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The operator at the next token if it is infix or postfix at
+    /// `precedence` or tighter, taking the tightest level it has.
+    fn infix_operator(
+        &mut self,
+        precedence: u8,
+    ) -> Result<Option<(Operator<'a>, u8)>, ParserError> {
+        let token = match self.peek()? {
+            Some(token) => token,
+            None => return Ok(None),
         };
-        let expr = self.parse_expr(tokens)?;
-        Ok(Node::BinaryExpr {
-            op: bop,
-            lhs: Box::new(term),
-            rhs: Box::new(expr),
-        })
-    }
-
-    fn parse_term(&self, tokens: &mut Peekable<Iter<Token>>) -> Result<Node, ParserError> {
-        let factor = self.parse_factor(tokens)?;
-        let bop = match tokens.peek() {
-            Some(op) => match op {
-                Token::Mul => {
-                    tokens.next();
-                    BinaryOperator::Mul
-                }
-                Token::Div => {
-                    tokens.next();
-                    BinaryOperator::Div
-                }
-                _ => return Ok(factor),
+        for level in (precedence..=TIGHTEST).rev() {
+            if let Some(bop) = infix(token, level) {
+                return Ok(Some((Operator::Infix(Infix::Builtin(bop)), level)));
+            }
+            let (grammar, symbol) = match (self.grammar, symbol(token)) {
+                (Some(grammar), Some(symbol)) => (grammar, symbol),
+                _ => continue,
+            };
+            if let Some(build) = grammar.infix(symbol, level) {
+                return Ok(Some((Operator::Infix(Infix::Custom(build)), level)));
+            }
+            if let Some(build) = grammar.postfix(symbol, level) {
+                return Ok(Some((Operator::Postfix(build), level)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Counts one more level of nesting, failing past the maximum depth.
+    fn deeper(&self, depth: &mut usize) -> Result<(), ParserError> {
+        if *depth >= self.max_depth {
+            let message = format!("expression nested more than {} deep", self.max_depth);
+            return Err(self.failure(ErrorCode::TooDeep, message));
+        }
+        *depth += 1;
+        Ok(())
+    }
+
+    /// `operand` followed by the postfix operator just consumed.
+    fn postfix(&self, build: &UnaryFn, (operand, spans): Parsed) -> Parsed {
+        let span = self.since(spans.span);
+        (
+            build(operand),
+            SpanTree {
+                span,
+                children: vec![spans],
             },
-            None => return Ok(factor),
+        )
+    }
+
+    /// There is no implicit multiplication, so a number followed by `x` or
+    /// `(` is a typo for `2 * x`.
+    fn juxtaposed(&mut self, (base, _): &Parsed) -> Result<(), ParserError> {
+        if let Node::Number(_) | Node::Float(_) = base {
+            let product = match self.peek()? {
+                Some(Token::Identifier(name)) => Some(format!("{} * {}", base, name)),
+                Some(Token::LParen) => Some(format!("{} * (...)", base)),
+                _ => None,
+            };
+            if let (Some(product), Some(token)) = (product, self.peek()?) {
+                let message = format!("unexpected token {}", token);
+                let err = self.failure(ErrorCode::UnexpectedToken, message);
+                return Err(err.with_hint(format!("did you mean `{}`?", product)));
+            }
+        }
+        Ok(())
+    }
+
+    /// `expr` followed by any postfix operators of the [`Grammar`] that
+    /// apply to atoms.
+    fn parse_postfix(&mut self, mut expr: Parsed) -> Result<Parsed, ParserError> {
+        while let (Some(grammar), Some(token)) = (self.grammar, self.peek()?) {
+            match symbol(token).and_then(|symbol| grammar.postfix(symbol, ATOM)) {
+                Some(build) => {
+                    self.next()?;
+                    expr = self.postfix(build, expr);
+                }
+                None => break,
+            }
+        }
+        Ok(expr)
+    }
+
+    /// Ends `group` after `expr`, its last operand: the atom it makes, or
+    /// the group again when a call takes another argument.
+    fn close(
+        &mut self,
+        group: Group<'a>,
+        (expr, spans): Parsed,
+    ) -> Result<Result<Parsed, Group<'a>>, ParserError> {
+        match group {
+            Group::Paren(start) => {
+                self.skip(|t| *t == Token::RParen)?;
+                // The parentheses belong to the span of the inner expression.
+                Ok(Ok((
+                    expr,
+                    SpanTree {
+                        span: self.since(start),
+                        children: spans.children,
+                    },
+                )))
+            }
+            Group::Call {
+                name,
+                start,
+                mut args,
+            } => {
+                args.push((expr, spans));
+                if self.peek()? == Some(Token::Comma) {
+                    self.next()?;
+                    return Ok(Err(Group::Call { name, start, args }));
+                }
+                self.skip(|t| *t == Token::RParen)?;
+                Ok(Ok(self.call(name, start, args)))
+            }
+            Group::Prefix(open, prefix, start) => {
+                if let Some(close) = prefix.close {
+                    match self.peek()? {
+                        Some(token) if symbol(token) == Some(close) => {
+                            self.next()?;
+                        }
+                        _ => {
+                            let message = format!("unclosed {}", open);
+                            let err = self.failure(ErrorCode::UnclosedParen, message);
+                            return Err(err.with_hint(format!("expected `{}`", close)));
+                        }
+                    }
+                }
+                Ok(Ok((
+                    (prefix.build)(expr),
+                    SpanTree {
+                        span: self.since(start),
+                        children: vec![spans],
+                    },
+                )))
+            }
+        }
+    }
+
+    /// The atom at the next token, or the group it opens.
+    fn parse_atom(&mut self) -> Result<Atom<'a>, ParserError> {
+        let token = match self.peek()? {
+            Some(token) => token,
+            None => {
+                let err = self.failure(ErrorCode::UnexpectedEnd, "expected factor");
+                return Err(err.with_hint(OPERAND));
+            }
         };
-        let term = self.parse_term(tokens)?;
-        Ok(Node::BinaryExpr {
-            op: bop,
-            lhs: Box::new(factor),
-            rhs: Box::new(term),
-        })
-    }
-
-    fn parse_factor(&self, tokens: &mut Peekable<Iter<Token>>) -> Result<Node, ParserError> {
-        match tokens.peek() {
-            Some(&factor) => match factor {
-                Token::Number(n) => {
-                    tokens.next();
-                    Ok(Node::Number(n.parse::<i32>().unwrap()))
-                }
-                Token::Minus => {
-                    tokens.next();
-                    let factor = self.parse_factor(tokens)?;
-                    Ok(Node::UnaryExpr {
-                        op: UnaryOperator::Neg,
-                        child: Box::new(factor),
-                    })
+        let start = self.peeked_span();
+        match token {
+            Token::Number(n) if n.contains(['.', 'e', 'E']) => match n.parse::<f64>() {
+                Ok(x) if x.is_finite() => {
+                    self.next()?;
+                    Ok(Atom::Done((Node::Float(Float(x)), SpanTree::leaf(start))))
                 }
-                Token::LParen => {
-                    tokens.next();
-                    let expr = self.parse_expr(tokens)?;
-                    self.skip(|t| *t == Token::RParen, tokens)?;
-                    Ok(expr)
+                _ => {
+                    let message = format!("number too large {}", n);
+                    let err = self.failure(ErrorCode::NumberTooLarge, message);
+                    Err(err.with_hint("floats must be finite"))
                 }
-                other => Err(ParserError {
-                    message: format!("unexpected token {}", other),
-                }),
             },
-            None => Err(ParserError {
-                message: format!("expected factor"),
-            }),
+            Token::Number(n) => match n.parse::<i32>() {
+                Ok(n) => {
+                    self.next()?;
+                    Ok(Atom::Done((Node::Number(n), SpanTree::leaf(start))))
+                }
+                Err(_) => {
+                    let message = format!("number too large {}", n);
+                    let err = self.failure(ErrorCode::NumberTooLarge, message);
+                    Err(err.with_hint("numbers must fit in a 32-bit integer"))
+                }
+            },
+            Token::LParen => {
+                self.next()?;
+                Ok(Atom::Open(Group::Paren(start), LOOSEST))
+            }
+            Token::Identifier(name) if name.ends_with("::*") => {
+                let message = format!("unexpected token {}", name);
+                let err = self.failure(ErrorCode::UnexpectedToken, message);
+                Err(err.with_hint("globs can only be imported, as in `use math::*`"))
+            }
+            Token::Identifier(name) => {
+                self.next()?;
+                if self.peek()? == Some(Token::LParen) && !name.starts_with('$') {
+                    self.next()?;
+                    let name = name.to_string();
+                    if self.peek()? == Some(Token::RParen) {
+                        self.next()?;
+                        return Ok(Atom::Done(self.call(name, start, vec![])));
+                    }
+                    let args = vec![];
+                    return Ok(Atom::Open(Group::Call { name, start, args }, LOOSEST));
+                }
+                Ok(Atom::Done((
+                    Node::Identifier(name.to_string()),
+                    SpanTree::leaf(start),
+                )))
+            }
+            other => {
+                let grammar = self.grammar;
+                let prefix = symbol(other).and_then(|open| Some((open, grammar?.prefix(open)?)));
+                if let Some((open, prefix)) = prefix {
+                    self.next()?;
+                    let precedence = match prefix.precedence {
+                        Some(precedence) => precedence.clamp(LOOSEST, ATOM),
+                        None => LOOSEST,
+                    };
+                    return Ok(Atom::Open(Group::Prefix(open, prefix, start), precedence));
+                }
+                let message = format!("unexpected token {}", other);
+                Err(self
+                    .failure(ErrorCode::UnexpectedToken, message)
+                    .with_hint(OPERAND))
+            }
         }
     }
 
-    fn skip(
-        &self,
-        mut predicate: impl FnMut(&Token) -> bool,
-        tokens: &mut Peekable<Iter<Token>>,
-    ) -> Result<(), ParserError> {
-        match tokens.peek() {
-            Some(&token) if predicate(token) => {
-                tokens.next();
+    fn call(&self, name: String, start: Span, args: Vec<Parsed>) -> Parsed {
+        let (args, children) = args.into_iter().unzip();
+        (
+            Node::Call { name, args },
+            SpanTree {
+                span: self.since(start),
+                children,
+            },
+        )
+    }
+
+    fn skip(&mut self, mut predicate: impl FnMut(&Token) -> bool) -> Result<(), ParserError> {
+        match self.peek()? {
+            Some(token) if predicate(&token) => {
+                self.next()?;
                 Ok(())
             }
-            _ => Err(ParserError {
-                message: format!("unknow token"),
-            }),
+            _ => {
+                let err = self.failure(ErrorCode::UnclosedParen, "unknow token");
+                Err(err.with_hint("expected `)`"))
+            }
         }
     }
 }
 
+/// A single statement on its own, otherwise a `Node::Block`.
+pub(crate) fn block(mut stmts: Vec<Parsed>) -> Parsed {
+    if stmts.len() == 1 {
+        return stmts.remove(0);
+    }
+    let span = stmts[0].1.span.to(stmts[stmts.len() - 1].1.span);
+    let (stmts, children) = stmts.into_iter().unzip();
+    (Node::Block(stmts), SpanTree { span, children })
+}
+
+/// The operand `lhs` and `rhs` joined by the infix operator `op`.
+fn combine(op: Infix, lhs: Parsed, rhs: Parsed) -> Parsed {
+    match op {
+        Infix::Builtin(bop) => binary(bop, lhs, rhs),
+        Infix::Custom(build) => {
+            let ((lhs, lhs_spans), (rhs, rhs_spans)) = (lhs, rhs);
+            let span = lhs_spans.span.to(rhs_spans.span);
+            let children = vec![lhs_spans, rhs_spans];
+            (build(lhs, rhs), SpanTree { span, children })
+        }
+    }
+}
+
+fn unary(op: UnaryOperator, start: Span, (child, spans): Parsed) -> Parsed {
+    (
+        Node::UnaryExpr {
+            op,
+            child: Box::new(child),
+        },
+        SpanTree {
+            span: start.to(spans.span),
+            children: vec![spans],
+        },
+    )
+}
+
+fn binary(op: BinaryOperator, (lhs, lhs_spans): Parsed, (rhs, rhs_spans): Parsed) -> Parsed {
+    (
+        Node::BinaryExpr {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        SpanTree {
+            span: lhs_spans.span.to(rhs_spans.span),
+            children: vec![lhs_spans, rhs_spans],
+        },
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn single_number() {
-        let mut tokenizer = Tokenizer::new("1");
+        let tokenizer = Tokenizer::new("1");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
         assert_eq!(expr, Node::Number(1))
     }
 
+    #[test]
+    fn float_literals() {
+        let expr: Node = "2.5 * 1e-3".parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::BinaryExpr {
+                op: BinaryOperator::Mul,
+                lhs: Box::new(Node::Float(Float(2.5))),
+                rhs: Box::new(Node::Float(Float(0.001))),
+            }
+        );
+        assert_eq!(expr.to_string(), "2.5 * 0.001");
+        assert!("1e400".parse::<Node>().is_err())
+    }
+
     #[test]
     fn negtive_number() {
-        let mut tokenizer = Tokenizer::new("-1");
+        let tokenizer = Tokenizer::new("-1");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
@@ -193,7 +1296,7 @@ mod test {
 
     #[test]
     fn negtive_expr() {
-        let mut tokenizer = Tokenizer::new("-(1 + 2)");
+        let tokenizer = Tokenizer::new("-(1 + 2)");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
@@ -211,7 +1314,7 @@ mod test {
     }
     #[test]
     fn normal_prior() {
-        let mut tokenizer = Tokenizer::new("1 + 2 * 3");
+        let tokenizer = Tokenizer::new("1 + 2 * 3");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
@@ -231,7 +1334,7 @@ mod test {
 
     #[test]
     fn parenthesis_prior() {
-        let mut tokenizer = Tokenizer::new("(1 + 2) * 3");
+        let tokenizer = Tokenizer::new("(1 + 2) * 3");
         let tokens = tokenizer.tokenize().unwrap();
         let parser = Parser::new(tokens);
         let expr = parser.parse().unwrap();
@@ -248,4 +1351,478 @@ mod test {
             }
         )
     }
+
+    #[test]
+    fn parse_from_lexer() {
+        let parser = Parser::from_lexer(Lexer::new("2 * (3 + 4)"));
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::BinaryExpr {
+                op: BinaryOperator::Mul,
+                lhs: Box::new(Node::Number(2)),
+                rhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Plus,
+                    lhs: Box::new(Node::Number(3)),
+                    rhs: Box::new(Node::Number(4))
+                }),
+            }
+        )
+    }
+
+    #[test]
+    fn lexer_error_surfaces_in_parser() {
+        let parser = Parser::from_lexer(Lexer::new("1 + $"));
+        assert!(parser.parse().is_err())
+    }
+
+    #[test]
+    fn skip_comment_trivia() {
+        let parser = Parser::from_lexer(Lexer::new("1 /* plus */ + 2").keep_comments(true));
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::BinaryExpr {
+                op: BinaryOperator::Plus,
+                lhs: Box::new(Node::Number(1)),
+                rhs: Box::new(Node::Number(2))
+            }
+        )
+    }
+
+    #[test]
+    fn assignment() {
+        let tokenizer = Tokenizer::new("x = 1 + y");
+        let tokens = tokenizer.tokenize().unwrap();
+        let parser = Parser::new(tokens);
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::Assign {
+                name: "x".to_string(),
+                value: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Plus,
+                    lhs: Box::new(Node::Number(1)),
+                    rhs: Box::new(Node::Identifier("y".to_string()))
+                })
+            }
+        )
+    }
+
+    #[test]
+    fn multiple_statements() {
+        let tokenizer = Tokenizer::new("x = 3; y = 4; x * y;");
+        let tokens = tokenizer.tokenize().unwrap();
+        let parser = Parser::new(tokens);
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::Block(vec![
+                Node::Assign {
+                    name: "x".to_string(),
+                    value: Box::new(Node::Number(3))
+                },
+                Node::Assign {
+                    name: "y".to_string(),
+                    value: Box::new(Node::Number(4))
+                },
+                Node::BinaryExpr {
+                    op: BinaryOperator::Mul,
+                    lhs: Box::new(Node::Identifier("x".to_string())),
+                    rhs: Box::new(Node::Identifier("y".to_string()))
+                }
+            ])
+        )
+    }
+
+    #[test]
+    fn logical_prior() {
+        let tokenizer = Tokenizer::new("!a || b && c + 1");
+        let tokens = tokenizer.tokenize().unwrap();
+        let parser = Parser::new(tokens);
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::BinaryExpr {
+                op: BinaryOperator::Or,
+                lhs: Box::new(Node::UnaryExpr {
+                    op: UnaryOperator::Not,
+                    child: Box::new(Node::Identifier("a".to_string()))
+                }),
+                rhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::And,
+                    lhs: Box::new(Node::Identifier("b".to_string())),
+                    rhs: Box::new(Node::BinaryExpr {
+                        op: BinaryOperator::Plus,
+                        lhs: Box::new(Node::Identifier("c".to_string())),
+                        rhs: Box::new(Node::Number(1))
+                    })
+                })
+            }
+        )
+    }
+
+    #[test]
+    fn function_calls() {
+        let expr: Node = "-max(a, b + 1) ^ 2 * now()".parse().unwrap();
+        assert_eq!(expr.to_string(), "-max(a, b + 1) ^ 2 * now()");
+        let call = Node::Call {
+            name: "max".to_string(),
+            args: vec![Node::Identifier("a".to_string()), Node::Number(1)],
+        };
+        assert_eq!("max(a, 1)".parse::<Node>().unwrap(), call);
+        for text in ["f(1,", "f(1 2)", "f(,)", "$1(2)"] {
+            assert!(text.parse::<Node>().is_err(), "{}", text)
+        }
+    }
+
+    #[test]
+    fn use_directives() {
+        let expr: Node = "use math::*; use stats::mean; math::sqrt(mean(use, 2))"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            expr,
+            Node::block([
+                Node::Use("math::*".to_string()),
+                Node::Use("stats::mean".to_string()),
+                Node::call(
+                    "math::sqrt",
+                    [Node::call("mean", [Node::var("use"), 2.into()])]
+                ),
+            ])
+        );
+        assert_eq!(
+            expr.to_string(),
+            "use math::*; use stats::mean; math::sqrt(mean(use, 2))"
+        );
+        assert_eq!(
+            "use = 2; use".parse::<Node>().unwrap().to_string(),
+            "use = 2; use"
+        );
+        let err = "use math".parse::<Node>().unwrap_err().to_string();
+        assert_eq!(err, "ParserError: cannot use math");
+        let err = "1 + math::*".parse::<Node>().unwrap_err().to_string();
+        assert_eq!(err, "ParserError: unexpected token math::*");
+    }
+
+    #[test]
+    fn error_spans() {
+        let span = |text: &str| text.parse::<Node>().unwrap_err().span().unwrap();
+        let err = "1 + )".parse::<Node>().unwrap_err();
+        assert_eq!(err.to_string(), "ParserError: unexpected token )");
+        assert_eq!((span("1 + )").col, span("1 + )").offset), (5, 4));
+        assert_eq!(span("x = 1;\n  (2 * y").line, 2);
+        assert_eq!(span("x = 1;\n  (2 * y").col, 9);
+        assert_eq!(span("1 + 99999999999").len, 11);
+        assert_eq!(span("a = 1 + @").offset, 8);
+        assert!(matches!(
+            "a = 1 + @".parse::<Node>(),
+            Err(RexprError::Tokenizer(_))
+        ))
+    }
+
+    #[test]
+    fn node_spans() {
+        let parser = Parser::from_lexer(Lexer::new("a = -b * (c + 1); f(a, 2)"));
+        let (node, spans) = parser.parse_spanned().unwrap();
+        assert_eq!(node.to_string(), "a = -b * (c + 1); f(a, 2)");
+        let text = |path: &[usize]| {
+            let span = spans.get(path).unwrap().span;
+            &"a = -b * (c + 1); f(a, 2)"[span.offset..span.offset + span.len]
+        };
+        assert_eq!(text(&[]), "a = -b * (c + 1); f(a, 2)");
+        assert_eq!(text(&[0]), "a = -b * (c + 1)");
+        assert_eq!(text(&[0, 0]), "-b * (c + 1)");
+        assert_eq!(text(&[0, 0, 0]), "-b");
+        assert_eq!(text(&[0, 0, 1]), "(c + 1)");
+        assert_eq!(text(&[0, 0, 1, 1]), "1");
+        assert_eq!(text(&[1]), "f(a, 2)");
+        assert_eq!(text(&[1, 1]), "2");
+        assert_eq!(spans.get(&[1, 2]), None)
+    }
+
+    #[test]
+    fn report_every_error() {
+        let parse = |text| Parser::from_lexer(Lexer::new(text).recover(true)).parse_recovering();
+        let errors = parse("a = 1 + ; b = (2 * 3; c = 4 5; d = 6").unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "ParserError: unexpected token ;",
+                "ParserError: unknow token",
+                "ParserError: unexpected token 5",
+            ]
+        );
+        let cols: Vec<u32> = errors.iter().map(|err| err.span().unwrap().col).collect();
+        assert_eq!(cols, [9, 21, 29]);
+
+        let errors = parse("x = @ 1; y = * 2; z +").unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("ParserError: Unknow symbol"));
+        assert_eq!(errors[2].span().unwrap().offset, 21);
+
+        // Nothing is reported after a token the lexer failed on.
+        let errors = parse("1 % 2; 3 +; \"").unwrap_err();
+        let codes: Vec<ErrorCode> = errors.iter().map(ParserError::code).collect();
+        assert_eq!(
+            codes,
+            [
+                ErrorCode::UnknownSymbol,
+                ErrorCode::UnexpectedToken,
+                ErrorCode::UnknownSymbol
+            ]
+        );
+
+        let (node, _) = parse("a = 1; a * 2;").unwrap();
+        assert_eq!(node, "a = 1; a * 2".parse::<Node>().unwrap())
+    }
+
+    #[test]
+    fn suggest_explicit_multiplication() {
+        let hint = |text: &str| match text.parse::<Node>() {
+            Err(RexprError::Parser(err)) => err.hint().map(str::to_string),
+            _ => None,
+        };
+        assert_eq!(hint("2x + 1").as_deref(), Some("did you mean `2 * x`?"));
+        assert_eq!(
+            hint("1 + 3(y)").as_deref(),
+            Some("did you mean `3 * (...)`?")
+        );
+        assert_eq!(hint("x y").as_deref(), Some("separate statements with `;`"))
+    }
+
+    #[test]
+    fn reject_trailing_tokens() {
+        let tokenizer = Tokenizer::new("1 2");
+        let tokens = tokenizer.tokenize().unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(err.to_string(), "ParserError: unexpected token 2")
+    }
+
+    #[test]
+    fn reject_assignment_to_expression() {
+        let tokenizer = Tokenizer::new("1 + x = 2");
+        let tokens = tokenizer.tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+        let err = "$1 = 2".parse::<Node>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ParserError: cannot assign to placeholder $1"
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let tokenizer = Tokenizer::new("x = -(1 + 2) * y");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Node>(&json).unwrap(), expr)
+    }
+
+    #[test]
+    fn left_associative() {
+        let tokenizer = Tokenizer::new("8 - 4 - 2");
+        let tokens = tokenizer.tokenize().unwrap();
+        let parser = Parser::new(tokens);
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr,
+            Node::BinaryExpr {
+                op: BinaryOperator::Minus,
+                lhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Minus,
+                    lhs: Box::new(Node::Number(8)),
+                    rhs: Box::new(Node::Number(4))
+                }),
+                rhs: Box::new(Node::Number(2)),
+            }
+        )
+    }
+
+    #[test]
+    fn display_minimal_parentheses() {
+        for (input, expected) in [
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("((1 * 2)) + 3", "1 * 2 + 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("8 / (4 * 2)", "8 / (4 * 2)"),
+            ("-(x + 1) * -y", "-(x + 1) * -y"),
+            ("x = (1); y = x", "x = 1; y = x"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("-(x ^ 2) + (-x) ^ 2", "-x ^ 2 + (-x) ^ 2"),
+            ("(x << 1) + (1 << (y >> 2))", "(x << 1) + (1 << (y >> 2))"),
+            ("(a + b) << 2 >> 1", "a + b << 2 >> 1"),
+            ("(a && b) || (c && !d)", "a && b || c && !d"),
+            ("a && (b || c)", "a && (b || c)"),
+            ("!(a && b) && (x << 1)", "!(a && b) && x << 1"),
+        ] {
+            let tokenizer = Tokenizer::new(input);
+            let tokens = tokenizer.tokenize().unwrap();
+            let expr = Parser::new(tokens).parse().unwrap();
+            assert_eq!(expr.to_string(), expected)
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let tokenizer = Tokenizer::new("a = 2; -(a - (3 - a)) / (4.0 * a) - -1e20");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+
+        let text = expr.to_string();
+        let tokenizer = Tokenizer::new(&text);
+        let tokens = tokenizer.tokenize().unwrap();
+        assert_eq!(Parser::new(tokens).parse().unwrap(), expr)
+    }
+
+    #[test]
+    fn reject_number_too_large() {
+        let tokenizer = Tokenizer::new("2147483648");
+        let tokens = tokenizer.tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err())
+    }
+
+    #[test]
+    fn normalized_round_trip() {
+        let num = |n| Box::new(Node::Number(n));
+        let var = |name: &str| Box::new(Node::Identifier(name.to_string()));
+        let nodes = vec![
+            Node::Number(-5),
+            Node::Number(i32::MIN),
+            Node::BinaryExpr {
+                op: BinaryOperator::Minus,
+                lhs: num(1),
+                rhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Plus,
+                    lhs: num(-2),
+                    rhs: var("x"),
+                }),
+            },
+            Node::BinaryExpr {
+                op: BinaryOperator::Div,
+                lhs: Box::new(Node::UnaryExpr {
+                    op: UnaryOperator::Neg,
+                    child: Box::new(Node::UnaryExpr {
+                        op: UnaryOperator::Neg,
+                        child: var("y"),
+                    }),
+                }),
+                rhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Mul,
+                    lhs: num(3),
+                    rhs: num(-4),
+                }),
+            },
+            Node::Block(vec![
+                Node::Block(vec![Node::Assign {
+                    name: "x".to_string(),
+                    value: num(1),
+                }]),
+                Node::Block(vec![]),
+                Node::Block(vec![Node::Number(2), Node::Number(3)]),
+            ]),
+            Node::Float(Float(-0.0)),
+            Node::Float(Float(-1.5e-7)),
+            Node::Float(Float(f64::NEG_INFINITY)),
+            Node::Float(Float(f64::NAN)),
+        ];
+        for node in nodes {
+            let node = normalize(&node);
+            let text = node.to_string();
+            let tokenizer = Tokenizer::new(&text);
+            let tokens = tokenizer.tokenize().unwrap();
+            assert_eq!(Parser::new(tokens).parse().unwrap(), node, "{}", text)
+        }
+    }
+
+    #[test]
+    fn normalize_lifts_nested_statements() {
+        let expected = [
+            "x = 2; 1 + x",
+            "x = 1; y = x; use a::b; f(y, 0)",
+            "1; z = 3; -(z * 2)",
+        ];
+        let assign = |name: &str, value| Node::Assign {
+            name: name.to_string(),
+            value: Box::new(value),
+        };
+        let nested = [
+            Node::BinaryExpr {
+                op: BinaryOperator::Plus,
+                lhs: Box::new(Node::Number(1)),
+                rhs: Box::new(assign("x", Node::Number(2))),
+            },
+            Node::Call {
+                name: "f".to_string(),
+                args: vec![
+                    assign("y", assign("x", Node::Number(1))),
+                    Node::Use("a::b".to_string()),
+                ],
+            },
+            Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child: Box::new(Node::Block(vec![
+                    Node::Number(1),
+                    assign("z", Node::Number(3)),
+                    Node::BinaryExpr {
+                        op: BinaryOperator::Mul,
+                        lhs: Box::new(Node::Identifier("z".to_string())),
+                        rhs: Box::new(Node::Number(2)),
+                    },
+                ])),
+            },
+        ];
+        for (expected, node) in expected.iter().zip(nested) {
+            let node = normalize(&node);
+            let text = node.to_string();
+            assert_eq!(text, *expected);
+            assert_eq!(text.parse::<Node>().unwrap(), node)
+        }
+    }
+
+    #[test]
+    fn normalize_is_idempotent_on_parsed_trees() {
+        let tokenizer = Tokenizer::new("x = -1; (x - 2) * -(3 / x)");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(normalize(&expr), expr)
+    }
+
+    #[test]
+    fn from_str() {
+        let expr: Node = "1 + 2 * 3".parse().unwrap();
+        assert_eq!(expr, Node::try_from("1 + (2 * 3)").unwrap());
+        assert!(matches!(
+            "1 + $".parse::<Node>(),
+            Err(RexprError::Tokenizer(_))
+        ));
+        assert!(matches!("1 +".parse::<Node>(), Err(RexprError::Parser(_))));
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let parens = |n| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(
+            Node::try_from(parens(MAX_DEPTH).as_str()).unwrap(),
+            Node::Number(1)
+        );
+        let parse = |text: &str| Parser::new(Tokenizer::new(text).tokenize().unwrap()).parse();
+        assert_eq!(
+            parse(&parens(MAX_DEPTH + 1)).unwrap_err().code(),
+            ErrorCode::TooDeep
+        );
+        let calls = format!("{}x{}", "f(1, ".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        // Dropping the tree recurses once per call, more than a test thread holds.
+        core::mem::forget(parse(&calls).unwrap());
+        let err = parse(&format!("-{}", calls)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TooDeep);
+    }
 }
@@ -0,0 +1,118 @@
+//!
+//! Plots of sampled functions for the terminal, drawn with braille
+//! characters: each character is a cell of 2 by 4 dots, so a plot has twice
+//! as many dots across as it has characters and four times as many down.
+//!
+//! ```
+//! use rexpr::plot::plot;
+//!
+//! let samples: Vec<f64> = (0..8).map(|i| i as f64).collect();
+//! let plot = plot(&samples, 2);
+//! assert_eq!(plot.lines, ["⠀⠀⡠⠊", "⡠⠊⠀⠀"]);
+//! assert_eq!((plot.low, plot.high), (0.0, 7.0));
+//! ```
+//!
+//! Samples that are not finite, such as the `NaN` of a point where the
+//! function is undefined, leave a gap.
+//!
+
+/// The bits of the dots in a braille cell, by row and then column.
+const DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plot {
+    /// The rows of the plot, top first.
+    pub lines: Vec<String>,
+    /// The value of the bottom row of dots.
+    pub low: f64,
+    /// The value of the top row of dots.
+    pub high: f64,
+}
+
+/// A grid of dots, drawn as braille characters.
+struct Canvas {
+    columns: usize,
+    cells: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(columns: usize, rows: usize) -> Self {
+        Canvas {
+            columns,
+            cells: vec![0; columns * rows],
+        }
+    }
+
+    /// Sets the dot `x` across and `y` down.
+    fn set(&mut self, x: usize, y: usize) {
+        self.cells[y / 4 * self.columns + x / 2] |= DOTS[y % 4][x % 2];
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.columns.max(1))
+            .map(|row| {
+                row.iter()
+                    .map(|bits| char::from_u32(0x2800 + *bits as u32).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+///
+/// Plots `samples`, evenly spaced from left to right, one column of dots
+/// each, on `rows` lines scaled to fit the finite samples. Neighbouring
+/// samples are joined by a vertical line so that steep curves stay
+/// connected.
+///
+pub fn plot(samples: &[f64], rows: usize) -> Plot {
+    let finite = samples.iter().copied().filter(|y| y.is_finite());
+    let low = finite.clone().fold(f64::INFINITY, f64::min);
+    let high = finite.fold(f64::NEG_INFINITY, f64::max);
+    let (low, high) = match (low, high) {
+        (low, high) if low > high => (-1.0, 1.0),
+        (low, high) if low == high => (low - 1.0, high + 1.0),
+        bounds => bounds,
+    };
+    let dots = rows * 4;
+    let mut canvas = Canvas::new(samples.len().div_ceil(2), rows);
+    let mut previous: Option<usize> = None;
+    for (x, y) in samples.iter().enumerate() {
+        if rows == 0 || !y.is_finite() {
+            previous = None;
+            continue;
+        }
+        let y = ((high - y) / (high - low) * (dots - 1) as f64).round() as usize;
+        let (from, to) = match previous {
+            Some(previous) if previous < y => (previous + 1, y),
+            Some(previous) if previous > y => (y, previous - 1),
+            _ => (y, y),
+        };
+        for y in from..=to {
+            canvas.set(x, y);
+        }
+        previous = Some(y);
+    }
+    Plot {
+        lines: canvas.lines(),
+        low,
+        high,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plot_samples() {
+        let plot = plot(&[0.0, 3.0, f64::NAN, 3.0, 1.5], 1);
+        assert_eq!(plot.lines, ["⡸⠈⠆"]);
+        assert_eq!((plot.low, plot.high), (0.0, 3.0));
+        let flat = super::plot(&[2.0, 2.0], 1);
+        assert_eq!(flat.lines, ["⠤"]);
+        assert_eq!((flat.low, flat.high), (1.0, 3.0));
+        assert_eq!(super::plot(&[f64::NAN], 2).lines, ["⠀", "⠀"])
+    }
+}
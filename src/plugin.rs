@@ -0,0 +1,310 @@
+//!
+//! Function libraries loaded at run time from shared libraries, built with
+//! the `plugin` feature.
+//!
+//! A plugin is a `cdylib` exporting `rexpr_plugin_declare`, which returns a
+//! [`PluginDeclaration`] listing its functions. The interface is plain C, as
+//! declared in `include/rexpr.h`, so plugins need not be written in Rust or
+//! built with the same compiler:
+//!
+//! ```c
+//! static int mean2(const double *args, size_t len, double *out) {
+//!     *out = (args[0] + args[1]) / 2;
+//!     return 0;
+//! }
+//!
+//! static const rexpr_function functions[] = {{"mean2", 2, mean2}};
+//! static const rexpr_plugin plugin = {REXPR_PLUGIN_ABI, "stats", functions, 1};
+//!
+//! const rexpr_plugin *rexpr_plugin_declare(void) { return &plugin; }
+//! ```
+//!
+use crate::context::Context;
+use crate::eval::{EvalError, Value};
+use libloading::Library;
+use std::error::Error;
+use std::ffi::{c_char, c_int, CStr, OsStr};
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// The version of the interface below, bumped on any change to it.
+pub const ABI_VERSION: u32 = 1;
+
+/// The symbol every plugin exports.
+pub const DECLARE_SYMBOL: &str = "rexpr_plugin_declare";
+
+///
+/// A plugin function: reads `len` arguments from `args`, stores its result
+/// in `out` and returns 0, or returns anything else to fail the evaluation.
+///
+pub type NativeCall = unsafe extern "C" fn(args: *const f64, len: usize, out: *mut f64) -> c_int;
+
+#[repr(C)]
+pub struct PluginFunction {
+    /// A NUL-terminated name.
+    pub name: *const c_char,
+    pub arity: usize,
+    pub call: NativeCall,
+}
+
+#[repr(C)]
+pub struct PluginDeclaration {
+    /// Must equal [`ABI_VERSION`].
+    pub abi_version: u32,
+    /// A NUL-terminated name for the library, such as `finance`.
+    pub name: *const c_char,
+    pub functions: *const PluginFunction,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PluginError {
+    pub message: String,
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PluginError: {}", self.message)
+    }
+}
+
+impl Error for PluginError {}
+
+fn invalid(message: impl Into<String>) -> PluginError {
+    PluginError {
+        message: message.into(),
+    }
+}
+
+/// Reads a NUL-terminated string owned by the plugin.
+unsafe fn name(ptr: *const c_char, what: &str) -> Result<String, PluginError> {
+    if ptr.is_null() {
+        return Err(invalid(format!("{} has no name", what)));
+    }
+    match CStr::from_ptr(ptr).to_str() {
+        Ok(name) => Ok(name.to_string()),
+        Err(_) => Err(invalid(format!("{} name is not valid UTF-8", what))),
+    }
+}
+
+struct Function {
+    name: String,
+    arity: usize,
+    call: NativeCall,
+}
+
+///
+/// A loaded function library. The shared library stays loaded for as long
+/// as the plugin or any function registered from it is alive.
+///
+pub struct Plugin {
+    name: String,
+    functions: Vec<Function>,
+    library: Option<Arc<Library>>,
+}
+
+impl Plugin {
+    ///
+    /// Loads the shared library at `path` and reads its declaration.
+    ///
+    /// # Safety
+    ///
+    /// Loading runs the library's initializers, and the declaration it
+    /// returns is trusted: its pointers must be valid and its functions must
+    /// follow [`NativeCall`]. Only load libraries you trust.
+    ///
+    pub unsafe fn load(path: impl AsRef<OsStr>) -> Result<Plugin, PluginError> {
+        let path = path.as_ref();
+        let library = Library::new(path)
+            .map_err(|err| invalid(format!("cannot load {}: {}", path.to_string_lossy(), err)))?;
+        let declare = library
+            .get::<unsafe extern "C" fn() -> *const PluginDeclaration>(DECLARE_SYMBOL.as_bytes())
+            .map_err(|_| {
+                invalid(format!(
+                    "{} does not export {}",
+                    path.to_string_lossy(),
+                    DECLARE_SYMBOL
+                ))
+            })?;
+        let declaration = declare();
+        if declaration.is_null() {
+            return Err(invalid(format!("{} returned null", DECLARE_SYMBOL)));
+        }
+        let declaration = &*declaration;
+        Plugin::from_declaration(declaration, Some(Arc::new(library)))
+    }
+
+    unsafe fn from_declaration(
+        declaration: &PluginDeclaration,
+        library: Option<Arc<Library>>,
+    ) -> Result<Plugin, PluginError> {
+        if declaration.abi_version != ABI_VERSION {
+            return Err(invalid(format!(
+                "plugin interface version {} is not supported, expected {}",
+                declaration.abi_version, ABI_VERSION
+            )));
+        }
+        let name = name(declaration.name, "plugin")?;
+        let functions = if declaration.len == 0 {
+            &[]
+        } else if declaration.functions.is_null() {
+            return Err(invalid(format!("plugin {} has no function table", name)));
+        } else {
+            std::slice::from_raw_parts(declaration.functions, declaration.len)
+        };
+        let functions = functions
+            .iter()
+            .map(|function| {
+                Ok(Function {
+                    name: self::name(function.name, "function")?,
+                    arity: function.arity,
+                    call: function.call,
+                })
+            })
+            .collect::<Result<_, PluginError>>()?;
+        Ok(Plugin {
+            name,
+            functions,
+            library,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of the functions the plugin provides, in declaration order.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|function| function.name.as_str())
+    }
+
+    ///
+    /// Registers every function of the plugin in `ctx` in a namespace named
+    /// after the plugin, as `stats::mean2`, replacing functions of the same
+    /// name, and imports the namespace so they can be called unqualified
+    /// unless another import already claims the name. Arguments are passed
+    /// as `f64` and results come back as [`Value::Float`].
+    ///
+    pub fn register(&self, ctx: &mut Context) {
+        ctx.import(format!("{}::*", self.name));
+        for function in &self.functions {
+            let (name, call) = (function.name.clone(), function.call);
+            let library = self.library.clone();
+            let path = format!("{}::{}", self.name, function.name);
+            ctx.register_fn(path, function.arity, move |args| {
+                let _loaded = &library;
+                let args: Vec<f64> = args.iter().map(|arg| arg.as_f64()).collect();
+                let mut out = 0.0;
+                match unsafe { call(args.as_ptr(), args.len(), &mut out) } {
+                    0 => Ok(Value::Float(out)),
+                    status => Err(EvalError::new(format!(
+                        "{} failed with status {}",
+                        name, status
+                    ))),
+                }
+            });
+        }
+    }
+}
+
+impl std::fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugin")
+            .field("name", &self.name)
+            .field("functions", &self.functions().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    unsafe extern "C" fn mean(args: *const f64, len: usize, out: *mut f64) -> c_int {
+        let args = std::slice::from_raw_parts(args, len);
+        *out = args.iter().sum::<f64>() / len as f64;
+        0
+    }
+
+    unsafe extern "C" fn fail(_: *const f64, _: usize, _: *mut f64) -> c_int {
+        7
+    }
+
+    fn declaration(abi_version: u32, functions: &[PluginFunction]) -> PluginDeclaration {
+        PluginDeclaration {
+            abi_version,
+            name: c"stats".as_ptr(),
+            functions: functions.as_ptr(),
+            len: functions.len(),
+        }
+    }
+
+    #[test]
+    fn register_declared_functions() {
+        let functions = [
+            PluginFunction {
+                name: c"mean3".as_ptr(),
+                arity: 3,
+                call: mean,
+            },
+            PluginFunction {
+                name: c"fail".as_ptr(),
+                arity: 0,
+                call: fail,
+            },
+        ];
+        let plugin =
+            unsafe { Plugin::from_declaration(&declaration(ABI_VERSION, &functions), None) }
+                .unwrap();
+        assert_eq!(plugin.name(), "stats");
+        assert_eq!(plugin.functions().collect::<Vec<_>>(), ["mean3", "fail"]);
+
+        let mut ctx = Context::new();
+        plugin.register(&mut ctx);
+        let eval = |text: &str| Eval::new().eval_with(&text.parse().unwrap(), &ctx);
+        assert_eq!(eval("mean3(1, 2, 6) * 2").unwrap(), Value::Float(6.0));
+        assert_eq!(eval("stats::mean3(3, 3, 3)").unwrap(), Value::Float(3.0));
+        assert_eq!(
+            eval("mean3(1)").unwrap_err().to_string(),
+            "EvalError: mean3 expects 3 arguments, got 1"
+        );
+        assert_eq!(
+            eval("fail()").unwrap_err().to_string(),
+            "EvalError: fail failed with status 7"
+        )
+    }
+
+    #[test]
+    fn reject_invalid_plugins() {
+        let err = unsafe { Plugin::from_declaration(&declaration(99, &[]), None) }.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "PluginError: plugin interface version 99 is not supported, expected 1"
+        );
+        let err = unsafe { Plugin::load("./no-such-plugin.so") }.unwrap_err();
+        assert!(err.message.starts_with("cannot load ./no-such-plugin.so"))
+    }
+
+    #[test]
+    fn keep_assignments_of_plugin_calls() {
+        let functions = [PluginFunction {
+            name: c"mean3".as_ptr(),
+            arity: 3,
+            call: mean,
+        }];
+        let plugin =
+            unsafe { Plugin::from_declaration(&declaration(ABI_VERSION, &functions), None) }
+                .unwrap();
+        let mut ctx = Context::new();
+        plugin.register(&mut ctx);
+        let mut eval = Eval::new();
+        let node = "m = mean3(1, 2, 4); n = 2".parse().unwrap();
+        assert_eq!(
+            eval.eval_with_assigning(&node, &ctx, &mut ()).unwrap(),
+            Value::Int(2)
+        );
+        assert_eq!(eval.vars().get("m"), Some(&Value::Float(7.0 / 3.0)));
+        assert_eq!(eval.vars().get("n"), Some(&Value::Int(2)))
+    }
+}
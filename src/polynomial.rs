@@ -0,0 +1,324 @@
+//!
+//! Symbolic manipulation of polynomials: expanding products and powers, and
+//! collecting terms by powers of a variable.
+//!
+//! Subexpressions that are not polynomial, such as `y / 2`, are kept whole and
+//! treated like variables. Coefficients are `i32` and overflow is an error.
+//!
+use crate::eval::{binary, EvalError, Overflow};
+use crate::parser::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+/// Factors keyed by their printed form, each with its exponent.
+type Monomial = BTreeMap<String, u32>;
+
+#[derive(Debug, Clone, Default)]
+struct Polynomial {
+    terms: BTreeMap<Monomial, i32>,
+}
+
+fn checked(op: BinaryOperator, lhs: i32, rhs: i32) -> Result<i32, EvalError> {
+    binary(&op, lhs, rhs, Overflow::Checked)
+}
+
+impl Polynomial {
+    fn constant(n: i32) -> Self {
+        let mut poly = Polynomial::default();
+        poly.add_term(Monomial::new(), n).unwrap();
+        poly
+    }
+
+    fn factor(key: String) -> Self {
+        let mut poly = Polynomial::default();
+        poly.terms.insert(Monomial::from([(key, 1)]), 1);
+        poly
+    }
+
+    fn add_term(&mut self, monomial: Monomial, coefficient: i32) -> Result<(), EvalError> {
+        let sum = checked(
+            BinaryOperator::Plus,
+            self.terms.get(&monomial).copied().unwrap_or(0),
+            coefficient,
+        )?;
+        if sum == 0 {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+        Ok(())
+    }
+
+    fn add(mut self, other: Polynomial, sign: i32) -> Result<Self, EvalError> {
+        for (monomial, coefficient) in other.terms {
+            self.add_term(monomial, checked(BinaryOperator::Mul, sign, coefficient)?)?;
+        }
+        Ok(self)
+    }
+
+    fn mul(&self, other: &Polynomial) -> Result<Self, EvalError> {
+        let mut product = Polynomial::default();
+        for (lhs, a) in &self.terms {
+            for (rhs, b) in &other.terms {
+                let mut monomial = lhs.clone();
+                for (key, exp) in rhs {
+                    let power = monomial.entry(key.clone()).or_insert(0);
+                    *power = checked(BinaryOperator::Plus, *power as i32, *exp as i32)? as u32;
+                }
+                product.add_term(monomial, checked(BinaryOperator::Mul, *a, *b)?)?;
+            }
+        }
+        Ok(product)
+    }
+
+    /// Raises to a non-negative power by repeated squaring.
+    fn pow(&self, mut exp: i32) -> Result<Self, EvalError> {
+        let mut power = Polynomial::constant(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                power = power.mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(power)
+    }
+}
+
+fn degree(monomial: &Monomial) -> u32 {
+    monomial.values().sum()
+}
+
+/// Graded lexicographic order: higher total degree first, then higher powers
+/// of earlier factors first.
+fn graded(a: &Monomial, b: &Monomial) -> Ordering {
+    degree(b).cmp(&degree(a)).then_with(|| {
+        let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.iter()
+            .map(|key| b.get(*key).cmp(&a.get(*key)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Multiplies `term` by `factor`, putting `factor` first in the product.
+fn scale(factor: Node, term: Node) -> Node {
+    match term {
+        Node::BinaryExpr {
+            op: BinaryOperator::Mul,
+            lhs,
+            rhs,
+        } => scale(factor, *lhs) * *rhs,
+        term => factor * term,
+    }
+}
+
+#[derive(Default)]
+struct Converter {
+    atoms: HashMap<String, Node>,
+}
+
+impl Converter {
+    fn atom(&mut self, node: Node) -> Result<Polynomial, EvalError> {
+        let key = node.to_string();
+        self.atoms.insert(key.clone(), node);
+        Ok(Polynomial::factor(key))
+    }
+
+    fn polynomial(&mut self, node: &Node) -> Result<Polynomial, EvalError> {
+        match node {
+            Node::Number(n) => Ok(Polynomial::constant(*n)),
+            Node::Float(_) | Node::Identifier(_) => self.atom(node.clone()),
+            Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child,
+            } => Polynomial::default().add(self.polynomial(child)?, -1),
+            Node::UnaryExpr { .. } | Node::Call { .. } => {
+                self.atom(node.clone().try_map_children(|child| expand(&child))?)
+            }
+            Node::BinaryExpr { op, lhs, rhs } => match (op, &**rhs) {
+                (BinaryOperator::Plus, _) => self.polynomial(lhs)?.add(self.polynomial(rhs)?, 1),
+                (BinaryOperator::Minus, _) => self.polynomial(lhs)?.add(self.polynomial(rhs)?, -1),
+                (BinaryOperator::Mul, _) => self.polynomial(lhs)?.mul(&self.polynomial(rhs)?),
+                (BinaryOperator::Pow, Node::Number(exp)) if *exp >= 0 => {
+                    self.polynomial(lhs)?.pow(*exp)
+                }
+                _ => self.atom(node.clone().try_map_children(|child| expand(&child))?),
+            },
+            Node::Assign { .. } | Node::Block(_) | Node::Use(_) => self.atom(node.clone()),
+        }
+    }
+
+    fn term(&self, monomial: &Monomial) -> Option<Node> {
+        monomial
+            .iter()
+            .map(|(key, exp)| match exp {
+                1 => self.atoms[key].clone(),
+                exp => Node::binary(BinaryOperator::Pow, self.atoms[key].clone(), *exp as i32),
+            })
+            .reduce(|product, factor| product * factor)
+    }
+
+    /// A sum of `terms`, with negative coefficients turned into subtraction.
+    fn sum(&self, terms: Vec<(Node, i32)>) -> Node {
+        let mut sum: Option<Node> = None;
+        for (term, coefficient) in terms {
+            let magnitude = |term: Node| match coefficient.unsigned_abs() {
+                1 => term,
+                n => scale(Node::num(n as i32), term),
+            };
+            sum = Some(match sum {
+                None if coefficient == -1 => -term,
+                None if coefficient < 0 => scale(Node::num(coefficient), term),
+                None => magnitude(term),
+                Some(sum) if coefficient == i32::MIN => sum + scale(Node::num(coefficient), term),
+                Some(sum) if coefficient < 0 => sum - magnitude(term),
+                Some(sum) => sum + magnitude(term),
+            });
+        }
+        sum.unwrap_or(Node::num(0))
+    }
+
+    fn node(&self, poly: &Polynomial) -> Node {
+        let mut terms: Vec<(&Monomial, &i32)> = poly.terms.iter().collect();
+        terms.sort_by(|(a, _), (b, _)| graded(a, b));
+        self.sum(
+            terms
+                .into_iter()
+                .map(|(monomial, &coefficient)| match self.term(monomial) {
+                    Some(term) => (term, coefficient),
+                    None if coefficient == i32::MIN => (Node::num(coefficient), 1),
+                    None => (
+                        Node::num(coefficient.unsigned_abs() as i32),
+                        coefficient.signum(),
+                    ),
+                })
+                .collect(),
+        )
+    }
+}
+
+///
+/// Multiplies out products and non-negative integer powers and combines like
+/// terms, e.g. `(x + 1) * (x + 2)` becomes `x ^ 2 + 3 * x + 2`.
+///
+/// Statements are expanded one by one.
+///
+pub fn expand(node: &Node) -> Result<Node, EvalError> {
+    match node {
+        Node::Assign { name, value } => Ok(Node::assign(name.clone(), expand(value)?)),
+        Node::Block(stmts) => Ok(Node::Block(
+            stmts.iter().map(expand).collect::<Result<_, _>>()?,
+        )),
+        node => {
+            let mut converter = Converter::default();
+            let poly = converter.polynomial(node)?;
+            Ok(converter.node(&poly))
+        }
+    }
+}
+
+///
+/// Expands `node` and groups its terms by descending powers of `var`, e.g.
+/// `x * y + x + 2 * x ^ 2 + y` becomes `2 * x ^ 2 + (y + 1) * x + y`.
+///
+pub fn collect(node: &Node, var: &str) -> Result<Node, EvalError> {
+    let mut converter = Converter::default();
+    let poly = converter.polynomial(node)?;
+    let mut groups: BTreeMap<u32, Polynomial> = BTreeMap::new();
+    for (monomial, coefficient) in poly.terms {
+        let mut rest = monomial;
+        let exp = rest.remove(var).unwrap_or(0);
+        groups.entry(exp).or_default().add_term(rest, coefficient)?;
+    }
+    let terms = groups
+        .iter()
+        .rev()
+        .map(|(exp, coefficient)| {
+            let power = match exp {
+                0 => None,
+                1 => Some(Node::var(var)),
+                exp => Some(Node::binary(
+                    BinaryOperator::Pow,
+                    Node::var(var),
+                    *exp as i32,
+                )),
+            };
+            match (converter.node(coefficient), power) {
+                (coefficient, None) => (coefficient, 1),
+                (Node::Number(n), Some(power)) => (power, n),
+                (
+                    Node::UnaryExpr {
+                        op: UnaryOperator::Neg,
+                        child,
+                    },
+                    Some(power),
+                ) => (*child * power, -1),
+                (coefficient, Some(power)) => (coefficient * power, 1),
+            }
+        })
+        .collect();
+    Ok(converter.sum(terms))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expanded(text: &str) -> String {
+        expand(&text.parse().unwrap()).unwrap().to_string()
+    }
+
+    fn collected(text: &str, var: &str) -> String {
+        collect(&text.parse().unwrap(), var).unwrap().to_string()
+    }
+
+    #[test]
+    fn expand_products() {
+        assert_eq!(expanded("(x + 1) * (x + 2)"), "x ^ 2 + 3 * x + 2");
+        assert_eq!(expanded("(x - y) ^ 2"), "x ^ 2 - 2 * x * y + y ^ 2");
+        assert_eq!(expanded("(a + b) * (a - b) + b * b"), "a ^ 2");
+        assert_eq!(expanded("-(x - 1) * 3"), "-3 * x + 3");
+        assert_eq!(expanded("x - x"), "0")
+    }
+
+    #[test]
+    fn expand_keeps_other_subexpressions() {
+        assert_eq!(expanded("(x + 1) * (y / 2)"), "x * (y / 2) + y / 2");
+        assert_eq!(expanded("(x + 1) ^ n"), "(x + 1) ^ n");
+        assert_eq!(expanded("((x + 1) * (x - 1)) / 2"), "(x ^ 2 - 1) / 2");
+        assert_eq!(expanded("a = (x + 1) ^ 2; a"), "a = x ^ 2 + 2 * x + 1; a");
+        assert_eq!(expanded("!(x * (x + 1)) * 2"), "2 * !(x ^ 2 + x)")
+    }
+
+    #[test]
+    fn collect_by_variable() {
+        assert_eq!(
+            collected("x * y + x + 2 * x ^ 2 + y", "x"),
+            "2 * x ^ 2 + (y + 1) * x + y"
+        );
+        assert_eq!(collected("(x + y) ^ 2", "y"), "y ^ 2 + 2 * x * y + x ^ 2");
+        assert_eq!(collected("a * x - b * x - x", "x"), "(a - b - 1) * x");
+        assert_eq!(collected("!a * x", "x"), "!a * x")
+    }
+
+    #[test]
+    fn coefficient_overflow() {
+        let err = expand(&"(2147483647 * x) * 2".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "EvalError: integer overflow")
+    }
+
+    #[test]
+    fn large_powers() {
+        assert_eq!(
+            expanded("x ^ 2147483647 * 1 ^ 2147483647"),
+            "x ^ 2147483647"
+        );
+        assert!(expand(&"(x ^ 2147483647) ^ 2".parse().unwrap()).is_err())
+    }
+}
@@ -0,0 +1,177 @@
+//!
+//! A line-delimited JSON protocol for evaluating expressions from another
+//! process, served by `rexpr --serve-jsonrpc` on stdin and stdout.
+//!
+//! Each request is one line holding an object with the expression and,
+//! optionally, values for its variables and an `id` echoed in the response:
+//!
+//! ```text
+//! {"id": 1, "expr": "price * (1 + rate)", "vars": {"price": 200, "rate": 0.5}}
+//! ```
+//!
+//! The response is one line with either the `result` or an `error`, in the
+//! form described in [`crate::json`]:
+//!
+//! ```text
+//! {"id":1,"result":300}
+//! {"id":2,"error":{"code":"E0101","message":"undefined variable rate","span":{...}}}
+//! ```
+//!
+//! Expressions are evaluated with the standard library in scope, each in a
+//! fresh session. Requests that are not an object with an `expr` string are
+//! answered with an error with code `E0010`, and expressions nested more
+//! than [`MAX_DEPTH`] deep with one with code `E0011`.
+//!
+use crate::context::Context;
+use crate::error::{ErrorCode, RexprError};
+use crate::eval::{Eval, EvalError, Value};
+use crate::json::{object, Json};
+use crate::parser::Parser;
+use crate::tokenizer::Lexer;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+/// How deeply a request's expression may nest, far below what the parser
+/// allows, as the server evaluates on the stack of its main thread.
+pub const MAX_DEPTH: usize = 256;
+
+fn invalid_request(message: impl Into<String>) -> Json {
+    object(vec![
+        ("code", Json::String(ErrorCode::InvalidRequest.to_string())),
+        ("message", Json::String(message.into())),
+        ("span", Json::Null),
+    ])
+}
+
+/// The value of a variable in a request: integers stay integers.
+fn value(name: &str, json: &Json) -> Result<Value, EvalError> {
+    match json.as_f64() {
+        Some(x) if x.fract() == 0.0 && x >= i32::MIN as f64 && x <= i32::MAX as f64 => {
+            Ok(Value::Int(x as i32))
+        }
+        Some(x) => Ok(Value::Float(x)),
+        None => Err(EvalError::with_code(
+            ErrorCode::InvalidBinding,
+            format!("variable {} must be a number, got {}", name, json),
+        )),
+    }
+}
+
+fn evaluate(expr: &str, vars: Option<&Json>, base: &Arc<Context>) -> Result<Value, RexprError> {
+    let mut ctx = Context::extend(base.clone());
+    match vars {
+        Some(Json::Object(vars)) => {
+            for (name, json) in vars {
+                ctx.set(name.as_str(), value(name, json)?);
+            }
+        }
+        None | Some(Json::Null) => {}
+        Some(other) => {
+            return Err(EvalError::with_code(
+                ErrorCode::InvalidBinding,
+                format!("vars must be an object, got {}", other),
+            )
+            .into())
+        }
+    }
+    let (node, spans) = Parser::from_lexer(Lexer::new(expr))
+        .with_max_depth(MAX_DEPTH)
+        .parse_spanned()?;
+    Eval::new().eval_with(&node, &ctx).map_err(|mut err| {
+        err.locate(&spans);
+        err.into()
+    })
+}
+
+///
+/// Answers one request line, evaluating against `base` extended with the
+/// request's variables.
+///
+pub fn respond(request: &str, base: &Arc<Context>) -> Json {
+    let request = match Json::parse(request) {
+        Ok(request) => request,
+        Err(err) => return object(vec![("error", invalid_request(err.to_string()))]),
+    };
+    let mut fields = vec![];
+    if let Some(id) = request.get("id") {
+        fields.push(("id", id.clone()));
+    }
+    let outcome = match request.get("expr").and_then(Json::as_str) {
+        Some(expr) => match evaluate(expr, request.get("vars"), base) {
+            Ok(value) => ("result", Json::Number(value.as_f64())),
+            Err(err) => ("error", err.to_json_value()),
+        },
+        None => (
+            "error",
+            invalid_request("expected an object with an expr string"),
+        ),
+    };
+    fields.push(outcome);
+    object(fields)
+}
+
+///
+/// Answers every line of `reader` on a line of `writer`, flushing after
+/// each so that the caller can wait for the response. Blank lines are
+/// skipped.
+///
+pub fn serve(base: Arc<Context>, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", respond(&line, &base))?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn serve_lines(input: &str) -> String {
+        let mut out = vec![];
+        serve(Arc::new(Context::stdlib()), input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn answer_requests() {
+        let input = r#"{"id": 1, "expr": "price * (1 + rate)", "vars": {"price": 200, "rate": 0.5}}
+
+{"expr": "7 / 2"}
+{"id": "a", "expr": "sqrt(x)", "vars": {"x": 2.25}}
+"#;
+        assert_eq!(
+            serve_lines(input),
+            "{\"id\":1,\"result\":300}\n{\"result\":3}\n{\"id\":\"a\",\"result\":1.5}\n"
+        )
+    }
+
+    #[test]
+    fn answer_errors() {
+        let base = Arc::new(Context::stdlib());
+        assert_eq!(
+            respond(r#"{"id": 2, "expr": "1 + rate"}"#, &base).to_string(),
+            r#"{"id":2,"error":{"code":"E0101","message":"undefined variable rate","span":{"line":1,"col":5,"offset":4,"len":4},"expression":"rate"}}"#
+        );
+        let code = |request: &str| {
+            let response = respond(request, &base);
+            let error = response.get("error").unwrap();
+            error.get("code").unwrap().as_str().unwrap().to_string()
+        };
+        assert_eq!(code(r#"{"expr": "1 +"}"#), "E0003");
+        assert_eq!(code(r#"{"expr": "x", "vars": {"x": "one"}}"#), "E0109");
+        assert_eq!(code(r#"{"expr": "x", "vars": [1]}"#), "E0109");
+        assert_eq!(code(r#"{"vars": {}}"#), "E0010");
+        assert_eq!(code("1 + 2"), "E0010");
+        let nested = |n| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        let request = |n| format!(r#"{{"expr": "-{}"}}"#, nested(n));
+        assert_eq!(code(&request(50_000)), "E0011");
+        let response = respond(&request(MAX_DEPTH - 1), &base);
+        assert_eq!(response.to_string(), r#"{"result":-1}"#);
+        assert_eq!(code(&format!("[{}]", "[".repeat(200_000))), "E0010")
+    }
+}
@@ -0,0 +1,279 @@
+//!
+//! S-expression form of the AST, e.g. `(+ 1 (* 2 (- x)))`.
+//!
+//! Operators keep their infix symbol, `-` with a single operand is negation,
+//! assignments are written `(= x value)`, blocks `(do stmt ...)` and calls
+//! `(f arg ...)`.
+//!
+use crate::parser::*;
+use std::error::Error;
+use std::fmt::Display;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Debug)]
+pub struct SexprError {
+    message: String,
+}
+
+impl Display for SexprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SexprError: {}", self.message)
+    }
+}
+
+impl Error for SexprError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, SexprError> {
+    Err(SexprError {
+        message: message.into(),
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Sexpr<'a> {
+    Atom(&'a str),
+    List(Vec<Sexpr<'a>>),
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Reader<'a> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some((_, c)) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn read(&mut self) -> Result<Sexpr<'a>, SexprError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some((_, '(')) => {
+                self.chars.next();
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        Some((_, ')')) => {
+                            self.chars.next();
+                            return Ok(Sexpr::List(items));
+                        }
+                        Some(_) => items.push(self.read()?),
+                        None => return error("unclosed list"),
+                    }
+                }
+            }
+            Some((_, ')')) => error("unexpected )"),
+            Some(&(start, _)) => {
+                let mut end = self.text.len();
+                while let Some(&(i, c)) = self.chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        end = i;
+                        break;
+                    }
+                    self.chars.next();
+                }
+                Ok(Sexpr::Atom(&self.text[start..end]))
+            }
+            None => error("unexpected end of input"),
+        }
+    }
+}
+
+fn binary_operator(symbol: &str) -> Option<BinaryOperator> {
+    match symbol {
+        "+" => Some(BinaryOperator::Plus),
+        "-" => Some(BinaryOperator::Minus),
+        "*" => Some(BinaryOperator::Mul),
+        "/" => Some(BinaryOperator::Div),
+        "^" => Some(BinaryOperator::Pow),
+        "<<" => Some(BinaryOperator::Shl),
+        ">>" => Some(BinaryOperator::Shr),
+        "&&" => Some(BinaryOperator::And),
+        "||" => Some(BinaryOperator::Or),
+        _ => None,
+    }
+}
+
+fn is_name(atom: &str) -> bool {
+    let mut chars = atom.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A name, or a qualified one such as `math::sin`.
+fn is_identifier(atom: &str) -> bool {
+    atom.split("::").all(is_name)
+}
+
+/// A qualified name, or a glob such as `math::*`.
+fn is_path(atom: &str) -> bool {
+    let prefix = atom.strip_suffix("::*").unwrap_or(atom);
+    atom.contains("::") && is_identifier(prefix)
+}
+
+/// A float literal in source syntax, such as `2.5` or `-1e3`; never `inf`.
+fn float(atom: &str) -> Option<f64> {
+    let digits = atom.strip_prefix('-').unwrap_or(atom);
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) || !atom.contains(['.', 'e', 'E']) {
+        return None;
+    }
+    atom.parse().ok().filter(|x: &f64| x.is_finite())
+}
+
+fn to_node(sexpr: &Sexpr) -> Result<Node, SexprError> {
+    match sexpr {
+        Sexpr::Atom(atom) => {
+            if let Ok(n) = atom.parse::<i32>() {
+                Ok(Node::Number(n))
+            } else if let Some(x) = float(atom) {
+                Ok(Node::Float(Float(x)))
+            } else if is_identifier(atom) {
+                Ok(Node::Identifier(atom.to_string()))
+            } else {
+                error(format!("invalid atom {}", atom))
+            }
+        }
+        Sexpr::List(items) => match items.as_slice() {
+            [Sexpr::Atom("-"), child] => Ok(Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child: Box::new(to_node(child)?),
+            }),
+            [Sexpr::Atom("!"), child] => Ok(Node::UnaryExpr {
+                op: UnaryOperator::Not,
+                child: Box::new(to_node(child)?),
+            }),
+            [Sexpr::Atom("="), Sexpr::Atom(name), value] if is_identifier(name) => {
+                Ok(Node::Assign {
+                    name: name.to_string(),
+                    value: Box::new(to_node(value)?),
+                })
+            }
+            [Sexpr::Atom("do"), stmts @ ..] if !stmts.is_empty() => Ok(Node::Block(
+                stmts.iter().map(to_node).collect::<Result<_, _>>()?,
+            )),
+            [Sexpr::Atom("use"), Sexpr::Atom(path)] if is_path(path) => {
+                Ok(Node::Use(path.to_string()))
+            }
+            [Sexpr::Atom(symbol), lhs, rhs] if binary_operator(symbol).is_some() => {
+                Ok(Node::BinaryExpr {
+                    op: binary_operator(symbol).unwrap(),
+                    lhs: Box::new(to_node(lhs)?),
+                    rhs: Box::new(to_node(rhs)?),
+                })
+            }
+            [Sexpr::Atom(name), args @ ..] if is_identifier(name) && *name != "do" => {
+                Ok(Node::Call {
+                    name: name.to_string(),
+                    args: args.iter().map(to_node).collect::<Result<_, _>>()?,
+                })
+            }
+            [Sexpr::Atom(head), ..] => error(format!("invalid form ({} ...)", head)),
+            _ => error("invalid form"),
+        },
+    }
+}
+
+impl Node {
+    pub fn to_sexpr(&self) -> String {
+        match self {
+            Node::Number(n) => n.to_string(),
+            Node::Float(Float(x)) if !x.is_finite() => normalize(self).to_sexpr(),
+            Node::Float(Float(x)) => format!("{:?}", x),
+            Node::Identifier(name) => name.clone(),
+            Node::UnaryExpr { op, child } => format!("({} {})", op, child.to_sexpr()),
+            Node::BinaryExpr { op, lhs, rhs } => {
+                format!("({} {} {})", op, lhs.to_sexpr(), rhs.to_sexpr())
+            }
+            Node::Assign { name, value } => format!("(= {} {})", name, value.to_sexpr()),
+            Node::Block(stmts) => {
+                let stmts: Vec<String> = stmts.iter().map(Node::to_sexpr).collect();
+                format!("(do {})", stmts.join(" "))
+            }
+            Node::Call { name, args } => {
+                let mut items = vec![name.clone()];
+                items.extend(args.iter().map(Node::to_sexpr));
+                format!("({})", items.join(" "))
+            }
+            Node::Use(path) => format!("(use {})", path),
+        }
+    }
+
+    pub fn from_sexpr(text: &str) -> Result<Node, SexprError> {
+        let mut reader = Reader {
+            text,
+            chars: text.char_indices().peekable(),
+        };
+        let sexpr = reader.read()?;
+        reader.skip_whitespace();
+        if reader.chars.peek().is_some() {
+            return error("trailing input after expression");
+        }
+        to_node(&sexpr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn node_to_sexpr() {
+        let tokenizer = Tokenizer::new("1 + 2 * -x");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(expr.to_sexpr(), "(+ 1 (* 2 (- x)))")
+    }
+
+    #[test]
+    fn statements_to_sexpr() {
+        let tokenizer = Tokenizer::new("x = 3; x / 2");
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(expr.to_sexpr(), "(do (= x 3) (/ x 2))")
+    }
+
+    #[test]
+    fn parse_sexpr() {
+        let expr = Node::from_sexpr("(* (+ 1 2)\n   (- 3 -4))").unwrap();
+        assert_eq!(
+            expr,
+            Node::BinaryExpr {
+                op: BinaryOperator::Mul,
+                lhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Plus,
+                    lhs: Box::new(Node::Number(1)),
+                    rhs: Box::new(Node::Number(2))
+                }),
+                rhs: Box::new(Node::BinaryExpr {
+                    op: BinaryOperator::Minus,
+                    lhs: Box::new(Node::Number(3)),
+                    rhs: Box::new(Node::Number(-4))
+                }),
+            }
+        )
+    }
+
+    #[test]
+    fn sexpr_round_trip() {
+        let tokenizer = Tokenizer::new(
+            "use math::*; a = 2.5e-3; b = a * (3 - a) / 4; -b || !a && max(b, 1) + stats::now()",
+        );
+        let tokens = tokenizer.tokenize().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(Node::from_sexpr(&expr.to_sexpr()).unwrap(), expr)
+    }
+
+    #[test]
+    fn reject_invalid_sexpr() {
+        assert!(Node::from_sexpr("(+ 1").is_err());
+        assert!(Node::from_sexpr("(+ 1 2 3)").is_err());
+        assert!(Node::from_sexpr("(% 1 2)").is_err());
+        assert!(Node::from_sexpr("1 2").is_err());
+        assert!(Node::from_sexpr("()").is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//!
+//! Ready-made functions and constants for a [`Context`], in groups that are
+//! each behind a cargo feature of the same name. Functions are registered in
+//! a namespace named after their group, such as `math::sqrt`, which is
+//! imported so they can be called as plain `sqrt` too:
+//!
+//! - `math`: `pi`, `e`, `sqrt`, `sin`, `ln`, `round`, `min`, ...
+//! - `stats`: `sum`, `mean`, `median`, `variance` and `stddev` over any
+//!   number of arguments
+//! - `units`: conversion factors to SI units, such as `km`, `mi` and `hour`,
+//!   so `5 * km / mi` is five kilometres in miles
+//!
+//! All three are on by default; embedders that only need arithmetic can turn
+//! off default features and build without them.
+//!
+//! The `strings` feature is reserved for a group of string functions, which
+//! needs values to hold strings first. It is off by default and, for now,
+//! registers nothing, so turning it on is harmless.
+//!
+//! Trigonometric functions take and return radians, unless registered again
+//! for degrees with [`trigonometry`].
+//!
+//! ```
+//! use rexpr::context::Context;
+//! use rexpr::eval::{Eval, Value};
+//!
+//! let ctx = Context::stdlib();
+//! let node = "round(sqrt(mean(9, 16, 20)) * 100)".parse().unwrap();
+//! assert_eq!(Eval::new().eval_with(&node, &ctx).unwrap(), Value::Float(387.0));
+//! ```
+//!
+use crate::context::Context;
+#[cfg(any(feature = "math", feature = "stats"))]
+use crate::error::ErrorCode;
+#[cfg(any(feature = "math", feature = "stats"))]
+use crate::eval::EvalError;
+#[cfg(any(feature = "math", feature = "stats", feature = "units"))]
+use crate::eval::Value;
+use crate::prelude::*;
+use core::fmt::Display;
+use core::str::FromStr;
+
+/// The enabled groups.
+const GROUPS: &[fn(&mut Context)] = &[
+    #[cfg(feature = "math")]
+    math,
+    #[cfg(feature = "stats")]
+    stats,
+    #[cfg(feature = "units")]
+    units,
+];
+
+impl Context {
+    /// A context with every group of the standard library that is enabled.
+    pub fn stdlib() -> Self {
+        let mut ctx = Context::new();
+        for register in GROUPS {
+            register(&mut ctx);
+        }
+        ctx
+    }
+}
+
+/// The unit of the angles trigonometric functions take and return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Angle {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl Angle {
+    /// A short name, also accepted by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Angle::Radians => "rad",
+            Angle::Degrees => "deg",
+        }
+    }
+}
+
+impl Display for Angle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct AngleError {
+    message: String,
+}
+
+impl Display for AngleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "AngleError: {}", self.message)
+    }
+}
+
+impl core::error::Error for AngleError {}
+
+impl FromStr for Angle {
+    type Err = AngleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rad" | "radians" => Ok(Angle::Radians),
+            "deg" | "degrees" => Ok(Angle::Degrees),
+            _ => Err(AngleError {
+                message: format!("unknown angle unit {}, expected rad or deg", s),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "math")]
+fn unary(ctx: &mut Context, name: &str, f: fn(f64) -> f64) {
+    ctx.register_fn(format!("math::{}", name), 1, move |args| {
+        Ok(Value::Float(f(args[0].as_f64())))
+    });
+}
+
+/// Rounding an integer leaves it unchanged.
+#[cfg(feature = "math")]
+fn rounding(ctx: &mut Context, name: &str, f: fn(f64) -> f64) {
+    ctx.register_fn(format!("math::{}", name), 1, move |args| match args[0] {
+        Value::Int(n) => Ok(Value::Int(n)),
+        Value::Float(x) => Ok(Value::Float(f(x))),
+    });
+}
+
+/// Registers the `math` group: the constants `pi` and `e`, and the usual
+/// functions on floats under `math::`.
+#[cfg(feature = "math")]
+pub fn math(ctx: &mut Context) {
+    ctx.import("math::*");
+    ctx.set("pi", core::f64::consts::PI);
+    ctx.set("e", core::f64::consts::E);
+    unary(ctx, "sqrt", libm::sqrt);
+    unary(ctx, "cbrt", libm::cbrt);
+    unary(ctx, "exp", libm::exp);
+    unary(ctx, "ln", libm::log);
+    unary(ctx, "log2", libm::log2);
+    unary(ctx, "log10", libm::log10);
+    trigonometry(ctx, Angle::Radians);
+    ctx.register_fn("math::hypot", 2, |args| {
+        Ok(Value::Float(libm::hypot(
+            args[0].as_f64(),
+            args[1].as_f64(),
+        )))
+    });
+    rounding(ctx, "floor", libm::floor);
+    rounding(ctx, "ceil", libm::ceil);
+    rounding(ctx, "round", libm::round);
+    rounding(ctx, "trunc", libm::trunc);
+    ctx.register_fn("math::abs", 1, |args| match args[0] {
+        Value::Int(n) => n
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| EvalError::with_code(ErrorCode::IntegerOverflow, "integer overflow")),
+        Value::Float(x) => Ok(Value::Float(libm::fabs(x))),
+    });
+    // The smaller or larger argument itself, so integers stay integers.
+    ctx.register_fn("math::min", 2, |args| {
+        Ok(if args[1].as_f64() < args[0].as_f64() {
+            args[1]
+        } else {
+            args[0]
+        })
+    });
+    ctx.register_fn("math::max", 2, |args| {
+        Ok(if args[1].as_f64() > args[0].as_f64() {
+            args[1]
+        } else {
+            args[0]
+        })
+    });
+}
+
+///
+/// Registers `sin`, `cos`, `tan`, `asin`, `acos`, `atan` and `atan2` under
+/// `math::` for angles in `unit`, replacing those registered before.
+///
+#[cfg(feature = "math")]
+pub fn trigonometry(ctx: &mut Context, unit: Angle) {
+    match unit {
+        Angle::Radians => {
+            unary(ctx, "sin", libm::sin);
+            unary(ctx, "cos", libm::cos);
+            unary(ctx, "tan", libm::tan);
+            unary(ctx, "asin", libm::asin);
+            unary(ctx, "acos", libm::acos);
+            unary(ctx, "atan", libm::atan);
+        }
+        Angle::Degrees => {
+            unary(ctx, "sin", |x| libm::sin(x.to_radians()));
+            unary(ctx, "cos", |x| libm::cos(x.to_radians()));
+            unary(ctx, "tan", |x| libm::tan(x.to_radians()));
+            unary(ctx, "asin", |x| libm::asin(x).to_degrees());
+            unary(ctx, "acos", |x| libm::acos(x).to_degrees());
+            unary(ctx, "atan", |x| libm::atan(x).to_degrees());
+        }
+    }
+    let scale = match unit {
+        Angle::Radians => 1.0,
+        Angle::Degrees => 180.0 / core::f64::consts::PI,
+    };
+    ctx.register_fn("math::atan2", 2, move |args| {
+        Ok(Value::Float(
+            libm::atan2(args[0].as_f64(), args[1].as_f64()) * scale,
+        ))
+    });
+}
+
+/// The arguments as floats, failing when there are none.
+#[cfg(feature = "stats")]
+fn sample(name: &str, args: &[Value]) -> Result<Vec<f64>, EvalError> {
+    if args.is_empty() {
+        return Err(EvalError::with_code(
+            ErrorCode::ArgumentCount,
+            format!("{} expects at least 1 argument, got 0", name),
+        ));
+    }
+    Ok(args.iter().map(|arg| arg.as_f64()).collect())
+}
+
+#[cfg(feature = "stats")]
+fn mean(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+/// The population variance.
+#[cfg(feature = "stats")]
+fn variance(xs: &[f64]) -> f64 {
+    let mean = mean(xs);
+    xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / xs.len() as f64
+}
+
+/// Registers the `stats` group under `stats::`, whose functions take one or
+/// more arguments.
+#[cfg(feature = "stats")]
+pub fn stats(ctx: &mut Context) {
+    ctx.import("stats::*");
+    ctx.register_variadic("stats::sum", |args| {
+        Ok(Value::Float(sample("sum", args)?.iter().sum()))
+    });
+    ctx.register_variadic("stats::mean", |args| {
+        Ok(Value::Float(mean(&sample("mean", args)?)))
+    });
+    ctx.register_variadic("stats::median", |args| {
+        let mut xs = sample("median", args)?;
+        xs.sort_by(f64::total_cmp);
+        let mid = xs.len() / 2;
+        Ok(Value::Float(if xs.len() % 2 == 0 {
+            (xs[mid - 1] + xs[mid]) / 2.0
+        } else {
+            xs[mid]
+        }))
+    });
+    ctx.register_variadic("stats::variance", |args| {
+        Ok(Value::Float(variance(&sample("variance", args)?)))
+    });
+    ctx.register_variadic("stats::stddev", |args| {
+        Ok(Value::Float(libm::sqrt(variance(&sample("stddev", args)?))))
+    });
+}
+
+///
+/// Units and their size in the SI unit of their dimension: metres,
+/// kilograms, seconds, square metres and cubic metres. Base units are
+/// spelled out so they do not shadow one-letter variables.
+///
+#[cfg(feature = "units")]
+pub const UNITS: &[(&str, f64)] = &[
+    ("metre", 1.0),
+    ("km", 1000.0),
+    ("cm", 0.01),
+    ("mm", 0.001),
+    ("inch", 0.0254),
+    ("ft", 0.3048),
+    ("yd", 0.9144),
+    ("mi", 1609.344),
+    ("nmi", 1852.0),
+    ("kg", 1.0),
+    ("gram", 0.001),
+    ("tonne", 1000.0),
+    ("lb", 0.45359237),
+    ("oz", 0.028349523125),
+    ("second", 1.0),
+    ("ms", 0.001),
+    ("minute", 60.0),
+    ("hour", 3600.0),
+    ("day", 86400.0),
+    ("week", 604800.0),
+    ("ha", 10000.0),
+    ("acre", 4046.8564224),
+    ("litre", 0.001),
+    ("ml", 0.000001),
+    ("gal", 0.003785411784),
+];
+
+/// Registers the `units` group: every entry of [`UNITS`] as a variable.
+#[cfg(feature = "units")]
+pub fn units(ctx: &mut Context) {
+    for (name, size) in UNITS {
+        ctx.set(*name, Value::Float(*size));
+    }
+}
+
+#[cfg(all(test, any(feature = "math", feature = "stats", feature = "units")))]
+mod test {
+    use super::*;
+    use crate::eval::Eval;
+
+    fn eval(text: &str) -> Value {
+        Eval::new()
+            .eval_with(&text.parse().unwrap(), &Context::stdlib())
+            .unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        Eval::new()
+            .eval_with(&text.parse().unwrap(), &Context::stdlib())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[cfg(feature = "math")]
+    #[test]
+    fn math_functions() {
+        assert_eq!(eval("sqrt(16) + ln(e)"), Value::Float(5.0));
+        assert_eq!(eval("round(sin(pi / 2) * 10)"), Value::Float(10.0));
+        assert_eq!(eval("floor(7)"), Value::Int(7));
+        assert_eq!(eval("floor(7.5) / 2"), Value::Float(3.5));
+        assert_eq!(eval("abs(-3) + max(2, 5) - min(1, 0)"), Value::Int(8));
+        assert_eq!(eval("min(1, cos(0) / 2)"), Value::Float(0.5));
+        assert_eq!(eval("hypot(3, 4)"), Value::Float(5.0));
+        assert_eq!(eval("math::sqrt(4) + math::max(1, 2)"), Value::Float(4.0));
+        assert_eq!(error("abs(-2147483647 - 1)"), "EvalError: integer overflow");
+        assert_eq!(
+            error("sqrt(1, 2)"),
+            "EvalError: sqrt expects 1 arguments, got 2"
+        )
+    }
+
+    #[cfg(feature = "math")]
+    #[test]
+    fn trigonometry_in_degrees() {
+        let mut ctx = Context::stdlib();
+        trigonometry(&mut ctx, "deg".parse().unwrap());
+        let eval = |text: &str, ctx: &Context| {
+            let value = Eval::new().eval_with(&text.parse().unwrap(), ctx);
+            (value.unwrap().as_f64() * 1e9).round() / 1e9
+        };
+        assert_eq!(eval("sin(30) + cos(60)", &ctx), 1.0);
+        assert_eq!(eval("atan2(1, 1) + asin(1)", &ctx), 135.0);
+        trigonometry(&mut ctx, Angle::Radians);
+        assert_eq!(eval("sin(pi / 2)", &ctx), 1.0);
+        assert_eq!(
+            "grad".parse::<Angle>().unwrap_err().to_string(),
+            "AngleError: unknown angle unit grad, expected rad or deg"
+        )
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_functions() {
+        assert_eq!(eval("sum(1, 2, 3)"), Value::Float(6.0));
+        assert_eq!(eval("mean(2, 4, 9)"), Value::Float(5.0));
+        assert_eq!(eval("stats::mean(2, 4, 9)"), Value::Float(5.0));
+        assert_eq!(eval("median(5, 1, 3)"), Value::Float(3.0));
+        assert_eq!(eval("median(4, 1, 3, 2)"), Value::Float(2.5));
+        assert_eq!(eval("variance(2, 4, 4, 4, 5, 5, 7, 9)"), Value::Float(4.0));
+        assert_eq!(eval("stddev(2, 4, 4, 4, 5, 5, 7, 9)"), Value::Float(2.0));
+        assert_eq!(
+            error("mean()"),
+            "EvalError: mean expects at least 1 argument, got 0"
+        )
+    }
+
+    #[cfg(feature = "units")]
+    #[test]
+    fn unit_conversions() {
+        assert_eq!(eval("2 * hour / minute"), Value::Float(120.0));
+        assert_eq!(eval("round(3 * ft / inch)"), Value::Float(36.0));
+        assert!((eval("5 * km / mi").as_f64() - 3.106856).abs() < 1e-6)
+    }
+}
@@ -1,44 +1,118 @@
-use std::error::Error;
-use std::fmt::Display;
-use std::iter::Peekable;
-use std::str::Chars;
+use crate::error::ErrorCode;
+use crate::prelude::*;
+use core::error::Error;
+use core::fmt::Display;
+use core::iter::Peekable;
+use core::str::Chars;
+#[cfg(feature = "std")]
+use std::io::BufRead;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Token {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token<'a> {
     Space,
-    Number(String),
+    Number(&'a str),
+    Identifier(&'a str),
+    Keyword(&'a str),
+    Comment(&'a str),
     Plus,
     Minus,
     Mul,
     Div,
+    Pow,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Not,
     LParen,
     RParen,
+    Assign,
+    Semicolon,
+    Comma,
+    /// A symbol passed to [`Lexer::with_symbols`].
+    Symbol(&'a str),
 }
 
-impl Display for Token {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Token::Space => f.write_str(" "),
             Token::Number(n) => write!(f, "{}", n),
+            Token::Identifier(name) | Token::Keyword(name) | Token::Symbol(name) => {
+                f.write_str(name)
+            }
+            Token::Comment(text) => f.write_str(text),
             Token::Plus => f.write_str("+"),
             Token::Minus => f.write_str("-"),
             Token::Mul => f.write_str("*"),
             Token::Div => f.write_str("/"),
+            Token::Pow => f.write_str("^"),
+            Token::Shl => f.write_str("<<"),
+            Token::Shr => f.write_str(">>"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::Not => f.write_str("!"),
             Token::LParen => f.write_str("("),
             Token::RParen => f.write_str(")"),
+            Token::Assign => f.write_str("="),
+            Token::Semicolon => f.write_str(";"),
+            Token::Comma => f.write_str(","),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub line: u32,
+    pub col: u32,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `end`.
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            len: end.offset + end.len - self.offset,
+            ..self
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpannedToken<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub token: Token<'a>,
+    pub span: Span,
+}
+
 #[derive(Debug)]
 pub struct TokenizerError {
+    pub code: ErrorCode,
     pub message: String,
     pub line: u32,
     pub col: u32,
+    /// Byte offset of the offending character in the whole input.
+    pub offset: usize,
+}
+
+impl TokenizerError {
+    /// The offending character, as a span of length one.
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            col: self.col,
+            offset: self.offset,
+            len: 1,
+        }
+    }
 }
 
 impl Display for TokenizerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "{} at Line: {}, Column {}",
@@ -51,85 +125,578 @@ impl Error for TokenizerError {}
 
 pub struct Tokenizer<'a> {
     pub text: &'a str,
-    pub line: u32,
-    pub col: u32,
+    pub keywords: &'a [&'a str],
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(text: &'a str) -> Self {
         Tokenizer {
             text,
-            line: 1,
-            col: 1,
+            keywords: &[],
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, TokenizerError> {
-        let mut peekable = self.text.chars().peekable();
-        let mut tokens: Vec<Token> = vec![];
+    pub fn with_keywords(text: &'a str, keywords: &'a [&'a str]) -> Self {
+        Tokenizer { text, keywords }
+    }
+
+    pub fn lexer(&self) -> Lexer<'a> {
+        Lexer::new(self.text).with_keywords(self.keywords)
+    }
 
-        while let Some(tok) = self.next_token(&mut peekable)? {
-            match &tok {
-                Token::Space | Token::LParen | Token::RParen => self.col += 1,
-                Token::Number(n) => self.col += n.len() as u32,
-                Token::Plus | Token::Minus | Token::Mul | Token::Div => self.col += 1,
-            }
-            if tok == Token::Space {
-                continue;
-            }
-            tokens.push(tok)
+    pub fn tokenize(&self) -> Result<Vec<SpannedToken<'a>>, TokenizerError> {
+        self.lexer().collect()
+    }
+}
+
+pub struct Lexer<'a> {
+    text: &'a str,
+    chars: Peekable<Chars<'a>>,
+    line: u32,
+    col: u32,
+    offset: usize,
+    base: usize,
+    keywords: &'a [&'a str],
+    symbols: &'a [&'a str],
+    keep_comments: bool,
+    recover: bool,
+    open_comment: bool,
+    failed: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self::with_position(text, 1, 1, 0)
+    }
+
+    /// A lexer for `text` found at `line`, `col` and byte offset `base` of
+    /// a larger input, so spans point into that input.
+    pub(crate) fn with_position(text: &'a str, line: u32, col: u32, base: usize) -> Self {
+        Lexer {
+            text,
+            chars: text.chars().peekable(),
+            line,
+            col,
+            offset: 0,
+            base,
+            keywords: &[],
+            symbols: &[],
+            keep_comments: false,
+            recover: false,
+            open_comment: false,
+            failed: false,
         }
+    }
+
+    /// Words listed in `keywords` are emitted as `Token::Keyword` instead of
+    /// `Token::Identifier`.
+    pub fn with_keywords(mut self, keywords: &'a [&'a str]) -> Self {
+        self.keywords = keywords;
+        self
+    }
+
+    ///
+    /// Operators added on top of the built-in ones, such as `..`, emitted as
+    /// `Token::Symbol` wherever they are longer than the built-in token at
+    /// the same place. Symbols are made of punctuation.
+    ///
+    pub fn with_symbols(mut self, symbols: &'a [&'a str]) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Emit comments as `Token::Comment` trivia instead of skipping them.
+    pub fn keep_comments(mut self, keep: bool) -> Self {
+        self.keep_comments = keep;
+        self
+    }
 
-        Ok(tokens)
+    /// After an error, skip the offending character and keep lexing instead
+    /// of stopping, so that every error in the input is reported.
+    pub fn recover(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
     }
 
-    fn next_token(&self, chars: &mut Peekable<Chars<'_>>) -> Result<Option<Token>, TokenizerError> {
-        match chars.peek() {
+    /// The line, column and offset of the next character.
+    pub(crate) fn position(&self) -> (u32, u32, usize) {
+        (self.line, self.col, self.base + self.offset)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.offset..]
+    }
+
+    /// The longest symbol at the current position that beats the built-in
+    /// token there.
+    fn symbol(&self) -> Option<&'a str> {
+        let rest = self.rest();
+        let builtin = match rest.chars().next()? {
+            c if c.is_alphanumeric() || c.is_whitespace() || c == '_' || c == '$' => return None,
+            _ if ["<<", ">>", "&&", "||", "//", "/*"]
+                .iter()
+                .any(|pair| rest.starts_with(pair)) =>
+            {
+                2
+            }
+            c if "()=;,+-*^!#/".contains(c) => 1,
+            _ => 0,
+        };
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.len() > builtin && rest.starts_with(**symbol))
+            .max_by_key(|symbol| symbol.len())
+            .copied()
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, TokenizerError> {
+        if let Some(symbol) = self.symbol() {
+            return self.consume_n(symbol.chars().count(), Token::Symbol(symbol));
+        }
+        match self.chars.peek() {
             Some(&c) => match c {
-                ' ' => self.consume(chars, Token::Space),
-                '(' => self.consume(chars, Token::LParen),
-                ')' => self.consume(chars, Token::RParen),
-                '+' => self.consume(chars, Token::Plus),
-                '-' => self.consume(chars, Token::Minus),
-                '*' => self.consume(chars, Token::Mul),
-                '/' => self.consume(chars, Token::Div),
-                '0'..='9' => Ok(Some(Token::Number(
-                    self.take_while(chars, |ch| matches!(ch, '0'..='9')),
-                ))),
+                c if c.is_whitespace() => {
+                    self.take_while(char::is_whitespace);
+                    Ok(Some(Token::Space))
+                }
+                '(' => self.consume(Token::LParen),
+                ')' => self.consume(Token::RParen),
+                '=' => self.consume(Token::Assign),
+                ';' => self.consume(Token::Semicolon),
+                ',' => self.consume(Token::Comma),
+                '+' => self.consume(Token::Plus),
+                '-' => self.consume(Token::Minus),
+                '*' => self.consume(Token::Mul),
+                '^' => self.consume(Token::Pow),
+                '<' if self.rest().starts_with("<<") => self.consume_n(2, Token::Shl),
+                '>' if self.rest().starts_with(">>") => self.consume_n(2, Token::Shr),
+                '&' if self.rest().starts_with("&&") => self.consume_n(2, Token::And),
+                '|' if self.rest().starts_with("||") => self.consume_n(2, Token::Or),
+                '!' => self.consume(Token::Not),
+                '#' => self.line_comment(),
+                '/' if self.rest().starts_with("//") => self.line_comment(),
+                '/' if self.rest().starts_with("/*") => self.block_comment(),
+                '/' => self.consume(Token::Div),
+                '0'..='9' => Ok(Some(Token::Number(self.number()))),
+                '$' => self.placeholder(),
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let start = self.offset;
+                    let word = self.word();
+                    if self.rest().starts_with("::") {
+                        return self.path(start);
+                    }
+                    if self.keywords.contains(&word) {
+                        Ok(Some(Token::Keyword(word)))
+                    } else {
+                        Ok(Some(Token::Identifier(word)))
+                    }
+                }
                 _ => Err(TokenizerError {
+                    code: ErrorCode::UnknownSymbol,
                     message: "Unknow symbol".to_string(),
                     line: self.line,
                     col: self.col,
+                    offset: self.base + self.offset,
                 }),
             },
             None => Ok(None),
         }
     }
 
-    fn consume(
-        &self,
-        chars: &mut Peekable<Chars<'_>>,
-        token: Token,
-    ) -> Result<Option<Token>, TokenizerError> {
-        chars.next();
+    fn word(&mut self) -> &'a str {
+        self.take_while(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    }
+
+    ///
+    /// A qualified name such as `math::sin`, or a glob such as `math::*`,
+    /// lexed as one identifier starting at `start`.
+    ///
+    fn path(&mut self, start: usize) -> Result<Option<Token<'a>>, TokenizerError> {
+        while self.rest().starts_with("::") {
+            let (line, col, offset) = (self.line, self.col, self.offset);
+            self.bump();
+            self.bump();
+            match self.chars.peek() {
+                Some('a'..='z' | 'A'..='Z' | '_') => {
+                    self.word();
+                }
+                Some('*') => {
+                    self.bump();
+                    break;
+                }
+                _ => {
+                    return Err(TokenizerError {
+                        code: ErrorCode::UnknownSymbol,
+                        message: "Expected a name after ::".to_string(),
+                        line,
+                        col,
+                        offset: self.base + offset,
+                    })
+                }
+            }
+        }
+        Ok(Some(Token::Identifier(&self.text[start..self.offset])))
+    }
+
+    /// The digits of an integer, or of a float when followed by a fraction
+    /// such as `.5` or an exponent such as `e-3`. A dot or `e` that no digit
+    /// follows ends the number.
+    fn number(&mut self) -> &'a str {
+        let start = self.offset;
+        let digit = |text: &str| text.starts_with(|ch: char| ch.is_ascii_digit());
+        self.take_while(|ch| ch.is_ascii_digit());
+        if self.rest().starts_with('.') && digit(&self.rest()[1..]) {
+            self.bump();
+            self.take_while(|ch| ch.is_ascii_digit());
+        }
+        let rest = self.rest();
+        if rest.starts_with(['e', 'E']) {
+            let sign = usize::from(rest[1..].starts_with(['+', '-']));
+            if digit(&rest[1 + sign..]) {
+                for _ in 0..=sign {
+                    self.bump();
+                }
+                self.take_while(|ch| ch.is_ascii_digit());
+            }
+        }
+        &self.text[start..self.offset]
+    }
+
+    /// A positional placeholder `$1`, `$2`, ..., lexed as an identifier so
+    /// that it is bound like any other free variable.
+    fn placeholder(&mut self) -> Result<Option<Token<'a>>, TokenizerError> {
+        let (start, line, col) = (self.offset, self.line, self.col);
+        self.bump();
+        let digits = self.take_while(|ch| ch.is_ascii_digit());
+        if digits.is_empty() || digits.starts_with('0') {
+            return Err(TokenizerError {
+                code: ErrorCode::InvalidPlaceholder,
+                message: "Placeholders are numbered from $1".to_string(),
+                line,
+                col,
+                offset: self.base + start,
+            });
+        }
+        Ok(Some(Token::Identifier(&self.text[start..self.offset])))
+    }
+
+    fn line_comment(&mut self) -> Result<Option<Token<'a>>, TokenizerError> {
+        Ok(Some(Token::Comment(self.take_while(|ch| ch != '\n'))))
+    }
+
+    fn block_comment(&mut self) -> Result<Option<Token<'a>>, TokenizerError> {
+        let (start, line, col) = (self.offset, self.line, self.col);
+        self.bump();
+        self.bump();
+        while !self.rest().starts_with("*/") {
+            if self.bump().is_none() {
+                self.open_comment = true;
+                return Err(TokenizerError {
+                    code: ErrorCode::UnterminatedComment,
+                    message: "Unterminated comment".to_string(),
+                    line,
+                    col,
+                    offset: self.base + start,
+                });
+            }
+        }
+        self.bump();
+        self.bump();
+        Ok(Some(Token::Comment(&self.text[start..self.offset])))
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.chars.next()?;
+        self.offset += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(ch)
+    }
+
+    fn consume(&mut self, token: Token<'a>) -> Result<Option<Token<'a>>, TokenizerError> {
+        self.consume_n(1, token)
+    }
+
+    fn consume_n(
+        &mut self,
+        n: usize,
+        token: Token<'a>,
+    ) -> Result<Option<Token<'a>>, TokenizerError> {
+        for _ in 0..n {
+            self.bump();
+        }
         Ok(Some(token))
     }
 
-    fn take_while(
-        &self,
-        chars: &mut Peekable<Chars<'_>>,
-        mut predicate: impl FnMut(char) -> bool,
-    ) -> String {
-        let mut s = String::new();
-        while let Some(&ch) = chars.peek() {
+    fn take_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.offset;
+        while let Some(&ch) = self.chars.peek() {
             if predicate(ch) {
-                chars.next();
-                s.push(ch);
+                self.bump();
             } else {
                 break;
             }
         }
-        s
+        &self.text[start..self.offset]
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken<'a>, TokenizerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let (line, col, offset) = (self.line, self.col, self.offset);
+            let token = match self.next_token() {
+                Ok(Some(Token::Space)) => continue,
+                Ok(Some(Token::Comment(_))) if !self.keep_comments => continue,
+                Ok(Some(token)) => token,
+                Ok(None) => return None,
+                Err(err) => {
+                    if !self.recover {
+                        self.failed = true;
+                    } else if self.offset == offset {
+                        self.bump();
+                    }
+                    return Some(Err(err));
+                }
+            };
+            return Some(Ok(SpannedToken {
+                token,
+                span: Span {
+                    line,
+                    col,
+                    offset: self.base + offset,
+                    len: self.offset - offset,
+                },
+            }));
+        }
+    }
+}
+
+/// The bytes a [`StreamTokenizer`] reads at a time.
+#[cfg(feature = "std")]
+const CHUNK: usize = 8 * 1024;
+
+/// The characters the lexer may look at past the end of a token, as in the
+/// `e-3` after the digits of `1e-3`.
+#[cfg(feature = "std")]
+const LOOKAHEAD: usize = 3;
+
+/// A comment a chunk ended in, to be skipped in the next one.
+#[cfg(feature = "std")]
+enum OpenComment {
+    Line,
+    /// A block comment, with where it started for reporting it unterminated.
+    Block {
+        line: u32,
+        col: u32,
+        offset: usize,
+    },
+}
+
+#[cfg(feature = "std")]
+pub struct StreamTokenizer<R> {
+    reader: R,
+    /// Bytes read and not yet lexed: the start of a token that may go on in
+    /// the next chunk, and the end of a character split across chunks.
+    buf: Vec<u8>,
+    chunk: usize,
+    line: u32,
+    col: u32,
+    offset: usize,
+    comment: Option<OpenComment>,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> StreamTokenizer<R> {
+    pub fn new(reader: R) -> Self {
+        StreamTokenizer {
+            reader,
+            buf: vec![],
+            chunk: CHUNK,
+            line: 1,
+            col: 1,
+            offset: 0,
+            comment: None,
+        }
+    }
+
+    ///
+    /// Tokenizes the stream a fixed-size chunk at a time, handing every token
+    /// to `f`. Only the current chunk is held in memory, with the start of a
+    /// token it splits, so tokens borrow from it and must be copied out if
+    /// they need to outlive the callback. A comment open at the end of a
+    /// chunk is skipped in the next ones, never lexed twice.
+    ///
+    pub fn for_each_token(
+        &mut self,
+        mut f: impl FnMut(SpannedToken<'_>),
+    ) -> Result<(), TokenizerError> {
+        loop {
+            let eof = self.fill()? == 0;
+            let buf = core::mem::take(&mut self.buf);
+            let text = match core::str::from_utf8(&buf) {
+                Ok(text) => text,
+                // A character split at the end of the chunk is completed by
+                // the next one.
+                Err(err) if err.error_len().is_none() && !eof => {
+                    core::str::from_utf8(&buf[..err.valid_up_to()]).unwrap()
+                }
+                Err(err) => {
+                    let valid = core::str::from_utf8(&buf[..err.valid_up_to()]).unwrap();
+                    self.advance(valid);
+                    return Err(self.read_error("stream did not contain valid UTF-8"));
+                }
+            };
+            let lexed = self.lex(text, eof, &mut f)?;
+            self.buf = buf;
+            self.buf.drain(..lexed);
+            if eof {
+                return match self.comment.take() {
+                    Some(OpenComment::Block { line, col, offset }) => Err(TokenizerError {
+                        code: ErrorCode::UnterminatedComment,
+                        message: "Unterminated comment".to_string(),
+                        line,
+                        col,
+                        offset,
+                    }),
+                    _ => Ok(()),
+                };
+            }
+        }
+    }
+
+    /// Hands the tokens of `text` to `f`, returning the bytes lexed: all of
+    /// them at the end of the stream, else up to a token that may go on.
+    fn lex(
+        &mut self,
+        text: &str,
+        eof: bool,
+        f: &mut impl FnMut(SpannedToken<'_>),
+    ) -> Result<usize, TokenizerError> {
+        let mut start = 0;
+        loop {
+            let rest = &text[start..];
+            match self.comment {
+                Some(OpenComment::Line) => match rest.find('\n') {
+                    Some(end) => {
+                        self.advance(&rest[..end]);
+                        start += end;
+                        self.comment = None;
+                    }
+                    None => {
+                        self.advance(rest);
+                        return Ok(text.len());
+                    }
+                },
+                Some(OpenComment::Block { .. }) => match rest.find("*/") {
+                    Some(end) => {
+                        self.advance(&rest[..end + 2]);
+                        start += end + 2;
+                        self.comment = None;
+                    }
+                    None => {
+                        // A `*` at the end may be closed by a `/` next.
+                        let end = rest.len() - usize::from(rest.ends_with('*'));
+                        self.advance(&rest[..end]);
+                        return Ok(start + end);
+                    }
+                },
+                None => {}
+            }
+            let rest = &text[start..];
+            let mut lexer = Lexer::with_position(rest, self.line, self.col, self.offset);
+            loop {
+                let (line, col, offset) = (lexer.line, lexer.col, lexer.offset);
+                let near_end = |end: usize| !eof && end + LOOKAHEAD > rest.len();
+                match lexer.next_token() {
+                    Ok(None) => {
+                        (self.line, self.col, self.offset) = lexer.position();
+                        return Ok(text.len());
+                    }
+                    Ok(Some(Token::Comment(comment)))
+                        if !eof && lexer.offset == rest.len() && !comment.starts_with("/*") =>
+                    {
+                        (self.line, self.col, self.offset) = lexer.position();
+                        self.comment = Some(OpenComment::Line);
+                        return Ok(text.len());
+                    }
+                    Ok(Some(Token::Space | Token::Comment(_))) => {}
+                    Err(_) if lexer.open_comment && !eof => {
+                        (self.line, self.col, self.offset) = (line, col, self.offset + offset);
+                        self.comment = Some(OpenComment::Block {
+                            line,
+                            col,
+                            offset: self.offset,
+                        });
+                        self.advance("/*");
+                        start += offset + 2;
+                        break;
+                    }
+                    Ok(Some(_)) | Err(_) if near_end(lexer.offset.max(offset + 1)) => {
+                        (self.line, self.col, self.offset) = (line, col, self.offset + offset);
+                        return Ok(start + offset);
+                    }
+                    Ok(Some(token)) => f(SpannedToken {
+                        token,
+                        span: Span {
+                            line,
+                            col,
+                            offset: self.offset + offset,
+                            len: lexer.offset - offset,
+                        },
+                    }),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+
+    /// Moves the position past `text`.
+    fn advance(&mut self, text: &str) {
+        for ch in text.chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        self.offset += text.len();
+    }
+
+    /// Reads up to a chunk into the buffer, returning the bytes read.
+    fn fill(&mut self) -> Result<usize, TokenizerError> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(chunk) => {
+                    let read = chunk.len().min(self.chunk);
+                    self.buf.extend_from_slice(&chunk[..read]);
+                    self.reader.consume(read);
+                    return Ok(read);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(self.read_error(err)),
+            }
+        }
+    }
+
+    fn read_error(&self, err: impl Display) -> TokenizerError {
+        TokenizerError {
+            code: ErrorCode::Read,
+            message: format!("Read error: {}", err),
+            line: self.line,
+            col: self.col,
+            offset: self.offset,
+        }
     }
 }
 
@@ -140,44 +707,534 @@ mod test {
     #[test]
     fn tokenize_number() {
         let nubmer = String::from("123 456");
-        let mut tokenizer = Tokenizer::new(&nubmer);
-        let actual_tokens = tokenizer.tokenize().unwrap();
+        let tokenizer = Tokenizer::new(&nubmer);
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        let expected_tokens = vec![Token::Number("123"), Token::Number("456")];
 
+        assert_eq!(actual_tokens, expected_tokens)
+    }
+
+    #[test]
+    fn tokenize_float() {
+        let text = "2.5 1e3 0.5E-2 3e";
+        let actual_tokens: Vec<Token> = Tokenizer::new(text)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .filter(|t| *t != Token::Space)
+            .collect();
         let expected_tokens = vec![
-            Token::Number(String::from("123")),
-            Token::Number(String::from("456")),
+            Token::Number("2.5"),
+            Token::Number("1e3"),
+            Token::Number("0.5E-2"),
+            Token::Number("3"),
+            Token::Identifier("e"),
         ];
-
         assert_eq!(actual_tokens, expected_tokens)
     }
 
     #[test]
     fn tokenize_operator() {
         let operators = String::from("+ - * /");
-        let mut tokenizer = Tokenizer::new(&operators);
-        let actual_tokens = tokenizer.tokenize().unwrap();
+        let tokenizer = Tokenizer::new(&operators);
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
 
         let expected_tokens = vec![Token::Plus, Token::Minus, Token::Mul, Token::Div];
 
         assert_eq!(actual_tokens, expected_tokens)
     }
 
+    #[test]
+    fn tokenize_power_and_shifts() {
+        let tokenizer = Tokenizer::new("x^2 << 1 >> y");
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        let expected_tokens = vec![
+            Token::Identifier("x"),
+            Token::Pow,
+            Token::Number("2"),
+            Token::Shl,
+            Token::Number("1"),
+            Token::Shr,
+            Token::Identifier("y"),
+        ];
+
+        assert_eq!(actual_tokens, expected_tokens);
+        assert!(Tokenizer::new("x < 1").tokenize().is_err())
+    }
+
+    #[test]
+    fn tokenize_logical() {
+        let tokenizer = Tokenizer::new("!a && b || c");
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        let expected_tokens = vec![
+            Token::Not,
+            Token::Identifier("a"),
+            Token::And,
+            Token::Identifier("b"),
+            Token::Or,
+            Token::Identifier("c"),
+        ];
+
+        assert_eq!(actual_tokens, expected_tokens);
+        assert!(Tokenizer::new("a & b").tokenize().is_err());
+        assert!(Tokenizer::new("a | b").tokenize().is_err())
+    }
+
+    #[test]
+    fn tokenize_placeholder() {
+        let tokens = Tokenizer::new("$1 * $12").tokenize().unwrap();
+        assert_eq!(tokens[0].token, Token::Identifier("$1"));
+        assert_eq!(tokens[2].token, Token::Identifier("$12"));
+        assert_eq!(tokens[2].span.len, 3);
+        assert!(Tokenizer::new("$ 1").tokenize().is_err());
+        assert!(Tokenizer::new("$0").tokenize().is_err())
+    }
+
     #[test]
     fn tokenize_with_parenthesis() {
         let arithmetic = String::from("(1 + 2) * 3");
-        let mut tokenizer = Tokenizer::new(&arithmetic);
-        let actual_tokens = tokenizer.tokenize().unwrap();
+        let tokenizer = Tokenizer::new(&arithmetic);
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
 
         let expected_tokens = vec![
             Token::LParen,
-            Token::Number(String::from("1")),
+            Token::Number("1"),
             Token::Plus,
-            Token::Number(String::from("2")),
+            Token::Number("2"),
             Token::RParen,
             Token::Mul,
-            Token::Number(String::from("3")),
+            Token::Number("3"),
         ];
 
         assert_eq!(actual_tokens, expected_tokens)
     }
+
+    #[test]
+    fn token_spans() {
+        let tokenizer = Tokenizer::new("12 + (345)");
+        let spans: Vec<Span> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.span)
+            .collect();
+
+        let expected_spans = vec![
+            Span {
+                line: 1,
+                col: 1,
+                offset: 0,
+                len: 2,
+            },
+            Span {
+                line: 1,
+                col: 4,
+                offset: 3,
+                len: 1,
+            },
+            Span {
+                line: 1,
+                col: 6,
+                offset: 5,
+                len: 1,
+            },
+            Span {
+                line: 1,
+                col: 7,
+                offset: 6,
+                len: 3,
+            },
+            Span {
+                line: 1,
+                col: 10,
+                offset: 9,
+                len: 1,
+            },
+        ];
+
+        assert_eq!(spans, expected_spans)
+    }
+
+    #[test]
+    fn error_position_after_number() {
+        let tokenizer = Tokenizer::new("123 $");
+        let err = tokenizer.tokenize().unwrap_err();
+        assert_eq!((err.line, err.col), (1, 5))
+    }
+
+    #[test]
+    fn lexer_stops_after_error() {
+        let mut lexer = Lexer::new("1 $ 2");
+        assert_eq!(lexer.next().unwrap().unwrap().token, Token::Number("1"));
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stream_tokenize_lines() {
+        let input: &[u8] = b"1 + 2\n(3)\n";
+        let mut tokens = vec![];
+        StreamTokenizer::new(input)
+            .for_each_token(|t| tokens.push((t.token.to_string(), t.span)))
+            .unwrap();
+
+        let expected_tokens = vec![
+            (
+                "1".to_string(),
+                Span {
+                    line: 1,
+                    col: 1,
+                    offset: 0,
+                    len: 1,
+                },
+            ),
+            (
+                "+".to_string(),
+                Span {
+                    line: 1,
+                    col: 3,
+                    offset: 2,
+                    len: 1,
+                },
+            ),
+            (
+                "2".to_string(),
+                Span {
+                    line: 1,
+                    col: 5,
+                    offset: 4,
+                    len: 1,
+                },
+            ),
+            (
+                "(".to_string(),
+                Span {
+                    line: 2,
+                    col: 1,
+                    offset: 6,
+                    len: 1,
+                },
+            ),
+            (
+                "3".to_string(),
+                Span {
+                    line: 2,
+                    col: 2,
+                    offset: 7,
+                    len: 1,
+                },
+            ),
+            (
+                ")".to_string(),
+                Span {
+                    line: 2,
+                    col: 3,
+                    offset: 8,
+                    len: 1,
+                },
+            ),
+        ];
+
+        assert_eq!(tokens, expected_tokens)
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stream_error_reports_line() {
+        let input: &[u8] = b"1\n2 $\n";
+        let err = StreamTokenizer::new(input)
+            .for_each_token(|_| {})
+            .unwrap_err();
+        assert_eq!((err.line, err.col), (2, 3))
+    }
+
+    #[test]
+    fn tokenize_identifier() {
+        let tokenizer = Tokenizer::new("x1 + _tmp * 2y");
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        let expected_tokens = vec![
+            Token::Identifier("x1"),
+            Token::Plus,
+            Token::Identifier("_tmp"),
+            Token::Mul,
+            Token::Number("2"),
+            Token::Identifier("y"),
+        ];
+
+        assert_eq!(actual_tokens, expected_tokens)
+    }
+
+    #[test]
+    fn tokenize_paths() {
+        let actual_tokens: Vec<Token> = Lexer::new("use math::*; stats::mean(a::b::c)")
+            .map(|t| t.unwrap().token)
+            .filter(|t| *t != Token::Space)
+            .collect();
+
+        let expected_tokens = vec![
+            Token::Identifier("use"),
+            Token::Identifier("math::*"),
+            Token::Semicolon,
+            Token::Identifier("stats::mean"),
+            Token::LParen,
+            Token::Identifier("a::b::c"),
+            Token::RParen,
+        ];
+        assert_eq!(actual_tokens, expected_tokens);
+
+        let err = Tokenizer::new("math::1").tokenize().unwrap_err();
+        assert_eq!(
+            (err.message.as_str(), err.col),
+            ("Expected a name after ::", 5)
+        )
+    }
+
+    #[test]
+    fn tokenize_keyword() {
+        let tokenizer = Tokenizer::with_keywords("let x", &["let"]);
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(
+            actual_tokens,
+            vec![Token::Keyword("let"), Token::Identifier("x")]
+        )
+    }
+
+    #[test]
+    fn tokenize_symbols() {
+        let actual_tokens: Vec<Token> = Lexer::new("a..b == |c|| d")
+            .with_symbols(&["..", "==", "|", "."])
+            .map(|t| t.unwrap().token)
+            .collect();
+
+        assert_eq!(
+            actual_tokens,
+            vec![
+                Token::Identifier("a"),
+                Token::Symbol(".."),
+                Token::Identifier("b"),
+                Token::Symbol("=="),
+                Token::Symbol("|"),
+                Token::Identifier("c"),
+                Token::Or,
+                Token::Identifier("d"),
+            ]
+        )
+    }
+
+    #[test]
+    fn skip_comments() {
+        let tokenizer = Tokenizer::new("1 /* one */ 2 // two");
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(actual_tokens, vec![Token::Number("1"), Token::Number("2")]);
+
+        let tokenizer = Tokenizer::new("3 # three");
+        assert_eq!(tokenizer.tokenize().unwrap().len(), 1)
+    }
+
+    #[test]
+    fn keep_comments_as_trivia() {
+        let actual_tokens: Vec<Token> = Lexer::new("1 /* one */ // two")
+            .keep_comments(true)
+            .map(|t| t.unwrap().token)
+            .collect();
+
+        let expected_tokens = vec![
+            Token::Number("1"),
+            Token::Comment("/* one */"),
+            Token::Comment("// two"),
+        ];
+
+        assert_eq!(actual_tokens, expected_tokens)
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let err = Tokenizer::new("1 /* open").tokenize().unwrap_err();
+        assert_eq!((err.line, err.col), (1, 3))
+    }
+
+    #[test]
+    fn recover_after_errors() {
+        let results: Vec<_> = Lexer::new("a @ $0 + b").recover(true).collect();
+        let cols: Vec<u32> = results
+            .iter()
+            .filter_map(|result| result.as_ref().err().map(|err| err.col))
+            .collect();
+        assert_eq!(cols, [3, 5]);
+        let tokens: Vec<Token> = results
+            .into_iter()
+            .filter_map(|result| result.ok().map(|t| t.token))
+            .collect();
+        assert_eq!(
+            tokens,
+            [Token::Identifier("a"), Token::Plus, Token::Identifier("b")]
+        );
+        assert_eq!(Lexer::new("a @ b").count(), 2)
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stream_block_comment_across_lines() {
+        let input: &[u8] = b"1 /* a\nb */ 2\n3\n";
+        let mut tokens = vec![];
+        StreamTokenizer::new(input)
+            .for_each_token(|t| tokens.push((t.token.to_string(), t.span.line)))
+            .unwrap();
+
+        let expected_tokens = vec![
+            ("1".to_string(), 1),
+            ("2".to_string(), 2),
+            ("3".to_string(), 3),
+        ];
+
+        assert_eq!(tokens, expected_tokens)
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stream_tokens_across_chunks() {
+        let text = "x1 = 2.5e-3 << 1; # c\n/* a\n* b **/ y::z // d\n$12 && 3/4; é + /* x */ 1e5";
+        let expected: Vec<(String, Span)> = Lexer::new(text)
+            .map_while(Result::ok)
+            .map(|t| (t.token.to_string(), t.span))
+            .collect();
+        for chunk in 1..=text.len() {
+            let mut tokens = vec![];
+            let mut stream = StreamTokenizer::new(text.as_bytes());
+            stream.chunk = chunk;
+            let result = stream.for_each_token(|t| tokens.push((t.token.to_string(), t.span)));
+            // `é` is not a token: everything before it is.
+            let err = result.unwrap_err();
+            assert_eq!((err.line, err.col), (4, 13), "chunk {}", chunk);
+            assert_eq!(tokens, expected, "chunk {}", chunk);
+        }
+        for chunk in [1, 2, 5] {
+            let mut stream = StreamTokenizer::new(&b"1 /* a\n*"[..]);
+            stream.chunk = chunk;
+            let err = stream.for_each_token(|_| {}).unwrap_err();
+            assert_eq!(
+                (err.code, err.line, err.col),
+                (ErrorCode::UnterminatedComment, 1, 3)
+            );
+        }
+    }
+
+    #[test]
+    fn tokenize_tabs_and_newlines() {
+        let tokenizer = Tokenizer::new("1\t+\r\n  2\u{a0}*\n3");
+        let actual_tokens: Vec<(Token, u32, u32)> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.token, t.span.line, t.span.col))
+            .collect();
+
+        let expected_tokens = vec![
+            (Token::Number("1"), 1, 1),
+            (Token::Plus, 1, 3),
+            (Token::Number("2"), 2, 3),
+            (Token::Mul, 2, 5),
+            (Token::Number("3"), 3, 1),
+        ];
+
+        assert_eq!(actual_tokens, expected_tokens)
+    }
+
+    #[test]
+    fn error_position_on_later_line() {
+        let err = Tokenizer::new("1 +\n  22 $").tokenize().unwrap_err();
+        assert_eq!((err.line, err.col), (2, 6))
+    }
+
+    #[test]
+    fn line_comment_ends_at_newline() {
+        let tokenizer = Tokenizer::new("1 # one\n2");
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(actual_tokens, vec![Token::Number("1"), Token::Number("2")])
+    }
+
+    #[test]
+    fn tokenize_statements() {
+        let tokenizer = Tokenizer::new("x = 3; x");
+        let actual_tokens: Vec<Token> = tokenizer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        let expected_tokens = vec![
+            Token::Identifier("x"),
+            Token::Assign,
+            Token::Number("3"),
+            Token::Semicolon,
+            Token::Identifier("x"),
+        ];
+
+        assert_eq!(actual_tokens, expected_tokens)
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let text = "(x + 12) / 3";
+        let tokens = Tokenizer::new(text).tokenize().unwrap();
+        let json = serde_json::to_string(&tokens).unwrap();
+        let actual_tokens: Vec<SpannedToken> = serde_json::from_str(&json).unwrap();
+        assert_eq!(actual_tokens, tokens)
+    }
 }
@@ -0,0 +1,341 @@
+//!
+//! Visitors over the AST.
+//!
+//! Every hook defaults to walking into the children of the node, so an
+//! implementation only overrides the hooks it cares about and calls the
+//! matching `walk_*` function when it still wants to descend.
+//!
+use crate::parser::*;
+use crate::prelude::*;
+use alloc::collections::BTreeSet;
+
+pub trait Visitor {
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node)
+    }
+
+    fn visit_number(&mut self, _n: i32) {}
+
+    fn visit_float(&mut self, _x: f64) {}
+
+    fn visit_identifier(&mut self, _name: &str) {}
+
+    fn visit_unary(&mut self, _op: &UnaryOperator, child: &Node) {
+        self.visit_node(child)
+    }
+
+    fn visit_binary(&mut self, _op: &BinaryOperator, lhs: &Node, rhs: &Node) {
+        walk_binary(self, lhs, rhs)
+    }
+
+    fn visit_assign(&mut self, _name: &str, value: &Node) {
+        self.visit_node(value)
+    }
+
+    fn visit_block(&mut self, stmts: &[Node]) {
+        walk_block(self, stmts)
+    }
+
+    fn visit_call(&mut self, _name: &str, args: &[Node]) {
+        walk_block(self, args)
+    }
+
+    fn visit_use(&mut self, _path: &str) {}
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    match node {
+        Node::Number(n) => visitor.visit_number(*n),
+        Node::Float(Float(x)) => visitor.visit_float(*x),
+        Node::Identifier(name) => visitor.visit_identifier(name),
+        Node::UnaryExpr { op, child } => visitor.visit_unary(op, child),
+        Node::BinaryExpr { op, lhs, rhs } => visitor.visit_binary(op, lhs, rhs),
+        Node::Assign { name, value } => visitor.visit_assign(name, value),
+        Node::Block(stmts) => visitor.visit_block(stmts),
+        Node::Call { name, args } => visitor.visit_call(name, args),
+        Node::Use(path) => visitor.visit_use(path),
+    }
+}
+
+pub fn walk_binary<V: Visitor + ?Sized>(visitor: &mut V, lhs: &Node, rhs: &Node) {
+    visitor.visit_node(lhs);
+    visitor.visit_node(rhs);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, stmts: &[Node]) {
+    for stmt in stmts {
+        visitor.visit_node(stmt);
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        walk_node_mut(self, node)
+    }
+
+    fn visit_number_mut(&mut self, _n: &mut i32) {}
+
+    fn visit_float_mut(&mut self, _x: &mut f64) {}
+
+    fn visit_identifier_mut(&mut self, _name: &mut String) {}
+
+    fn visit_unary_mut(&mut self, _op: &mut UnaryOperator, child: &mut Node) {
+        self.visit_node_mut(child)
+    }
+
+    fn visit_binary_mut(&mut self, _op: &mut BinaryOperator, lhs: &mut Node, rhs: &mut Node) {
+        self.visit_node_mut(lhs);
+        self.visit_node_mut(rhs);
+    }
+
+    fn visit_assign_mut(&mut self, _name: &mut String, value: &mut Node) {
+        self.visit_node_mut(value)
+    }
+
+    fn visit_block_mut(&mut self, stmts: &mut Vec<Node>) {
+        for stmt in stmts {
+            self.visit_node_mut(stmt);
+        }
+    }
+
+    fn visit_call_mut(&mut self, _name: &mut String, args: &mut Vec<Node>) {
+        for arg in args {
+            self.visit_node_mut(arg);
+        }
+    }
+
+    fn visit_use_mut(&mut self, _path: &mut String) {}
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut Node) {
+    match node {
+        Node::Number(n) => visitor.visit_number_mut(n),
+        Node::Float(Float(x)) => visitor.visit_float_mut(x),
+        Node::Identifier(name) => visitor.visit_identifier_mut(name),
+        Node::UnaryExpr { op, child } => visitor.visit_unary_mut(op, child),
+        Node::BinaryExpr { op, lhs, rhs } => visitor.visit_binary_mut(op, lhs, rhs),
+        Node::Assign { name, value } => visitor.visit_assign_mut(name, value),
+        Node::Block(stmts) => visitor.visit_block_mut(stmts),
+        Node::Call { name, args } => visitor.visit_call_mut(name, args),
+        Node::Use(path) => visitor.visit_use_mut(path),
+    }
+}
+
+struct Identifiers(BTreeSet<String>);
+
+struct Imports(Vec<String>);
+
+impl Visitor for Imports {
+    fn visit_use(&mut self, path: &str) {
+        self.0.push(path.to_string());
+    }
+}
+
+/// Names read before any assignment to them, in order of first use.
+#[derive(Default)]
+struct FreeVariables {
+    assigned: BTreeSet<String>,
+    free: Vec<String>,
+}
+
+impl Visitor for FreeVariables {
+    fn visit_identifier(&mut self, name: &str) {
+        if !self.assigned.contains(name) && !self.free.iter().any(|free| free == name) {
+            self.free.push(name.to_string());
+        }
+    }
+
+    fn visit_assign(&mut self, name: &str, value: &Node) {
+        self.visit_node(value);
+        self.assigned.insert(name.to_string());
+    }
+}
+
+impl Visitor for Identifiers {
+    fn visit_identifier(&mut self, name: &str) {
+        self.0.insert(name.to_string());
+    }
+}
+
+impl Node {
+    pub fn accept<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        visitor.visit_node(self)
+    }
+
+    pub fn accept_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.visit_node_mut(self)
+    }
+
+    /// Names of all variables read anywhere in the tree, sorted.
+    pub fn identifiers(&self) -> BTreeSet<String> {
+        let mut visitor = Identifiers(BTreeSet::new());
+        self.accept(&mut visitor);
+        visitor.0
+    }
+
+    /// Paths of the `use` directives in the tree, in source order.
+    pub fn imports(&self) -> Vec<String> {
+        let mut visitor = Imports(vec![]);
+        self.accept(&mut visitor);
+        visitor.0
+    }
+
+    /// Names the tree assigns, sorted.
+    pub fn assigned_variables(&self) -> BTreeSet<String> {
+        let mut visitor = FreeVariables::default();
+        self.accept(&mut visitor);
+        visitor.assigned
+    }
+
+    /// Names read before the tree assigns them, i.e. the inputs it needs,
+    /// in order of first use.
+    pub fn free_variables(&self) -> Vec<String> {
+        let mut visitor = FreeVariables::default();
+        self.accept(&mut visitor);
+        visitor.free
+    }
+
+    ///
+    /// The bytes the tree takes in memory: the node itself and everything
+    /// it owns on the heap, children, names and the spare capacity of its
+    /// vectors included. What the allocator adds to each allocation is not
+    /// counted.
+    ///
+    /// The nodes are walked with a stack on the heap, so that any tree
+    /// that could be built can be measured.
+    ///
+    pub fn deep_size(&self) -> usize {
+        let node_size = core::mem::size_of::<Node>();
+        let mut size = node_size;
+        let mut nodes = vec![self];
+        while let Some(node) = nodes.pop() {
+            size += match node {
+                Node::Number(_) | Node::Float(_) => 0,
+                Node::Identifier(name) | Node::Use(name) => name.capacity(),
+                Node::UnaryExpr { child, .. } => {
+                    nodes.push(child);
+                    node_size
+                }
+                Node::BinaryExpr { lhs, rhs, .. } => {
+                    nodes.extend([&**lhs, &**rhs]);
+                    2 * node_size
+                }
+                Node::Assign { name, value } => {
+                    nodes.push(value);
+                    name.capacity() + node_size
+                }
+                Node::Block(stmts) => {
+                    nodes.extend(stmts);
+                    stmts.capacity() * node_size
+                }
+                Node::Call { name, args } => {
+                    nodes.extend(args);
+                    name.capacity() + args.capacity() * node_size
+                }
+            };
+        }
+        size
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn parse(text: &str) -> Node {
+        let tokenizer = Tokenizer::new(text);
+        let tokens = tokenizer.tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    struct Count(usize);
+
+    impl Visitor for Count {
+        fn visit_node(&mut self, node: &Node) {
+            self.0 += 1;
+            walk_node(self, node)
+        }
+    }
+
+    struct Depth {
+        current: usize,
+        max: usize,
+    }
+
+    impl Visitor for Depth {
+        fn visit_node(&mut self, node: &Node) {
+            self.current += 1;
+            self.max = self.max.max(self.current);
+            walk_node(self, node);
+            self.current -= 1;
+        }
+    }
+
+    #[test]
+    fn count_nodes() {
+        let mut count = Count(0);
+        parse("x = 1 + 2 * -y; x").accept(&mut count);
+        assert_eq!(count.0, 9)
+    }
+
+    #[test]
+    fn deep_size() {
+        let node_size = core::mem::size_of::<Node>();
+        assert_eq!(Node::Number(1).deep_size(), node_size);
+        assert_eq!(parse("1 + -2").deep_size(), 4 * node_size);
+        let name = String::with_capacity(16);
+        assert_eq!(Node::Identifier(name).deep_size(), node_size + 16);
+        let mut args = Vec::with_capacity(4);
+        args.push(Node::Number(1));
+        let call = Node::Call {
+            name: "f".to_string(),
+            args,
+        };
+        assert_eq!(call.deep_size(), 5 * node_size + 1);
+        let sum = (1..100_000).fold(Node::Number(1), |sum, _| sum + 1);
+        assert_eq!(sum.deep_size(), (1 + 2 * 99_999) * node_size);
+        core::mem::forget(sum)
+    }
+
+    #[test]
+    fn max_depth() {
+        let mut depth = Depth { current: 0, max: 0 };
+        parse("1 + 2 * -(3 - 4)").accept(&mut depth);
+        assert_eq!(depth.max, 5)
+    }
+
+    #[test]
+    fn collect_identifiers() {
+        let names: Vec<String> = parse("b = a * c + a; b")
+            .identifiers()
+            .into_iter()
+            .collect();
+        assert_eq!(names, vec!["a", "b", "c"])
+    }
+
+    #[test]
+    fn free_variables() {
+        let names = parse("y = x * 2; x = y + z; x + w + z").free_variables();
+        assert_eq!(names, vec!["x", "z", "w"]);
+        let names = parse("max(a, f(b)) + a").free_variables();
+        assert_eq!(names, vec!["a", "b"])
+    }
+
+    #[test]
+    fn rename_identifiers() {
+        struct Rename;
+
+        impl VisitorMut for Rename {
+            fn visit_identifier_mut(&mut self, name: &mut String) {
+                if name == "x" {
+                    *name = "y".to_string();
+                }
+            }
+        }
+
+        let mut expr = parse("x * (x + z)");
+        expr.accept_mut(&mut Rename);
+        assert_eq!(expr.to_string(), "y * (y + z)")
+    }
+}
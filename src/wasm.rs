@@ -0,0 +1,49 @@
+//!
+//! Bindings for JavaScript, built with the `wasm` feature, e.g. for a
+//! calculator widget in the browser:
+//!
+//! ```js
+//! import { evaluate, astToJson } from "rexpr";
+//!
+//! evaluate("x = 6; x * 7");          // 42
+//! JSON.parse(astToJson("1 + x"));    // { type: "binary", op: "+", ... }
+//! try {
+//!     evaluate("1 / 0");
+//! } catch (err) {
+//!     JSON.parse(err).code;          // "E0102"
+//! }
+//! ```
+//!
+//! Errors are thrown as their JSON form, described in [`crate::json`].
+//!
+//! The crate builds a `cdylib` next to the Rust library, which is the
+//! module:
+//!
+//! ```sh
+//! cargo build --lib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir pkg \
+//!     target/wasm32-unknown-unknown/release/rexpr.wasm
+//! ```
+//!
+use crate::error::RexprError;
+use crate::parser::Node;
+use wasm_bindgen::prelude::*;
+
+fn throw(err: RexprError) -> JsValue {
+    JsValue::from_str(&err.to_json())
+}
+
+/// Evaluates `source` in a fresh session and returns its value as a number.
+#[wasm_bindgen]
+pub fn evaluate(source: &str) -> Result<JsValue, JsValue> {
+    let value = crate::eval_str(source).map_err(throw)?;
+    Ok(JsValue::from_f64(value.as_f64()))
+}
+
+/// Parses `source` and returns the JSON form of its tree.
+#[wasm_bindgen(js_name = astToJson)]
+pub fn ast_to_json(source: &str) -> Result<String, JsValue> {
+    let node: Node = source.parse().map_err(throw)?;
+    Ok(node.to_json())
+}
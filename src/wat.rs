@@ -0,0 +1,223 @@
+//!
+//! WebAssembly text output: a module exporting one function that computes
+//! the expression over `i32`.
+//!
+//! WebAssembly arithmetic wraps like `Overflow::Wrapping`, except that
+//! division by zero, `i32::MIN / -1` and negative exponents trap, and shift
+//! amounts are taken modulo 32. A float literal, which has no `i32` value,
+//! traps when it is reached.
+//!
+use crate::parser::*;
+use crate::visit::{walk_block, Visitor};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Integer power by repeated multiplication, emitted only when `^` is used.
+const POW: &str = "  (func $pow (param $base i32) (param $exp i32) (result i32)
+    (local $result i32)
+    (if (i32.lt_s (local.get $exp) (i32.const 0)) (then unreachable))
+    (local.set $result (i32.const 1))
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $exp)))
+        (local.set $result (i32.mul (local.get $result) (local.get $base)))
+        (local.set $exp (i32.sub (local.get $exp) (i32.const 1)))
+        (br $next)))
+    (local.get $result))
+";
+
+fn instruction(op: &BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Plus => "i32.add",
+        BinaryOperator::Minus => "i32.sub",
+        BinaryOperator::Mul => "i32.mul",
+        BinaryOperator::Div => "i32.div_s",
+        BinaryOperator::Pow => "call $pow",
+        BinaryOperator::Shl => "i32.shl",
+        BinaryOperator::Shr => "i32.shr_s",
+        BinaryOperator::And => "i32.and",
+        BinaryOperator::Or => "i32.or",
+    }
+}
+
+fn uses_pow(node: &Node) -> bool {
+    match node {
+        Node::Number(_) | Node::Float(_) | Node::Identifier(_) | Node::Use(_) => false,
+        Node::UnaryExpr { child, .. } => uses_pow(child),
+        Node::BinaryExpr { op, lhs, rhs } => {
+            *op == BinaryOperator::Pow || uses_pow(lhs) || uses_pow(rhs)
+        }
+        Node::Assign { value, .. } => uses_pow(value),
+        Node::Block(stmts) | Node::Call { args: stmts, .. } => stmts.iter().any(uses_pow),
+    }
+}
+
+/// Host functions called, with the number of arguments of their first call.
+struct Calls(BTreeMap<String, usize>);
+
+impl Visitor for Calls {
+    fn visit_call(&mut self, name: &str, args: &[Node]) {
+        self.0.entry(name.to_string()).or_insert(args.len());
+        walk_block(self, args)
+    }
+}
+
+fn assigned(node: &Node, names: &mut BTreeSet<String>) {
+    match node {
+        Node::Assign { name, value } => {
+            assigned(value, names);
+            names.insert(name.clone());
+        }
+        Node::Block(stmts) => stmts.iter().for_each(|stmt| assigned(stmt, names)),
+        _ => {}
+    }
+}
+
+impl Node {
+    /// A folded WAT instruction leaving the value of the node on the stack.
+    fn wat(&self) -> String {
+        match self {
+            Node::Number(n) => format!("(i32.const {})", n),
+            Node::Float(Float(x)) => format!("(unreachable (; {:?} ;))", x),
+            Node::Identifier(name) => format!("(local.get ${})", name),
+            Node::UnaryExpr {
+                op: UnaryOperator::Neg,
+                child,
+            } => format!("(i32.sub (i32.const 0) {})", child.wat()),
+            Node::UnaryExpr {
+                op: UnaryOperator::Not,
+                child,
+            } => format!("(i32.eqz {})", child.wat()),
+            // Operands are normalized to 0 or 1 so the bitwise instruction
+            // computes the logical one.
+            Node::BinaryExpr { op, lhs, rhs } if self.is_logical() => format!(
+                "({} (i32.ne {} (i32.const 0)) (i32.ne {} (i32.const 0)))",
+                instruction(op),
+                lhs.wat(),
+                rhs.wat()
+            ),
+            Node::BinaryExpr { op, lhs, rhs } => {
+                format!("({} {} {})", instruction(op), lhs.wat(), rhs.wat())
+            }
+            Node::Assign { name, value } => format!("(local.tee ${} {})", name, value.wat()),
+            Node::Block(stmts) if stmts.is_empty() => "(i32.const 0)".to_string(),
+            Node::Use(_) => "(i32.const 0)".to_string(),
+            Node::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(|arg| format!(" {}", arg.wat())).collect();
+                format!("(call $host.{}{})", name, args.concat())
+            }
+            Node::Block(stmts) => {
+                let mut stmts: Vec<String> = stmts.iter().map(Node::wat).collect();
+                let last = stmts.pop().unwrap();
+                stmts
+                    .into_iter()
+                    .map(|stmt| format!("(drop {})\n    ", stmt))
+                    .chain(std::iter::once(last))
+                    .collect()
+            }
+        }
+    }
+
+    ///
+    /// Emits a WebAssembly text module exporting a function `eval`. Every
+    /// variable read before it is assigned becomes an `i32` parameter, in
+    /// order of first use; the others become locals. Functions called are
+    /// imported from the `env` module under the same name.
+    ///
+    pub fn to_wat(&self) -> String {
+        let params = self.free_variables();
+        let mut locals = BTreeSet::new();
+        assigned(self, &mut locals);
+
+        let mut calls = Calls(BTreeMap::new());
+        self.accept(&mut calls);
+
+        let mut wat = String::from("(module\n");
+        for (name, argc) in &calls.0 {
+            wat.push_str(&format!(
+                "  (import \"env\" \"{}\" (func $host.{}{} (result i32)))\n",
+                name,
+                name,
+                " (param i32)".repeat(*argc)
+            ));
+        }
+        if uses_pow(self) {
+            wat.push_str(POW);
+        }
+        wat.push_str("  (func $eval (export \"eval\")");
+        for param in &params {
+            wat.push_str(&format!(" (param ${} i32)", param));
+        }
+        wat.push_str(" (result i32)\n");
+        for local in locals.iter().filter(|local| !params.contains(local)) {
+            wat.push_str(&format!("    (local ${} i32)\n", local));
+        }
+        wat.push_str(&format!("    {}))\n", self.wat()));
+        wat
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wat(text: &str) -> String {
+        let wat = text.parse::<Node>().unwrap().to_wat();
+        wat::parse_str(&wat).unwrap();
+        wat
+    }
+
+    #[test]
+    fn expression() {
+        assert_eq!(
+            wat("-(x + 1) * y"),
+            "(module\n  \
+             (func $eval (export \"eval\") (param $x i32) (param $y i32) (result i32)\n    \
+             (i32.mul (i32.sub (i32.const 0) (i32.add (local.get $x) (i32.const 1))) (local.get $y))))\n"
+        )
+    }
+
+    #[test]
+    fn statements_and_locals() {
+        assert_eq!(
+            wat("t = x << 2; x = t >> 1; x / t"),
+            "(module\n  \
+             (func $eval (export \"eval\") (param $x i32) (result i32)\n    \
+             (local $t i32)\n    \
+             (drop (local.tee $t (i32.shl (local.get $x) (i32.const 2))))\n    \
+             (drop (local.tee $x (i32.shr_s (local.get $t) (i32.const 1))))\n    \
+             (i32.div_s (local.get $x) (local.get $t))))\n"
+        )
+    }
+
+    #[test]
+    fn power_helper() {
+        assert!(wat("n ^ 3 - 1").contains("(call $pow (local.get $n) (i32.const 3))"));
+        assert!(!wat("n * 3").contains("$pow"))
+    }
+
+    #[test]
+    fn trap_on_floats() {
+        assert!(wat("n * 0.5").contains("(i32.mul (local.get $n) (unreachable (; 0.5 ;)))"))
+    }
+
+    #[test]
+    fn import_host_functions() {
+        assert_eq!(
+            wat("clamp(x, 0, limit()) + clamp(1, 2, 3)"),
+            "(module\n  \
+             (import \"env\" \"clamp\" (func $host.clamp (param i32) (param i32) (param i32) (result i32)))\n  \
+             (import \"env\" \"limit\" (func $host.limit (result i32)))\n  \
+             (func $eval (export \"eval\") (param $x i32) (result i32)\n    \
+             (i32.add (call $host.clamp (local.get $x) (i32.const 0) (call $host.limit)) \
+             (call $host.clamp (i32.const 1) (i32.const 2) (i32.const 3)))))\n"
+        )
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert!(wat("a && !b").contains(
+            "(i32.and (i32.ne (local.get $a) (i32.const 0)) \
+             (i32.ne (i32.eqz (local.get $b)) (i32.const 0)))"
+        ))
+    }
+}